@@ -1,13 +1,30 @@
 use crate::error::ApiError;
-use crate::repository::user_repo::UserRepo;
+use crate::logger::{RequestUser, UserDeleted};
 use crate::AppState;
-use crate::{models::user::User, KEYS};
-use axum::routing::Route;
+use crate::{
+    models::{
+        api_token::{ApiToken, Scope},
+        session::Session,
+        user::User,
+    },
+    services::{api_token_service, session_service},
+    KEYS,
+};
 use axum::{
-    body::Body, extract::State, http::Request, middleware::Next, response::Response, Extension,
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Method, Request, Uri},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation,
 };
-use jsonwebtoken::{decode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
@@ -15,6 +32,8 @@ use std::{
 use tracing::error;
 
 pub type AuthUser = Extension<Arc<User>>;
+/// Login the token of the request belongs to
+pub type CurrentSession = Extension<Arc<Session>>;
 
 pub async fn auth_middleware(
     State(app_state): State<AppState>,
@@ -28,38 +47,151 @@ pub async fn auth_middleware(
         .ok_or(ApiError::Unathorized)?;
 
     let token = token.trim_start_matches("Bearer ");
-    let key = &KEYS.decoding;
-    let validation = Validation::new(Algorithm::HS256);
+    //personal access tokens carry no session, require_scopes limits them
+    if token.starts_with(api_token_service::TOKEN_PREFIX) {
+        let (user, api_token) = api_token_service::authenticate(&app_state.database, token).await?;
+        let user_id = user.id;
+        let mut req = req;
+        req.extensions_mut().insert(Arc::new(user));
+        req.extensions_mut().insert(Arc::new(api_token));
+        return Ok(tag_user(next.run(req).await, user_id));
+    }
+    //refresh tokens only buy new tokens at /auth/refresh
+    let claims = decode_token(token, TokenType::Access)?;
 
-    match decode::<Claims>(token, key, &validation) {
-        Ok(token_data) => {
-            println!("Authenicated user: {}", token_data.claims.username);
+    //tokens outlive a deleted account until they expire
+    let user = match app_state
+        .database
+        .user_repo()
+        .get_user(&claims.username)
+        .await
+    {
+        Ok(user) => user,
+        Err(ApiError::NotFound) => return Err(ApiError::Unathorized),
+        Err(err) => return Err(err),
+    };
+    //a revoked session takes its tokens with it
+    let session = session_service::current_session(
+        app_state.database.session_repo(),
+        user.id,
+        claims.session_id()?,
+    )
+    .await?;
+    let user_id = user.id;
+    let mut req = req;
+    req.extensions_mut().insert(Arc::new(claims));
+    req.extensions_mut().insert(Arc::new(user));
+    req.extensions_mut().insert(Arc::new(session));
 
-            let user = app_state
-                .database
-                .user_repo()
-                .get_user(&token_data.claims.username)
-                .await?;
-            let mut req = req;
-            req.extensions_mut().insert(Arc::new(token_data));
-            req.extensions_mut().insert(Arc::new(user));
+    Ok(tag_user(next.run(req).await, user_id))
+}
 
-            Ok(next.run(req).await)
-        }
-        Err(err) => {
-            println!("Error {}", err);
-            Err(ApiError::InternalError)
+//for logger_middleware, which runs outside and only sees the response
+fn tag_user(mut res: Response, user_id: ObjectId) -> Response {
+    if res.extensions().get::<UserDeleted>().is_none() {
+        res.extensions_mut().insert(RequestUser(user_id));
+    }
+    res
+}
+
+/// Scopes a personal access token needs for a route group, reads are GET and HEAD
+#[derive(Clone, Copy)]
+pub struct RequiredScopes {
+    pub read: Scope,
+    pub write: Scope,
+}
+
+/// Goes inside auth_middleware, requests signed in with a password pass as they are
+pub async fn require_scopes(
+    State(scopes): State<RequiredScopes>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(api_token) = req.extensions().get::<Arc<ApiToken>>() {
+        let needed = match *req.method() {
+            Method::GET | Method::HEAD => scopes.read,
+            _ => scopes.write,
+        };
+        if !api_token.scopes.contains(&needed) {
+            return Err(ApiError::Forbidden);
         }
     }
+    Ok(next.run(req).await)
+}
+
+/// Keeps personal access tokens out of route groups no scope covers
+pub async fn sessions_only(req: Request<Body>, next: Next) -> Result<Response, ApiError> {
+    match req.extensions().get::<Arc<ApiToken>>() {
+        Some(_) => Err(ApiError::Forbidden),
+        None => Ok(next.run(req).await),
+    }
+}
+
+/// Moves `?access_token=` into the Authorization header for auth_middleware
+/// and drops it from the uri
+pub async fn token_from_query(mut req: Request<Body>, next: Next) -> Response {
+    let uri = req.uri().clone();
+    let Some(query) = uri.query() else {
+        return next.run(req).await;
+    };
+    let (tokens, rest): (Vec<&str>, Vec<&str>) = query
+        .split('&')
+        .partition(|pair| pair.starts_with("access_token="));
+    let Some(token) = tokens.first().map(|pair| &pair["access_token=".len()..]) else {
+        return next.run(req).await;
+    };
+    if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token)) {
+        req.headers_mut()
+            .entry(header::AUTHORIZATION)
+            .or_insert(value);
+    }
+    let path = match rest.is_empty() {
+        true => uri.path().to_string(),
+        false => format!("{}?{}", uri.path(), rest.join("&")),
+    };
+    if let Ok(uri) = path.parse::<Uri>() {
+        *req.uri_mut() = uri;
+    }
+    next.run(req).await
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    //password was right, login waits for the second factor
+    Challenge,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub username: String,
     pub company: String,
+    //seconds since the epoch
     pub exp: usize,
+    pub typ: TokenType,
+    //unique per token, refresh tokens are stored under it
+    pub jti: String,
+    //hex id of the session the token was issued for, empty on challenge tokens
+    pub sid: String,
 }
 
+impl Claims {
+    pub fn session_id(&self) -> Result<ObjectId, ApiError> {
+        ObjectId::parse_str(&self.sid).map_err(|_| ApiError::Unathorized)
+    }
+}
+
+const ACCESS_TOKEN_SECS: usize = 2 * 60 * 60; //2h
+const CHALLENGE_TOKEN_SECS: usize = 5 * 60; //5min
+pub const REFRESH_TOKEN_SECS: usize = 2 * 24 * 60 * 60; //2days
+/// bcrypt cost of stored passwords, the tests use the cheapest one bcrypt takes
+#[cfg(not(test))]
+pub const PASSWORD_COST: u32 = bcrypt::DEFAULT_COST;
+#[cfg(test)]
+pub const PASSWORD_COST: u32 = 4;
+
 pub struct Keys {
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
@@ -93,39 +225,62 @@ impl AuthResponseBody {
     }
 }
 
-pub fn generate_acces_token(username: &str) -> Result<String, ApiError> {
-    let expiration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as usize
-        + 2 * 60 * 60; //2h
-
-    let claims = Claims {
-        username: username.to_owned(),
-        company: "flexnotes".to_owned(),
-        exp: expiration,
-    };
+pub fn generate_acces_token(username: &str, session_id: ObjectId) -> Result<String, ApiError> {
+    encode_token(
+        username,
+        &session_id.to_hex(),
+        TokenType::Access,
+        &random_token(),
+        ACCESS_TOKEN_SECS,
+    )
+}
 
-    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &KEYS.encoding)
-        .map_err(|err| {
-            error!("{}", err.to_string());
-            ApiError::InternalError
-        })?;
+/// `jti` is the id the token is stored under, see RefreshTokenRepo
+pub fn generate_refresh_token(
+    username: &str,
+    session_id: ObjectId,
+    jti: &str,
+) -> Result<String, ApiError> {
+    encode_token(
+        username,
+        &session_id.to_hex(),
+        TokenType::Refresh,
+        jti,
+        REFRESH_TOKEN_SECS,
+    )
+}
 
-    Ok(token)
+/// Only buys tokens at /auth/login/2fa together with a valid code
+pub fn generate_challenge_token(username: &str) -> Result<String, ApiError> {
+    encode_token(
+        username,
+        "",
+        TokenType::Challenge,
+        &random_token(),
+        CHALLENGE_TOKEN_SECS,
+    )
 }
 
-pub fn generate_refresh_token(username: &str) -> Result<String, ApiError> {
+fn encode_token(
+    username: &str,
+    session_id: &str,
+    typ: TokenType,
+    jti: &str,
+    lifetime_secs: usize,
+) -> Result<String, ApiError> {
     let expiration = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs() as usize
-        + 2 * 24 * 60 * 60; //2days
+        + lifetime_secs;
 
     let claims = Claims {
         username: username.to_owned(),
         company: "flexnotes".to_owned(),
         exp: expiration,
+        typ,
+        jti: jti.to_owned(),
+        sid: session_id.to_owned(),
     };
 
     let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &KEYS.encoding)
@@ -136,3 +291,33 @@ pub fn generate_refresh_token(username: &str) -> Result<String, ApiError> {
 
     Ok(token)
 }
+
+/// Checks signature, expiry and that the token is of the type `typ`
+pub fn decode_token(token: &str, typ: TokenType) -> Result<Claims, ApiError> {
+    let validation = Validation::new(Algorithm::HS256);
+    let claims = decode::<Claims>(token, &KEYS.decoding, &validation)
+        .map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => ApiError::TokenExpired,
+            _ => ApiError::Unathorized,
+        })?
+        .claims;
+    match claims.typ == typ {
+        true => Ok(claims),
+        false => Err(ApiError::Unathorized),
+    }
+}
+
+/// Unguessable url safe token, 256 bits of randomness
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::fill(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hex sha256, for secrets that are compared but never shown again
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -1,23 +1,96 @@
 use crate::{
-    logger::{DatabaseLog, MognoDBLogger},
-    models::{note::Note, todo::TodoList, user::User},
-    repository::{note_repo::MongoNoteRepo, todo_repo::MongoTodoRepo, user_repo::MongoUserRepo},
+    logger::{DatabaseLog, DatabaseLogger, MemoryLogger, MognoDBLogger, SledLogger},
+    migrations,
+    models::{
+        api_token::ApiToken, attachment::Attachment, live_doc::LiveDoc, note::Note,
+        notebook::Notebook, refresh_token::RefreshToken, revision::NoteRevision, session::Session,
+        share_link::ShareLink, todo::TodoList, tombstone::Tombstone, two_factor::TwoFactor,
+        user::User,
+    },
+    repository::{
+        api_token_repo::{ApiTokenRepo, MemoryApiTokenRepo, MongoApiTokenRepo, SledApiTokenRepo},
+        attachment_repo::{
+            AttachmentRepo, MemoryAttachmentRepo, MongoAttachmentRepo, SledAttachmentRepo,
+        },
+        attachment_storage::{AttachmentStorage, GridFsStorage, LocalFsStorage, MemoryStorage},
+        live_doc_repo::{LiveDocRepo, MemoryLiveDocRepo, MongoLiveDocRepo, SledLiveDocRepo},
+        note_repo::{MemoryNoteRepo, MongoNoteRepo, NoteRepo, SledNoteRepo},
+        notebook_repo::{MemoryNotebookRepo, MongoNotebookRepo, NotebookRepo, SledNotebookRepo},
+        refresh_token_repo::{
+            MemoryRefreshTokenRepo, MongoRefreshTokenRepo, RefreshTokenRepo, SledRefreshTokenRepo,
+        },
+        revision_repo::{MemoryRevisionRepo, MongoRevisionRepo, RevisionRepo, SledRevisionRepo},
+        session_repo::{MemorySessionRepo, MongoSessionRepo, SessionRepo, SledSessionRepo},
+        share_link_repo::{
+            MemoryShareLinkRepo, MongoShareLinkRepo, ShareLinkRepo, SledShareLinkRepo,
+        },
+        sled_store,
+        todo_repo::{MemoryTodoRepo, MongoTodoRepo, SledTodoRepo, TodoRepo},
+        tombstone_repo::{
+            MemoryTombstoneRepo, MongoTombstoneRepo, SledTombstoneRepo, TombstoneRepo,
+        },
+        two_factor_repo::{
+            MemoryTwoFactorRepo, MongoTwoFactorRepo, SledTwoFactorRepo, TwoFactorRepo,
+        },
+        user_repo::{MemoryUserRepo, MongoUserRepo, SledUserRepo, UserRepo},
+    },
     MONGO_URL,
 };
-use mongodb::{options::ClientOptions, Client, Collection};
+use mongodb::{options::ClientOptions, Client};
 use std::{sync::Arc, time::Duration};
-use tracing::error;
+use tracing::{error, info};
 
+/*
+* DB_BACKEND picks the storage: "mongo" (default) needs DB_URL,
+* "sled" keeps everything in an embedded database under SLED_PATH,
+* "memory" forgets everything on restart
+*
+* ATTACHMENT_STORAGE picks where attachment files go: "gridfs" (default with
+* mongo) or "fs", a directory at ATTACHMENT_PATH (default with sled)
+*/
 #[derive(Clone)]
 pub struct Database {
-    users: Collection<User>,
-    notes: Collection<Note>,
-    todos: Collection<TodoList>,
-    logs: Collection<DatabaseLog>,
+    users: Arc<dyn UserRepo>,
+    refresh_tokens: Arc<dyn RefreshTokenRepo>,
+    sessions: Arc<dyn SessionRepo>,
+    api_tokens: Arc<dyn ApiTokenRepo>,
+    two_factors: Arc<dyn TwoFactorRepo>,
+    notes: Arc<dyn NoteRepo>,
+    notebooks: Arc<dyn NotebookRepo>,
+    todos: Arc<dyn TodoRepo>,
+    revisions: Arc<dyn RevisionRepo>,
+    share_links: Arc<dyn ShareLinkRepo>,
+    attachments: Arc<dyn AttachmentRepo>,
+    tombstones: Arc<dyn TombstoneRepo>,
+    live_docs: Arc<dyn LiveDocRepo>,
+    attachment_storage: Arc<dyn AttachmentStorage>,
+    logs: Arc<dyn DatabaseLogger>,
+}
+
+fn local_attachment_storage() -> Arc<dyn AttachmentStorage> {
+    let path = std::env::var("ATTACHMENT_PATH").unwrap_or("./attachments".to_string());
+    info!("Storing attachments under {}", path);
+    Arc::new(LocalFsStorage::new(path))
 }
 
 impl Database {
     pub async fn new() -> Self {
+        let backend = std::env::var("DB_BACKEND").unwrap_or("mongo".to_string());
+        match backend.as_str() {
+            "mongo" => Self::mongo().await,
+            "sled" => {
+                let path = std::env::var("SLED_PATH").unwrap_or("./data".to_string());
+                Self::sled(&path)
+            }
+            "memory" => Self::memory(),
+            other => panic!(
+                "Unknown DB_BACKEND {}, expected mongo, sled or memory",
+                other
+            ),
+        }
+    }
+
+    pub async fn mongo() -> Self {
         let mut options = ClientOptions::parse(MONGO_URL.clone())
             .await
             .map_err(|err| {
@@ -40,32 +113,221 @@ impl Database {
 
         let users_collection = mongo_client.collection::<User>("users");
 
+        let refresh_tokens_collection = mongo_client.collection::<RefreshToken>("refresh_tokens");
+
+        let sessions_collection = mongo_client.collection::<Session>("sessions");
+
+        let api_tokens_collection = mongo_client.collection::<ApiToken>("api_tokens");
+
+        let two_factor_collection = mongo_client.collection::<TwoFactor>("two_factor");
+
         let notes_collection = mongo_client.collection::<Note>("notes");
 
+        let notebooks_collection = mongo_client.collection::<Notebook>("notebooks");
+
         let todos_collection = mongo_client.collection::<TodoList>("todos");
 
+        let revisions_collection = mongo_client.collection::<NoteRevision>("revisions");
+
+        let share_links_collection = mongo_client.collection::<ShareLink>("share_links");
+
+        let attachments_collection = mongo_client.collection::<Attachment>("attachments");
+
+        let tombstones_collection = mongo_client.collection::<Tombstone>("tombstones");
+
+        let live_docs_collection = mongo_client.collection::<LiveDoc>("live_docs");
+
         let logs_collection = mongo_client.collection::<DatabaseLog>("logs");
 
+        let attachment_storage: Arc<dyn AttachmentStorage> =
+            match std::env::var("ATTACHMENT_STORAGE").as_deref() {
+                Ok("fs") => local_attachment_storage(),
+                Ok("gridfs") | Err(_) => {
+                    Arc::new(GridFsStorage::new(mongo_client.gridfs_bucket(None)))
+                }
+                Ok(other) => panic!(
+                    "Unknown ATTACHMENT_STORAGE {}, expected gridfs or fs",
+                    other
+                ),
+            };
+
+        migrations::timestamps_to_dates_mongo(&mongo_client).await;
+        migrations::backfill_timestamps_mongo(&mongo_client).await;
+        migrations::backfill_links_mongo(&mongo_client).await;
+        migrations::revision_index_mongo(&mongo_client).await;
+
+        Self {
+            users: Arc::new(MongoUserRepo::new(users_collection)),
+            refresh_tokens: Arc::new(MongoRefreshTokenRepo::new(refresh_tokens_collection)),
+            sessions: Arc::new(MongoSessionRepo::new(sessions_collection)),
+            api_tokens: Arc::new(MongoApiTokenRepo::new(api_tokens_collection)),
+            two_factors: Arc::new(MongoTwoFactorRepo::new(two_factor_collection)),
+            notes: Arc::new(MongoNoteRepo::new(notes_collection)),
+            notebooks: Arc::new(MongoNotebookRepo::new(notebooks_collection)),
+            todos: Arc::new(MongoTodoRepo::new(todos_collection)),
+            revisions: Arc::new(MongoRevisionRepo::new(revisions_collection)),
+            share_links: Arc::new(MongoShareLinkRepo::new(share_links_collection)),
+            attachments: Arc::new(MongoAttachmentRepo::new(attachments_collection)),
+            tombstones: Arc::new(MongoTombstoneRepo::new(tombstones_collection)),
+            live_docs: Arc::new(MongoLiveDocRepo::new(live_docs_collection)),
+            attachment_storage,
+            logs: Arc::new(MognoDBLogger::new(logs_collection)),
+        }
+    }
+
+    pub fn sled(path: &str) -> Self {
+        let db = sled_store::open(path);
+        info!("Using sled database at {}", path);
+
+        let notes = sled_store::open_tree(&db, "notes");
+        let todos = sled_store::open_tree(&db, "todos");
+        migrations::backfill_timestamps_sled(&notes, &todos);
+        migrations::backfill_links_sled(&notes);
+
+        Self {
+            users: Arc::new(SledUserRepo::new(
+                sled_store::open_tree(&db, "users"),
+                sled_store::open_tree(&db, "usernames"),
+            )),
+            refresh_tokens: Arc::new(SledRefreshTokenRepo::new(sled_store::open_tree(
+                &db,
+                "refresh_tokens",
+            ))),
+            sessions: Arc::new(SledSessionRepo::new(sled_store::open_tree(&db, "sessions"))),
+            api_tokens: Arc::new(SledApiTokenRepo::new(
+                sled_store::open_tree(&db, "api_tokens"),
+                sled_store::open_tree(&db, "api_token_hashes"),
+            )),
+            two_factors: Arc::new(SledTwoFactorRepo::new(sled_store::open_tree(
+                &db,
+                "two_factor",
+            ))),
+            notes: Arc::new(SledNoteRepo::new(
+                notes,
+                sled_store::open_tree(&db, "note_shares"),
+            )),
+            notebooks: Arc::new(SledNotebookRepo::new(sled_store::open_tree(
+                &db,
+                "notebooks",
+            ))),
+            todos: Arc::new(SledTodoRepo::new(todos)),
+            revisions: Arc::new(SledRevisionRepo::new(sled_store::open_tree(
+                &db,
+                "revisions",
+            ))),
+            share_links: Arc::new(SledShareLinkRepo::new(
+                sled_store::open_tree(&db, "share_links"),
+                sled_store::open_tree(&db, "share_link_tokens"),
+            )),
+            attachments: Arc::new(SledAttachmentRepo::new(sled_store::open_tree(
+                &db,
+                "attachments",
+            ))),
+            tombstones: Arc::new(SledTombstoneRepo::new(sled_store::open_tree(
+                &db,
+                "tombstones",
+            ))),
+            live_docs: Arc::new(SledLiveDocRepo::new(sled_store::open_tree(
+                &db,
+                "live_docs",
+            ))),
+            //gridfs needs mongo
+            attachment_storage: local_attachment_storage(),
+            logs: Arc::new(SledLogger::new(sled_store::open_tree(&db, "logs"))),
+        }
+    }
+
+    pub fn memory() -> Self {
         Self {
-            users: users_collection,
-            notes: notes_collection,
-            todos: todos_collection,
-            logs: logs_collection,
+            users: Arc::new(MemoryUserRepo::new()),
+            refresh_tokens: Arc::new(MemoryRefreshTokenRepo::new()),
+            sessions: Arc::new(MemorySessionRepo::new()),
+            api_tokens: Arc::new(MemoryApiTokenRepo::new()),
+            two_factors: Arc::new(MemoryTwoFactorRepo::new()),
+            notes: Arc::new(MemoryNoteRepo::new()),
+            notebooks: Arc::new(MemoryNotebookRepo::new()),
+            todos: Arc::new(MemoryTodoRepo::new()),
+            revisions: Arc::new(MemoryRevisionRepo::new()),
+            share_links: Arc::new(MemoryShareLinkRepo::new()),
+            attachments: Arc::new(MemoryAttachmentRepo::new()),
+            tombstones: Arc::new(MemoryTombstoneRepo::new()),
+            live_docs: Arc::new(MemoryLiveDocRepo::new()),
+            attachment_storage: Arc::new(MemoryStorage::new()),
+            logs: Arc::new(MemoryLogger::new()),
         }
     }
 
-    pub fn user_repo(&self) -> MongoUserRepo {
-        MongoUserRepo::new(self.users.clone())
+    /// Swaps the attachment storage, lets the tests make it fail
+    #[cfg(test)]
+    pub fn with_attachment_storage(mut self, storage: Arc<dyn AttachmentStorage>) -> Self {
+        self.attachment_storage = storage;
+        self
     }
 
-    pub fn note_repo(&self) -> MongoNoteRepo {
-        MongoNoteRepo::new(self.notes.clone())
+    /// Swaps the request logger, lets the tests read what was logged
+    #[cfg(test)]
+    pub fn with_logs(mut self, logs: Arc<dyn DatabaseLogger>) -> Self {
+        self.logs = logs;
+        self
     }
-    pub fn todos_repo(&self) -> MongoTodoRepo {
-        MongoTodoRepo::new(self.todos.clone())
+
+    pub fn user_repo(&self) -> &dyn UserRepo {
+        self.users.as_ref()
+    }
+
+    pub fn refresh_token_repo(&self) -> &dyn RefreshTokenRepo {
+        self.refresh_tokens.as_ref()
+    }
+
+    pub fn session_repo(&self) -> &dyn SessionRepo {
+        self.sessions.as_ref()
+    }
+
+    pub fn api_token_repo(&self) -> &dyn ApiTokenRepo {
+        self.api_tokens.as_ref()
+    }
+
+    pub fn two_factor_repo(&self) -> &dyn TwoFactorRepo {
+        self.two_factors.as_ref()
+    }
+
+    pub fn note_repo(&self) -> &dyn NoteRepo {
+        self.notes.as_ref()
+    }
+
+    pub fn notebook_repo(&self) -> &dyn NotebookRepo {
+        self.notebooks.as_ref()
+    }
+
+    pub fn todos_repo(&self) -> &dyn TodoRepo {
+        self.todos.as_ref()
+    }
+
+    pub fn revision_repo(&self) -> &dyn RevisionRepo {
+        self.revisions.as_ref()
+    }
+
+    pub fn share_link_repo(&self) -> &dyn ShareLinkRepo {
+        self.share_links.as_ref()
+    }
+
+    pub fn attachment_repo(&self) -> &dyn AttachmentRepo {
+        self.attachments.as_ref()
+    }
+
+    pub fn tombstone_repo(&self) -> &dyn TombstoneRepo {
+        self.tombstones.as_ref()
+    }
+
+    pub fn live_doc_repo(&self) -> &dyn LiveDocRepo {
+        self.live_docs.as_ref()
+    }
+
+    pub fn attachment_storage(&self) -> &dyn AttachmentStorage {
+        self.attachment_storage.as_ref()
     }
 
-    pub fn logs_repo(&self) -> MognoDBLogger {
-        MognoDBLogger::new(self.logs.clone())
+    pub fn logs_repo(&self) -> Arc<dyn DatabaseLogger> {
+        self.logs.clone()
     }
 }
@@ -1,11 +1,14 @@
+use crate::routes::etag;
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
 use serde::Serialize;
+use serde_json::json;
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone, Copy, Serialize)]
+#[derive(Error, Debug, Clone, Serialize)]
 pub enum ApiError {
     #[error("Resource not found")]
     NotFound,
@@ -15,6 +18,8 @@ pub enum ApiError {
     MissingPayload,
     #[error("User unathorized")]
     Unathorized,
+    #[error("Not allowed")]
+    Forbidden,
     #[error("User already exists")]
     UserExist,
     #[error("Your token expired")]
@@ -23,6 +28,27 @@ pub enum ApiError {
     MissingCredential,
     #[error("Nothing changed")]
     NothingChanged,
+    #[error("Invalid search query: {0}")]
+    InvalidQuery(String),
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    #[error("Invalid sync token")]
+    InvalidSyncToken,
+    #[error("Invalid two-factor code")]
+    InvalidCode,
+    #[error("Too many attempts, try again later")]
+    TooManyAttempts,
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+    #[error("Storage quota exceeded")]
+    QuotaExceeded,
+    #[error("A notebook can't end up inside itself")]
+    InvalidParent,
+    #[error("Requested range not satisfiable")]
+    RangeNotSatisfiable,
+    //carries the current version so the client can refetch and retry
+    #[error("Version mismatch")]
+    PreconditionFailed(u64),
 }
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
@@ -31,13 +57,32 @@ impl IntoResponse for ApiError {
             ApiError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::MissingPayload => StatusCode::BAD_REQUEST,
             ApiError::Unathorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
             ApiError::UserExist => StatusCode::FOUND,
             ApiError::TokenExpired => StatusCode::UNAUTHORIZED,
             ApiError::MissingCredential => StatusCode::UNAUTHORIZED,
             ApiError::NothingChanged => StatusCode::NOT_MODIFIED,
+            ApiError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidCursor => StatusCode::BAD_REQUEST,
+            ApiError::InvalidSyncToken => StatusCode::BAD_REQUEST,
+            ApiError::InvalidCode => StatusCode::UNAUTHORIZED,
+            ApiError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::InvalidUpload(_) => StatusCode::BAD_REQUEST,
+            ApiError::QuotaExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::InvalidParent => StatusCode::CONFLICT,
+            ApiError::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
         };
 
-        let mut res = (status_code, self.to_string()).into_response();
+        let mut res = match self {
+            ApiError::PreconditionFailed(version) => (
+                status_code,
+                [(header::ETAG, etag(version))],
+                Json(json!({ "error": self.to_string(), "version": version })),
+            )
+                .into_response(),
+            _ => (status_code, self.to_string()).into_response(),
+        };
 
         res.extensions_mut().insert(self);
         res
@@ -0,0 +1,141 @@
+use crate::models::{note::Note, todo::TodoList};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/*
+* Change feed. Services publish what they changed on the bus, every `/events`
+* stream picks the changes of its user out of it. Nothing is stored, a client
+* that was offline fetches the lists again. Revoked sessions are announced
+* the same way so streams opened with them can end.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entity {
+    Note,
+    TodoList,
+    Todo,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    #[serde(skip)]
+    pub entity: Entity,
+    #[serde(skip)]
+    pub kind: ChangeKind,
+    //everyone who can see the document
+    #[serde(skip)]
+    pub users: Vec<ObjectId>,
+    pub id: ObjectId,
+    //list of a todo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo_list_id: Option<ObjectId>,
+    //of the note or todo list after the change, not known for deletes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+impl Change {
+    /// SSE event name, `note.created`, `todo_list.deleted`, ...
+    pub fn name(&self) -> String {
+        let entity = match self.entity {
+            Entity::Note => "note",
+            Entity::TodoList => "todo_list",
+            Entity::Todo => "todo",
+        };
+        let kind = match self.kind {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        };
+        format!("{}.{}", entity, kind)
+    }
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<Change>,
+    //ids of revoked sessions
+    revoked: broadcast::Sender<ObjectId>,
+}
+
+impl EventBus {
+    /// `capacity` changes are kept for slow streams before they lag behind
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity).0,
+            revoked: broadcast::channel(capacity).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscribe_revoked(&self) -> broadcast::Receiver<ObjectId> {
+        self.revoked.subscribe()
+    }
+
+    pub fn session_revoked(&self, session_id: ObjectId) {
+        let _ = self.revoked.send(session_id);
+    }
+
+    fn publish(&self, change: Change) {
+        //no receivers is fine, nobody is listening
+        let _ = self.sender.send(change);
+    }
+
+    pub fn note(&self, kind: ChangeKind, note: &Note) {
+        let mut users = vec![note.user_id];
+        users.extend(note.shared_with.iter().map(|share| share.user_id));
+        self.publish(Change {
+            entity: Entity::Note,
+            kind,
+            users,
+            id: note.id,
+            todo_list_id: None,
+            version: (kind != ChangeKind::Deleted).then_some(note.version),
+        });
+    }
+
+    /// For notes that are only known by id, seen by the owner alone
+    pub fn note_deleted(&self, user_id: ObjectId, note_id: ObjectId) {
+        self.publish(Change {
+            entity: Entity::Note,
+            kind: ChangeKind::Deleted,
+            users: vec![user_id],
+            id: note_id,
+            todo_list_id: None,
+            version: None,
+        });
+    }
+
+    pub fn todo_list(&self, kind: ChangeKind, todo_list: &TodoList) {
+        self.publish(Change {
+            entity: Entity::TodoList,
+            kind,
+            users: vec![todo_list.user_id],
+            id: todo_list.id,
+            todo_list_id: None,
+            version: (kind != ChangeKind::Deleted).then_some(todo_list.version),
+        });
+    }
+
+    /// `todo_list` as it is after the change
+    pub fn todo(&self, kind: ChangeKind, todo_list: &TodoList, todo_id: ObjectId) {
+        self.publish(Change {
+            entity: Entity::Todo,
+            kind,
+            users: vec![todo_list.user_id],
+            id: todo_id,
+            todo_list_id: Some(todo_list.id),
+            version: Some(todo_list.version),
+        });
+    }
+}
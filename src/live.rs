@@ -0,0 +1,558 @@
+use crate::{
+    error::ApiError,
+    models::{
+        live_doc::LiveDoc,
+        note::{Note, NoteRole},
+        user::User,
+    },
+    services::note_service,
+    AppState,
+};
+use automerge::{
+    sync::{self, SyncDoc},
+    transaction::Transactable,
+    AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc, ROOT,
+};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use chrono::Utc;
+use mongodb::bson::{oid::ObjectId, spec::BinarySubtype, Binary};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::error;
+
+/*
+* Live editing. Every note someone is editing gets a room holding an automerge
+* document with the content as a text object under `content`. Peers exchange
+* automerge sync messages with the room over a websocket, the room writes the
+* merged text back into the note every few seconds and merges in whatever was
+* saved over the rest api meanwhile. The document itself is saved next to the
+* note, a room opened later picks it up again so a peer reconnecting with its
+* old copy still shares the history of the room. Roles are looked up again on
+* every save, peers who lost access are sent away.
+*/
+
+const CONTENT: &str = "content";
+//presence is passed on as is, it has no business being large
+const MAX_PRESENCE: usize = 1024;
+/// Largest sync message a peer may send
+pub const MAX_MESSAGE: usize = 4 * 1024 * 1024;
+//websocket close code for a peer breaking the rules
+const POLICY_VIOLATION: u16 = 1008;
+
+#[derive(Clone, Debug)]
+enum RoomEvent {
+    //the document changed, every peer sends what the others are missing
+    Changed,
+    //json for everyone except `from`
+    Presence { from: ObjectId, message: String },
+    //the note is gone
+    Closed,
+    //the note is no longer shared with the user
+    Revoked { user_id: ObjectId },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Peer {
+    pub peer: ObjectId,
+    pub user_id: ObjectId,
+    pub username: String,
+    pub role: NoteRole,
+    //whatever the client sent last, usually a selection
+    pub cursor: Option<Value>,
+}
+
+/// Text frames from the room
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome { peer: ObjectId, peers: Vec<Peer> },
+    Join { peer: Peer },
+    Cursor { peer: ObjectId, cursor: Value },
+    Leave { peer: ObjectId },
+}
+
+/// Text frames from a peer
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Cursor { cursor: Value },
+}
+
+fn automerge_error(err: automerge::AutomergeError) -> ApiError {
+    error!("{}", err);
+    ApiError::InternalError
+}
+
+pub struct Room {
+    note_id: ObjectId,
+    owner_id: ObjectId,
+    doc: Mutex<AutoCommit>,
+    content: ObjId,
+    peers: Mutex<BTreeMap<ObjectId, Peer>>,
+    //last peer that changed the document, saves are made in their name
+    editor: Mutex<Option<Arc<User>>>,
+    events: broadcast::Sender<RoomEvent>,
+}
+
+//what the note looked like after the last save
+struct Saved {
+    version: u64,
+    heads: Vec<ChangeHash>,
+    text: String,
+    first: bool,
+    //heads of the document saved in the live doc repo
+    persisted: Vec<ChangeHash>,
+}
+
+//the saved document of the note when there is a usable one, caught up with
+//the note content
+fn load_doc(note: &Note, live_doc: Option<LiveDoc>) -> Result<(AutoCommit, ObjId), ApiError> {
+    let loaded = live_doc.and_then(|live_doc| {
+        let doc = AutoCommit::load(&live_doc.doc.bytes).ok()?;
+        match doc.get(ROOT, CONTENT).ok()? {
+            Some((_value, content)) if doc.text(&content).is_ok() => Some((doc, content)),
+            _ => None,
+        }
+    });
+    let (mut doc, content) = match loaded {
+        Some(loaded) => loaded,
+        None => {
+            let mut doc = AutoCommit::new();
+            let content = doc
+                .put_object(ROOT, CONTENT, ObjType::Text)
+                .map_err(automerge_error)?;
+            (doc, content)
+        }
+    };
+    if doc.text(&content).map_err(automerge_error)? != note.content {
+        doc.update_text(&content, &note.content)
+            .map_err(automerge_error)?;
+    }
+    Ok((doc, content))
+}
+
+impl Room {
+    fn new(note: &Note, live_doc: Option<LiveDoc>) -> Result<Self, ApiError> {
+        let (doc, content) = load_doc(note, live_doc)?;
+        Ok(Self {
+            note_id: note.id,
+            owner_id: note.user_id,
+            doc: Mutex::new(doc),
+            content,
+            peers: Mutex::new(BTreeMap::new()),
+            editor: Mutex::new(None),
+            events: broadcast::channel(64).0,
+        })
+    }
+
+    fn text(&self) -> String {
+        self.doc
+            .lock()
+            .unwrap()
+            .text(&self.content)
+            .unwrap_or_default()
+    }
+
+    fn presence(&self, from: ObjectId, message: &ServerMessage) {
+        if let Ok(message) = serde_json::to_string(message) {
+            let _ = self.events.send(RoomEvent::Presence { from, message });
+        }
+    }
+
+    fn leave(&self, peer: ObjectId) {
+        self.peers.lock().unwrap().remove(&peer);
+        self.presence(peer, &ServerMessage::Leave { peer });
+    }
+
+    fn can_edit(&self, peer: ObjectId) -> bool {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(&peer)
+            .is_some_and(|peer| matches!(peer.role, NoteRole::Owner | NoteRole::Editor))
+    }
+
+    //`None` sends the user's peers away
+    fn set_role(&self, user_id: ObjectId, role: Option<NoteRole>) {
+        let mut peers = self.peers.lock().unwrap();
+        match role {
+            Some(role) => peers
+                .values_mut()
+                .filter(|peer| peer.user_id == user_id)
+                .for_each(|peer| peer.role = role),
+            None => {
+                peers.retain(|_id, peer| peer.user_id != user_id);
+                let _ = self.events.send(RoomEvent::Revoked { user_id });
+            }
+        }
+    }
+
+    //roles as the note has them now
+    fn update_roles(&self, note: &Note) {
+        let users: Vec<ObjectId> = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|peer| peer.user_id)
+            .collect();
+        for user_id in users {
+            self.set_role(user_id, note.role_of(user_id));
+        }
+    }
+
+    //applies a sync message of a peer, viewers may only ask for changes
+    fn receive(
+        &self,
+        state: &mut sync::State,
+        message: &[u8],
+        user: &Arc<User>,
+        peer: ObjectId,
+    ) -> Result<(), ApiError> {
+        let message = sync::Message::decode(message).map_err(|_err| ApiError::MissingPayload)?;
+        if !message.changes.is_empty() && !self.can_edit(peer) {
+            return Err(ApiError::Forbidden);
+        }
+        let changed = {
+            let mut doc = self.doc.lock().unwrap();
+            let before = doc.get_heads();
+            doc.sync()
+                .receive_sync_message(state, message)
+                .map_err(|_err| ApiError::MissingPayload)?;
+            doc.get_heads() != before
+        };
+        if changed {
+            *self.editor.lock().unwrap() = Some(user.clone());
+            let _ = self.events.send(RoomEvent::Changed);
+        }
+        Ok(())
+    }
+
+    fn generate(&self, state: &mut sync::State) -> Option<Vec<u8>> {
+        self.doc
+            .lock()
+            .unwrap()
+            .sync()
+            .generate_sync_message(state)
+            .map(|message| message.encode())
+    }
+
+    //writes the merged text into the note, edits made over the rest api since
+    //the last save are merged in first like the ones of any other peer
+    async fn save(&self, app_state: &AppState, saved: &mut Saved) -> Result<(), ApiError> {
+        let note_repo = app_state.database.note_repo();
+        let note = note_repo
+            .get_note_by_id(self.note_id, self.owner_id)
+            .await?;
+        self.update_roles(&note);
+        if note.version != saved.version {
+            let heads = {
+                let mut doc = self.doc.lock().unwrap();
+                let mut fork = doc.fork_at(&saved.heads).map_err(automerge_error)?;
+                fork.update_text(&self.content, &note.content)
+                    .map_err(automerge_error)?;
+                let heads = fork.get_heads();
+                doc.merge(&mut fork).map_err(automerge_error)?;
+                heads
+            };
+            saved.version = note.version;
+            saved.heads = heads;
+            saved.text = note.content.clone();
+            let _ = self.events.send(RoomEvent::Changed);
+        }
+
+        let (text, heads) = {
+            let mut doc = self.doc.lock().unwrap();
+            (
+                doc.text(&self.content).map_err(automerge_error)?,
+                doc.get_heads(),
+            )
+        };
+        if text == saved.text {
+            return Ok(());
+        }
+        let Some(editor) = self.editor.lock().unwrap().clone() else {
+            return Ok(());
+        };
+        //edits since the last save can't be told apart, they all go
+        if !note.can_edit(editor.id) {
+            self.doc
+                .lock()
+                .unwrap()
+                .update_text(&self.content, &saved.text)
+                .map_err(automerge_error)?;
+            *self.editor.lock().unwrap() = None;
+            let _ = self.events.send(RoomEvent::Changed);
+            return Ok(());
+        }
+        let updated = note_service::save_live_content(
+            note_repo,
+            app_state.database.revision_repo(),
+            &app_state.search_index,
+            &app_state.events,
+            &editor,
+            note,
+            text.clone(),
+            saved.first,
+        )
+        .await?;
+        saved.version = updated.version;
+        saved.heads = heads;
+        saved.text = text;
+        saved.first = false;
+        Ok(())
+    }
+
+    //saves the document when it changed since the last time
+    async fn persist(&self, app_state: &AppState, saved: &mut Saved) -> Result<(), ApiError> {
+        let (heads, bytes) = {
+            let mut doc = self.doc.lock().unwrap();
+            let heads = doc.get_heads();
+            if heads == saved.persisted {
+                return Ok(());
+            }
+            (heads, doc.save())
+        };
+        let live_doc = LiveDoc {
+            note_id: self.note_id,
+            user_id: self.owner_id,
+            doc: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes,
+            },
+            updated_at: Utc::now(),
+        };
+        app_state
+            .database
+            .live_doc_repo()
+            .save_live_doc(&live_doc)
+            .await?;
+        saved.persisted = heads;
+        Ok(())
+    }
+}
+
+/*
+* Rooms by note id, a room lives while it has peers or unsaved changes
+*/
+pub struct LiveRooms {
+    rooms: Mutex<HashMap<ObjectId, Arc<Room>>>,
+    save_interval: Duration,
+}
+
+impl LiveRooms {
+    pub fn new(save_interval: Duration) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            save_interval,
+        }
+    }
+
+    /// LIVE_SAVE_SECONDS, 5 by default
+    pub fn from_env() -> Self {
+        let seconds = std::env::var("LIVE_SAVE_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or(5);
+        Self::new(Duration::from_secs(seconds))
+    }
+
+    /// Room of the note with `peer` in it, opened when nobody is editing yet
+    pub async fn join(
+        &self,
+        app_state: &AppState,
+        note: &Note,
+        peer: Peer,
+    ) -> Result<(Arc<Room>, Vec<Peer>), ApiError> {
+        let live_doc = match app_state
+            .database
+            .live_doc_repo()
+            .get_live_doc(note.id, note.user_id)
+            .await
+        {
+            Ok(live_doc) => Some(live_doc),
+            Err(ApiError::NotFound) => None,
+            Err(err) => return Err(err),
+        };
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = match rooms.get(&note.id) {
+            Some(room) => room.clone(),
+            None => {
+                let room = Arc::new(Room::new(note, live_doc)?);
+                rooms.insert(note.id, room.clone());
+                tokio::spawn(keep_saved(app_state.clone(), room.clone(), note.version));
+                room
+            }
+        };
+        let others = {
+            let mut peers = room.peers.lock().unwrap();
+            let others = peers.values().cloned().collect();
+            peers.insert(peer.peer, peer.clone());
+            others
+        };
+        room.presence(peer.peer, &ServerMessage::Join { peer });
+        Ok((room, others))
+    }
+
+    /// Lets the tests wait for a room to close
+    #[cfg(test)]
+    pub fn is_open(&self, note_id: ObjectId) -> bool {
+        self.rooms.lock().unwrap().contains_key(&note_id)
+    }
+
+    /// Passes a changed share on to the room of the note, `None` when the
+    /// user lost access
+    pub fn set_role(&self, note_id: ObjectId, user_id: ObjectId, role: Option<NoteRole>) {
+        let room = self.rooms.lock().unwrap().get(&note_id).cloned();
+        if let Some(room) = room {
+            room.set_role(user_id, role);
+        }
+    }
+}
+
+async fn keep_saved(app_state: AppState, room: Arc<Room>, version: u64) {
+    let heads = room.doc.lock().unwrap().get_heads();
+    let mut saved = Saved {
+        version,
+        heads,
+        text: room.text(),
+        first: true,
+        persisted: vec![],
+    };
+    let mut ticks = tokio::time::interval(app_state.live_rooms.save_interval);
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        match room.save(&app_state, &mut saved).await {
+            Ok(()) => {}
+            //trashed or the owner is gone, nothing left to edit
+            Err(ApiError::NotFound) => {
+                app_state
+                    .live_rooms
+                    .rooms
+                    .lock()
+                    .unwrap()
+                    .remove(&room.note_id);
+                let _ = room.events.send(RoomEvent::Closed);
+                return;
+            }
+            //saved over the rest api in between, merged on the next tick
+            Err(ApiError::PreconditionFailed(_)) => {}
+            Err(err) => error!("saving live note {}: {}", room.note_id, err),
+        }
+        if let Err(err) = room.persist(&app_state, &mut saved).await {
+            error!("saving live document {}: {}", room.note_id, err);
+        }
+        //peers join under the same lock, nobody can slip into a closing room
+        let mut rooms = app_state.live_rooms.rooms.lock().unwrap();
+        if room.peers.lock().unwrap().is_empty() && room.text() == saved.text {
+            rooms.remove(&room.note_id);
+            return;
+        }
+    }
+}
+
+/// Drives one peer: sync messages in binary frames, presence as json text
+/// frames. Ends when `revoked` resolves.
+pub async fn connect(
+    mut socket: WebSocket,
+    room: Arc<Room>,
+    user: Arc<User>,
+    peer: Peer,
+    others: Vec<Peer>,
+    revoked: impl Future<Output = ()>,
+) {
+    tokio::pin!(revoked);
+    let mut events = room.events.subscribe();
+    let mut state = sync::State::new();
+    let peer_id = peer.peer;
+
+    let welcome = ServerMessage::Welcome {
+        peer: peer_id,
+        peers: others,
+    };
+    let mut ok =
+        send_json(&mut socket, &welcome).await && send_sync(&mut socket, &room, &mut state).await;
+    while ok {
+        tokio::select! {
+            message = socket.recv() => ok = match message {
+                Some(Ok(Message::Binary(bytes))) => {
+                    match room.receive(&mut state, &bytes, &user, peer_id) {
+                        Ok(()) => send_sync(&mut socket, &room, &mut state).await,
+                        Err(err) => {
+                            close(&mut socket, &err.to_string()).await;
+                            false
+                        }
+                    }
+                }
+                Some(Ok(Message::Text(text))) => {
+                    if let (true, Ok(ClientMessage::Cursor { cursor })) =
+                        (text.len() <= MAX_PRESENCE, serde_json::from_str(&text))
+                    {
+                        if let Some(peer) = room.peers.lock().unwrap().get_mut(&peer_id) {
+                            peer.cursor = Some(cursor.clone());
+                        }
+                        room.presence(peer_id, &ServerMessage::Cursor { peer: peer_id, cursor });
+                    }
+                    true
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+                Some(Ok(_)) => true,
+            },
+            () = &mut revoked => {
+                close(&mut socket, "Session was revoked").await;
+                ok = false;
+            }
+            event = events.recv() => ok = match event {
+                Ok(RoomEvent::Changed) | Err(RecvError::Lagged(_)) => {
+                    send_sync(&mut socket, &room, &mut state).await
+                }
+                Ok(RoomEvent::Presence { from, message }) if from != peer_id => {
+                    socket.send(Message::Text(message.into())).await.is_ok()
+                }
+                Ok(RoomEvent::Presence { .. }) => true,
+                Ok(RoomEvent::Revoked { user_id }) if user_id == user.id => {
+                    close(&mut socket, "Access was revoked").await;
+                    false
+                }
+                Ok(RoomEvent::Revoked { .. }) => true,
+                Ok(RoomEvent::Closed) | Err(RecvError::Closed) => {
+                    close(&mut socket, "Note was deleted").await;
+                    false
+                }
+            },
+        }
+    }
+    room.leave(peer_id);
+}
+
+async fn send_json(socket: &mut WebSocket, message: &ServerMessage) -> bool {
+    match serde_json::to_string(message) {
+        Ok(text) => socket.send(Message::Text(text.into())).await.is_ok(),
+        Err(_err) => false,
+    }
+}
+
+//sends whatever the peer is missing, if anything
+async fn send_sync(socket: &mut WebSocket, room: &Room, state: &mut sync::State) -> bool {
+    match room.generate(state) {
+        Some(message) => socket.send(Message::Binary(message.into())).await.is_ok(),
+        None => true,
+    }
+}
+
+async fn close(socket: &mut WebSocket, reason: &str) {
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code: POLICY_VIOLATION,
+            reason: reason.into(),
+        })))
+        .await;
+}
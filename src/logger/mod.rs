@@ -4,12 +4,17 @@ use axum::{
     http::StatusCode,
     middleware::Next,
     response::Response,
-    Extension,
 };
 use chrono::{DateTime, Utc};
-use mongodb::Collection;
-use serde::Serialize;
-use std::{sync::Arc, time::Instant};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 use thiserror::Error;
 use tracing::{error, info};
 use tracing_appender::{
@@ -18,7 +23,7 @@ use tracing_appender::{
 };
 use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
 
-use crate::{error::ApiError, AppState};
+use crate::{error::ApiError, models::timestamp, AppState};
 
 #[derive(Error, Debug)]
 pub enum LoggerError {
@@ -26,8 +31,14 @@ pub enum LoggerError {
     FileLogger(#[from] std::io::Error),
     #[error("Database logger error: {0}")]
     MongoDbError(#[source] mongodb::error::Error),
+    #[error("Database logger error: {0}")]
+    SledError(#[source] sled::Error),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Serialization error: {0}")]
+    BsonError(#[source] bson::ser::Error),
+    #[error("Deserialization error: {0}")]
+    BsonDeError(#[source] bson::de::Error),
 }
 
 #[async_trait]
@@ -38,16 +49,32 @@ pub trait DatabaseLogger: Send + Sync {
         message: String,
         duration: u64,
         uri: String,
+        user_id: Option<ObjectId>,
     ) -> Result<(), LoggerError>;
+    /// Forgets every request the user made, logs from before requests were
+    /// attributed stay
+    async fn delete_logs_of_user(&self, user_id: ObjectId) -> Result<(), LoggerError>;
 }
 
-#[derive(Serialize, Debug)]
+/// Put on the response by auth_middleware, the log names whose request it was
+#[derive(Clone, Copy, Debug)]
+pub struct RequestUser(pub ObjectId);
+
+/// Put on the response by a handler that deleted its user, the request stays unattributed
+#[derive(Clone, Copy, Debug)]
+pub struct UserDeleted;
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct DatabaseLog {
     message: String,
     status_code: u16,
+    #[serde(with = "timestamp")]
     time: DateTime<Utc>,
     duration: u64,
     uri: String,
+    //None for requests without a login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<ObjectId>,
 }
 pub struct MognoDBLogger {
     collection: Collection<DatabaseLog>,
@@ -61,6 +88,7 @@ impl DatabaseLogger for MognoDBLogger {
         message: String,
         duration: u64,
         uri: String,
+        user_id: Option<ObjectId>,
     ) -> Result<(), LoggerError> {
         let log = DatabaseLog {
             time: Utc::now(),
@@ -68,12 +96,21 @@ impl DatabaseLogger for MognoDBLogger {
             status_code: status_code.as_u16(),
             duration,
             uri,
+            user_id,
         };
         match self.collection.insert_one(log).await {
             Ok(_res) => return Ok(()),
             Err(err) => return Err(LoggerError::MongoDbError(err)),
         };
     }
+
+    async fn delete_logs_of_user(&self, user_id: ObjectId) -> Result<(), LoggerError> {
+        self.collection
+            .delete_many(doc! {"user_id": user_id})
+            .await
+            .map_err(LoggerError::MongoDbError)?;
+        Ok(())
+    }
 }
 
 impl MognoDBLogger {
@@ -82,8 +119,114 @@ impl MognoDBLogger {
     }
 }
 
+pub struct SledLogger {
+    tree: sled::Tree,
+}
+
+#[async_trait]
+impl DatabaseLogger for SledLogger {
+    async fn log(
+        &self,
+        status_code: StatusCode,
+        message: String,
+        duration: u64,
+        uri: String,
+        user_id: Option<ObjectId>,
+    ) -> Result<(), LoggerError> {
+        let log = DatabaseLog {
+            time: Utc::now(),
+            message,
+            status_code: status_code.as_u16(),
+            duration,
+            uri,
+            user_id,
+        };
+        //ObjectId keys keep the logs ordered by insertion time
+        let bytes = bson::to_vec(&log).map_err(LoggerError::BsonError)?;
+        self.tree
+            .insert(ObjectId::new().bytes(), bytes)
+            .map_err(LoggerError::SledError)?;
+        Ok(())
+    }
+
+    async fn delete_logs_of_user(&self, user_id: ObjectId) -> Result<(), LoggerError> {
+        for entry in self.tree.iter() {
+            let (key, bytes) = entry.map_err(LoggerError::SledError)?;
+            let log: DatabaseLog = bson::from_slice(&bytes).map_err(LoggerError::BsonDeError)?;
+            if log.user_id == Some(user_id) {
+                self.tree.remove(key).map_err(LoggerError::SledError)?;
+            }
+        }
+        self.tree
+            .flush_async()
+            .await
+            .map_err(LoggerError::SledError)?;
+        Ok(())
+    }
+}
+
+impl SledLogger {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+/*
+* Keeps request logs in process memory, used by the tests
+*/
+#[derive(Default)]
+pub struct MemoryLogger {
+    logs: RwLock<Vec<DatabaseLog>>,
+}
+
+#[async_trait]
+impl DatabaseLogger for MemoryLogger {
+    async fn log(
+        &self,
+        status_code: StatusCode,
+        message: String,
+        duration: u64,
+        uri: String,
+        user_id: Option<ObjectId>,
+    ) -> Result<(), LoggerError> {
+        self.logs.write().unwrap().push(DatabaseLog {
+            time: Utc::now(),
+            message,
+            status_code: status_code.as_u16(),
+            duration,
+            uri,
+            user_id,
+        });
+        Ok(())
+    }
+
+    async fn delete_logs_of_user(&self, user_id: ObjectId) -> Result<(), LoggerError> {
+        self.logs
+            .write()
+            .unwrap()
+            .retain(|log| log.user_id != Some(user_id));
+        Ok(())
+    }
+}
+
+impl MemoryLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub fn logs_of_user(&self, user_id: ObjectId) -> usize {
+        self.logs
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|log| log.user_id == Some(user_id))
+            .count()
+    }
+}
+
 pub struct FileLogger {
-    _guard: Arc<WorkerGuard>,
+    _guard: Option<Arc<WorkerGuard>>,
 }
 
 impl FileLogger {
@@ -104,16 +247,23 @@ impl FileLogger {
         info!("Logger initialized");
 
         Self {
-            _guard: Arc::new(guard),
+            _guard: Some(Arc::new(guard)),
         }
     }
+    //doesn't touch the global subscriber so it can be created many times (tests)
+    #[cfg(test)]
+    pub fn disabled() -> Self {
+        Self { _guard: None }
+    }
     pub fn flush(&self) {
         //lazy drop
-        let _ = std::thread::spawn({
-            let guard = Arc::clone(&self._guard);
-            move || drop(guard)
-        })
-        .join();
+        if let Some(guard) = &self._guard {
+            let _ = std::thread::spawn({
+                let guard = Arc::clone(guard);
+                move || drop(guard)
+            })
+            .join();
+        }
     }
 }
 
@@ -123,10 +273,18 @@ pub struct LoggerState {
 }
 
 impl LoggerState {
-    pub fn new(file_log_path: String, log_collection: MognoDBLogger) -> Self {
+    pub fn new(file_log_path: String, database_logger: Arc<dyn DatabaseLogger>) -> Self {
         Self {
             file_logger: Arc::new(FileLogger::init_logger(file_log_path)),
-            database_logger: Arc::new(log_collection),
+            database_logger,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn without_file_logger(database_logger: Arc<dyn DatabaseLogger>) -> Self {
+        Self {
+            file_logger: Arc::new(FileLogger::disabled()),
+            database_logger,
         }
     }
 }
@@ -141,12 +299,19 @@ pub async fn logger_middleware(
 
     let res = next.run(request).await;
     let duration = income_time.elapsed().as_secs();
+    let user_id = res.extensions().get::<RequestUser>().map(|user| user.0);
     match res.extensions().get::<ApiError>() {
         Some(err) => {
             let _res = app_state
                 .logger
                 .database_logger
-                .log(res.status(), err.to_string(), duration, uri.to_string())
+                .log(
+                    res.status(),
+                    err.to_string(),
+                    duration,
+                    uri.to_string(),
+                    user_id,
+                )
                 .await
                 .map_err(|err| error!("{}", err));
         }
@@ -159,6 +324,7 @@ pub async fn logger_middleware(
                     "Request done sucefully".to_string(),
                     duration,
                     uri.to_string(),
+                    user_id,
                 )
                 .await
                 .map_err(|err| error!("{}", err));
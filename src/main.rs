@@ -1,25 +1,43 @@
 use crate::logger::{logger_middleware, LoggerState};
-use auth::{auth_middleware, Keys};
+use auth::{
+    auth_middleware, require_scopes, sessions_only, token_from_query, Keys, RequiredScopes,
+};
 use axum::{
-    http::{header, HeaderValue, Method},
+    extract::DefaultBodyLimit,
+    http::{header, HeaderName, HeaderValue, Method},
     middleware,
-    routing::{get, patch, post},
+    routing::{delete, get, patch, post},
     Router,
 };
 use database::Database;
 use dotenv::dotenv;
-use std::sync::{Arc, LazyLock};
+use events::EventBus;
+use live::LiveRooms;
+use markdown::RenderCache;
+use models::api_token::Scope;
+use search::SearchIndex;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, LazyLock},
+};
 use tower_http::cors::CorsLayer;
 use tracing::info;
 
 mod auth;
 mod database;
 mod error;
+mod events;
+mod live;
 mod logger;
+mod markdown;
+mod migrations;
 mod models;
 mod repository;
 mod routes;
+mod search;
 mod services;
+#[cfg(test)]
+mod tests;
 
 static KEYS: LazyLock<Keys> = LazyLock::new(|| {
     let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
@@ -33,6 +51,12 @@ static MONGO_URL: LazyLock<String> =
 pub struct AppState {
     pub database: Arc<Database>,
     pub logger: Arc<LoggerState>,
+    pub search_index: Arc<SearchIndex>,
+    pub render_cache: Arc<RenderCache>,
+    //bytes of attachments a user can store
+    pub attachment_quota: u64,
+    pub live_rooms: Arc<LiveRooms>,
+    pub events: Arc<EventBus>,
 }
 
 impl AppState {
@@ -44,42 +68,103 @@ impl AppState {
                 "./logs/".to_string(),
                 db_state.logs_repo(),
             )),
+            search_index: Arc::new(SearchIndex::new()),
+            render_cache: Arc::new(RenderCache::new()),
+            attachment_quota: services::attachment_service::quota_from_env(),
+            live_rooms: Arc::new(LiveRooms::from_env()),
+            events: Arc::new(EventBus::new(1024)),
         }
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenv().ok();
-
-    let app_state = AppState::new().await;
 
-    let port: String = std::env::var("BACKEND_PORT").unwrap_or("3001".to_string());
+    //in-memory repositories and no file logs, used by the tests
+    #[cfg(test)]
+    pub fn memory() -> Self {
+        Self::with_database(Database::memory())
+    }
 
-    info!("Server is starting");
+    #[cfg(test)]
+    pub fn with_database(database: Database) -> Self {
+        let db_state = Arc::new(database);
+        Self {
+            database: db_state.clone(),
+            logger: Arc::new(LoggerState::without_file_logger(db_state.logs_repo())),
+            search_index: Arc::new(SearchIndex::new()),
+            render_cache: Arc::new(RenderCache::new()),
+            //small enough for the tests to run into
+            attachment_quota: 1024 * 1024,
+            live_rooms: Arc::new(LiveRooms::new(std::time::Duration::from_millis(50))),
+            events: Arc::new(EventBus::new(1024)),
+        }
+    }
+}
 
+pub fn app(app_state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
-        .allow_headers([header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::ACCEPT,
+            header::CONTENT_TYPE,
+            header::RANGE,
+            header::IF_MATCH,
+            HeaderName::from_static(routes::public::PASSWORD_HEADER),
+        ])
+        .expose_headers([header::ETAG])
         .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
         .allow_credentials(true);
+    //a single upload can never be larger than the whole quota
+    let upload_limit =
+        usize::try_from(app_state.attachment_quota.saturating_add(64 * 1024)).unwrap_or(usize::MAX);
 
     let auth_routes = Router::new()
         .route("/login", post(routes::auth::authorize))
         .route("/register", post(routes::auth::register))
         .route("/refresh", post(routes::auth::refresh_token))
-        .route(
-            "/check",
-            get(routes::auth::check_auth).layer(middleware::from_fn_with_state(
-                app_state.clone(),
-                auth_middleware,
-            )),
+        .route("/login/2fa", post(routes::auth::authorize_two_factor))
+        .merge(
+            Router::new()
+                .route("/logout", post(routes::sessions::logout))
+                .route(
+                    "/sessions",
+                    get(routes::sessions::get_sessions)
+                        .delete(routes::sessions::revoke_other_sessions),
+                )
+                .route("/sessions/{id}", delete(routes::sessions::revoke_session))
+                .route(
+                    "/tokens",
+                    get(routes::api_tokens::get_tokens).post(routes::api_tokens::create_token),
+                )
+                .route("/tokens/{id}", delete(routes::api_tokens::revoke_token))
+                .route("/2fa", get(routes::two_factor::get_status))
+                .route("/2fa/enroll", post(routes::two_factor::enroll))
+                .route("/2fa/confirm", post(routes::two_factor::confirm))
+                .route("/2fa/disable", post(routes::two_factor::disable))
+                .route("/account", delete(routes::account::delete_account))
+                .route("/account/password", patch(routes::account::change_password))
+                .route("/account/email", patch(routes::account::change_email))
+                //a token can't mint or revoke tokens, end sessions or touch the account
+                .layer(middleware::from_fn(sessions_only))
+                //added after sessions_only, any credential can check itself
+                .route("/check", get(routes::auth::check_auth))
+                .layer(middleware::from_fn_with_state(
+                    app_state.clone(),
+                    auth_middleware,
+                )),
         )
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             logger_middleware,
         ));
 
+    let notes_scopes = RequiredScopes {
+        read: Scope::NotesRead,
+        write: Scope::NotesWrite,
+    };
+    let todos_scopes = RequiredScopes {
+        read: Scope::TodosRead,
+        write: Scope::TodosWrite,
+    };
+
     let todo_list_route = Router::new()
         .route(
             "/",
@@ -88,7 +173,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/id", get(routes::todos::get_all_todos_by_id))
         .route(
             "/id/{todo_list_id}",
-            patch(routes::todos::rename_todo_list)
+            get(routes::todos::get_todo_list)
+                .patch(routes::todos::rename_todo_list)
                 .delete(routes::todos::delete_todo_list)
                 .post(routes::todos::create_todo),
         )
@@ -96,6 +182,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/id/{todo_list_id}/todo/id/{todo_id}",
             patch(routes::todos::modify_todo).delete(routes::todos::delete_todo),
         )
+        .layer(middleware::from_fn_with_state(todos_scopes, require_scopes))
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             auth_middleware,
@@ -108,16 +195,84 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let note_routes = Router::new()
         .route("/create", post(routes::notes::create_note))
         .route("/", get(routes::notes::get_all_notes_info))
+        .route("/search", get(routes::notes::search_notes))
+        .route("/graph", get(routes::graph::get_graph))
+        .route("/attachments", get(routes::attachments::get_usage))
         .route(
             "/id/{id}",
             get(routes::notes::get_note_by_id)
                 .patch(routes::notes::update_note_by_id)
                 .delete(routes::notes::delete_note),
         )
+        .route("/id/{id}/notebook", patch(routes::notes::move_note))
         .route(
             "/id/{id}/pin/{todo_list_id}",
             patch(routes::notes::pin_todo_list).delete(routes::notes::unpin_todo_list),
         )
+        .route("/id/{id}/links", get(routes::links::get_links))
+        .route("/id/{id}/backlinks", get(routes::links::get_backlinks))
+        .route(
+            "/id/{id}/shares",
+            get(routes::shares::get_shares).post(routes::shares::share_note),
+        )
+        .route(
+            "/id/{id}/shares/{username}",
+            delete(routes::shares::unshare_note),
+        )
+        .route(
+            "/id/{id}/share-links",
+            get(routes::share_links::get_links).post(routes::share_links::create_link),
+        )
+        .route(
+            "/id/{id}/share-links/{link_id}",
+            patch(routes::share_links::expire_link).delete(routes::share_links::revoke_link),
+        )
+        .route(
+            "/id/{id}/attachments",
+            get(routes::attachments::get_attachments)
+                .post(routes::attachments::upload_attachments)
+                .layer(DefaultBodyLimit::max(upload_limit)),
+        )
+        .route(
+            "/id/{id}/attachments/{attachment_id}",
+            get(routes::attachments::download_attachment)
+                .delete(routes::attachments::delete_attachment),
+        )
+        .route("/id/{id}/revisions", get(routes::revisions::get_revisions))
+        .route(
+            "/id/{id}/revisions/diff",
+            get(routes::revisions::diff_revisions),
+        )
+        .route(
+            "/id/{id}/revisions/{rev}",
+            get(routes::revisions::get_revision),
+        )
+        .route(
+            "/id/{id}/revisions/{rev}/restore",
+            post(routes::revisions::restore_revision),
+        )
+        .layer(middleware::from_fn_with_state(notes_scopes, require_scopes))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ));
+
+    let notebook_routes = Router::new()
+        .route(
+            "/",
+            get(routes::notebooks::get_notebooks).post(routes::notebooks::create_notebook),
+        )
+        .route(
+            "/{id}",
+            get(routes::notebooks::get_notebook)
+                .patch(routes::notebooks::update_notebook)
+                .delete(routes::notebooks::delete_notebook),
+        )
+        .layer(middleware::from_fn_with_state(notes_scopes, require_scopes))
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             auth_middleware,
@@ -127,16 +282,132 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             logger_middleware,
         ));
 
-    let app = Router::new()
+    let tag_routes = Router::new()
+        .route("/", get(routes::tags::get_tags))
+        .route("/merge", post(routes::tags::merge_tags))
+        .route(
+            "/{tag}",
+            patch(routes::tags::rename_tag).delete(routes::tags::delete_tag),
+        )
+        .layer(middleware::from_fn_with_state(notes_scopes, require_scopes))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ));
+
+    let trash_routes = Router::new()
+        .route("/", get(routes::trash::get_trash))
+        .route("/notes/{id}", delete(routes::trash::purge_note))
+        .route("/notes/{id}/restore", post(routes::trash::restore_note))
+        .route("/todos/{id}", delete(routes::trash::purge_todo_list))
+        .route(
+            "/todos/{id}/restore",
+            post(routes::trash::restore_todo_list),
+        )
+        .layer(middleware::from_fn(sessions_only))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ));
+
+    //browsers can't set headers on websockets, token_from_query moves the
+    //token out of the url before anything logs it
+    let live_routes = Router::new()
+        .route("/notes/{id}", get(routes::live::live_note))
+        .layer(middleware::from_fn(sessions_only))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ))
+        .layer(middleware::from_fn(token_from_query));
+
+    //EventSource can't set headers either
+    let event_routes = Router::new()
+        .route("/", get(routes::events::get_events))
+        .layer(middleware::from_fn(sessions_only))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ))
+        .layer(middleware::from_fn(token_from_query));
+
+    let sync_routes = Router::new()
+        .route("/", post(routes::sync::sync))
+        .layer(middleware::from_fn(sessions_only))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ));
+
+    //no auth_middleware, the token in the path is the credential
+    let public_routes = Router::new()
+        .route(
+            "/{token}",
+            get(routes::public::get_shared_note).post(routes::public::unlock_shared_note),
+        )
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logger_middleware,
+        ));
+
+    Router::new()
         .nest("/auth", auth_routes)
         .nest("/notes", note_routes)
         .nest("/todos", todo_list_route)
+        .nest("/notebooks", notebook_routes)
+        .nest("/tags", tag_routes)
+        .nest("/trash", trash_routes)
+        .nest("/public", public_routes)
+        .nest("/live", live_routes)
+        .nest("/events", event_routes)
+        .nest("/sync", sync_routes)
         .with_state(app_state.clone())
-        .layer(cors);
+        .layer(cors)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let app_state = AppState::new().await;
+
+    services::trash_service::spawn_purge_task(app_state.database.clone());
+
+    let port: String = std::env::var("BACKEND_PORT").unwrap_or("3001".to_string());
+
+    info!("Server is starting");
+
+    let app = app(app_state.clone());
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!("Server listen on 0.0.0.0:{}", port);
-    axum::serve(listener, app).await.unwrap();
+    //the peer address ends up in the session list
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     //enforce to lazy drop of file logger state idk if this is good aproach but works...
     app_state.logger.file_logger.flush();
@@ -0,0 +1,93 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+/*
+* CommonMark + GFM (tables, task lists, strikethrough) to html with pulldown-cmark.
+* Raw html in the source is kept as text and the output goes through ammonia,
+* links/images only keep http(s), mailto or relative urls.
+*/
+
+const CACHE_SIZE: usize = 512;
+
+pub fn to_html(markdown: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+    //raw html is shown as written, ammonia below still guards the rest
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+        event => event,
+    });
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+const ALIGNMENTS: [&str; 3] = [
+    "text-align: left",
+    "text-align: center",
+    "text-align: right",
+];
+
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("nofollow noopener"))
+        //task list checkboxes
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        //fenced code language for highlighters
+        .add_tag_attributes("code", ["class"])
+        //table column alignment
+        .add_tag_attributes("th", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("code", "class") if !value.starts_with("language-") => None,
+            ("input", "type") if value != "checkbox" => None,
+            (_, "style") if !ALIGNMENTS.contains(&value) => None,
+            _ => Some(value.into()),
+        });
+    builder
+});
+
+/// Rendered html by sha256 of the markdown, the oldest entry is dropped when full
+#[derive(Default)]
+pub struct RenderCache {
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    html: HashMap<[u8; 32], Arc<str>>,
+    order: VecDeque<[u8; 32]>,
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn render(&self, markdown: &str) -> Arc<str> {
+        let key: [u8; 32] = Sha256::digest(markdown.as_bytes()).into();
+        if let Some(html) = self.entries.lock().unwrap().html.get(&key) {
+            return html.clone();
+        }
+        //rendered outside the lock, a concurrent miss just renders twice
+        let html: Arc<str> = to_html(markdown).into();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.html.insert(key, html.clone()).is_none() {
+            entries.order.push_back(key);
+        }
+        while entries.order.len() > CACHE_SIZE {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.html.remove(&oldest);
+            }
+        }
+        html
+    }
+}
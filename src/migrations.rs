@@ -0,0 +1,271 @@
+use crate::{models::timestamp, services::link_service};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use tracing::{error, info};
+
+/*
+* Notes, todo lists and todos stored before created_at/updated_at/updated_by
+* existed get them filled in on startup. The creation time comes from the
+* ObjectId and the owner is taken as the last editor.
+*/
+
+fn mongo_id_date(id: &str) -> Document {
+    doc! {"$toDate": id}
+}
+
+pub async fn backfill_timestamps_mongo(database: &mongodb::Database) {
+    let notes = database.collection::<Document>("notes");
+    let result = notes
+        .update_many(
+            doc! {"created_at": {"$exists": false}},
+            vec![doc! {"$set": {
+                "created_at": mongo_id_date("$_id"),
+                "updated_at": {"$ifNull": ["$updated_at", mongo_id_date("$_id")]},
+                "updated_by": {"$ifNull": ["$updated_by", "$user_id"]}
+            }}],
+        )
+        .await;
+    report("notes", result);
+
+    let todos = database.collection::<Document>("todos");
+    let result = todos
+        .update_many(
+            doc! {"$or": [
+                {"created_at": {"$exists": false}},
+                {"todos": {"$elemMatch": {"created_at": {"$exists": false}}}}
+            ]},
+            vec![doc! {"$set": {
+                "created_at": {"$ifNull": ["$created_at", mongo_id_date("$_id")]},
+                "updated_at": {"$ifNull": ["$updated_at", mongo_id_date("$_id")]},
+                "updated_by": {"$ifNull": ["$updated_by", "$user_id"]},
+                "todos": {"$map": {
+                    "input": "$todos",
+                    "as": "todo",
+                    "in": {"$mergeObjects": ["$$todo", {
+                        "created_at": {"$ifNull": ["$$todo.created_at", mongo_id_date("$$todo._id")]},
+                        "updated_at": {"$ifNull": ["$$todo.updated_at", mongo_id_date("$$todo._id")]},
+                        "updated_by": {"$ifNull": ["$$todo.updated_by", "$user_id"]}
+                    }]}
+                }}
+            }}],
+        )
+        .await;
+    report("todo lists", result);
+}
+
+fn report(name: &str, result: mongodb::error::Result<mongodb::results::UpdateResult>) {
+    match result {
+        Ok(res) if res.modified_count > 0 => {
+            info!("Added timestamps to {} {}", res.modified_count, name)
+        }
+        Ok(_res) => {}
+        Err(err) => error!("Failed adding timestamps to {}: {}", name, err),
+    }
+}
+
+pub fn backfill_timestamps_sled(notes: &sled::Tree, todos: &sled::Tree) {
+    let mut updated = 0;
+    for tree in [notes, todos] {
+        for entry in tree.iter() {
+            let (key, bytes) = entry.expect("Failed reading sled tree");
+            let mut document: Document =
+                mongodb::bson::from_slice(&bytes).expect("Failed decoding sled document");
+            let Ok(user_id) = document.get_object_id("user_id") else {
+                continue;
+            };
+            let mut changed = fill(&mut document, user_id);
+            if let Ok(items) = document.get_array_mut("todos") {
+                for item in items.iter_mut() {
+                    if let Bson::Document(todo) = item {
+                        changed |= fill(todo, user_id);
+                    }
+                }
+            }
+            if changed {
+                let bytes = mongodb::bson::to_vec(&document).expect("Failed encoding document");
+                tree.insert(key, bytes).expect("Failed writing sled tree");
+                updated += 1;
+            }
+        }
+        tree.flush().expect("Failed flushing sled tree");
+    }
+    if updated > 0 {
+        info!("Added timestamps to {} documents", updated);
+    }
+}
+
+//returns true when something was missing
+fn fill(document: &mut Document, user_id: ObjectId) -> bool {
+    let Ok(id) = document.get_object_id("_id") else {
+        return false;
+    };
+    let created = timestamp::to_bson(&timestamp::from_object_id(&id));
+    let mut changed = false;
+    for field in ["created_at", "updated_at"] {
+        if !document.contains_key(field) {
+            document.insert(field, created);
+            changed = true;
+        }
+    }
+    if !document.contains_key("updated_by") {
+        document.insert("updated_by", user_id);
+        changed = true;
+    }
+    changed
+}
+
+/*
+* Timestamps used to be stored as RFC 3339 strings, mongo only runs date
+* queries and TTL indexes on real datetimes. Sled keeps the strings until a
+* document is written again, reading takes both.
+*/
+const TIMESTAMPS: &[(&str, &[&str])] = &[
+    ("notes", &["created_at", "updated_at", "deleted_at"]),
+    ("todos", &["created_at", "updated_at", "deleted_at"]),
+    ("notebooks", &["created_at", "updated_at"]),
+    ("revisions", &["created_at"]),
+    ("refresh_tokens", &["expires_at", "used_at"]),
+    ("sessions", &["created_at", "last_used_at"]),
+    ("api_tokens", &["created_at", "expires_at", "last_used_at"]),
+    ("two_factor", &["created_at", "last_failed_at"]),
+    (
+        "share_links",
+        &["created_at", "expires_at", "last_viewed_at"],
+    ),
+    ("attachments", &["created_at"]),
+    ("tombstones", &["deleted_at"]),
+    ("logs", &["time"]),
+];
+
+fn to_date(field: &str) -> Document {
+    let value = format!("${}", field);
+    doc! {"$cond": [{"$eq": [{"$type": &value}, "string"]}, {"$toDate": &value}, &value]}
+}
+
+pub async fn timestamps_to_dates_mongo(database: &mongodb::Database) {
+    for (name, fields) in TIMESTAMPS {
+        let filter: Vec<Document> = fields
+            .iter()
+            .map(|field| doc! {*field: {"$type": "string"}})
+            .collect();
+        let mut set: Document = fields
+            .iter()
+            .map(|field| (field.to_string(), Bson::Document(to_date(field))))
+            .collect();
+        let mut filter = doc! {"$or": filter};
+        //the todos inside a list have their own
+        if *name == "todos" {
+            filter = doc! {"$or": [
+                filter,
+                {"todos.created_at": {"$type": "string"}},
+                {"todos.updated_at": {"$type": "string"}}
+            ]};
+            set.insert(
+                "todos",
+                doc! {"$map": {
+                    "input": "$todos",
+                    "as": "todo",
+                    "in": {"$mergeObjects": ["$$todo", {
+                        "created_at": to_date("$todo.created_at"),
+                        "updated_at": to_date("$todo.updated_at")
+                    }]}
+                }},
+            );
+        }
+        let result = database
+            .collection::<Document>(name)
+            .update_many(filter, vec![doc! {"$set": set}])
+            .await;
+        match result {
+            Ok(res) if res.modified_count > 0 => {
+                info!(
+                    "Stored the timestamps of {} {} as dates",
+                    res.modified_count, name
+                )
+            }
+            Ok(_res) => {}
+            Err(err) => error!("Failed converting the timestamps of {}: {}", name, err),
+        }
+    }
+}
+
+/*
+* Notes written before [[...]] references were tracked get their links
+* parsed once, later saves keep them current
+*/
+pub async fn backfill_links_mongo(database: &mongodb::Database) {
+    let notes = database.collection::<Document>("notes");
+    let missing: Vec<Document> = match notes.find(doc! {"links": {"$exists": false}}).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(missing) => missing,
+            Err(err) => return error!("Failed reading notes without links: {}", err),
+        },
+        Err(err) => return error!("Failed reading notes without links: {}", err),
+    };
+    let mut updated = 0;
+    for note in missing {
+        let (Ok(id), Ok(content)) = (note.get_object_id("_id"), note.get_str("content")) else {
+            continue;
+        };
+        let links = match bson::to_bson(&link_service::parse_links(content)) {
+            Ok(links) => links,
+            Err(err) => return error!("Failed encoding links: {}", err),
+        };
+        match notes
+            .update_one(doc! {"_id": id}, doc! {"$set": {"links": links}})
+            .await
+        {
+            Ok(_res) => updated += 1,
+            Err(err) => return error!("Failed adding links to notes: {}", err),
+        }
+    }
+    if updated > 0 {
+        info!("Added links to {} notes", updated);
+    }
+}
+
+/*
+* Revision numbers are unique per note, concurrent saves retry with the next
+* one instead of both keeping the same number
+*/
+pub async fn revision_index_mongo(database: &mongodb::Database) {
+    let index = IndexModel::builder()
+        .keys(doc! {"user_id": 1, "note_id": 1, "rev": 1})
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    if let Err(err) = database
+        .collection::<Document>("revisions")
+        .create_index(index)
+        .await
+    {
+        error!("Failed creating the revision index: {}", err);
+    }
+}
+
+pub fn backfill_links_sled(notes: &sled::Tree) {
+    let mut updated = 0;
+    for entry in notes.iter() {
+        let (key, bytes) = entry.expect("Failed reading sled tree");
+        let mut document: Document =
+            mongodb::bson::from_slice(&bytes).expect("Failed decoding sled document");
+        if document.contains_key("links") {
+            continue;
+        }
+        let links = link_service::parse_links(document.get_str("content").unwrap_or_default());
+        document.insert(
+            "links",
+            bson::to_bson(&links).expect("Failed encoding links"),
+        );
+        let bytes = mongodb::bson::to_vec(&document).expect("Failed encoding document");
+        notes.insert(key, bytes).expect("Failed writing sled tree");
+        updated += 1;
+    }
+    notes.flush().expect("Failed flushing sled tree");
+    if updated > 0 {
+        info!("Added links to {} notes", updated);
+    }
+}
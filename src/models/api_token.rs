@@ -0,0 +1,46 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* Personal access token for scripts and integrations. Only the sha256 of the
+* token is stored, the token itself is shown once when it is created.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    //hex sha256 of the token
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    //None = valid until revoked
+    #[serde(default, with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+    //updated at most once a minute
+    #[serde(default, with = "timestamp::optional")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// What a token may do, write doesn't include read
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    #[serde(rename = "notes:read")]
+    NotesRead,
+    #[serde(rename = "notes:write")]
+    NotesWrite,
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+}
@@ -0,0 +1,23 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* File attached to a note, the bytes live in an AttachmentStorage under the
+* same id
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub note_id: ObjectId,
+    //owner of the note, the file counts against their quota
+    pub user_id: ObjectId,
+    pub uploaded_by: ObjectId,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+}
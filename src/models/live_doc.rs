@@ -0,0 +1,20 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{oid::ObjectId, Binary};
+use serde::{Deserialize, Serialize};
+
+/*
+* Automerge document of a live editing room, one per note. A room opened later
+* continues it so peers reconnecting with an older copy still share its history.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LiveDoc {
+    #[serde(rename = "_id")]
+    pub note_id: ObjectId,
+    //owner of the note
+    pub user_id: ObjectId,
+    //output of `AutoCommit::save`
+    pub doc: Binary,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+}
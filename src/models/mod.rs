@@ -1,3 +1,14 @@
+pub(crate) mod api_token;
+pub(crate) mod attachment;
+pub(crate) mod live_doc;
 pub(crate) mod note;
+pub(crate) mod notebook;
+pub(crate) mod refresh_token;
+pub(crate) mod revision;
+pub(crate) mod session;
+pub(crate) mod share_link;
+pub(crate) mod timestamp;
 pub(crate) mod todo;
+pub(crate) mod tombstone;
+pub(crate) mod two_factor;
 pub(crate) mod user;
@@ -1,7 +1,9 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Note {
     #[serde(rename = "_id")]
     pub id: ObjectId,
@@ -10,4 +12,115 @@ pub struct Note {
     pub content: String,
     pub tags: Vec<String>,
     pub todo_lists: Vec<ObjectId>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+    //last user that changed the note
+    pub updated_by: ObjectId,
+    //set while the document sits in the trash
+    #[serde(default, with = "timestamp::optional")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    //other users with access, the owner is user_id
+    #[serde(default)]
+    pub shared_with: Vec<NoteShare>,
+    //None = not in a notebook
+    #[serde(default)]
+    pub notebook_id: Option<ObjectId>,
+    //[[...]] references parsed out of the content
+    #[serde(default)]
+    pub links: Vec<WikiLink>,
+    //bumped by every change, clients send it back in If-Match
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NoteShare {
+    pub user_id: ObjectId,
+    pub role: NoteRole,
+}
+
+/// A `[[Title]]` or `[[id:...]]` reference, resolved when it is read so a
+/// note created or renamed later fixes links that were broken before
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    //as written, without the |alias
+    pub target: String,
+    #[serde(default)]
+    pub id: Option<ObjectId>,
+    //title_key of the target for [[Title]] references
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// Titles are matched ignoring case and surrounding whitespace
+pub fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Trimmed and lowercase so "Rust" and "rust " are the same tag
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalized, without empty tags and duplicates, in the original order
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for tag in tags.iter().map(|tag| normalize_tag(tag)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Replaces every tag in `from` by `to` (drops them for `None`), returns
+/// false when the note had none of them
+pub fn replace_tags(tags: &mut Vec<String>, from: &[String], to: Option<&str>) -> bool {
+    if !tags.iter().any(|tag| from.contains(tag)) {
+        return false;
+    }
+    let mut replaced: Vec<String> = vec![];
+    for tag in tags.drain(..) {
+        let tag = match from.contains(&tag) {
+            true => match to {
+                Some(to) => to.to_string(),
+                None => continue,
+            },
+            false => tag,
+        };
+        if !replaced.contains(&tag) {
+            replaced.push(tag);
+        }
+    }
+    *tags = replaced;
+    true
+}
+
+impl Note {
+    pub fn role_of(&self, user_id: ObjectId) -> Option<NoteRole> {
+        if self.user_id == user_id {
+            return Some(NoteRole::Owner);
+        }
+        self.shared_with
+            .iter()
+            .find(|share| share.user_id == user_id)
+            .map(|share| share.role)
+    }
+
+    pub fn can_edit(&self, user_id: ObjectId) -> bool {
+        matches!(
+            self.role_of(user_id),
+            Some(NoteRole::Owner | NoteRole::Editor)
+        )
+    }
 }
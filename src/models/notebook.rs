@@ -0,0 +1,21 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* Folder for notes, notebooks nest through parent_id (None = top level)
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notebook {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    #[serde(default)]
+    pub parent_id: Option<ObjectId>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,22 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* Server side record of an issued refresh token, every refresh swaps it for a
+* new one of the same family. A family starts at login.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RefreshToken {
+    //the token's jti
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub user_id: ObjectId,
+    pub family: ObjectId,
+    #[serde(with = "timestamp")]
+    pub expires_at: DateTime<Utc>,
+    //set once it was swapped, seeing it again means it leaked
+    #[serde(default, with = "timestamp::optional")]
+    pub used_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,23 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* Snapshot of a note as it was before a save overwrote it
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteRevision {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub note_id: ObjectId,
+    pub user_id: ObjectId,
+    pub rev: u32,
+    pub author_id: ObjectId,
+    pub author: String,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+}
@@ -0,0 +1,24 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* One login of a user. Every token issued for it carries its id, deleting the
+* session ends them all. The refresh token family of the login has the same id.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    //updated at most once a minute
+    #[serde(with = "timestamp")]
+    pub last_used_at: DateTime<Utc>,
+}
@@ -0,0 +1,39 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* Public read only link to a note, anyone with the token can open it
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShareLink {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub token: String,
+    pub note_id: ObjectId,
+    //owner of the note
+    pub user_id: ObjectId,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+    //bcrypt hash
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub views: u64,
+    #[serde(default, with = "timestamp::optional")]
+    pub last_viewed_at: Option<DateTime<Utc>>,
+    //wrong passwords since the last right one
+    #[serde(default)]
+    pub failed_attempts: u32,
+    #[serde(default, with = "timestamp::optional")]
+    pub last_failed_at: Option<DateTime<Utc>>,
+}
+
+impl ShareLink {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
@@ -0,0 +1,93 @@
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use mongodb::bson::{self, oid::ObjectId, serde_helpers::chrono_datetime_as_bson_datetime, Bson};
+use serde::{Deserialize, Deserializer, Serializer};
+
+/*
+* created_at/updated_at and the other times are BSON datetimes in the database,
+* so mongo can run date queries and TTL indexes on them, and RFC 3339 strings
+* with millisecond precision in json. Strings stored before are still read.
+* Use with #[serde(with = "timestamp")]
+*/
+
+pub fn now() -> DateTime<Utc> {
+    let now = Utc::now();
+    Utc.timestamp_millis_opt(now.timestamp_millis())
+        .single()
+        .unwrap_or(now)
+}
+
+pub fn format(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+pub fn parse(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// The stored form, for filters and updates written by hand
+pub fn to_bson(time: &DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_chrono(*time)
+}
+
+/// Creation time of documents that were stored before timestamps existed
+pub fn from_object_id(id: &ObjectId) -> DateTime<Utc> {
+    id.timestamp().to_chrono()
+}
+
+pub fn serialize<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&format(time))
+    } else {
+        chrono_datetime_as_bson_datetime::serialize(time, serializer)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = match deserializer.is_human_readable() {
+        true => Bson::String(String::deserialize(deserializer)?),
+        false => Bson::deserialize(deserializer)?,
+    };
+    from_bson(value)
+}
+
+fn from_bson<E: serde::de::Error>(value: Bson) -> Result<DateTime<Utc>, E> {
+    match value {
+        Bson::DateTime(time) => Ok(time.to_chrono()),
+        Bson::String(value) => {
+            parse(&value).ok_or_else(|| E::custom(format!("invalid timestamp {}", value)))
+        }
+        other => Err(E::custom(format!("expected a timestamp, got {}", other))),
+    }
+}
+
+/// Same as the parent module for `Option<DateTime<Utc>>`, `None` is stored as null
+pub mod optional {
+    use chrono::{DateTime, Utc};
+    use mongodb::bson::Bson;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => super::serialize(time, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        let value = match deserializer.is_human_readable() {
+            true => Option::<String>::deserialize(deserializer)?.map(Bson::String),
+            false => Option::<Bson>::deserialize(deserializer)?,
+        };
+        match value {
+            None | Some(Bson::Null) => Ok(None),
+            Some(value) => super::from_bson(value).map(Some),
+        }
+    }
+}
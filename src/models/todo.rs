@@ -1,3 +1,5 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
@@ -5,22 +7,39 @@ use serde::{Deserialize, Serialize};
 * Note <-> TodoList <-> Todo
 */
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TodoList {
     #[serde(rename = "_id")]
     pub id: ObjectId,
     pub user_id: ObjectId,
     pub todos: Vec<Todo>,
     pub title: String,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+    //last user that changed the list or one of its todos
+    pub updated_by: ObjectId,
+    //set while the document sits in the trash
+    #[serde(default, with = "timestamp::optional")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    //bumped by every change to the list or its todos
+    #[serde(default)]
+    pub version: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Todo {
     #[serde(rename = "_id")]
     pub id: ObjectId,
     pub title: String,
     pub status: bool,
     pub priority: TodoPriority,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: ObjectId,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy)]
@@ -0,0 +1,41 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* What is left of a document purged from the trash, so clients that were
+* offline meanwhile still learn that it is gone
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tombstone {
+    //id of the purged document
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub entity: SyncEntity,
+    #[serde(with = "timestamp")]
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncEntity {
+    Note,
+    TodoList,
+    Todo,
+}
+
+/// Whether a document belongs in a sync from `since` on: changed or trashed
+/// since then, or for a sync without one every live document
+pub fn changed_since(
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    since: Option<DateTime<Utc>>,
+) -> bool {
+    match (since, deleted_at) {
+        (None, deleted_at) => deleted_at.is_none(),
+        (Some(since), Some(deleted_at)) => deleted_at >= since || updated_at >= since,
+        (Some(since), None) => updated_at >= since,
+    }
+}
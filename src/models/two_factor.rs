@@ -0,0 +1,30 @@
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+* TOTP second factor of a user, one per user. It stays pending until the first
+* code proves the authenticator app got the secret, only then login asks for it.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TwoFactor {
+    #[serde(rename = "_id")]
+    pub user_id: ObjectId,
+    //base32, the authenticator app needs the secret itself so it can't be hashed
+    pub secret: String,
+    pub enabled: bool,
+    //hex sha256 of the recovery codes not used yet
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    //time step of the last accepted code, every code works once
+    #[serde(default)]
+    pub last_step: i64,
+    //wrong codes since the last right one
+    #[serde(default)]
+    pub failed_attempts: u32,
+    #[serde(default, with = "timestamp::optional")]
+    pub last_failed_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,265 @@
+use crate::{
+    error::ApiError,
+    models::{api_token::ApiToken, timestamp},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait ApiTokenRepo: Send + Sync {
+    async fn create_token(&self, token: &ApiToken) -> Result<(), ApiError>;
+    /// Oldest first
+    async fn get_tokens(&self, user_id: ObjectId) -> Result<Vec<ApiToken>, ApiError>;
+    async fn get_token_by_hash(&self, hash: &str) -> Result<ApiToken, ApiError>;
+    async fn touch_token(
+        &self,
+        token_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError>;
+    async fn delete_token(&self, token_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoApiTokenRepo {
+    collection: Collection<ApiToken>,
+}
+
+impl MongoApiTokenRepo {
+    pub fn new(collection: Collection<ApiToken>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepo for MongoApiTokenRepo {
+    async fn create_token(&self, token: &ApiToken) -> Result<(), ApiError> {
+        match self.collection.insert_one(token).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_tokens(&self, user_id: ObjectId) -> Result<Vec<ApiToken>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id})
+            .sort(doc! {"created_at": 1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_token_by_hash(&self, hash: &str) -> Result<ApiToken, ApiError> {
+        match self.collection.find_one(doc! {"hash": hash}).await {
+            Ok(Some(token)) => Ok(token),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn touch_token(
+        &self,
+        token_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": token_id, "user_id": user_id},
+                doc! {"$set": {"last_used_at": timestamp::to_bson(&at)}},
+            )
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_token(&self, token_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_one(doc! {"_id": token_id, "user_id": user_id})
+            .await
+        {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Tokens are keyed by user_id ++ token_id, hashes maps a hash to that key
+*/
+pub struct SledApiTokenRepo {
+    tree: sled::Tree,
+    hashes: sled::Tree,
+}
+
+impl SledApiTokenRepo {
+    pub fn new(tree: sled::Tree, hashes: sled::Tree) -> Self {
+        Self { tree, hashes }
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepo for SledApiTokenRepo {
+    async fn create_token(&self, token: &ApiToken) -> Result<(), ApiError> {
+        let key = sled_store::key(&[token.user_id, token.id]);
+        self.hashes
+            .insert(token.hash.as_bytes(), key.as_slice())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.hashes).await?;
+        sled_store::insert(&self.tree, &key, token).await
+    }
+
+    async fn get_tokens(&self, user_id: ObjectId) -> Result<Vec<ApiToken>, ApiError> {
+        let mut tokens: Vec<ApiToken> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        tokens.sort_by_key(|token| token.created_at);
+        Ok(tokens)
+    }
+
+    async fn get_token_by_hash(&self, hash: &str) -> Result<ApiToken, ApiError> {
+        match self
+            .hashes
+            .get(hash.as_bytes())
+            .map_err(sled_store::sled_error)?
+        {
+            Some(key) => sled_store::get(&self.tree, &key)?.ok_or(ApiError::NotFound),
+            None => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn touch_token(
+        &self,
+        token_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, token_id]),
+            |token: &mut ApiToken| {
+                token.last_used_at = Some(at);
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_token(&self, token_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id, token_id]);
+        let token = sled_store::remove::<ApiToken>(&self.tree, &key)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        self.hashes
+            .remove(token.hash.as_bytes())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.hashes).await
+    }
+}
+
+/*
+* Keeps tokens in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryApiTokenRepo {
+    tokens: RwLock<BTreeMap<ObjectId, ApiToken>>,
+}
+
+impl MemoryApiTokenRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiTokenRepo for MemoryApiTokenRepo {
+    async fn create_token(&self, token: &ApiToken) -> Result<(), ApiError> {
+        self.tokens.write().unwrap().insert(token.id, token.clone());
+        Ok(())
+    }
+
+    async fn get_tokens(&self, user_id: ObjectId) -> Result<Vec<ApiToken>, ApiError> {
+        let mut tokens: Vec<ApiToken> = self
+            .tokens
+            .read()
+            .unwrap()
+            .values()
+            .filter(|token| token.user_id == user_id)
+            .cloned()
+            .collect();
+        tokens.sort_by_key(|token| token.created_at);
+        Ok(tokens)
+    }
+
+    async fn get_token_by_hash(&self, hash: &str) -> Result<ApiToken, ApiError> {
+        self.tokens
+            .read()
+            .unwrap()
+            .values()
+            .find(|token| token.hash == hash)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn touch_token(
+        &self,
+        token_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        if let Some(token) = self
+            .tokens
+            .write()
+            .unwrap()
+            .get_mut(&token_id)
+            .filter(|token| token.user_id == user_id)
+        {
+            token.last_used_at = Some(at);
+        }
+        Ok(())
+    }
+
+    async fn delete_token(&self, token_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        let mut tokens = self.tokens.write().unwrap();
+        match tokens.get(&token_id) {
+            Some(token) if token.user_id == user_id => {
+                tokens.remove(&token_id);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+}
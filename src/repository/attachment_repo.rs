@@ -0,0 +1,359 @@
+use crate::{error::ApiError, models::attachment::Attachment, repository::sled_store};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    Collection,
+};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+/*
+* Only the metadata, the bytes are kept by an AttachmentStorage.
+* `user_id` is always the owner of the note.
+*/
+#[async_trait]
+pub trait AttachmentRepo: Send + Sync {
+    async fn create_attachment(&self, attachment: &Attachment) -> Result<(), ApiError>;
+    async fn get_attachments(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError>;
+    async fn get_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError>;
+    async fn delete_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError>;
+    /// Drops every attachment of a note and returns them so the files can go too
+    async fn delete_attachments_of_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError>;
+    /// Bytes used by all attachments on notes the user owns
+    async fn storage_used(&self, user_id: ObjectId) -> Result<u64, ApiError>;
+}
+
+pub struct MongoAttachmentRepo {
+    collection: Collection<Attachment>,
+}
+
+impl MongoAttachmentRepo {
+    pub fn new(collection: Collection<Attachment>) -> Self {
+        Self { collection }
+    }
+
+    async fn find_attachments(&self, filter: Document) -> Result<Vec<Attachment>, ApiError> {
+        match self
+            .collection
+            .find(filter)
+            .sort(doc! {"created_at": 1, "_id": 1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AttachmentRepo for MongoAttachmentRepo {
+    async fn create_attachment(&self, attachment: &Attachment) -> Result<(), ApiError> {
+        match self.collection.insert_one(attachment).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_attachments(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        self.find_attachments(doc! {"note_id": note_id, "user_id": user_id})
+            .await
+    }
+
+    async fn get_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": attachment_id, "note_id": note_id, "user_id": user_id})
+            .await
+        {
+            Ok(Some(attachment)) => Ok(attachment),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        match self
+            .collection
+            .find_one_and_delete(
+                doc! {"_id": attachment_id, "note_id": note_id, "user_id": user_id},
+            )
+            .await
+        {
+            Ok(Some(attachment)) => Ok(attachment),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_attachments_of_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        let filter = doc! {"note_id": note_id, "user_id": user_id};
+        let attachments = self.find_attachments(filter.clone()).await?;
+        match self.collection.delete_many(filter).await {
+            Ok(_res) => Ok(attachments),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn storage_used(&self, user_id: ObjectId) -> Result<u64, ApiError> {
+        let pipeline = vec![
+            doc! {"$match": {"user_id": user_id}},
+            doc! {"$group": {"_id": null, "used": {"$sum": "$size"}}},
+        ];
+        let groups: Vec<Document> = match self.collection.aggregate(pipeline).await {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?,
+            Err(err) => {
+                error!("{}", err);
+                return Err(ApiError::InternalError);
+            }
+        };
+        //$sum stays an int32 while it fits
+        Ok(groups
+            .first()
+            .and_then(|group| match group.get("used") {
+                Some(Bson::Int64(used)) => Some(*used as u64),
+                Some(Bson::Int32(used)) => Some(*used as u64),
+                _ => None,
+            })
+            .unwrap_or(0))
+    }
+}
+
+/*
+* Attachments are keyed by user_id ++ note_id ++ attachment_id
+*/
+pub struct SledAttachmentRepo {
+    tree: sled::Tree,
+}
+
+impl SledAttachmentRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl AttachmentRepo for SledAttachmentRepo {
+    async fn create_attachment(&self, attachment: &Attachment) -> Result<(), ApiError> {
+        sled_store::insert(
+            &self.tree,
+            &sled_store::key(&[attachment.user_id, attachment.note_id, attachment.id]),
+            attachment,
+        )
+        .await
+    }
+
+    async fn get_attachments(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        let mut attachments: Vec<Attachment> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id, note_id]))?;
+        attachments.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        Ok(attachments)
+    }
+
+    async fn get_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        sled_store::get(
+            &self.tree,
+            &sled_store::key(&[user_id, note_id, attachment_id]),
+        )?
+        .ok_or(ApiError::NotFound)
+    }
+
+    async fn delete_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        sled_store::remove(
+            &self.tree,
+            &sled_store::key(&[user_id, note_id, attachment_id]),
+        )
+        .await?
+        .ok_or(ApiError::NotFound)
+    }
+
+    async fn delete_attachments_of_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        let attachments = self.get_attachments(note_id, user_id).await?;
+        for attachment in attachments.iter() {
+            self.tree
+                .remove(sled_store::key(&[user_id, note_id, attachment.id]))
+                .map_err(sled_store::sled_error)?;
+        }
+        sled_store::flush(&self.tree).await?;
+        Ok(attachments)
+    }
+
+    async fn storage_used(&self, user_id: ObjectId) -> Result<u64, ApiError> {
+        let attachments: Vec<Attachment> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        Ok(attachments.iter().map(|attachment| attachment.size).sum())
+    }
+}
+
+/*
+* Keeps attachment metadata in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryAttachmentRepo {
+    attachments: RwLock<BTreeMap<ObjectId, Attachment>>,
+}
+
+impl MemoryAttachmentRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AttachmentRepo for MemoryAttachmentRepo {
+    async fn create_attachment(&self, attachment: &Attachment) -> Result<(), ApiError> {
+        self.attachments
+            .write()
+            .unwrap()
+            .insert(attachment.id, attachment.clone());
+        Ok(())
+    }
+
+    async fn get_attachments(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        let mut attachments: Vec<Attachment> = self
+            .attachments
+            .read()
+            .unwrap()
+            .values()
+            .filter(|attachment| attachment.note_id == note_id && attachment.user_id == user_id)
+            .cloned()
+            .collect();
+        attachments.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        Ok(attachments)
+    }
+
+    async fn get_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        match self.attachments.read().unwrap().get(&attachment_id) {
+            Some(attachment) if attachment.note_id == note_id && attachment.user_id == user_id => {
+                Ok(attachment.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_attachment(
+        &self,
+        attachment_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Attachment, ApiError> {
+        let mut attachments = self.attachments.write().unwrap();
+        match attachments.get(&attachment_id) {
+            Some(attachment) if attachment.note_id == note_id && attachment.user_id == user_id => {
+                Ok(attachments.remove(&attachment_id).unwrap())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_attachments_of_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<Attachment>, ApiError> {
+        let mut removed = Vec::new();
+        self.attachments.write().unwrap().retain(|_id, attachment| {
+            let keep = attachment.note_id != note_id || attachment.user_id != user_id;
+            if !keep {
+                removed.push(attachment.clone());
+            }
+            keep
+        });
+        Ok(removed)
+    }
+
+    async fn storage_used(&self, user_id: ObjectId) -> Result<u64, ApiError> {
+        Ok(self
+            .attachments
+            .read()
+            .unwrap()
+            .values()
+            .filter(|attachment| attachment.user_id == user_id)
+            .map(|attachment| attachment.size)
+            .sum())
+    }
+}
@@ -0,0 +1,279 @@
+use crate::error::ApiError;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures::{
+    io::AsyncReadExt as _, io::AsyncWriteExt as _, stream::BoxStream, StreamExt, TryStreamExt,
+};
+use mongodb::{
+    bson::{oid::ObjectId, Bson},
+    error::{ErrorKind, GridFsErrorKind},
+    gridfs::GridFsBucket,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+    sync::RwLock,
+};
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _};
+use tracing::error;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Bytes of an upload as they arrive, an error ends the upload with that error
+pub type UploadStream<'a> = BoxStream<'a, Result<Bytes, ApiError>>;
+
+/*
+* Where the bytes of attachments live, files are stored under the id of their
+* Attachment document
+*/
+#[async_trait]
+pub trait AttachmentStorage: Send + Sync {
+    /// Writes the file chunk by chunk and returns its size, nothing is kept
+    /// when the stream fails
+    async fn put(
+        &self,
+        id: ObjectId,
+        filename: &str,
+        data: UploadStream<'_>,
+    ) -> Result<u64, ApiError>;
+    /// Streams `range` of the file, the caller checks it against the size
+    async fn open(&self, id: ObjectId, range: Range<u64>) -> Result<ByteStream, ApiError>;
+    /// Missing files are not an error, there is nothing left to remove
+    async fn delete(&self, id: ObjectId) -> Result<(), ApiError>;
+}
+
+fn io_error(err: io::Error) -> ApiError {
+    error!("{}", err);
+    ApiError::InternalError
+}
+
+fn mongo_error(err: mongodb::error::Error) -> ApiError {
+    error!("{}", err);
+    ApiError::InternalError
+}
+
+fn is_missing_file(err: &mongodb::error::Error) -> bool {
+    matches!(
+        *err.kind,
+        ErrorKind::GridFs(GridFsErrorKind::FileNotFound { .. })
+    )
+}
+
+/*
+* Keeps files in the fs.files/fs.chunks collections of the mongo database
+*/
+pub struct GridFsStorage {
+    bucket: GridFsBucket,
+}
+
+impl GridFsStorage {
+    pub fn new(bucket: GridFsBucket) -> Self {
+        Self { bucket }
+    }
+}
+
+#[async_trait]
+impl AttachmentStorage for GridFsStorage {
+    async fn put(
+        &self,
+        id: ObjectId,
+        filename: &str,
+        mut data: UploadStream<'_>,
+    ) -> Result<u64, ApiError> {
+        let mut upload = self
+            .bucket
+            .open_upload_stream(filename)
+            .id(Bson::ObjectId(id))
+            .await
+            .map_err(mongo_error)?;
+        let mut size = 0;
+        let written = async {
+            while let Some(chunk) = data.try_next().await? {
+                upload.write_all(&chunk).await.map_err(io_error)?;
+                size += chunk.len() as u64;
+            }
+            Ok(())
+        }
+        .await;
+        match written {
+            Ok(()) => upload.close().await.map_err(io_error).map(|()| size),
+            Err(err) => {
+                //drops the chunks written so far
+                upload.abort().await.map_err(mongo_error)?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn open(&self, id: ObjectId, range: Range<u64>) -> Result<ByteStream, ApiError> {
+        let mut download = match self.bucket.open_download_stream(Bson::ObjectId(id)).await {
+            Ok(download) => download,
+            Err(err) if is_missing_file(&err) => return Err(ApiError::NotFound),
+            Err(err) => return Err(mongo_error(err)),
+        };
+        //gridfs can't seek, the chunks in front of the range are read and dropped
+        futures::io::copy((&mut download).take(range.start), &mut futures::io::sink())
+            .await
+            .map_err(io_error)?;
+        let download = download.take(range.end - range.start);
+        Ok(
+            futures::stream::try_unfold(download, |mut download| async move {
+                let mut chunk = vec![0; CHUNK_SIZE as usize];
+                match download.read(&mut chunk).await? {
+                    0 => Ok(None),
+                    read => {
+                        chunk.truncate(read);
+                        Ok(Some((Bytes::from(chunk), download)))
+                    }
+                }
+            })
+            .boxed(),
+        )
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), ApiError> {
+        match self.bucket.delete(Bson::ObjectId(id)).await {
+            Ok(()) => Ok(()),
+            Err(err) if is_missing_file(&err) => Ok(()),
+            Err(err) => Err(mongo_error(err)),
+        }
+    }
+}
+
+/*
+* One file per attachment in a directory, named after its id
+*/
+pub struct LocalFsStorage {
+    root: PathBuf,
+}
+
+impl LocalFsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, id: ObjectId) -> PathBuf {
+        self.root.join(id.to_hex())
+    }
+}
+
+#[async_trait]
+impl AttachmentStorage for LocalFsStorage {
+    async fn put(
+        &self,
+        id: ObjectId,
+        _filename: &str,
+        mut data: UploadStream<'_>,
+    ) -> Result<u64, ApiError> {
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err(io_error)?;
+        //written next to the target first so a crash never leaves half a file
+        let partial = self.root.join(format!("{}.part", id.to_hex()));
+        let mut size = 0;
+        let written = async {
+            let mut file = tokio::fs::File::create(&partial).await.map_err(io_error)?;
+            while let Some(chunk) = data.try_next().await? {
+                file.write_all(&chunk).await.map_err(io_error)?;
+                size += chunk.len() as u64;
+            }
+            file.flush().await.map_err(io_error)
+        }
+        .await;
+        if let Err(err) = written {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(err);
+        }
+        tokio::fs::rename(&partial, self.path(id))
+            .await
+            .map_err(io_error)?;
+        Ok(size)
+    }
+
+    async fn open(&self, id: ObjectId, range: Range<u64>) -> Result<ByteStream, ApiError> {
+        let mut file = match tokio::fs::File::open(self.path(id)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(ApiError::NotFound),
+            Err(err) => return Err(io_error(err)),
+        };
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(io_error)?;
+        let file = file.take(range.end - range.start);
+        Ok(futures::stream::try_unfold(file, |mut file| async move {
+            let mut chunk = vec![0; CHUNK_SIZE as usize];
+            match file.read(&mut chunk).await? {
+                0 => Ok(None),
+                read => {
+                    chunk.truncate(read);
+                    Ok(Some((Bytes::from(chunk), file)))
+                }
+            }
+        })
+        .boxed())
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), ApiError> {
+        match tokio::fs::remove_file(self.path(id)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+}
+
+/*
+* Keeps files in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: RwLock<BTreeMap<ObjectId, Bytes>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AttachmentStorage for MemoryStorage {
+    async fn put(
+        &self,
+        id: ObjectId,
+        _filename: &str,
+        mut data: UploadStream<'_>,
+    ) -> Result<u64, ApiError> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = data.try_next().await? {
+            chunks.push(chunk);
+            //lets other requests run in between like a real write would
+            tokio::task::yield_now().await;
+        }
+        let data = Bytes::from(chunks.concat());
+        let size = data.len() as u64;
+        self.files.write().unwrap().insert(id, data);
+        Ok(size)
+    }
+
+    async fn open(&self, id: ObjectId, range: Range<u64>) -> Result<ByteStream, ApiError> {
+        let data = self
+            .files
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(ApiError::NotFound)?;
+        let chunk = data.slice(range.start as usize..range.end as usize);
+        Ok(futures::stream::once(async move { Ok(chunk) }).boxed())
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), ApiError> {
+        self.files.write().unwrap().remove(&id);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{error::ApiError, models::live_doc::LiveDoc, repository::sled_store};
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
+use std::{collections::HashMap, sync::RwLock};
+use tracing::error;
+
+/*
+* `user_id` is always the owner of the note
+*/
+#[async_trait]
+pub trait LiveDocRepo: Send + Sync {
+    async fn get_live_doc(&self, note_id: ObjectId, user_id: ObjectId)
+        -> Result<LiveDoc, ApiError>;
+    /// Replaces the document saved for the note before
+    async fn save_live_doc(&self, live_doc: &LiveDoc) -> Result<(), ApiError>;
+    /// Fine when the note never had one
+    async fn delete_live_doc(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoLiveDocRepo {
+    collection: Collection<LiveDoc>,
+}
+
+impl MongoLiveDocRepo {
+    pub fn new(collection: Collection<LiveDoc>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl LiveDocRepo for MongoLiveDocRepo {
+    async fn get_live_doc(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<LiveDoc, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": note_id, "user_id": user_id})
+            .await
+        {
+            Ok(Some(live_doc)) => Ok(live_doc),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn save_live_doc(&self, live_doc: &LiveDoc) -> Result<(), ApiError> {
+        match self
+            .collection
+            .replace_one(doc! {"_id": live_doc.note_id}, live_doc)
+            .upsert(true)
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_live_doc(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_one(doc! {"_id": note_id, "user_id": user_id})
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Keyed by user_id + note_id
+*/
+pub struct SledLiveDocRepo {
+    tree: sled::Tree,
+}
+
+impl SledLiveDocRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl LiveDocRepo for SledLiveDocRepo {
+    async fn get_live_doc(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<LiveDoc, ApiError> {
+        sled_store::get(&self.tree, &sled_store::key(&[user_id, note_id]))?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn save_live_doc(&self, live_doc: &LiveDoc) -> Result<(), ApiError> {
+        let key = sled_store::key(&[live_doc.user_id, live_doc.note_id]);
+        sled_store::insert(&self.tree, &key, live_doc).await
+    }
+
+    async fn delete_live_doc(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        sled_store::remove::<LiveDoc>(&self.tree, &sled_store::key(&[user_id, note_id])).await?;
+        Ok(())
+    }
+}
+
+/*
+* Keeps live documents in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryLiveDocRepo {
+    live_docs: RwLock<HashMap<ObjectId, LiveDoc>>,
+}
+
+impl MemoryLiveDocRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LiveDocRepo for MemoryLiveDocRepo {
+    async fn get_live_doc(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<LiveDoc, ApiError> {
+        self.live_docs
+            .read()
+            .unwrap()
+            .get(&note_id)
+            .filter(|live_doc| live_doc.user_id == user_id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn save_live_doc(&self, live_doc: &LiveDoc) -> Result<(), ApiError> {
+        self.live_docs
+            .write()
+            .unwrap()
+            .insert(live_doc.note_id, live_doc.clone());
+        Ok(())
+    }
+
+    async fn delete_live_doc(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        let mut live_docs = self.live_docs.write().unwrap();
+        if live_docs
+            .get(&note_id)
+            .is_some_and(|live_doc| live_doc.user_id == user_id)
+        {
+            live_docs.remove(&note_id);
+        }
+        Ok(())
+    }
+}
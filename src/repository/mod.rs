@@ -1,3 +1,43 @@
+pub(crate) mod api_token_repo;
+pub(crate) mod attachment_repo;
+pub(crate) mod attachment_storage;
+pub(crate) mod live_doc_repo;
 pub(crate) mod note_repo;
+pub(crate) mod notebook_repo;
+pub(crate) mod pagination;
+pub(crate) mod refresh_token_repo;
+pub(crate) mod revision_repo;
+pub(crate) mod session_repo;
+pub(crate) mod share_link_repo;
+pub(crate) mod sled_store;
 pub(crate) mod todo_repo;
+pub(crate) mod tombstone_repo;
+pub(crate) mod two_factor_repo;
 pub(crate) mod user_repo;
+
+use crate::models::timestamp;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+
+/// Matches a stored `version`, documents written before versions existed
+/// have none and count as version 0
+pub fn mongo_version(version: u64) -> Bson {
+    match version {
+        0 => Bson::Document(doc! {"$in": [0_i64, Bson::Null]}),
+        version => Bson::Int64(version as i64),
+    }
+}
+
+/// Mongo side of `tombstone::changed_since` for the documents of a user
+pub fn mongo_changed_since(user_id: ObjectId, since: Option<DateTime<Utc>>) -> Document {
+    match since {
+        Some(since) => {
+            let since = timestamp::to_bson(&since);
+            doc! {"user_id": user_id, "$or": [
+                {"updated_at": {"$gte": since}},
+                {"deleted_at": {"$gte": since}}
+            ]}
+        }
+        None => doc! {"user_id": user_id, "deleted_at": null},
+    }
+}
@@ -1,10 +1,28 @@
 use crate::{
-    error::ApiError, models::note::Note, repository::todo_repo::TodoRepo,
+    error::ApiError,
+    models::{
+        note::{replace_tags, title_key, Note, NoteRole, NoteShare, WikiLink},
+        timestamp,
+        tombstone::changed_since,
+    },
+    repository::{
+        mongo_changed_since, mongo_version,
+        pagination::{self, ListOptions, Page},
+        sled_store,
+        todo_repo::TodoRepo,
+    },
     routes::notes::AllNotesResponse,
 };
 use async_trait::async_trait;
-use futures::StreamExt;
-use mongodb::{bson::doc, bson::oid::ObjectId, options::*, Collection};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::*,
+    Collection,
+};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use std::{collections::BTreeMap, sync::RwLock};
 use tracing::error;
 
 #[async_trait]
@@ -16,7 +34,34 @@ pub trait NoteRepo: Send + Sync {
         content: &str,
         tags: Vec<String>,
     ) -> Result<Note, ApiError>;
-    async fn delete_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError>;
+    /// Moves the note to the trash, every other call except the trash ones ignores it.
+    /// With `version` set only while the note is still at it, otherwise fails with
+    /// PreconditionFailed carrying the current one.
+    async fn delete_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<Note, ApiError>;
+    /// Takes the note out of the trash, counts as a change for updated_at and version
+    async fn restore_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError>;
+    /// Removes a note from the trash for good
+    async fn purge_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError>;
+    async fn get_trashed_notes(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError>;
+    /// Own notes changed or trashed from `since` on, every live one without it
+    async fn get_notes_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Note>, ApiError>;
+    /// Notes of every user trashed before the given time
+    async fn get_notes_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<Note>, ApiError>;
+    /// Removes every note of the user, trashed ones included, and the user
+    /// from the notes of others shared with them. Returns the removed notes.
+    async fn purge_notes_of_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError>;
+    /// `user_id` is the owner or an editor the note is shared with. Only
+    /// applied while the note is still at `version`, otherwise fails with
+    /// PreconditionFailed carrying the current one.
     async fn update_note(
         &self,
         user_id: ObjectId,
@@ -24,15 +69,85 @@ pub trait NoteRepo: Send + Sync {
         title: &str,
         content: &str,
         tags: Vec<String>,
-    ) -> Result<(), ApiError>;
+        version: u64,
+    ) -> Result<Note, ApiError>;
+    /// Finds notes owned by the user or shared with them
     async fn get_note_by_id(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError>;
+    /// Own and shared notes, `notebook_ids` keeps the ones in these notebooks
     async fn get_all_notes_from_user(
         &self,
         user_id: ObjectId,
-    ) -> Result<Vec<AllNotesResponse>, ApiError>;
-    async fn pin_todo_list<T: TodoRepo>(
+        tags: &[String],
+        notebook_ids: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<AllNotesResponse>, ApiError>;
+    async fn get_notes_from_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError>;
+    /// Owner only, `None` takes the note out of its notebook
+    async fn move_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        notebook_id: Option<ObjectId>,
+    ) -> Result<(), ApiError>;
+    /// Moves every note of the user in one of `from`, trashed ones included
+    async fn move_notes(
+        &self,
+        user_id: ObjectId,
+        from: &[ObjectId],
+        to: Option<ObjectId>,
+    ) -> Result<(), ApiError>;
+    /// Moves the live notes in these notebooks to the trash, returns their ids
+    async fn trash_notes_in(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, ApiError>;
+    /// Replaces the `from` tags by `to` (removes them for `None`) on every note
+    /// of the user, trashed ones included, in one atomic step. Returns the
+    /// changed notes.
+    async fn rewrite_tags(
+        &self,
+        user_id: ObjectId,
+        from: &[String],
+        to: Option<&str>,
+    ) -> Result<Vec<Note>, ApiError>;
+    /// Stores the references parsed out of the content, leaves updated_at alone
+    async fn set_links(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        links: Vec<WikiLink>,
+    ) -> Result<(), ApiError>;
+    /// Live notes of the user referencing the note by id or by `title_key`
+    async fn get_linking_notes(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title_key: &str,
+    ) -> Result<Vec<Note>, ApiError>;
+    /// Live notes of the user whose `title_key` is one of `title_keys`
+    async fn get_notes_by_titles(
+        &self,
+        user_id: ObjectId,
+        title_keys: &[String],
+    ) -> Result<Vec<Note>, ApiError>;
+    /// Gives `user_id` access to a note of `owner_id`, replaces an earlier role
+    async fn share_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+        role: NoteRole,
+    ) -> Result<Note, ApiError>;
+    async fn unshare_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError>;
+    async fn pin_todo_list(
         &self,
-        todo_repo: &T,
+        todo_repo: &dyn TodoRepo,
         todo_list_id: ObjectId,
         note_id: ObjectId,
         user_id: ObjectId,
@@ -48,10 +163,46 @@ pub struct MongoNoteRepo {
     collection: Collection<Note>,
 }
 
+//notes the user owns or that are shared with them
+fn mongo_access(user_id: ObjectId) -> Document {
+    doc! {"$or": [{"user_id": user_id}, {"shared_with.user_id": user_id}]}
+}
+
+fn mongo_edit_access(user_id: ObjectId) -> Document {
+    doc! {"$or": [
+        {"user_id": user_id},
+        {"shared_with": {"$elemMatch": {"user_id": user_id, "role": "editor"}}}
+    ]}
+}
+
 impl MongoNoteRepo {
     pub fn new(collection: Collection<Note>) -> Self {
         Self { collection }
     }
+
+    async fn find_notes(&self, filter: Document) -> Result<Vec<Note>, ApiError> {
+        match self.collection.find(filter).await {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
 #[async_trait]
 impl NoteRepo for MongoNoteRepo {
@@ -62,6 +213,7 @@ impl NoteRepo for MongoNoteRepo {
         content: &str,
         tags: Vec<String>,
     ) -> Result<Note, ApiError> {
+        let now = timestamp::now();
         let new_note = Note {
             id: ObjectId::new(),
             user_id,
@@ -69,6 +221,14 @@ impl NoteRepo for MongoNoteRepo {
             content: content.to_string(),
             tags,
             todo_lists: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            shared_with: vec![],
+            notebook_id: None,
+            links: vec![],
+            version: 1,
         };
         match self.collection.insert_one(&new_note).await {
             Ok(res) => {
@@ -85,19 +245,140 @@ impl NoteRepo for MongoNoteRepo {
         }
     }
 
-    async fn delete_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
-        let filter = doc! {
+    async fn delete_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<Note, ApiError> {
+        let mut filter = doc! {
             "_id": note_id,
-            "client_id": user_id
+            "user_id": user_id,
+            "deleted_at": null
         };
-        match self.collection.find_one_and_delete(filter).await {
-            Ok(result) => {
-                if let Some(_result) = result {
-                    //println!("{:?}", result);
-                    return Ok(());
+        if let Some(version) = version {
+            filter.insert("version", mongo_version(version));
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        match self
+            .collection
+            .find_one_and_update(
+                filter,
+                doc! {"$set": {"deleted_at": timestamp::to_bson(&timestamp::now())}},
+            )
+            .with_options(options)
+            .await
+        {
+            Ok(Some(note)) => Ok(note),
+            //either gone or changed since `version`
+            Ok(None) => match self.get_note_by_id(note_id, user_id).await {
+                Ok(note)
+                    if note.user_id == user_id
+                        && version.is_some_and(|version| version != note.version) =>
+                {
+                    Err(ApiError::PreconditionFailed(note.version))
                 }
-                Err(ApiError::NotFound)
+                Ok(_) | Err(ApiError::NotFound) => Err(ApiError::NotFound),
+                Err(err) => Err(err),
+            },
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn restore_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        match self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": note_id, "user_id": user_id, "deleted_at": {"$ne": null}},
+                doc! {
+                    "$set": {
+                        "deleted_at": null,
+                        "updated_at": timestamp::to_bson(&timestamp::now())
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+            )
+            .with_options(options)
+            .await
+        {
+            Ok(Some(note)) => Ok(note),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn purge_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_one(doc! {"_id": note_id, "user_id": user_id, "deleted_at": {"$ne": null}})
+            .await
+        {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_trashed_notes(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id, "deleted_at": {"$ne": null}})
+            .sort(doc! {"deleted_at": -1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_notes_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Note>, ApiError> {
+        match self
+            .collection
+            .find(mongo_changed_since(user_id, since))
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
             }
+        }
+    }
+
+    async fn get_notes_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<Note>, ApiError> {
+        let filter = doc! {"deleted_at": {"$ne": null, "$lt": timestamp::to_bson(&before)}};
+        match self.collection.find(filter).await {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
             Err(err) => {
                 error!("{}", err);
                 Err(ApiError::InternalError)
@@ -105,6 +386,28 @@ impl NoteRepo for MongoNoteRepo {
         }
     }
 
+    async fn purge_notes_of_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let notes = self.find_notes(doc! {"user_id": user_id}).await?;
+        self.collection
+            .delete_many(doc! {"user_id": user_id})
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+        self.collection
+            .update_many(
+                doc! {"shared_with.user_id": user_id},
+                doc! {"$pull": {"shared_with": {"user_id": user_id}}},
+            )
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+        Ok(notes)
+    }
+
     async fn update_note(
         &self,
         user_id: ObjectId,
@@ -112,8 +415,11 @@ impl NoteRepo for MongoNoteRepo {
         title: &str,
         content: &str,
         tags: Vec<String>,
-    ) -> Result<(), ApiError> {
-        let filter = doc! {"_id": note_id, "client_id": user_id, };
+        version: u64,
+    ) -> Result<Note, ApiError> {
+        let mut filter =
+            doc! {"_id": note_id, "deleted_at": null, "version": mongo_version(version)};
+        filter.extend(mongo_edit_access(user_id));
         let options = FindOneAndUpdateOptions::builder()
             .return_document(ReturnDocument::After)
             .build();
@@ -124,15 +430,25 @@ impl NoteRepo for MongoNoteRepo {
                 doc! {"$set": {
                         "title": title,
                         "content": content,
-                        "tags": tags
-                    }
+                        "tags": tags,
+                        "updated_at": timestamp::to_bson(&timestamp::now()),
+                        "updated_by": user_id
+                    },
+                    "$inc": {"version": 1_i64}
                 },
             )
             .with_options(options)
             .await
         {
-            Ok(Some(_data)) => Ok(()),
-            Ok(None) => Err(ApiError::NothingChanged),
+            Ok(Some(note)) => Ok(note),
+            //either gone or changed since `version`
+            Ok(None) => match self.get_note_by_id(note_id, user_id).await {
+                Ok(note) if note.can_edit(user_id) && note.version != version => {
+                    Err(ApiError::PreconditionFailed(note.version))
+                }
+                Ok(_) | Err(ApiError::NotFound) => Err(ApiError::NothingChanged),
+                Err(err) => Err(err),
+            },
             Err(err) => {
                 error!("{}", err);
                 Err(ApiError::InternalError)
@@ -141,7 +457,8 @@ impl NoteRepo for MongoNoteRepo {
     }
 
     async fn get_note_by_id(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
-        let filter = doc! {"user_id": user_id, "_id": note_id};
+        let mut filter = doc! {"_id": note_id, "deleted_at": null};
+        filter.extend(mongo_access(user_id));
         match self.collection.find_one(filter).await {
             Ok(Some(note)) => Ok(note),
             Ok(None) => Err(ApiError::NotFound),
@@ -154,25 +471,40 @@ impl NoteRepo for MongoNoteRepo {
     async fn get_all_notes_from_user(
         &self,
         user_id: ObjectId,
-    ) -> Result<Vec<AllNotesResponse>, ApiError> {
-        let filter = doc! {"user_id": user_id};
-        match self.collection.find(filter).await {
+        tags: &[String],
+        notebook_ids: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<AllNotesResponse>, ApiError> {
+        let mut filter = doc! {"deleted_at": null};
+        filter.extend(mongo_access(user_id));
+        if !tags.is_empty() {
+            filter.insert("tags", doc! {"$all": tags});
+        }
+        if let Some(notebook_ids) = notebook_ids {
+            filter.insert("notebook_id", doc! {"$in": notebook_ids});
+        }
+        if let Some(after) = options.mongo_after() {
+            filter.insert("$and", vec![after]);
+        }
+        match self
+            .collection
+            .find(filter)
+            .sort(options.mongo_sort())
+            .limit(options.mongo_limit())
+            .await
+        {
             Ok(cursor) => {
                 let notes: Vec<AllNotesResponse> = cursor
                     .filter_map(|doc| async {
                         match doc {
-                            Ok(info) => Some(AllNotesResponse {
-                                title: info.title,
-                                id: info.id,
-                                tags: info.tags,
-                            }),
+                            Ok(info) => Some(AllNotesResponse::new(info, user_id)),
                             //?
                             Err(_err) => None,
                         }
                     })
                     .collect()
                     .await;
-                Ok(notes)
+                Ok(pagination::paginate(notes, options))
             }
             Err(err) => {
                 error!("{}", err);
@@ -180,54 +512,1292 @@ impl NoteRepo for MongoNoteRepo {
             }
         }
     }
-    async fn pin_todo_list<T: TodoRepo>(
+    async fn get_notes_from_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id, "deleted_at": null})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+    async fn move_note(
         &self,
-        todo_repo: &T,
-        todo_list_id: ObjectId,
         note_id: ObjectId,
         user_id: ObjectId,
+        notebook_id: Option<ObjectId>,
     ) -> Result<(), ApiError> {
-        //check if todo_list exist
-        let todo_list = todo_repo.get_todo_list(todo_list_id, user_id).await?;
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": note_id, "user_id": user_id, "deleted_at": null},
+                doc! {
+                    "$set": {
+                        "notebook_id": notebook_id,
+                        "updated_at": timestamp::to_bson(&timestamp::now()),
+                        "updated_by": user_id
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+            )
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
 
-        //add todo_list to todo_list vec
+    async fn move_notes(
+        &self,
+        user_id: ObjectId,
+        from: &[ObjectId],
+        to: Option<ObjectId>,
+    ) -> Result<(), ApiError> {
         self.collection
-            .update_one(
-                doc! {"_id": note_id, "user_id": user_id},
-                doc! { "$push" : {
-                    "todo_lists": todo_list.id
-                }},
+            .update_many(
+                doc! {"user_id": user_id, "notebook_id": {"$in": from}},
+                doc! {
+                    "$set": {
+                        "notebook_id": to,
+                        "updated_at": timestamp::to_bson(&timestamp::now()),
+                        "updated_by": user_id
+                    },
+                    "$inc": {"version": 1_i64}
+                },
             )
             .await
             .map_err(|err| {
                 error!("{}", err);
                 ApiError::InternalError
             })?;
-
         Ok(())
     }
-    async fn unpin_todo_list(
+
+    async fn trash_notes_in(
         &self,
-        todo_list_id: ObjectId,
-        note_id: ObjectId,
         user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "notebook_id": {"$in": notebook_ids},
+            "deleted_at": null
+        };
+        let ids: Vec<ObjectId> = self
+            .find_notes(filter.clone())
+            .await?
+            .into_iter()
+            .map(|note| note.id)
+            .collect();
+        self.collection
+            .update_many(
+                filter,
+                doc! {"$set": {"deleted_at": timestamp::to_bson(&timestamp::now())}},
+            )
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+        Ok(ids)
+    }
+
+    async fn rewrite_tags(
+        &self,
+        user_id: ObjectId,
+        from: &[String],
+        to: Option<&str>,
+    ) -> Result<Vec<Note>, ApiError> {
+        let filter = doc! {"user_id": user_id, "tags": {"$in": from}};
+        let ids: Vec<ObjectId> = self
+            .find_notes(filter.clone())
+            .await?
+            .into_iter()
+            .map(|note| note.id)
+            .collect();
+        //same as note::replace_tags: map, then drop duplicates keeping the order
+        let tags = match to {
+            Some(to) => doc! {"$reduce": {
+                "input": {"$map": {
+                    "input": "$tags",
+                    "in": {"$cond": [{"$in": ["$$this", from]}, to, "$$this"]}
+                }},
+                "initialValue": [],
+                "in": {"$cond": [
+                    {"$in": ["$$this", "$$value"]},
+                    "$$value",
+                    {"$concatArrays": ["$$value", ["$$this"]]}
+                ]}
+            }},
+            None => doc! {"$filter": {
+                "input": "$tags",
+                "cond": {"$not": [{"$in": ["$$this", from]}]}
+            }},
+        };
+        //a single update_many, every note is rewritten in one step so
+        //repeating a failed call gives the same result
+        self.collection
+            .update_many(
+                filter,
+                vec![doc! {"$set": {
+                    "tags": tags,
+                    "updated_at": timestamp::to_bson(&timestamp::now()),
+                    "updated_by": user_id,
+                    "version": {"$add": [{"$ifNull": ["$version", 0_i64]}, 1_i64]}
+                }}],
+            )
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+        self.find_notes(doc! {"_id": {"$in": ids}}).await
+    }
+
+    async fn set_links(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        links: Vec<WikiLink>,
     ) -> Result<(), ApiError> {
-        //check if todo_list is in todo_list vec
-        //delete todo_list from todo_list vec
+        let links = bson::to_bson(&links).map_err(|err| {
+            error!("{}", err);
+            ApiError::InternalError
+        })?;
         match self
             .collection
-            .find_one_and_update(
-                doc! {"_id": note_id,"user_id": user_id, "todo_lists": todo_list_id},
-                doc! {"$pull": {"todo_lists": todo_list_id}},
+            .update_one(
+                doc! {"_id": note_id, "user_id": owner_id},
+                doc! {"$set": {"links": links}},
             )
             .await
         {
-            Ok(Some(_)) => Ok(()),
-            Ok(None) => Err(ApiError::NotFound),
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
             Err(err) => {
                 error!("{}", err);
                 Err(ApiError::InternalError)
             }
         }
     }
+
+    async fn get_linking_notes(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title_key: &str,
+    ) -> Result<Vec<Note>, ApiError> {
+        self.find_notes(doc! {
+            "user_id": user_id,
+            "deleted_at": null,
+            "$or": [{"links.id": note_id}, {"links.key": title_key}]
+        })
+        .await
+    }
+
+    async fn get_notes_by_titles(
+        &self,
+        user_id: ObjectId,
+        title_keys: &[String],
+    ) -> Result<Vec<Note>, ApiError> {
+        if title_keys.is_empty() {
+            return Ok(vec![]);
+        }
+        //case insensitive regex finds the candidates, title_key decides
+        let titles: Vec<Document> = title_keys
+            .iter()
+            .map(|key| {
+                doc! {"title": {
+                    "$regex": format!("^\\s*{}\\s*$", regex_escape(key)),
+                    "$options": "i"
+                }}
+            })
+            .collect();
+        let mut notes = self
+            .find_notes(doc! {"user_id": user_id, "deleted_at": null, "$or": titles})
+            .await?;
+        notes.retain(|note| title_keys.contains(&title_key(&note.title)));
+        Ok(notes)
+    }
+
+    async fn share_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+        role: NoteRole,
+    ) -> Result<Note, ApiError> {
+        let role = bson::to_bson(&role).map_err(|err| {
+            error!("{}", err);
+            ApiError::InternalError
+        })?;
+        //drop an earlier share of the user and append the new one in one update
+        let update = vec![doc! {"$set": {"shared_with": {"$concatArrays": [
+            {"$filter": {
+                "input": {"$ifNull": ["$shared_with", []]},
+                "cond": {"$ne": ["$$this.user_id", user_id]}
+            }},
+            [{"user_id": user_id, "role": role}]
+        ]}}}];
+        match self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": note_id, "user_id": owner_id, "deleted_at": null},
+                update,
+            )
+            .return_document(ReturnDocument::After)
+            .await
+        {
+            Ok(Some(note)) => Ok(note),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn unshare_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {
+                    "_id": note_id,
+                    "user_id": owner_id,
+                    "shared_with.user_id": user_id,
+                    "deleted_at": null
+                },
+                doc! {"$pull": {"shared_with": {"user_id": user_id}}},
+            )
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn pin_todo_list(
+        &self,
+        todo_repo: &dyn TodoRepo,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        //check if todo_list exist
+        let todo_list = todo_repo.get_todo_list(todo_list_id, user_id).await?;
+
+        //add todo_list to todo_list vec
+        self.collection
+            .update_one(
+                doc! {"_id": note_id, "user_id": user_id, "deleted_at": null},
+                doc! {
+                    "$push" : {"todo_lists": todo_list.id},
+                    "$set": {
+                        "updated_at": timestamp::to_bson(&timestamp::now()),
+                        "updated_by": user_id
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+            )
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+
+        Ok(())
+    }
+    async fn unpin_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        //check if todo_list is in todo_list vec
+        //delete todo_list from todo_list vec
+        match self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": note_id,"user_id": user_id, "todo_lists": todo_list_id, "deleted_at": null},
+                doc! {
+                    "$pull": {"todo_lists": todo_list_id},
+                    "$set": {
+                        "updated_at": timestamp::to_bson(&timestamp::now()),
+                        "updated_by": user_id
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+            )
+            .await
+        {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+fn in_notebooks(note: &Note, notebook_ids: Option<&[ObjectId]>) -> bool {
+    notebook_ids.is_none_or(|ids| note.notebook_id.is_some_and(|id| ids.contains(&id)))
+}
+
+fn links_to(note: &Note, note_id: ObjectId, title_key: &str) -> bool {
+    note.links
+        .iter()
+        .any(|link| link.id == Some(note_id) || link.key.as_deref() == Some(title_key))
+}
+
+/*
+* Notes are keyed by owner, shares keeps user_id ++ note_id -> owner id
+* for every user a note is shared with so their notes can be found too
+*/
+pub struct SledNoteRepo {
+    tree: sled::Tree,
+    shares: sled::Tree,
+}
+
+impl SledNoteRepo {
+    pub fn new(tree: sled::Tree, shares: sled::Tree) -> Self {
+        Self { tree, shares }
+    }
+
+    //owner of a note the user has access to
+    fn owner_of(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Option<ObjectId>, ApiError> {
+        let key = sled_store::key(&[user_id, note_id]);
+        if self
+            .tree
+            .contains_key(&key)
+            .map_err(sled_store::sled_error)?
+        {
+            return Ok(Some(user_id));
+        }
+        Ok(self
+            .shares
+            .get(&key)
+            .map_err(sled_store::sled_error)?
+            .and_then(|owner| <[u8; 12]>::try_from(owner.as_ref()).ok())
+            .map(ObjectId::from_bytes))
+    }
+
+    //drops the index entries of a purged note
+    async fn remove_shares(&self, note: &Note) -> Result<(), ApiError> {
+        for share in &note.shared_with {
+            self.shares
+                .remove(sled_store::key(&[share.user_id, note.id]))
+                .map_err(sled_store::sled_error)?;
+        }
+        sled_store::flush(&self.shares).await
+    }
+
+    fn shared_notes(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let mut notes = vec![];
+        for entry in self.shares.scan_prefix(sled_store::key(&[user_id])) {
+            let (key, owner) = entry.map_err(sled_store::sled_error)?;
+            let mut note_key = owner.to_vec();
+            note_key.extend_from_slice(&key[12..]);
+            if let Some(note) = sled_store::get::<Note>(&self.tree, &note_key)? {
+                if note.deleted_at.is_none() && note.role_of(user_id).is_some() {
+                    notes.push(note);
+                }
+            }
+        }
+        Ok(notes)
+    }
+
+    //same as sled_store::update but a note in the trash counts as missing
+    async fn update_live<F>(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        modify: F,
+    ) -> Result<Note, ApiError>
+    where
+        F: Fn(&mut Note) -> Result<(), ApiError>,
+    {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, note_id]),
+            |note: &mut Note| {
+                if note.deleted_at.is_some() {
+                    return Err(ApiError::NotFound);
+                }
+                modify(note)
+            },
+        )
+        .await
+    }
+
+    fn user_notes(&self, user_id: ObjectId, trashed: bool) -> Result<Vec<Note>, ApiError> {
+        let notes: Vec<Note> = sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        Ok(notes
+            .into_iter()
+            .filter(|note| note.deleted_at.is_some() == trashed)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl NoteRepo for SledNoteRepo {
+    async fn create_note(
+        &self,
+        user_id: ObjectId,
+        title: &str,
+        content: &str,
+        tags: Vec<String>,
+    ) -> Result<Note, ApiError> {
+        let now = timestamp::now();
+        let new_note = Note {
+            id: ObjectId::new(),
+            user_id,
+            title: title.to_string(),
+            content: content.to_string(),
+            tags,
+            todo_lists: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            shared_with: vec![],
+            notebook_id: None,
+            links: vec![],
+            version: 1,
+        };
+        sled_store::insert(
+            &self.tree,
+            &sled_store::key(&[user_id, new_note.id]),
+            &new_note,
+        )
+        .await?;
+        Ok(new_note)
+    }
+
+    async fn delete_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<Note, ApiError> {
+        let now = timestamp::now();
+        self.update_live(user_id, note_id, |note| {
+            if version.is_some_and(|version| version != note.version) {
+                return Err(ApiError::PreconditionFailed(note.version));
+            }
+            note.deleted_at = Some(now);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn restore_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, note_id]),
+            |note: &mut Note| {
+                if note.deleted_at.is_none() {
+                    return Err(ApiError::NotFound);
+                }
+                note.deleted_at = None;
+                note.updated_at = timestamp::now();
+                note.version += 1;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    async fn purge_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id, note_id]);
+        match sled_store::get::<Note>(&self.tree, &key)? {
+            Some(note) if note.deleted_at.is_some() => {
+                sled_store::remove::<Note>(&self.tree, &key).await?;
+                self.remove_shares(&note).await
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_trashed_notes(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let mut notes = self.user_notes(user_id, true)?;
+        notes.sort_by_key(|note| std::cmp::Reverse(note.deleted_at));
+        Ok(notes)
+    }
+
+    async fn get_notes_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Note>, ApiError> {
+        let mut notes: Vec<Note> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        notes.retain(|note| changed_since(note.updated_at, note.deleted_at, since));
+        Ok(notes)
+    }
+
+    async fn get_notes_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<Note>, ApiError> {
+        let notes: Vec<Note> = sled_store::scan_prefix(&self.tree, &[])?;
+        Ok(notes
+            .into_iter()
+            .filter(|note| {
+                note.deleted_at
+                    .is_some_and(|deleted_at| deleted_at < before)
+            })
+            .collect())
+    }
+
+    async fn purge_notes_of_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let notes: Vec<Note> = sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        for note in notes.iter() {
+            sled_store::remove::<Note>(&self.tree, &sled_store::key(&[user_id, note.id])).await?;
+            self.remove_shares(note).await?;
+        }
+        let shared: Vec<(sled::IVec, sled::IVec)> = self
+            .shares
+            .scan_prefix(sled_store::key(&[user_id]))
+            .collect::<Result<_, _>>()
+            .map_err(sled_store::sled_error)?;
+        for (key, owner) in shared {
+            let mut note_key = owner.to_vec();
+            note_key.extend_from_slice(&key[12..]);
+            //trashed notes of others lose the share too
+            match sled_store::update(&self.tree, &note_key, |note: &mut Note| {
+                note.shared_with.retain(|share| share.user_id != user_id);
+                Ok(())
+            })
+            .await
+            {
+                Ok(_) | Err(ApiError::NotFound) => {}
+                Err(err) => return Err(err),
+            }
+            self.shares.remove(key).map_err(sled_store::sled_error)?;
+        }
+        sled_store::flush(&self.shares).await?;
+        Ok(notes)
+    }
+
+    async fn update_note(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title: &str,
+        content: &str,
+        tags: Vec<String>,
+        version: u64,
+    ) -> Result<Note, ApiError> {
+        let owner = self
+            .owner_of(note_id, user_id)?
+            .ok_or(ApiError::NothingChanged)?;
+        self.update_live(owner, note_id, |note| {
+            if !note.can_edit(user_id) {
+                return Err(ApiError::NotFound);
+            }
+            if note.version != version {
+                return Err(ApiError::PreconditionFailed(note.version));
+            }
+            note.title = title.to_string();
+            note.content = content.to_string();
+            note.tags = tags.clone();
+            note.updated_at = timestamp::now();
+            note.updated_by = user_id;
+            note.version += 1;
+            Ok(())
+        })
+        .await
+        .map_err(|err| match err {
+            ApiError::NotFound => ApiError::NothingChanged,
+            err => err,
+        })
+    }
+
+    async fn get_note_by_id(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
+        let owner = self.owner_of(note_id, user_id)?.ok_or(ApiError::NotFound)?;
+        sled_store::get::<Note>(&self.tree, &sled_store::key(&[owner, note_id]))?
+            .filter(|note| note.deleted_at.is_none() && note.role_of(user_id).is_some())
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn get_all_notes_from_user(
+        &self,
+        user_id: ObjectId,
+        tags: &[String],
+        notebook_ids: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<AllNotesResponse>, ApiError> {
+        let mut notes = self.user_notes(user_id, false)?;
+        notes.extend(self.shared_notes(user_id)?);
+        let notes = notes
+            .into_iter()
+            .filter(|note| tags.iter().all(|tag| note.tags.contains(tag)))
+            .filter(|note| in_notebooks(note, notebook_ids))
+            .map(|note| AllNotesResponse::new(note, user_id))
+            .collect();
+        Ok(pagination::paginate(notes, options))
+    }
+
+    async fn get_notes_from_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        self.user_notes(user_id, false)
+    }
+
+    async fn move_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        notebook_id: Option<ObjectId>,
+    ) -> Result<(), ApiError> {
+        self.update_live(user_id, note_id, |note| {
+            note.notebook_id = notebook_id;
+            note.updated_at = timestamp::now();
+            note.updated_by = user_id;
+            note.version += 1;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn move_notes(
+        &self,
+        user_id: ObjectId,
+        from: &[ObjectId],
+        to: Option<ObjectId>,
+    ) -> Result<(), ApiError> {
+        let notes: Vec<Note> = sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        for note in notes.iter().filter(|note| in_notebooks(note, Some(from))) {
+            sled_store::update(
+                &self.tree,
+                &sled_store::key(&[user_id, note.id]),
+                |note: &mut Note| {
+                    note.notebook_id = to;
+                    note.updated_at = timestamp::now();
+                    note.updated_by = user_id;
+                    note.version += 1;
+                    Ok(())
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn trash_notes_in(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let mut ids = vec![];
+        for note in self.user_notes(user_id, false)? {
+            if in_notebooks(&note, Some(notebook_ids)) {
+                self.delete_note(note.id, user_id, None).await?;
+                ids.push(note.id);
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn rewrite_tags(
+        &self,
+        user_id: ObjectId,
+        from: &[String],
+        to: Option<&str>,
+    ) -> Result<Vec<Note>, ApiError> {
+        let notes: Vec<Note> = sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        let keys: Vec<Vec<u8>> = notes
+            .iter()
+            .filter(|note| note.tags.iter().any(|tag| from.contains(tag)))
+            .map(|note| sled_store::key(&[user_id, note.id]))
+            .collect();
+        let changed = self
+            .tree
+            .transaction(|tree| {
+                let mut changed = vec![];
+                for key in &keys {
+                    let Some(bytes) = tree.get(key)? else {
+                        continue;
+                    };
+                    let mut note: Note =
+                        sled_store::decode(&bytes).map_err(ConflictableTransactionError::Abort)?;
+                    if !replace_tags(&mut note.tags, from, to) {
+                        continue;
+                    }
+                    note.updated_at = timestamp::now();
+                    note.updated_by = user_id;
+                    note.version += 1;
+                    tree.insert(
+                        key.as_slice(),
+                        sled_store::encode(&note).map_err(ConflictableTransactionError::Abort)?,
+                    )?;
+                    changed.push(note);
+                }
+                Ok(changed)
+            })
+            .map_err(|err| match err {
+                TransactionError::Abort(err) => err,
+                TransactionError::Storage(err) => sled_store::sled_error(err),
+            })?;
+        sled_store::flush(&self.tree).await?;
+        Ok(changed)
+    }
+
+    async fn set_links(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        links: Vec<WikiLink>,
+    ) -> Result<(), ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[owner_id, note_id]),
+            |note: &mut Note| {
+                note.links = links.clone();
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_linking_notes(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title_key: &str,
+    ) -> Result<Vec<Note>, ApiError> {
+        let mut notes = self.user_notes(user_id, false)?;
+        notes.retain(|note| links_to(note, note_id, title_key));
+        Ok(notes)
+    }
+
+    async fn get_notes_by_titles(
+        &self,
+        user_id: ObjectId,
+        title_keys: &[String],
+    ) -> Result<Vec<Note>, ApiError> {
+        let mut notes = self.user_notes(user_id, false)?;
+        notes.retain(|note| title_keys.contains(&title_key(&note.title)));
+        Ok(notes)
+    }
+
+    async fn share_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+        role: NoteRole,
+    ) -> Result<Note, ApiError> {
+        let note = self
+            .update_live(owner_id, note_id, |note| {
+                note.shared_with.retain(|share| share.user_id != user_id);
+                note.shared_with.push(NoteShare { user_id, role });
+                Ok(())
+            })
+            .await?;
+        self.shares
+            .insert(sled_store::key(&[user_id, note_id]), &owner_id.bytes())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.shares).await?;
+        Ok(note)
+    }
+
+    async fn unshare_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        self.update_live(owner_id, note_id, |note| {
+            if !note
+                .shared_with
+                .iter()
+                .any(|share| share.user_id == user_id)
+            {
+                return Err(ApiError::NotFound);
+            }
+            note.shared_with.retain(|share| share.user_id != user_id);
+            Ok(())
+        })
+        .await?;
+        self.shares
+            .remove(sled_store::key(&[user_id, note_id]))
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.shares).await
+    }
+
+    async fn pin_todo_list(
+        &self,
+        todo_repo: &dyn TodoRepo,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        //check if todo_list exist
+        let todo_list = todo_repo.get_todo_list(todo_list_id, user_id).await?;
+
+        self.update_live(user_id, note_id, |note| {
+            note.todo_lists.push(todo_list.id);
+            note.updated_at = timestamp::now();
+            note.updated_by = user_id;
+            note.version += 1;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn unpin_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        self.update_live(user_id, note_id, |note| {
+            if !note.todo_lists.contains(&todo_list_id) {
+                return Err(ApiError::NotFound);
+            }
+            note.todo_lists.retain(|id| *id != todo_list_id);
+            note.updated_at = timestamp::now();
+            note.updated_by = user_id;
+            note.version += 1;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/*
+* Keeps notes in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryNoteRepo {
+    notes: RwLock<BTreeMap<ObjectId, Note>>,
+}
+
+impl MemoryNoteRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NoteRepo for MemoryNoteRepo {
+    async fn create_note(
+        &self,
+        user_id: ObjectId,
+        title: &str,
+        content: &str,
+        tags: Vec<String>,
+    ) -> Result<Note, ApiError> {
+        let now = timestamp::now();
+        let new_note = Note {
+            id: ObjectId::new(),
+            user_id,
+            title: title.to_string(),
+            content: content.to_string(),
+            tags,
+            todo_lists: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            shared_with: vec![],
+            notebook_id: None,
+            links: vec![],
+            version: 1,
+        };
+        self.notes
+            .write()
+            .unwrap()
+            .insert(new_note.id, new_note.clone());
+        Ok(new_note)
+    }
+
+    async fn delete_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<Note, ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == user_id && note.deleted_at.is_none() => {
+                if version.is_some_and(|version| version != note.version) {
+                    return Err(ApiError::PreconditionFailed(note.version));
+                }
+                note.deleted_at = Some(timestamp::now());
+                Ok(note.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn restore_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == user_id && note.deleted_at.is_some() => {
+                note.deleted_at = None;
+                note.updated_at = timestamp::now();
+                note.version += 1;
+                Ok(note.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn purge_note(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        let mut notes = self.notes.write().unwrap();
+        match notes.get(&note_id) {
+            Some(note) if note.user_id == user_id && note.deleted_at.is_some() => {
+                notes.remove(&note_id);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_trashed_notes(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let mut notes: Vec<Note> = self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| note.user_id == user_id && note.deleted_at.is_some())
+            .cloned()
+            .collect();
+        notes.sort_by_key(|note| std::cmp::Reverse(note.deleted_at));
+        Ok(notes)
+    }
+
+    async fn get_notes_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Note>, ApiError> {
+        Ok(self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| {
+                note.user_id == user_id && changed_since(note.updated_at, note.deleted_at, since)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_notes_deleted_before(&self, before: DateTime<Utc>) -> Result<Vec<Note>, ApiError> {
+        Ok(self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| {
+                note.deleted_at
+                    .is_some_and(|deleted_at| deleted_at < before)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_notes_of_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        let mut notes = self.notes.write().unwrap();
+        let (purged, kept): (BTreeMap<ObjectId, Note>, BTreeMap<ObjectId, Note>) =
+            std::mem::take(&mut *notes)
+                .into_iter()
+                .partition(|(_id, note)| note.user_id == user_id);
+        *notes = kept;
+        for note in notes.values_mut() {
+            note.shared_with.retain(|share| share.user_id != user_id);
+        }
+        Ok(purged.into_values().collect())
+    }
+
+    async fn update_note(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title: &str,
+        content: &str,
+        tags: Vec<String>,
+        version: u64,
+    ) -> Result<Note, ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.can_edit(user_id) && note.deleted_at.is_none() => {
+                if note.version != version {
+                    return Err(ApiError::PreconditionFailed(note.version));
+                }
+                note.title = title.to_string();
+                note.content = content.to_string();
+                note.tags = tags;
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+                Ok(note.clone())
+            }
+            _ => Err(ApiError::NothingChanged),
+        }
+    }
+
+    async fn get_note_by_id(&self, note_id: ObjectId, user_id: ObjectId) -> Result<Note, ApiError> {
+        match self.notes.read().unwrap().get(&note_id) {
+            Some(note) if note.role_of(user_id).is_some() && note.deleted_at.is_none() => {
+                Ok(note.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_all_notes_from_user(
+        &self,
+        user_id: ObjectId,
+        tags: &[String],
+        notebook_ids: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<AllNotesResponse>, ApiError> {
+        let notes = self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| note.role_of(user_id).is_some() && note.deleted_at.is_none())
+            .filter(|note| tags.iter().all(|tag| note.tags.contains(tag)))
+            .filter(|note| in_notebooks(note, notebook_ids))
+            .cloned()
+            .map(|note| AllNotesResponse::new(note, user_id))
+            .collect();
+        Ok(pagination::paginate(notes, options))
+    }
+
+    async fn get_notes_from_user(&self, user_id: ObjectId) -> Result<Vec<Note>, ApiError> {
+        Ok(self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| note.user_id == user_id && note.deleted_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn move_note(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        notebook_id: Option<ObjectId>,
+    ) -> Result<(), ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == user_id && note.deleted_at.is_none() => {
+                note.notebook_id = notebook_id;
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn move_notes(
+        &self,
+        user_id: ObjectId,
+        from: &[ObjectId],
+        to: Option<ObjectId>,
+    ) -> Result<(), ApiError> {
+        for note in self.notes.write().unwrap().values_mut() {
+            if note.user_id == user_id && in_notebooks(note, Some(from)) {
+                note.notebook_id = to;
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn trash_notes_in(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let now = timestamp::now();
+        let mut ids = vec![];
+        for note in self.notes.write().unwrap().values_mut() {
+            if note.user_id == user_id
+                && note.deleted_at.is_none()
+                && in_notebooks(note, Some(notebook_ids))
+            {
+                note.deleted_at = Some(now);
+                ids.push(note.id);
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn rewrite_tags(
+        &self,
+        user_id: ObjectId,
+        from: &[String],
+        to: Option<&str>,
+    ) -> Result<Vec<Note>, ApiError> {
+        let mut changed = vec![];
+        for note in self.notes.write().unwrap().values_mut() {
+            if note.user_id == user_id && replace_tags(&mut note.tags, from, to) {
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+                changed.push(note.clone());
+            }
+        }
+        Ok(changed)
+    }
+
+    async fn set_links(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        links: Vec<WikiLink>,
+    ) -> Result<(), ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == owner_id => {
+                note.links = links;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_linking_notes(
+        &self,
+        user_id: ObjectId,
+        note_id: ObjectId,
+        title_key: &str,
+    ) -> Result<Vec<Note>, ApiError> {
+        Ok(self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| note.user_id == user_id && note.deleted_at.is_none())
+            .filter(|note| links_to(note, note_id, title_key))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_notes_by_titles(
+        &self,
+        user_id: ObjectId,
+        title_keys: &[String],
+    ) -> Result<Vec<Note>, ApiError> {
+        Ok(self
+            .notes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|note| note.user_id == user_id && note.deleted_at.is_none())
+            .filter(|note| title_keys.contains(&title_key(&note.title)))
+            .cloned()
+            .collect())
+    }
+
+    async fn share_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+        role: NoteRole,
+    ) -> Result<Note, ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == owner_id && note.deleted_at.is_none() => {
+                note.shared_with.retain(|share| share.user_id != user_id);
+                note.shared_with.push(NoteShare { user_id, role });
+                Ok(note.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn unshare_note(
+        &self,
+        note_id: ObjectId,
+        owner_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note)
+                if note.user_id == owner_id
+                    && note.deleted_at.is_none()
+                    && note
+                        .shared_with
+                        .iter()
+                        .any(|share| share.user_id == user_id) =>
+            {
+                note.shared_with.retain(|share| share.user_id != user_id);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn pin_todo_list(
+        &self,
+        todo_repo: &dyn TodoRepo,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        //check if todo_list exist
+        let todo_list = todo_repo.get_todo_list(todo_list_id, user_id).await?;
+
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note) if note.user_id == user_id && note.deleted_at.is_none() => {
+                note.todo_lists.push(todo_list.id);
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn unpin_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match self.notes.write().unwrap().get_mut(&note_id) {
+            Some(note)
+                if note.user_id == user_id
+                    && note.deleted_at.is_none()
+                    && note.todo_lists.contains(&todo_list_id) =>
+            {
+                note.todo_lists.retain(|id| *id != todo_list_id);
+                note.updated_at = timestamp::now();
+                note.updated_by = user_id;
+                note.version += 1;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
 }
@@ -0,0 +1,342 @@
+use crate::{
+    error::ApiError,
+    models::{notebook::Notebook, timestamp},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::{bson::doc, bson::oid::ObjectId, options::ReturnDocument, Collection};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait NotebookRepo: Send + Sync {
+    async fn create_notebook(
+        &self,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError>;
+    async fn get_notebooks(&self, user_id: ObjectId) -> Result<Vec<Notebook>, ApiError>;
+    async fn get_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Notebook, ApiError>;
+    /// Renames and moves, cycles are checked by the caller
+    async fn update_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError>;
+    async fn delete_notebooks(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<(), ApiError>;
+}
+
+pub struct MongoNotebookRepo {
+    collection: Collection<Notebook>,
+}
+
+impl MongoNotebookRepo {
+    pub fn new(collection: Collection<Notebook>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl NotebookRepo for MongoNotebookRepo {
+    async fn create_notebook(
+        &self,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        let now = timestamp::now();
+        let notebook = Notebook {
+            id: ObjectId::new(),
+            user_id,
+            name: name.to_string(),
+            parent_id,
+            created_at: now,
+            updated_at: now,
+        };
+        match self.collection.insert_one(&notebook).await {
+            Ok(_res) => Ok(notebook),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_notebooks(&self, user_id: ObjectId) -> Result<Vec<Notebook>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id})
+            .sort(doc! {"name": 1, "_id": 1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Notebook, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": notebook_id, "user_id": user_id})
+            .await
+        {
+            Ok(Some(notebook)) => Ok(notebook),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn update_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        match self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": notebook_id, "user_id": user_id},
+                doc! {"$set": {
+                    "name": name,
+                    "parent_id": parent_id,
+                    "updated_at": timestamp::to_bson(&timestamp::now())
+                }},
+            )
+            .return_document(ReturnDocument::After)
+            .await
+        {
+            Ok(Some(notebook)) => Ok(notebook),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_notebooks(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_many(doc! {"_id": {"$in": notebook_ids}, "user_id": user_id})
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Notebooks are keyed by user_id ++ notebook_id
+*/
+pub struct SledNotebookRepo {
+    tree: sled::Tree,
+}
+
+impl SledNotebookRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl NotebookRepo for SledNotebookRepo {
+    async fn create_notebook(
+        &self,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        let now = timestamp::now();
+        let notebook = Notebook {
+            id: ObjectId::new(),
+            user_id,
+            name: name.to_string(),
+            parent_id,
+            created_at: now,
+            updated_at: now,
+        };
+        sled_store::insert(
+            &self.tree,
+            &sled_store::key(&[user_id, notebook.id]),
+            &notebook,
+        )
+        .await?;
+        Ok(notebook)
+    }
+
+    async fn get_notebooks(&self, user_id: ObjectId) -> Result<Vec<Notebook>, ApiError> {
+        let mut notebooks: Vec<Notebook> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        notebooks.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        Ok(notebooks)
+    }
+
+    async fn get_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Notebook, ApiError> {
+        sled_store::get(&self.tree, &sled_store::key(&[user_id, notebook_id]))?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn update_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, notebook_id]),
+            |notebook: &mut Notebook| {
+                notebook.name = name.to_string();
+                notebook.parent_id = parent_id;
+                notebook.updated_at = timestamp::now();
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    async fn delete_notebooks(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<(), ApiError> {
+        for notebook_id in notebook_ids {
+            self.tree
+                .remove(sled_store::key(&[user_id, *notebook_id]))
+                .map_err(sled_store::sled_error)?;
+        }
+        sled_store::flush(&self.tree).await
+    }
+}
+
+/*
+* Keeps notebooks in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryNotebookRepo {
+    notebooks: RwLock<BTreeMap<ObjectId, Notebook>>,
+}
+
+impl MemoryNotebookRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NotebookRepo for MemoryNotebookRepo {
+    async fn create_notebook(
+        &self,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        let now = timestamp::now();
+        let notebook = Notebook {
+            id: ObjectId::new(),
+            user_id,
+            name: name.to_string(),
+            parent_id,
+            created_at: now,
+            updated_at: now,
+        };
+        self.notebooks
+            .write()
+            .unwrap()
+            .insert(notebook.id, notebook.clone());
+        Ok(notebook)
+    }
+
+    async fn get_notebooks(&self, user_id: ObjectId) -> Result<Vec<Notebook>, ApiError> {
+        let mut notebooks: Vec<Notebook> = self
+            .notebooks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|notebook| notebook.user_id == user_id)
+            .cloned()
+            .collect();
+        notebooks.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        Ok(notebooks)
+    }
+
+    async fn get_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Notebook, ApiError> {
+        match self.notebooks.read().unwrap().get(&notebook_id) {
+            Some(notebook) if notebook.user_id == user_id => Ok(notebook.clone()),
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn update_notebook(
+        &self,
+        notebook_id: ObjectId,
+        user_id: ObjectId,
+        name: &str,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Notebook, ApiError> {
+        match self.notebooks.write().unwrap().get_mut(&notebook_id) {
+            Some(notebook) if notebook.user_id == user_id => {
+                notebook.name = name.to_string();
+                notebook.parent_id = parent_id;
+                notebook.updated_at = timestamp::now();
+                Ok(notebook.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_notebooks(
+        &self,
+        user_id: ObjectId,
+        notebook_ids: &[ObjectId],
+    ) -> Result<(), ApiError> {
+        self.notebooks
+            .write()
+            .unwrap()
+            .retain(|id, notebook| notebook.user_id != user_id || !notebook_ids.contains(id));
+        Ok(())
+    }
+}
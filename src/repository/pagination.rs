@@ -0,0 +1,222 @@
+use crate::{
+    error::ApiError,
+    models::{note::normalize_tags, timestamp, todo::TodoList},
+    routes::notes::AllNotesResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+/*
+* Keyset pagination for the listings. Every sort key is a string (titles and
+* fixed width timestamps), ties are broken by _id so the order is total and a
+* cursor is just the (key, _id) pair of the last item on the previous page.
+* Mongo stores the timestamps as datetimes, their keys are converted back
+* before they go into a query.
+*/
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Title,
+    #[default]
+    Created,
+    Updated,
+}
+
+impl SortKey {
+    pub fn field(self) -> &'static str {
+        match self {
+            SortKey::Title => "title",
+            SortKey::Created => "created_at",
+            SortKey::Updated => "updated_at",
+        }
+    }
+
+    //the cursor key as mongo stores the field, `None` when it doesn't fit
+    fn mongo_key(self, key: &str) -> Option<Bson> {
+        match self {
+            SortKey::Title => Some(Bson::String(key.to_string())),
+            SortKey::Created | SortKey::Updated => {
+                timestamp::parse(key).map(|time| Bson::DateTime(timestamp::to_bson(&time)))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub sort: Option<SortKey>,
+    pub order: Option<Order>,
+    //comma separated, all of them have to match
+    pub tag: Option<String>,
+    //notes only, recursive also lists the sub notebooks
+    pub notebook: Option<ObjectId>,
+    pub recursive: Option<bool>,
+}
+
+impl ListQuery {
+    /// Normalized like stored tags, so `?tag=Work` finds notes tagged "work"
+    pub fn tags(&self) -> Vec<String> {
+        normalize_tags(
+            self.tag
+                .iter()
+                .flat_map(|tags| tags.split(','))
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub fn options(&self) -> Result<ListOptions, ApiError> {
+        let sort = self.sort.unwrap_or_default();
+        let after = self.cursor.as_deref().map(Cursor::decode).transpose()?;
+        //a cursor of another sort, checked here so mongo_after can't fail
+        if let Some(after) = &after {
+            sort.mongo_key(&after.key).ok_or(ApiError::InvalidCursor)?;
+        }
+        Ok(ListOptions {
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            sort,
+            order: self.order.unwrap_or_default(),
+            after,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub key: String,
+    pub id: ObjectId,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}{}", self.id.to_hex(), self.key))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Cursor, ApiError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| ApiError::InvalidCursor)?;
+        let text = String::from_utf8(bytes).map_err(|_| ApiError::InvalidCursor)?;
+        //24 hex chars of the id, the key after it
+        let (id, key) = text.split_at_checked(24).ok_or(ApiError::InvalidCursor)?;
+        Ok(Cursor {
+            key: key.to_string(),
+            id: ObjectId::parse_str(id).map_err(|_| ApiError::InvalidCursor)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    pub limit: usize,
+    pub sort: SortKey,
+    pub order: Order,
+    pub after: Option<Cursor>,
+}
+
+impl ListOptions {
+    pub fn mongo_sort(&self) -> Document {
+        let direction = match self.order {
+            Order::Asc => 1,
+            Order::Desc => -1,
+        };
+        doc! {self.sort.field(): direction, "_id": direction}
+    }
+
+    /// Condition for everything after the cursor, `None` on the first page
+    pub fn mongo_after(&self) -> Option<Document> {
+        let after = self.after.as_ref()?;
+        let operator = match self.order {
+            Order::Asc => "$gt",
+            Order::Desc => "$lt",
+        };
+        let key = self
+            .sort
+            .mongo_key(&after.key)
+            .unwrap_or_else(|| Bson::String(after.key.clone()));
+        Some(doc! {"$or": [
+            {self.sort.field(): {operator: &key}},
+            {self.sort.field(): &key, "_id": {operator: after.id}}
+        ]})
+    }
+
+    //one more than the page so we know if there is a next one
+    pub fn mongo_limit(&self) -> i64 {
+        self.limit as i64 + 1
+    }
+}
+
+/// Listed documents, gives the value of a sort key and the id for ties
+pub trait Sortable {
+    fn sort_key(&self, sort: SortKey) -> (String, ObjectId);
+}
+
+impl Sortable for AllNotesResponse {
+    fn sort_key(&self, sort: SortKey) -> (String, ObjectId) {
+        let key = match sort {
+            SortKey::Title => self.title.clone(),
+            SortKey::Created => timestamp::format(&self.created_at),
+            SortKey::Updated => timestamp::format(&self.updated_at),
+        };
+        (key, self.id)
+    }
+}
+
+impl Sortable for TodoList {
+    fn sort_key(&self, sort: SortKey) -> (String, ObjectId) {
+        let key = match sort {
+            SortKey::Title => self.title.clone(),
+            SortKey::Created => timestamp::format(&self.created_at),
+            SortKey::Updated => timestamp::format(&self.updated_at),
+        };
+        (key, self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Sorts, skips past the cursor and cuts the page. Mongo already does all of
+/// it in the query, running it again over a sorted page changes nothing.
+pub fn paginate<T: Sortable>(mut items: Vec<T>, options: &ListOptions) -> Page<T> {
+    let cursor_key = |item: &T| item.sort_key(options.sort);
+    items.sort_by(|a, b| match options.order {
+        Order::Asc => cursor_key(a).cmp(&cursor_key(b)),
+        Order::Desc => cursor_key(b).cmp(&cursor_key(a)),
+    });
+    if let Some(after) = &options.after {
+        let after = (after.key.clone(), after.id);
+        items.retain(|item| match options.order {
+            Order::Asc => cursor_key(item) > after,
+            Order::Desc => cursor_key(item) < after,
+        });
+    }
+    let next_cursor = if items.len() > options.limit {
+        items.truncate(options.limit);
+        items.last().map(|item| {
+            let (key, id) = cursor_key(item);
+            Cursor { key, id }.encode()
+        })
+    } else {
+        None
+    };
+    Page { items, next_cursor }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    error::ApiError,
+    models::{refresh_token::RefreshToken, timestamp},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::ReturnDocument,
+    Collection,
+};
+use std::{collections::HashMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait RefreshTokenRepo: Send + Sync {
+    async fn create_refresh_token(&self, token: &RefreshToken) -> Result<(), ApiError>;
+    /// Marks the token as used and returns it as it was before, so `used_at`
+    /// is only `None` for the one caller that gets to swap it
+    async fn use_refresh_token(&self, id: &str) -> Result<RefreshToken, ApiError>;
+    /// Forgets every token of the family, none of them can be swapped anymore
+    async fn revoke_family(&self, family: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoRefreshTokenRepo {
+    collection: Collection<RefreshToken>,
+}
+
+impl MongoRefreshTokenRepo {
+    pub fn new(collection: Collection<RefreshToken>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for MongoRefreshTokenRepo {
+    async fn create_refresh_token(&self, token: &RefreshToken) -> Result<(), ApiError> {
+        match self.collection.insert_one(token).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn use_refresh_token(&self, id: &str) -> Result<RefreshToken, ApiError> {
+        let used = self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": id, "used_at": null},
+                doc! {"$set": {"used_at": timestamp::to_bson(&timestamp::now())}},
+            )
+            .return_document(ReturnDocument::Before)
+            .await;
+        let res = match used {
+            Ok(Some(token)) => return Ok(token),
+            //used before or not there at all
+            Ok(None) => self.collection.find_one(doc! {"_id": id}).await,
+            Err(err) => Err(err),
+        };
+        match res {
+            Ok(Some(token)) => Ok(token),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn revoke_family(&self, family: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_many(doc! {"family": family}).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Tokens are keyed by their id
+*/
+pub struct SledRefreshTokenRepo {
+    tree: sled::Tree,
+}
+
+impl SledRefreshTokenRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for SledRefreshTokenRepo {
+    async fn create_refresh_token(&self, token: &RefreshToken) -> Result<(), ApiError> {
+        sled_store::insert(&self.tree, token.id.as_bytes(), token).await
+    }
+
+    async fn use_refresh_token(&self, id: &str) -> Result<RefreshToken, ApiError> {
+        loop {
+            let current = self
+                .tree
+                .get(id)
+                .map_err(sled_store::sled_error)?
+                .ok_or(ApiError::NotFound)?;
+            let token: RefreshToken = sled_store::decode(&current)?;
+            if token.used_at.is_some() {
+                return Ok(token);
+            }
+            let mut used = token.clone();
+            used.used_at = Some(timestamp::now());
+            //lost against a concurrent swap when the stored bytes changed
+            if self
+                .tree
+                .compare_and_swap(id, Some(current), Some(sled_store::encode(&used)?))
+                .map_err(sled_store::sled_error)?
+                .is_ok()
+            {
+                sled_store::flush(&self.tree).await?;
+                return Ok(token);
+            }
+        }
+    }
+
+    async fn revoke_family(&self, family: ObjectId) -> Result<(), ApiError> {
+        let tokens: Vec<RefreshToken> = sled_store::scan_prefix(&self.tree, &[])?;
+        for token in tokens.iter().filter(|token| token.family == family) {
+            sled_store::remove::<RefreshToken>(&self.tree, token.id.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+/*
+* Keeps tokens in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryRefreshTokenRepo {
+    tokens: RwLock<HashMap<String, RefreshToken>>,
+}
+
+impl MemoryRefreshTokenRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for MemoryRefreshTokenRepo {
+    async fn create_refresh_token(&self, token: &RefreshToken) -> Result<(), ApiError> {
+        self.tokens
+            .write()
+            .unwrap()
+            .insert(token.id.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn use_refresh_token(&self, id: &str) -> Result<RefreshToken, ApiError> {
+        let mut tokens = self.tokens.write().unwrap();
+        let token = tokens.get_mut(id).ok_or(ApiError::NotFound)?;
+        let before = token.clone();
+        token.used_at.get_or_insert_with(timestamp::now);
+        Ok(before)
+    }
+
+    async fn revoke_family(&self, family: ObjectId) -> Result<(), ApiError> {
+        self.tokens
+            .write()
+            .unwrap()
+            .retain(|_id, token| token.family != family);
+        Ok(())
+    }
+}
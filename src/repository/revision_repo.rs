@@ -0,0 +1,310 @@
+use crate::{error::ApiError, models::revision::NoteRevision, repository::sled_store};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::{ErrorKind, WriteError, WriteFailure},
+    Collection,
+};
+use std::sync::RwLock;
+use tracing::error;
+
+#[async_trait]
+pub trait RevisionRepo: Send + Sync {
+    /// `false` when the note already has a revision with that number, the
+    /// numbers are unique so concurrent saves can't both take the same one
+    async fn create_revision(&self, revision: &NoteRevision) -> Result<bool, ApiError>;
+    async fn get_revisions(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<NoteRevision>, ApiError>;
+    async fn get_revision(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        rev: u32,
+    ) -> Result<NoteRevision, ApiError>;
+    async fn last_revision_number(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<u32, ApiError>;
+    async fn delete_revisions(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoRevisionRepo {
+    collection: Collection<NoteRevision>,
+}
+
+impl MongoRevisionRepo {
+    pub fn new(collection: Collection<NoteRevision>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl RevisionRepo for MongoRevisionRepo {
+    async fn create_revision(&self, revision: &NoteRevision) -> Result<bool, ApiError> {
+        match self.collection.insert_one(revision).await {
+            Ok(_res) => Ok(true),
+            //unique index on user_id + note_id + rev, see migrations
+            Err(err)
+                if matches!(
+                    err.kind.as_ref(),
+                    ErrorKind::Write(WriteFailure::WriteError(WriteError { code: 11000, .. }))
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_revisions(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<NoteRevision>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"note_id": note_id, "user_id": user_id})
+            .sort(doc! {"rev": 1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_revision(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        rev: u32,
+    ) -> Result<NoteRevision, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"note_id": note_id, "user_id": user_id, "rev": rev as i64})
+            .await
+        {
+            Ok(Some(revision)) => Ok(revision),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn last_revision_number(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<u32, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"note_id": note_id, "user_id": user_id})
+            .sort(doc! {"rev": -1})
+            .await
+        {
+            Ok(Some(revision)) => Ok(revision.rev),
+            Ok(None) => Ok(0),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_revisions(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_many(doc! {"note_id": note_id, "user_id": user_id})
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+pub struct SledRevisionRepo {
+    tree: sled::Tree,
+}
+
+impl SledRevisionRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+//big endian revision number keeps the revisions of a note sorted
+fn revision_key(note_id: ObjectId, user_id: ObjectId, rev: u32) -> Vec<u8> {
+    let mut key = sled_store::key(&[user_id, note_id]);
+    key.extend_from_slice(&rev.to_be_bytes());
+    key
+}
+
+#[async_trait]
+impl RevisionRepo for SledRevisionRepo {
+    async fn create_revision(&self, revision: &NoteRevision) -> Result<bool, ApiError> {
+        let key = revision_key(revision.note_id, revision.user_id, revision.rev);
+        let bytes = sled_store::encode(revision)?;
+        match self
+            .tree
+            .compare_and_swap(key, None as Option<&[u8]>, Some(bytes))
+            .map_err(sled_store::sled_error)?
+        {
+            Ok(()) => {
+                sled_store::flush(&self.tree).await?;
+                Ok(true)
+            }
+            Err(_taken) => Ok(false),
+        }
+    }
+
+    async fn get_revisions(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<NoteRevision>, ApiError> {
+        sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id, note_id]))
+    }
+
+    async fn get_revision(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        rev: u32,
+    ) -> Result<NoteRevision, ApiError> {
+        sled_store::get(&self.tree, &revision_key(note_id, user_id, rev))?.ok_or(ApiError::NotFound)
+    }
+
+    async fn last_revision_number(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<u32, ApiError> {
+        match self
+            .tree
+            .scan_prefix(sled_store::key(&[user_id, note_id]))
+            .next_back()
+        {
+            Some(entry) => {
+                let (_key, bytes) = entry.map_err(sled_store::sled_error)?;
+                let revision: NoteRevision = sled_store::decode(&bytes)?;
+                Ok(revision.rev)
+            }
+            None => Ok(0),
+        }
+    }
+
+    async fn delete_revisions(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        for entry in self.tree.scan_prefix(sled_store::key(&[user_id, note_id])) {
+            let (key, _bytes) = entry.map_err(sled_store::sled_error)?;
+            self.tree.remove(key).map_err(sled_store::sled_error)?;
+        }
+        sled_store::flush(&self.tree).await
+    }
+}
+
+/*
+* Keeps revisions in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryRevisionRepo {
+    revisions: RwLock<Vec<NoteRevision>>,
+}
+
+impl MemoryRevisionRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RevisionRepo for MemoryRevisionRepo {
+    async fn create_revision(&self, revision: &NoteRevision) -> Result<bool, ApiError> {
+        let mut revisions = self.revisions.write().unwrap();
+        if revisions.iter().any(|other| {
+            other.note_id == revision.note_id
+                && other.user_id == revision.user_id
+                && other.rev == revision.rev
+        }) {
+            return Ok(false);
+        }
+        revisions.push(revision.clone());
+        Ok(true)
+    }
+
+    async fn get_revisions(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<NoteRevision>, ApiError> {
+        let mut revisions: Vec<NoteRevision> = self
+            .revisions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|revision| revision.note_id == note_id && revision.user_id == user_id)
+            .cloned()
+            .collect();
+        revisions.sort_by_key(|revision| revision.rev);
+        Ok(revisions)
+    }
+
+    async fn get_revision(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        rev: u32,
+    ) -> Result<NoteRevision, ApiError> {
+        self.revisions
+            .read()
+            .unwrap()
+            .iter()
+            .find(|revision| {
+                revision.note_id == note_id && revision.user_id == user_id && revision.rev == rev
+            })
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn last_revision_number(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<u32, ApiError> {
+        Ok(self
+            .revisions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|revision| revision.note_id == note_id && revision.user_id == user_id)
+            .map(|revision| revision.rev)
+            .max()
+            .unwrap_or(0))
+    }
+
+    async fn delete_revisions(&self, note_id: ObjectId, user_id: ObjectId) -> Result<(), ApiError> {
+        self.revisions
+            .write()
+            .unwrap()
+            .retain(|revision| !(revision.note_id == note_id && revision.user_id == user_id));
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    error::ApiError,
+    models::{session::Session, timestamp},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait SessionRepo: Send + Sync {
+    async fn create_session(&self, session: &Session) -> Result<(), ApiError>;
+    async fn get_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Session, ApiError>;
+    /// Most recently used first
+    async fn get_sessions(&self, user_id: ObjectId) -> Result<Vec<Session>, ApiError>;
+    async fn touch_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError>;
+    async fn delete_session(&self, session_id: ObjectId, user_id: ObjectId)
+        -> Result<(), ApiError>;
+    /// Deletes every session of the user but `keep`, returns the deleted ids
+    async fn delete_other_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: ObjectId,
+    ) -> Result<Vec<ObjectId>, ApiError>;
+}
+
+pub struct MongoSessionRepo {
+    collection: Collection<Session>,
+}
+
+impl MongoSessionRepo {
+    pub fn new(collection: Collection<Session>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl SessionRepo for MongoSessionRepo {
+    async fn create_session(&self, session: &Session) -> Result<(), ApiError> {
+        match self.collection.insert_one(session).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Session, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": session_id, "user_id": user_id})
+            .await
+        {
+            Ok(Some(session)) => Ok(session),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_sessions(&self, user_id: ObjectId) -> Result<Vec<Session>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id})
+            .sort(doc! {"last_used_at": -1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": session_id, "user_id": user_id},
+                doc! {"$set": {"last_used_at": timestamp::to_bson(&at)}},
+            )
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_one(doc! {"_id": session_id, "user_id": user_id})
+            .await
+        {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_other_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: ObjectId,
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let filter = doc! {"user_id": user_id, "_id": {"$ne": keep}};
+        let sessions: Vec<Session> = match self.collection.find(filter.clone()).await {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?,
+            Err(err) => {
+                error!("{}", err);
+                return Err(ApiError::InternalError);
+            }
+        };
+        let ids: Vec<ObjectId> = sessions.iter().map(|session| session.id).collect();
+        match self
+            .collection
+            .delete_many(doc! {"_id": {"$in": &ids}})
+            .await
+        {
+            Ok(_res) => Ok(ids),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Sessions are keyed by user_id ++ session_id
+*/
+pub struct SledSessionRepo {
+    tree: sled::Tree,
+}
+
+impl SledSessionRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl SessionRepo for SledSessionRepo {
+    async fn create_session(&self, session: &Session) -> Result<(), ApiError> {
+        let key = sled_store::key(&[session.user_id, session.id]);
+        sled_store::insert(&self.tree, &key, session).await
+    }
+
+    async fn get_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Session, ApiError> {
+        sled_store::get(&self.tree, &sled_store::key(&[user_id, session_id]))?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn get_sessions(&self, user_id: ObjectId) -> Result<Vec<Session>, ApiError> {
+        let mut sessions: Vec<Session> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id, session_id]);
+        sled_store::update(&self.tree, &key, |session: &mut Session| {
+            session.last_used_at = at;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id, session_id]);
+        sled_store::remove::<Session>(&self.tree, &key)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        Ok(())
+    }
+
+    async fn delete_other_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: ObjectId,
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let sessions: Vec<Session> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        let mut ids = vec![];
+        for session in sessions.iter().filter(|session| session.id != keep) {
+            let key = sled_store::key(&[user_id, session.id]);
+            sled_store::remove::<Session>(&self.tree, &key).await?;
+            ids.push(session.id);
+        }
+        Ok(ids)
+    }
+}
+
+/*
+* Keeps sessions in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemorySessionRepo {
+    sessions: RwLock<BTreeMap<ObjectId, Session>>,
+}
+
+impl MemorySessionRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionRepo for MemorySessionRepo {
+    async fn create_session(&self, session: &Session) -> Result<(), ApiError> {
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id, session.clone());
+        Ok(())
+    }
+
+    async fn get_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Session, ApiError> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(&session_id)
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn get_sessions(&self, user_id: ObjectId) -> Result<Vec<Session>, ApiError> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|session| session.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        if let Some(session) = self
+            .sessions
+            .write()
+            .unwrap()
+            .get_mut(&session_id)
+            .filter(|session| session.user_id == user_id)
+        {
+            session.last_used_at = at;
+        }
+        Ok(())
+    }
+
+    async fn delete_session(
+        &self,
+        session_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        let mut sessions = self.sessions.write().unwrap();
+        match sessions.get(&session_id) {
+            Some(session) if session.user_id == user_id => {
+                sessions.remove(&session_id);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_other_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: ObjectId,
+    ) -> Result<Vec<ObjectId>, ApiError> {
+        let mut sessions = self.sessions.write().unwrap();
+        let ids: Vec<ObjectId> = sessions
+            .values()
+            .filter(|session| session.user_id == user_id && session.id != keep)
+            .map(|session| session.id)
+            .collect();
+        for id in ids.iter() {
+            sessions.remove(id);
+        }
+        Ok(ids)
+    }
+}
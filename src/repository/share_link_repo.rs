@@ -0,0 +1,483 @@
+use crate::{
+    error::ApiError,
+    models::{share_link::ShareLink, timestamp},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson},
+    options::ReturnDocument,
+    Collection,
+};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait ShareLinkRepo: Send + Sync {
+    async fn create_link(&self, link: &ShareLink) -> Result<(), ApiError>;
+    async fn get_links(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, ApiError>;
+    async fn get_link_by_token(&self, token: &str) -> Result<ShareLink, ApiError>;
+    /// `None` keeps the link open until it is revoked. The changing calls only
+    /// find links of `note_id` owned by `user_id`.
+    async fn set_expiry(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShareLink, ApiError>;
+    async fn delete_link(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError>;
+    /// Counts a view and forgets earlier wrong passwords
+    async fn record_view(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError>;
+    async fn record_failed_attempt(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError>;
+    /// Drops every link of a note, used when the note is purged
+    async fn delete_links_of_note(&self, note_id: ObjectId) -> Result<(), ApiError>;
+}
+
+fn optional_timestamp(value: Option<DateTime<Utc>>) -> Bson {
+    match value {
+        Some(value) => Bson::DateTime(timestamp::to_bson(&value)),
+        None => Bson::Null,
+    }
+}
+
+pub struct MongoShareLinkRepo {
+    collection: Collection<ShareLink>,
+}
+
+impl MongoShareLinkRepo {
+    pub fn new(collection: Collection<ShareLink>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl ShareLinkRepo for MongoShareLinkRepo {
+    async fn create_link(&self, link: &ShareLink) -> Result<(), ApiError> {
+        match self.collection.insert_one(link).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_links(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"note_id": note_id, "user_id": user_id})
+            .sort(doc! {"created_at": 1})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_link_by_token(&self, token: &str) -> Result<ShareLink, ApiError> {
+        match self.collection.find_one(doc! {"token": token}).await {
+            Ok(Some(link)) => Ok(link),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn set_expiry(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShareLink, ApiError> {
+        match self
+            .collection
+            .find_one_and_update(
+                doc! {"_id": link_id, "note_id": note_id, "user_id": user_id},
+                doc! {"$set": {"expires_at": optional_timestamp(expires_at)}},
+            )
+            .return_document(ReturnDocument::After)
+            .await
+        {
+            Ok(Some(link)) => Ok(link),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_link(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_one(doc! {"_id": link_id, "note_id": note_id, "user_id": user_id})
+            .await
+        {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn record_view(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": link_id, "user_id": user_id},
+                doc! {
+                    "$inc": {"views": 1_i64},
+                    "$set": {"last_viewed_at": timestamp::to_bson(&at), "failed_attempts": 0}
+                },
+            )
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": link_id, "user_id": user_id},
+                doc! {
+                    "$inc": {"failed_attempts": 1},
+                    "$set": {"last_failed_at": timestamp::to_bson(&at)}
+                },
+            )
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_links_of_note(&self, note_id: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_many(doc! {"note_id": note_id}).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Links are keyed by user_id ++ link_id, tokens maps a token to that key
+*/
+pub struct SledShareLinkRepo {
+    tree: sled::Tree,
+    tokens: sled::Tree,
+}
+
+impl SledShareLinkRepo {
+    pub fn new(tree: sled::Tree, tokens: sled::Tree) -> Self {
+        Self { tree, tokens }
+    }
+
+    async fn remove(&self, link: &ShareLink) -> Result<(), ApiError> {
+        self.tokens
+            .remove(link.token.as_bytes())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.tokens).await?;
+        sled_store::remove::<ShareLink>(&self.tree, &sled_store::key(&[link.user_id, link.id]))
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShareLinkRepo for SledShareLinkRepo {
+    async fn create_link(&self, link: &ShareLink) -> Result<(), ApiError> {
+        let key = sled_store::key(&[link.user_id, link.id]);
+        self.tokens
+            .insert(link.token.as_bytes(), key.as_slice())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.tokens).await?;
+        sled_store::insert(&self.tree, &key, link).await
+    }
+
+    async fn get_links(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, ApiError> {
+        let mut links: Vec<ShareLink> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        links.retain(|link| link.note_id == note_id);
+        links.sort_by_key(|link| link.created_at);
+        Ok(links)
+    }
+
+    async fn get_link_by_token(&self, token: &str) -> Result<ShareLink, ApiError> {
+        match self
+            .tokens
+            .get(token.as_bytes())
+            .map_err(sled_store::sled_error)?
+        {
+            Some(key) => sled_store::get(&self.tree, &key)?.ok_or(ApiError::NotFound),
+            None => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn set_expiry(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShareLink, ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, link_id]),
+            |link: &mut ShareLink| {
+                if link.note_id != note_id {
+                    return Err(ApiError::NotFound);
+                }
+                link.expires_at = expires_at;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    async fn delete_link(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        match sled_store::get::<ShareLink>(&self.tree, &sled_store::key(&[user_id, link_id]))? {
+            Some(link) if link.note_id == note_id => self.remove(&link).await,
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn record_view(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, link_id]),
+            |link: &mut ShareLink| {
+                link.views += 1;
+                link.last_viewed_at = Some(at);
+                link.failed_attempts = 0;
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, link_id]),
+            |link: &mut ShareLink| {
+                link.failed_attempts += 1;
+                link.last_failed_at = Some(at);
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_links_of_note(&self, note_id: ObjectId) -> Result<(), ApiError> {
+        let links: Vec<ShareLink> = sled_store::scan_prefix(&self.tree, &[])?;
+        for link in links.iter().filter(|link| link.note_id == note_id) {
+            self.remove(link).await?;
+        }
+        Ok(())
+    }
+}
+
+/*
+* Keeps share links in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryShareLinkRepo {
+    links: RwLock<BTreeMap<ObjectId, ShareLink>>,
+}
+
+impl MemoryShareLinkRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ShareLinkRepo for MemoryShareLinkRepo {
+    async fn create_link(&self, link: &ShareLink) -> Result<(), ApiError> {
+        self.links.write().unwrap().insert(link.id, link.clone());
+        Ok(())
+    }
+
+    async fn get_links(
+        &self,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<ShareLink>, ApiError> {
+        Ok(self
+            .links
+            .read()
+            .unwrap()
+            .values()
+            .filter(|link| link.note_id == note_id && link.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_link_by_token(&self, token: &str) -> Result<ShareLink, ApiError> {
+        self.links
+            .read()
+            .unwrap()
+            .values()
+            .find(|link| link.token == token)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn set_expiry(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ShareLink, ApiError> {
+        match self.links.write().unwrap().get_mut(&link_id) {
+            Some(link) if link.note_id == note_id && link.user_id == user_id => {
+                link.expires_at = expires_at;
+                Ok(link.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_link(
+        &self,
+        link_id: ObjectId,
+        note_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        let mut links = self.links.write().unwrap();
+        match links.get(&link_id) {
+            Some(link) if link.note_id == note_id && link.user_id == user_id => {
+                links.remove(&link_id);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn record_view(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self.links.write().unwrap().get_mut(&link_id) {
+            Some(link) if link.user_id == user_id => {
+                link.views += 1;
+                link.last_viewed_at = Some(at);
+                link.failed_attempts = 0;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        link_id: ObjectId,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self.links.write().unwrap().get_mut(&link_id) {
+            Some(link) if link.user_id == user_id => {
+                link.failed_attempts += 1;
+                link.last_failed_at = Some(at);
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_links_of_note(&self, note_id: ObjectId) -> Result<(), ApiError> {
+        self.links
+            .write()
+            .unwrap()
+            .retain(|_id, link| link.note_id != note_id);
+        Ok(())
+    }
+}
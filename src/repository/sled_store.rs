@@ -0,0 +1,109 @@
+use crate::error::ApiError;
+use mongodb::bson::{self, oid::ObjectId};
+use serde::{de::DeserializeOwned, Serialize};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Tree,
+};
+use tracing::error;
+
+/*
+* Documents are stored as bson bytes, keys are built from raw ObjectId bytes
+* so that everything owned by a user can be found with a prefix scan.
+*/
+
+pub fn open(path: &str) -> sled::Db {
+    sled::open(path).expect("Failed opening sled database")
+}
+
+pub fn open_tree(db: &sled::Db, name: &str) -> Tree {
+    db.open_tree(name).expect("Failed opening sled tree")
+}
+
+pub fn key(ids: &[ObjectId]) -> Vec<u8> {
+    ids.iter().flat_map(|id| id.bytes()).collect()
+}
+
+pub fn sled_error(err: sled::Error) -> ApiError {
+    error!("{}", err);
+    ApiError::InternalError
+}
+
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, ApiError> {
+    bson::to_vec(value).map_err(|err| {
+        error!("{}", err);
+        ApiError::InternalError
+    })
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ApiError> {
+    bson::from_slice(bytes).map_err(|err| {
+        error!("{}", err);
+        ApiError::InternalError
+    })
+}
+
+pub fn get<T: DeserializeOwned>(tree: &Tree, key: &[u8]) -> Result<Option<T>, ApiError> {
+    match tree.get(key).map_err(sled_error)? {
+        Some(bytes) => Ok(Some(decode(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Waits until pending writes hit the disk, otherwise a killed process
+/// loses everything written since the last periodic flush.
+pub async fn flush(tree: &Tree) -> Result<(), ApiError> {
+    tree.flush_async().await.map_err(sled_error)?;
+    Ok(())
+}
+
+pub async fn insert<T: Serialize>(tree: &Tree, key: &[u8], value: &T) -> Result<(), ApiError> {
+    tree.insert(key, encode(value)?).map_err(sled_error)?;
+    flush(tree).await
+}
+
+pub async fn remove<T: DeserializeOwned>(tree: &Tree, key: &[u8]) -> Result<Option<T>, ApiError> {
+    let removed = tree.remove(key).map_err(sled_error)?;
+    flush(tree).await?;
+    match removed {
+        Some(bytes) => Ok(Some(decode(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn scan_prefix<T: DeserializeOwned>(tree: &Tree, prefix: &[u8]) -> Result<Vec<T>, ApiError> {
+    tree.scan_prefix(prefix)
+        .map(|entry| {
+            let (_key, bytes) = entry.map_err(sled_error)?;
+            decode(&bytes)
+        })
+        .collect()
+}
+
+/// Atomically reads, modifies and writes back a single document.
+/// Returns `NotFound` when the key does not exist.
+pub async fn update<T, F>(tree: &Tree, key: &[u8], modify: F) -> Result<T, ApiError>
+where
+    T: Serialize + DeserializeOwned,
+    F: Fn(&mut T) -> Result<(), ApiError>,
+{
+    let value = tree
+        .transaction(|tx| {
+            let bytes = tx
+                .get(key)?
+                .ok_or(ConflictableTransactionError::Abort(ApiError::NotFound))?;
+            let mut value: T = decode(&bytes).map_err(ConflictableTransactionError::Abort)?;
+            modify(&mut value).map_err(ConflictableTransactionError::Abort)?;
+            tx.insert(
+                key,
+                encode(&value).map_err(ConflictableTransactionError::Abort)?,
+            )?;
+            Ok(value)
+        })
+        .map_err(|err| match err {
+            TransactionError::Abort(err) => err,
+            TransactionError::Storage(err) => sled_error(err),
+        })?;
+    flush(tree).await?;
+    Ok(value)
+}
@@ -1,15 +1,25 @@
-use std::os::linux::raw::stat;
-
 use crate::{
     error::ApiError,
-    models::todo::{Todo, TodoList, TodoPriority},
+    models::{
+        timestamp,
+        todo::{Todo, TodoList, TodoPriority},
+        tombstone::changed_since,
+    },
+    repository::{
+        mongo_changed_since, mongo_version,
+        pagination::{self, ListOptions, Page},
+        sled_store,
+    },
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
 use mongodb::{
-    bson::{self, doc, oid::ObjectId, SerializerOptions},
+    bson::{self, doc, oid::ObjectId, Document, SerializerOptions},
+    options::ReturnDocument,
     Collection,
 };
+use std::{collections::BTreeMap, sync::RwLock};
 use tracing::error;
 
 #[async_trait]
@@ -19,7 +29,13 @@ pub trait TodoRepo: Send + Sync {
         list: Vec<ObjectId>,
         user_id: ObjectId,
     ) -> Result<Vec<TodoList>, ApiError>;
-    async fn get_all_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError>;
+    /// `only` limits the listing to the given todo lists
+    async fn get_all_todo_lists(
+        &self,
+        user_id: ObjectId,
+        only: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<TodoList>, ApiError>;
     async fn create_todo_list(
         &self,
         title: String,
@@ -30,17 +46,52 @@ pub trait TodoRepo: Send + Sync {
         todo_list_id: ObjectId,
         user_id: ObjectId,
     ) -> Result<TodoList, ApiError>;
+    /// Moves the todo list to the trash, every other call except the trash ones ignores it.
+    /// With `version` set only while the list is still at it.
     async fn delete_todo_list(
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError>;
+    /// Takes the list out of the trash, counts as a change for updated_at and version
+    async fn restore_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError>;
+    /// Removes a todo list from the trash for good
+    async fn purge_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
     ) -> Result<(), ApiError>;
+    async fn get_trashed_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError>;
+    /// Todo lists changed or trashed from `since` on, every live one without it
+    async fn get_todo_lists_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<TodoList>, ApiError>;
+    /// Removes todo lists of every user trashed before the given time and returns them
+    async fn purge_todo_lists_deleted_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<TodoList>, ApiError>;
+    /// Removes every todo list of the user, trashed ones included
+    async fn purge_todo_lists_of_user(&self, user_id: ObjectId) -> Result<(), ApiError>;
+    /*
+     * Changes to a list and its todos bump its version and return the changed
+     * list. With `version` set they fail with PreconditionFailed once the list
+     * has moved on.
+     */
     async fn rename_todo_list(
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
         title: String,
-    ) -> Result<(), ApiError>;
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError>;
     async fn create_todo(
         &self,
         todo_list_id: ObjectId,
@@ -48,7 +99,9 @@ pub trait TodoRepo: Send + Sync {
         title: String,
         status: bool,
         priority: TodoPriority,
-    ) -> Result<(), ApiError>;
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError>;
+    #[allow(clippy::too_many_arguments)]
     async fn modify_todo(
         &self,
         todo_list_id: ObjectId,
@@ -57,13 +110,15 @@ pub trait TodoRepo: Send + Sync {
         title: String,
         status: bool,
         priority: TodoPriority,
-    ) -> Result<(), ApiError>;
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError>;
     async fn delete_todo(
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
         todo_id: ObjectId,
-    ) -> Result<(), ApiError>;
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError>;
 }
 
 pub struct MongoTodoRepo {
@@ -74,6 +129,52 @@ impl MongoTodoRepo {
     pub fn new(collection: Collection<TodoList>) -> Self {
         Self { collection }
     }
+
+    //applies `update` to a live list of the user and bumps its version,
+    //`filter` narrows the match down further
+    async fn update_live(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+        filter: Document,
+        mut update: Document,
+    ) -> Result<TodoList, ApiError> {
+        let mut query = doc! {"_id": todo_list_id, "user_id": user_id, "deleted_at": null};
+        if let Some(version) = version {
+            query.insert("version", mongo_version(version));
+        }
+        query.extend(filter);
+        update.insert("$inc", doc! {"version": 1_i64});
+        match self
+            .collection
+            .find_one_and_update(query, update)
+            .return_document(ReturnDocument::After)
+            .await
+        {
+            Ok(Some(todo_list)) => Ok(todo_list),
+            //either gone or changed since `version`
+            Ok(None) => {
+                let todo_list = self.get_todo_list(todo_list_id, user_id).await?;
+                check_version(&todo_list, version)?;
+                Err(ApiError::NotFound)
+            }
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+//fails when the list moved on from the version a change is based on
+fn check_version(todo_list: &TodoList, version: Option<u64>) -> Result<(), ApiError> {
+    match version {
+        Some(version) if version != todo_list.version => {
+            Err(ApiError::PreconditionFailed(todo_list.version))
+        }
+        _ => Ok(()),
+    }
 }
 
 #[async_trait]
@@ -83,11 +184,17 @@ impl TodoRepo for MongoTodoRepo {
         title: String,
         user_id: ObjectId,
     ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
         let new_todo_list = TodoList {
             id: ObjectId::new(),
             user_id,
             title,
             todos: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            version: 1,
         };
         match self.collection.insert_one(&new_todo_list).await {
             Ok(_res) => Ok(new_todo_list),
@@ -98,17 +205,32 @@ impl TodoRepo for MongoTodoRepo {
         }
     }
 
-    async fn get_all_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError> {
-        match self.collection.find(doc! {"user_id": user_id}).await {
+    async fn get_all_todo_lists(
+        &self,
+        user_id: ObjectId,
+        only: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<TodoList>, ApiError> {
+        let mut filter = doc! {"user_id": user_id, "deleted_at": null};
+        if let Some(ids) = only {
+            filter.insert("_id", doc! {"$in": ids});
+        }
+        if let Some(after) = options.mongo_after() {
+            filter.extend(after);
+        }
+        match self
+            .collection
+            .find(filter)
+            .sort(options.mongo_sort())
+            .limit(options.mongo_limit())
+            .await
+        {
             Ok(res) => {
                 let todos: Vec<TodoList> = res.try_collect().await.map_err(|err| {
                     error!("{}", err);
                     ApiError::InternalError
                 })?;
-                if !todos.is_empty() {
-                    return Ok(todos);
-                }
-                Err(ApiError::NotFound)
+                Ok(pagination::paginate(todos, options))
             }
             Err(err) => {
                 error!("{}", err);
@@ -125,7 +247,7 @@ impl TodoRepo for MongoTodoRepo {
         for id in list.iter() {
             match self
                 .collection
-                .find_one(doc! { "_id": id, "user_id": user_id})
+                .find_one(doc! { "_id": id, "user_id": user_id, "deleted_at": null})
                 .await
             {
                 Ok(Some(todo)) => temp.push(todo),
@@ -145,21 +267,31 @@ impl TodoRepo for MongoTodoRepo {
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
-    ) -> Result<(), ApiError> {
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        let mut filter = doc! {
+            "_id": todo_list_id,
+            "user_id": user_id,
+            "deleted_at": null
+        };
+        if let Some(version) = version {
+            filter.insert("version", mongo_version(version));
+        }
         match self
             .collection
-            .delete_one(doc! {
-                "_id": todo_list_id,
-                "user_id": user_id
-            })
+            .find_one_and_update(
+                filter,
+                doc! {"$set": {"deleted_at": timestamp::to_bson(&timestamp::now())}},
+            )
+            .return_document(ReturnDocument::After)
             .await
         {
-            Ok(res) => {
-                if res.deleted_count > 0 {
-                    Ok(())
-                } else {
-                    Err(ApiError::NotFound)
-                }
+            Ok(Some(todo_list)) => Ok(todo_list),
+            //either gone or changed since `version`
+            Ok(None) => {
+                let todo_list = self.get_todo_list(todo_list_id, user_id).await?;
+                check_version(&todo_list, version)?;
+                Err(ApiError::NotFound)
             }
             Err(err) => {
                 error!("{}", err);
@@ -168,14 +300,24 @@ impl TodoRepo for MongoTodoRepo {
         }
     }
 
-    async fn get_todo_list(
+    async fn restore_todo_list(
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
     ) -> Result<TodoList, ApiError> {
         match self
             .collection
-            .find_one(doc! {"_id": todo_list_id, "user_id": user_id})
+            .find_one_and_update(
+                doc! {"_id": todo_list_id, "user_id": user_id, "deleted_at": {"$ne": null}},
+                doc! {
+                    "$set": {
+                        "deleted_at": null,
+                        "updated_at": timestamp::to_bson(&timestamp::now())
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+            )
+            .return_document(ReturnDocument::After)
             .await
         {
             Ok(Some(todo_list)) => Ok(todo_list),
@@ -187,38 +329,115 @@ impl TodoRepo for MongoTodoRepo {
         }
     }
 
-    async fn rename_todo_list(
+    async fn purge_todo_list(
         &self,
         todo_list_id: ObjectId,
         user_id: ObjectId,
-        title: String,
     ) -> Result<(), ApiError> {
         match self
             .collection
-            .find_one(doc! {"_id": todo_list_id, "user_id": user_id})
+            .delete_one(doc! {
+                "_id": todo_list_id,
+                "user_id": user_id,
+                "deleted_at": {"$ne": null}
+            })
             .await
         {
-            Ok(Some(todo_list)) => {
-                match self
-                    .collection
-                    .update_one(
-                        doc! {"_id": todo_list.id},
-                        doc! { "$set": { "title": title} },
-                    )
-                    .await
-                {
-                    Ok(res) => {
-                        if res.modified_count > 0 {
-                            return Ok(());
-                        }
-                        Err(ApiError::NotFound)
-                    }
-                    Err(err) => {
-                        error!("{}", err);
-                        Err(ApiError::InternalError)
-                    }
-                }
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_trashed_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id, "deleted_at": {"$ne": null}})
+            .sort(doc! {"deleted_at": -1})
+            .await
+        {
+            Ok(res) => res.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_todo_lists_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        match self
+            .collection
+            .find(mongo_changed_since(user_id, since))
+            .await
+        {
+            Ok(res) => res.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
             }
+        }
+    }
+
+    async fn purge_todo_lists_deleted_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let filter = doc! {"deleted_at": {"$ne": null, "$lt": timestamp::to_bson(&before)}};
+        let todo_lists: Vec<TodoList> = match self.collection.find(filter).await {
+            Ok(res) => res.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?,
+            Err(err) => {
+                error!("{}", err);
+                return Err(ApiError::InternalError);
+            }
+        };
+        let ids: Vec<ObjectId> = todo_lists.iter().map(|todo_list| todo_list.id).collect();
+        self.collection
+            .delete_many(doc! {"_id": {"$in": ids}})
+            .await
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
+        Ok(todo_lists)
+    }
+
+    async fn purge_todo_lists_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_many(doc! {"user_id": user_id}).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": todo_list_id, "user_id": user_id, "deleted_at": null})
+            .await
+        {
+            Ok(Some(todo_list)) => Ok(todo_list),
             Ok(None) => Err(ApiError::NotFound),
             Err(err) => {
                 error!("{}", err);
@@ -227,6 +446,27 @@ impl TodoRepo for MongoTodoRepo {
         }
     }
 
+    async fn rename_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        title: String,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.update_live(
+            todo_list_id,
+            user_id,
+            version,
+            doc! {},
+            doc! {"$set": {
+                "title": title,
+                "updated_at": timestamp::to_bson(&timestamp::now()),
+                "updated_by": user_id
+            }},
+        )
+        .await
+    }
+
     async fn create_todo(
         &self,
         todo_list_id: ObjectId,
@@ -234,40 +474,42 @@ impl TodoRepo for MongoTodoRepo {
         title: String,
         status: bool,
         priority: TodoPriority,
-    ) -> Result<(), ApiError> {
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
         let todo = Todo {
             id: ObjectId::new(),
             title: title.to_string(),
             status,
             priority,
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
         };
 
-        let todo_doc = bson::to_document(&todo).map_err(|err| {
-            error!("{}", err);
-            ApiError::InternalError
-        })?;
+        //serialized the way the driver does, timestamps become datetimes
+        let todo_doc = bson::to_raw_document_buf(&todo)
+            .map_err(|err| err.to_string())
+            .and_then(|todo| todo.to_document().map_err(|err| err.to_string()))
+            .map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            })?;
 
-        match self
-            .collection
-            .update_one(
-                doc! {"_id": todo_list_id, "user_id": user_id},
-                doc! { "$push" : {
-                    "todos": todo_doc
-                }},
-            )
-            .await
-        {
-            Ok(res) => {
-                if res.matched_count > 0 {
-                    return Ok(());
+        self.update_live(
+            todo_list_id,
+            user_id,
+            version,
+            doc! {},
+            doc! {
+                "$push" : {"todos": todo_doc},
+                "$set": {
+                    "updated_at": timestamp::to_bson(&now),
+                    "updated_by": user_id
                 }
-                Err(ApiError::NotFound)
-            }
-            Err(err) => {
-                error!("{}", err);
-                Err(ApiError::InternalError)
-            }
-        }
+            },
+        )
+        .await
     }
 
     async fn modify_todo(
@@ -278,24 +520,351 @@ impl TodoRepo for MongoTodoRepo {
         title: String,
         status: bool,
         priority: TodoPriority,
-    ) -> Result<(), ApiError> {
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
         let priority = bson::to_bson_with_options(&priority, SerializerOptions::default())
             .map_err(|err| {
                 error!("{}", err);
                 ApiError::InternalError
             })?;
-        match self.collection.update_one(doc! { "_id": todo_list_id, "user_id": user_id, "todos._id": todo_id}, doc !{"$set": { "todos.$.title": title, "todos.$.status": status, "todos.$.priority": priority}}).await {
-            Ok(res) => {
-                if res.matched_count > 0 {
-                    return Ok(());
+        let now = timestamp::to_bson(&timestamp::now());
+        self.update_live(
+            todo_list_id,
+            user_id,
+            version,
+            doc! {"todos._id": todo_id},
+            doc! {"$set": {
+                "todos.$.title": title,
+                "todos.$.status": status,
+                "todos.$.priority": priority,
+                "todos.$.updated_at": now,
+                "todos.$.updated_by": user_id,
+                "updated_at": now,
+                "updated_by": user_id
+            }},
+        )
+        .await
+    }
+
+    async fn delete_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        todo_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.update_live(
+            todo_list_id,
+            user_id,
+            version,
+            doc! {"todos._id": todo_id},
+            doc! {
+                "$pull": { "todos": { "_id": todo_id}},
+                "$set": {
+                    "updated_at": timestamp::to_bson(&timestamp::now()),
+                    "updated_by": user_id
                 }
-                Err(ApiError::NotFound)
+            },
+        )
+        .await
+    }
+}
+
+pub struct SledTodoRepo {
+    tree: sled::Tree,
+}
+
+impl SledTodoRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    //same as sled_store::update but a todo list in the trash counts as missing
+    async fn update_live<F>(
+        &self,
+        user_id: ObjectId,
+        todo_list_id: ObjectId,
+        modify: F,
+    ) -> Result<TodoList, ApiError>
+    where
+        F: Fn(&mut TodoList) -> Result<(), ApiError>,
+    {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, todo_list_id]),
+            |todo_list: &mut TodoList| {
+                if todo_list.deleted_at.is_some() {
+                    return Err(ApiError::NotFound);
+                }
+                modify(todo_list)
+            },
+        )
+        .await
+    }
+
+    fn user_todo_lists(&self, user_id: ObjectId, trashed: bool) -> Result<Vec<TodoList>, ApiError> {
+        let todo_lists: Vec<TodoList> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        Ok(todo_lists
+            .into_iter()
+            .filter(|todo_list| todo_list.deleted_at.is_some() == trashed)
+            .collect())
+    }
+
+    fn live_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<TodoList>, ApiError> {
+        Ok(
+            sled_store::get::<TodoList>(&self.tree, &sled_store::key(&[user_id, todo_list_id]))?
+                .filter(|todo_list| todo_list.deleted_at.is_none()),
+        )
+    }
+}
+
+#[async_trait]
+impl TodoRepo for SledTodoRepo {
+    async fn create_todo_list(
+        &self,
+        title: String,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
+        let new_todo_list = TodoList {
+            id: ObjectId::new(),
+            user_id,
+            title,
+            todos: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            version: 1,
+        };
+        sled_store::insert(
+            &self.tree,
+            &sled_store::key(&[user_id, new_todo_list.id]),
+            &new_todo_list,
+        )
+        .await?;
+        Ok(new_todo_list)
+    }
+
+    async fn get_all_todo_lists(
+        &self,
+        user_id: ObjectId,
+        only: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<TodoList>, ApiError> {
+        let mut todos = self.user_todo_lists(user_id, false)?;
+        if let Some(ids) = only {
+            todos.retain(|todo_list| ids.contains(&todo_list.id));
+        }
+        Ok(pagination::paginate(todos, options))
+    }
+
+    async fn get_todo_lists(
+        &self,
+        list: Vec<ObjectId>,
+        user_id: ObjectId,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let mut temp: Vec<TodoList> = vec![];
+        for id in list.iter() {
+            if let Some(todo) = self.live_todo_list(*id, user_id)? {
+                temp.push(todo);
             }
-            Err(err) => {
-                error!("{}", err);
-                Err(ApiError::InternalError)
+        }
+        Ok(temp)
+    }
+
+    async fn delete_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
+        self.update_live(user_id, todo_list_id, |todo_list| {
+            check_version(todo_list, version)?;
+            todo_list.deleted_at = Some(now);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn restore_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        sled_store::update(
+            &self.tree,
+            &sled_store::key(&[user_id, todo_list_id]),
+            |todo_list: &mut TodoList| {
+                if todo_list.deleted_at.is_none() {
+                    return Err(ApiError::NotFound);
+                }
+                todo_list.deleted_at = None;
+                todo_list.updated_at = timestamp::now();
+                todo_list.version += 1;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    async fn purge_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id, todo_list_id]);
+        match sled_store::get::<TodoList>(&self.tree, &key)? {
+            Some(todo_list) if todo_list.deleted_at.is_some() => {
+                sled_store::remove::<TodoList>(&self.tree, &key).await?;
+                Ok(())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_trashed_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError> {
+        let mut todo_lists = self.user_todo_lists(user_id, true)?;
+        todo_lists.sort_by_key(|todo_list| std::cmp::Reverse(todo_list.deleted_at));
+        Ok(todo_lists)
+    }
+
+    async fn get_todo_lists_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let mut todo_lists: Vec<TodoList> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        todo_lists
+            .retain(|todo_list| changed_since(todo_list.updated_at, todo_list.deleted_at, since));
+        Ok(todo_lists)
+    }
+
+    async fn purge_todo_lists_deleted_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let todo_lists: Vec<TodoList> = sled_store::scan_prefix(&self.tree, &[])?;
+        let mut purged = vec![];
+        for todo_list in todo_lists {
+            if todo_list
+                .deleted_at
+                .is_some_and(|deleted_at| deleted_at < before)
+            {
+                sled_store::remove::<TodoList>(
+                    &self.tree,
+                    &sled_store::key(&[todo_list.user_id, todo_list.id]),
+                )
+                .await?;
+                purged.push(todo_list);
             }
         }
+        Ok(purged)
+    }
+
+    async fn purge_todo_lists_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        let todo_lists: Vec<TodoList> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        for todo_list in todo_lists {
+            sled_store::remove::<TodoList>(&self.tree, &sled_store::key(&[user_id, todo_list.id]))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        self.live_todo_list(todo_list_id, user_id)?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn rename_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        title: String,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.update_live(user_id, todo_list_id, |todo_list| {
+            check_version(todo_list, version)?;
+            todo_list.title = title.clone();
+            todo_list.updated_at = timestamp::now();
+            todo_list.updated_by = user_id;
+            todo_list.version += 1;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        let todo_id = ObjectId::new();
+        let now = timestamp::now();
+        self.update_live(user_id, todo_list_id, |todo_list| {
+            check_version(todo_list, version)?;
+            todo_list.todos.push(Todo {
+                id: todo_id,
+                title: title.clone(),
+                status,
+                priority,
+                created_at: now,
+                updated_at: now,
+                updated_by: user_id,
+            });
+            todo_list.updated_at = now;
+            todo_list.updated_by = user_id;
+            todo_list.version += 1;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn modify_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        todo_id: ObjectId,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.update_live(user_id, todo_list_id, |todo_list| {
+            check_version(todo_list, version)?;
+            let todo = todo_list
+                .todos
+                .iter_mut()
+                .find(|todo| todo.id == todo_id)
+                .ok_or(ApiError::NotFound)?;
+            let now = timestamp::now();
+            todo.title = title.clone();
+            todo.status = status;
+            todo.priority = priority;
+            todo.updated_at = now;
+            todo.updated_by = user_id;
+            todo_list.updated_at = now;
+            todo_list.updated_by = user_id;
+            todo_list.version += 1;
+            Ok(())
+        })
+        .await
     }
 
     async fn delete_todo(
@@ -303,25 +872,313 @@ impl TodoRepo for MongoTodoRepo {
         todo_list_id: ObjectId,
         user_id: ObjectId,
         todo_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.update_live(user_id, todo_list_id, |todo_list| {
+            check_version(todo_list, version)?;
+            if !todo_list.todos.iter().any(|todo| todo.id == todo_id) {
+                return Err(ApiError::NotFound);
+            }
+            todo_list.todos.retain(|todo| todo.id != todo_id);
+            todo_list.updated_at = timestamp::now();
+            todo_list.updated_by = user_id;
+            todo_list.version += 1;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/*
+* Keeps todo lists in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryTodoRepo {
+    todo_lists: RwLock<BTreeMap<ObjectId, TodoList>>,
+}
+
+impl MemoryTodoRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn modify_todo_list<F>(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+        modify: F,
+    ) -> Result<TodoList, ApiError>
+    where
+        F: FnOnce(&mut TodoList, DateTime<Utc>) -> Result<(), ApiError>,
+    {
+        let now = timestamp::now();
+        match self.todo_lists.write().unwrap().get_mut(&todo_list_id) {
+            Some(todo_list) if todo_list.user_id == user_id && todo_list.deleted_at.is_none() => {
+                check_version(todo_list, version)?;
+                modify(todo_list, now)?;
+                todo_list.updated_at = now;
+                todo_list.updated_by = user_id;
+                todo_list.version += 1;
+                Ok(todo_list.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl TodoRepo for MemoryTodoRepo {
+    async fn create_todo_list(
+        &self,
+        title: String,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
+        let new_todo_list = TodoList {
+            id: ObjectId::new(),
+            user_id,
+            title,
+            todos: vec![],
+            created_at: now,
+            updated_at: now,
+            updated_by: user_id,
+            deleted_at: None,
+            version: 1,
+        };
+        self.todo_lists
+            .write()
+            .unwrap()
+            .insert(new_todo_list.id, new_todo_list.clone());
+        Ok(new_todo_list)
+    }
+
+    async fn get_all_todo_lists(
+        &self,
+        user_id: ObjectId,
+        only: Option<&[ObjectId]>,
+        options: &ListOptions,
+    ) -> Result<Page<TodoList>, ApiError> {
+        let todos = self
+            .todo_lists
+            .read()
+            .unwrap()
+            .values()
+            .filter(|todo_list| todo_list.user_id == user_id && todo_list.deleted_at.is_none())
+            .filter(|todo_list| only.is_none_or(|ids| ids.contains(&todo_list.id)))
+            .cloned()
+            .collect();
+        Ok(pagination::paginate(todos, options))
+    }
+
+    async fn get_todo_lists(
+        &self,
+        list: Vec<ObjectId>,
+        user_id: ObjectId,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let todo_lists = self.todo_lists.read().unwrap();
+        Ok(list
+            .iter()
+            .filter_map(|id| todo_lists.get(id))
+            .filter(|todo_list| todo_list.user_id == user_id && todo_list.deleted_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        let now = timestamp::now();
+        match self.todo_lists.write().unwrap().get_mut(&todo_list_id) {
+            Some(todo_list) if todo_list.user_id == user_id && todo_list.deleted_at.is_none() => {
+                check_version(todo_list, version)?;
+                todo_list.deleted_at = Some(now);
+                Ok(todo_list.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn restore_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        match self.todo_lists.write().unwrap().get_mut(&todo_list_id) {
+            Some(todo_list) if todo_list.user_id == user_id && todo_list.deleted_at.is_some() => {
+                todo_list.deleted_at = None;
+                todo_list.updated_at = timestamp::now();
+                todo_list.version += 1;
+                Ok(todo_list.clone())
+            }
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn purge_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
     ) -> Result<(), ApiError> {
-        match self
-            .collection
-            .update_one(
-                doc! { "_id": todo_list_id, "user_id": user_id },
-                doc! { "$pull": { "todos": { "_id": todo_id}}},
-            )
-            .await
-        {
-            Ok(res) => {
-                if res.matched_count > 0 {
-                    return Ok(());
-                }
-                Err(ApiError::NotFound)
+        let mut todo_lists = self.todo_lists.write().unwrap();
+        match todo_lists.get(&todo_list_id) {
+            Some(todo_list) if todo_list.user_id == user_id && todo_list.deleted_at.is_some() => {
+                todo_lists.remove(&todo_list_id);
+                Ok(())
             }
-            Err(err) => {
-                error!("{}", err);
-                Err(ApiError::InternalError)
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_trashed_todo_lists(&self, user_id: ObjectId) -> Result<Vec<TodoList>, ApiError> {
+        let mut todo_lists: Vec<TodoList> = self
+            .todo_lists
+            .read()
+            .unwrap()
+            .values()
+            .filter(|todo_list| todo_list.user_id == user_id && todo_list.deleted_at.is_some())
+            .cloned()
+            .collect();
+        todo_lists.sort_by_key(|todo_list| std::cmp::Reverse(todo_list.deleted_at));
+        Ok(todo_lists)
+    }
+
+    async fn get_todo_lists_changed_since(
+        &self,
+        user_id: ObjectId,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        Ok(self
+            .todo_lists
+            .read()
+            .unwrap()
+            .values()
+            .filter(|todo_list| {
+                todo_list.user_id == user_id
+                    && changed_since(todo_list.updated_at, todo_list.deleted_at, since)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_todo_lists_deleted_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<TodoList>, ApiError> {
+        let mut todo_lists = self.todo_lists.write().unwrap();
+        let expired: Vec<ObjectId> = todo_lists
+            .values()
+            .filter(|todo_list| {
+                todo_list
+                    .deleted_at
+                    .is_some_and(|deleted_at| deleted_at < before)
+            })
+            .map(|todo_list| todo_list.id)
+            .collect();
+        Ok(expired
+            .iter()
+            .filter_map(|id| todo_lists.remove(id))
+            .collect())
+    }
+
+    async fn purge_todo_lists_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        self.todo_lists
+            .write()
+            .unwrap()
+            .retain(|_id, todo_list| todo_list.user_id != user_id);
+        Ok(())
+    }
+
+    async fn get_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<TodoList, ApiError> {
+        match self.todo_lists.read().unwrap().get(&todo_list_id) {
+            Some(todo_list) if todo_list.user_id == user_id && todo_list.deleted_at.is_none() => {
+                Ok(todo_list.clone())
             }
+            _ => Err(ApiError::NotFound),
         }
     }
+
+    async fn rename_todo_list(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        title: String,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.modify_todo_list(todo_list_id, user_id, version, |todo_list, _| {
+            todo_list.title = title;
+            Ok(())
+        })
+    }
+
+    async fn create_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.modify_todo_list(todo_list_id, user_id, version, |todo_list, now| {
+            todo_list.todos.push(Todo {
+                id: ObjectId::new(),
+                title,
+                status,
+                priority,
+                created_at: now,
+                updated_at: now,
+                updated_by: user_id,
+            });
+            Ok(())
+        })
+    }
+
+    async fn modify_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        todo_id: ObjectId,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.modify_todo_list(todo_list_id, user_id, version, |todo_list, now| {
+            let todo = todo_list
+                .todos
+                .iter_mut()
+                .find(|todo| todo.id == todo_id)
+                .ok_or(ApiError::NotFound)?;
+            todo.title = title;
+            todo.status = status;
+            todo.priority = priority;
+            todo.updated_at = now;
+            todo.updated_by = user_id;
+            Ok(())
+        })
+    }
+
+    async fn delete_todo(
+        &self,
+        todo_list_id: ObjectId,
+        user_id: ObjectId,
+        todo_id: ObjectId,
+        version: Option<u64>,
+    ) -> Result<TodoList, ApiError> {
+        self.modify_todo_list(todo_list_id, user_id, version, |todo_list, _| {
+            if !todo_list.todos.iter().any(|todo| todo.id == todo_id) {
+                return Err(ApiError::NotFound);
+            }
+            todo_list.todos.retain(|todo| todo.id != todo_id);
+            Ok(())
+        })
+    }
 }
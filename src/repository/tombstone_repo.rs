@@ -0,0 +1,240 @@
+use crate::{
+    error::ApiError,
+    models::{timestamp, tombstone::Tombstone},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
+use std::{collections::BTreeMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait TombstoneRepo: Send + Sync {
+    async fn add_tombstones(&self, tombstones: &[Tombstone]) -> Result<(), ApiError>;
+    /// Tombstones of the user from `since` on
+    async fn get_tombstones_since(
+        &self,
+        user_id: ObjectId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Tombstone>, ApiError>;
+    /// Tombstone of a document the user owned
+    async fn get_tombstone(&self, user_id: ObjectId, id: ObjectId) -> Result<Tombstone, ApiError>;
+    /// Forgets tombstones of every user older than `before`
+    async fn delete_tombstones_before(&self, before: DateTime<Utc>) -> Result<(), ApiError>;
+    async fn delete_tombstones_of_user(&self, user_id: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoTombstoneRepo {
+    collection: Collection<Tombstone>,
+}
+
+impl MongoTombstoneRepo {
+    pub fn new(collection: Collection<Tombstone>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl TombstoneRepo for MongoTombstoneRepo {
+    async fn add_tombstones(&self, tombstones: &[Tombstone]) -> Result<(), ApiError> {
+        if tombstones.is_empty() {
+            return Ok(());
+        }
+        match self.collection.insert_many(tombstones).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_tombstones_since(
+        &self,
+        user_id: ObjectId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Tombstone>, ApiError> {
+        match self
+            .collection
+            .find(doc! {"user_id": user_id, "deleted_at": {"$gte": timestamp::to_bson(&since)}})
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.map_err(|err| {
+                error!("{}", err);
+                ApiError::InternalError
+            }),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_tombstone(&self, user_id: ObjectId, id: ObjectId) -> Result<Tombstone, ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"_id": id, "user_id": user_id})
+            .await
+        {
+            Ok(Some(tombstone)) => Ok(tombstone),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_tombstones_before(&self, before: DateTime<Utc>) -> Result<(), ApiError> {
+        match self
+            .collection
+            .delete_many(doc! {"deleted_at": {"$lt": timestamp::to_bson(&before)}})
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_tombstones_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_many(doc! {"user_id": user_id}).await {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Tombstones are keyed by user_id ++ id
+*/
+pub struct SledTombstoneRepo {
+    tree: sled::Tree,
+}
+
+impl SledTombstoneRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl TombstoneRepo for SledTombstoneRepo {
+    async fn add_tombstones(&self, tombstones: &[Tombstone]) -> Result<(), ApiError> {
+        for tombstone in tombstones {
+            let key = sled_store::key(&[tombstone.user_id, tombstone.id]);
+            sled_store::insert(&self.tree, &key, tombstone).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_tombstones_since(
+        &self,
+        user_id: ObjectId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Tombstone>, ApiError> {
+        let mut tombstones: Vec<Tombstone> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        tombstones.retain(|tombstone| tombstone.deleted_at >= since);
+        Ok(tombstones)
+    }
+
+    async fn get_tombstone(&self, user_id: ObjectId, id: ObjectId) -> Result<Tombstone, ApiError> {
+        sled_store::get(&self.tree, &sled_store::key(&[user_id, id]))?.ok_or(ApiError::NotFound)
+    }
+
+    async fn delete_tombstones_before(&self, before: DateTime<Utc>) -> Result<(), ApiError> {
+        let tombstones: Vec<Tombstone> = sled_store::scan_prefix(&self.tree, &[])?;
+        for tombstone in tombstones
+            .iter()
+            .filter(|tombstone| tombstone.deleted_at < before)
+        {
+            let key = sled_store::key(&[tombstone.user_id, tombstone.id]);
+            sled_store::remove::<Tombstone>(&self.tree, &key).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_tombstones_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        let tombstones: Vec<Tombstone> =
+            sled_store::scan_prefix(&self.tree, &sled_store::key(&[user_id]))?;
+        for tombstone in tombstones.iter() {
+            let key = sled_store::key(&[user_id, tombstone.id]);
+            sled_store::remove::<Tombstone>(&self.tree, &key).await?;
+        }
+        Ok(())
+    }
+}
+
+/*
+* Keeps tombstones in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryTombstoneRepo {
+    tombstones: RwLock<BTreeMap<ObjectId, Tombstone>>,
+}
+
+impl MemoryTombstoneRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TombstoneRepo for MemoryTombstoneRepo {
+    async fn add_tombstones(&self, tombstones: &[Tombstone]) -> Result<(), ApiError> {
+        let mut stored = self.tombstones.write().unwrap();
+        for tombstone in tombstones {
+            stored.insert(tombstone.id, tombstone.clone());
+        }
+        Ok(())
+    }
+
+    async fn get_tombstones_since(
+        &self,
+        user_id: ObjectId,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Tombstone>, ApiError> {
+        Ok(self
+            .tombstones
+            .read()
+            .unwrap()
+            .values()
+            .filter(|tombstone| tombstone.user_id == user_id && tombstone.deleted_at >= since)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_tombstone(&self, user_id: ObjectId, id: ObjectId) -> Result<Tombstone, ApiError> {
+        match self.tombstones.read().unwrap().get(&id) {
+            Some(tombstone) if tombstone.user_id == user_id => Ok(tombstone.clone()),
+            _ => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_tombstones_before(&self, before: DateTime<Utc>) -> Result<(), ApiError> {
+        self.tombstones
+            .write()
+            .unwrap()
+            .retain(|_id, tombstone| tombstone.deleted_at >= before);
+        Ok(())
+    }
+
+    async fn delete_tombstones_of_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        self.tombstones
+            .write()
+            .unwrap()
+            .retain(|_id, tombstone| tombstone.user_id != user_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{
+    error::ApiError,
+    models::{timestamp, two_factor::TwoFactor},
+    repository::sled_store,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
+use std::{collections::HashMap, sync::RwLock};
+use tracing::error;
+
+#[async_trait]
+pub trait TwoFactorRepo: Send + Sync {
+    /// Replaces whatever the user had before
+    async fn save_two_factor(&self, two_factor: &TwoFactor) -> Result<(), ApiError>;
+    async fn get_two_factor(&self, user_id: ObjectId) -> Result<TwoFactor, ApiError>;
+    /// Moves `last_step` forward and clears failed attempts, `InvalidCode`
+    /// when a code of `step` or later was already accepted
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<(), ApiError>;
+    /// Removes the recovery code and clears failed attempts, `InvalidCode`
+    /// when there is no such code
+    async fn use_recovery_code(&self, user_id: ObjectId, hash: &str) -> Result<(), ApiError>;
+    async fn record_failed_attempt(
+        &self,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError>;
+    async fn delete_two_factor(&self, user_id: ObjectId) -> Result<(), ApiError>;
+}
+
+pub struct MongoTwoFactorRepo {
+    collection: Collection<TwoFactor>,
+}
+
+impl MongoTwoFactorRepo {
+    pub fn new(collection: Collection<TwoFactor>) -> Self {
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepo for MongoTwoFactorRepo {
+    async fn save_two_factor(&self, two_factor: &TwoFactor) -> Result<(), ApiError> {
+        match self
+            .collection
+            .replace_one(doc! {"_id": two_factor.user_id}, two_factor)
+            .upsert(true)
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn get_two_factor(&self, user_id: ObjectId) -> Result<TwoFactor, ApiError> {
+        match self.collection.find_one(doc! {"_id": user_id}).await {
+            Ok(Some(two_factor)) => Ok(two_factor),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "last_step": {"$lt": step}},
+                doc! {"$set": {"last_step": step, "failed_attempts": 0}},
+            )
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::InvalidCode),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn use_recovery_code(&self, user_id: ObjectId, hash: &str) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": user_id, "recovery_codes": hash},
+                doc! {"$pull": {"recovery_codes": hash}, "$set": {"failed_attempts": 0}},
+            )
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::InvalidCode),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(
+                doc! {"_id": user_id},
+                doc! {
+                    "$inc": {"failed_attempts": 1},
+                    "$set": {"last_failed_at": timestamp::to_bson(&at)},
+                },
+            )
+            .await
+        {
+            Ok(_res) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_two_factor(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_one(doc! {"_id": user_id}).await {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+/*
+* Keyed by user_id
+*/
+pub struct SledTwoFactorRepo {
+    tree: sled::Tree,
+}
+
+impl SledTwoFactorRepo {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepo for SledTwoFactorRepo {
+    async fn save_two_factor(&self, two_factor: &TwoFactor) -> Result<(), ApiError> {
+        let key = sled_store::key(&[two_factor.user_id]);
+        sled_store::insert(&self.tree, &key, two_factor).await
+    }
+
+    async fn get_two_factor(&self, user_id: ObjectId) -> Result<TwoFactor, ApiError> {
+        sled_store::get(&self.tree, &sled_store::key(&[user_id]))?.ok_or(ApiError::NotFound)
+    }
+
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id]);
+        sled_store::update(&self.tree, &key, |two_factor: &mut TwoFactor| {
+            if two_factor.last_step >= step {
+                return Err(ApiError::InvalidCode);
+            }
+            two_factor.last_step = step;
+            two_factor.failed_attempts = 0;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn use_recovery_code(&self, user_id: ObjectId, hash: &str) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id]);
+        sled_store::update(&self.tree, &key, |two_factor: &mut TwoFactor| {
+            let before = two_factor.recovery_codes.len();
+            two_factor.recovery_codes.retain(|code| code != hash);
+            if two_factor.recovery_codes.len() == before {
+                return Err(ApiError::InvalidCode);
+            }
+            two_factor.failed_attempts = 0;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id]);
+        sled_store::update(&self.tree, &key, |two_factor: &mut TwoFactor| {
+            two_factor.failed_attempts += 1;
+            two_factor.last_failed_at = Some(at);
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_two_factor(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        let key = sled_store::key(&[user_id]);
+        sled_store::remove::<TwoFactor>(&self.tree, &key)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        Ok(())
+    }
+}
+
+/*
+* Keeps second factors in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryTwoFactorRepo {
+    two_factors: RwLock<HashMap<ObjectId, TwoFactor>>,
+}
+
+impl MemoryTwoFactorRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepo for MemoryTwoFactorRepo {
+    async fn save_two_factor(&self, two_factor: &TwoFactor) -> Result<(), ApiError> {
+        self.two_factors
+            .write()
+            .unwrap()
+            .insert(two_factor.user_id, two_factor.clone());
+        Ok(())
+    }
+
+    async fn get_two_factor(&self, user_id: ObjectId) -> Result<TwoFactor, ApiError> {
+        self.two_factors
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn use_step(&self, user_id: ObjectId, step: i64) -> Result<(), ApiError> {
+        match self.two_factors.write().unwrap().get_mut(&user_id) {
+            Some(two_factor) if two_factor.last_step < step => {
+                two_factor.last_step = step;
+                two_factor.failed_attempts = 0;
+                Ok(())
+            }
+            _ => Err(ApiError::InvalidCode),
+        }
+    }
+
+    async fn use_recovery_code(&self, user_id: ObjectId, hash: &str) -> Result<(), ApiError> {
+        let mut two_factors = self.two_factors.write().unwrap();
+        let Some(two_factor) = two_factors.get_mut(&user_id) else {
+            return Err(ApiError::InvalidCode);
+        };
+        match two_factor
+            .recovery_codes
+            .iter()
+            .position(|code| code == hash)
+        {
+            Some(index) => {
+                two_factor.recovery_codes.remove(index);
+                two_factor.failed_attempts = 0;
+                Ok(())
+            }
+            None => Err(ApiError::InvalidCode),
+        }
+    }
+
+    async fn record_failed_attempt(
+        &self,
+        user_id: ObjectId,
+        at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        if let Some(two_factor) = self.two_factors.write().unwrap().get_mut(&user_id) {
+            two_factor.failed_attempts += 1;
+            two_factor.last_failed_at = Some(at);
+        }
+        Ok(())
+    }
+
+    async fn delete_two_factor(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        self.two_factors
+            .write()
+            .unwrap()
+            .remove(&user_id)
+            .map(|_| ())
+            .ok_or(ApiError::NotFound)
+    }
+}
@@ -1,14 +1,23 @@
 use crate::error::ApiError;
 use crate::models::user::User;
+use crate::repository::sled_store;
 use async_trait::async_trait;
-use mongodb::bson::doc;
+use mongodb::bson::{doc, oid::ObjectId};
 use mongodb::Collection;
+use std::collections::HashMap;
+use std::sync::RwLock;
 use tracing::error;
 #[async_trait]
 pub trait UserRepo: Send + Sync {
     async fn get_user(&self, username: &str) -> Result<User, ApiError>;
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<User, ApiError>;
     async fn user_exist(&self, username: &str, email: &str) -> Result<bool, ApiError>;
     async fn create_user(&self, user: &User) -> Result<User, ApiError>;
+    /// `password` is the bcrypt hash
+    async fn update_password(&self, user_id: ObjectId, password: &str) -> Result<(), ApiError>;
+    /// `UserExist` when another user has the email already
+    async fn update_email(&self, user_id: ObjectId, email: &str) -> Result<(), ApiError>;
+    async fn delete_user(&self, user_id: ObjectId) -> Result<(), ApiError>;
 }
 
 pub struct MongoUserRepo {
@@ -33,6 +42,17 @@ impl UserRepo for MongoUserRepo {
         }
     }
 
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<User, ApiError> {
+        match self.collection.find_one(doc! {"_id": user_id}).await {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
     async fn user_exist(&self, username: &str, email: &str) -> Result<bool, ApiError> {
         //TODO think about the returns
         match self
@@ -61,4 +81,261 @@ impl UserRepo for MongoUserRepo {
             }
         }
     }
+
+    async fn update_password(&self, user_id: ObjectId, password: &str) -> Result<(), ApiError> {
+        match self
+            .collection
+            .update_one(doc! {"_id": user_id}, doc! {"$set": {"password": password}})
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn update_email(&self, user_id: ObjectId, email: &str) -> Result<(), ApiError> {
+        match self
+            .collection
+            .find_one(doc! {"email": email, "_id": {"$ne": user_id}})
+            .await
+        {
+            Ok(Some(_)) => return Err(ApiError::UserExist),
+            Ok(None) => {}
+            Err(err) => {
+                error!("{}", err);
+                return Err(ApiError::InternalError);
+            }
+        }
+        match self
+            .collection
+            .update_one(doc! {"_id": user_id}, doc! {"$set": {"email": email}})
+            .await
+        {
+            Ok(res) if res.matched_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        match self.collection.delete_one(doc! {"_id": user_id}).await {
+            Ok(res) if res.deleted_count > 0 => Ok(()),
+            Ok(_res) => Err(ApiError::NotFound),
+            Err(err) => {
+                error!("{}", err);
+                Err(ApiError::InternalError)
+            }
+        }
+    }
+}
+
+pub struct SledUserRepo {
+    users: sled::Tree,
+    usernames: sled::Tree,
+}
+
+impl SledUserRepo {
+    pub fn new(users: sled::Tree, usernames: sled::Tree) -> Self {
+        Self { users, usernames }
+    }
+}
+
+#[async_trait]
+impl UserRepo for SledUserRepo {
+    async fn get_user(&self, username: &str) -> Result<User, ApiError> {
+        match self
+            .usernames
+            .get(username)
+            .map_err(sled_store::sled_error)?
+        {
+            Some(id) => {
+                let id = ObjectId::from_bytes(id.as_ref().try_into().map_err(|_| {
+                    error!("Corrupted username index for {}", username);
+                    ApiError::InternalError
+                })?);
+                sled_store::get(&self.users, &sled_store::key(&[id]))?.ok_or(ApiError::NotFound)
+            }
+            None => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<User, ApiError> {
+        sled_store::get(&self.users, &sled_store::key(&[user_id]))?.ok_or(ApiError::NotFound)
+    }
+
+    async fn user_exist(&self, username: &str, email: &str) -> Result<bool, ApiError> {
+        if self
+            .usernames
+            .contains_key(username)
+            .map_err(sled_store::sled_error)?
+        {
+            return Err(ApiError::UserExist);
+        }
+        let users: Vec<User> = sled_store::scan_prefix(&self.users, &[])?;
+        if users.iter().any(|user| user.email == email) {
+            return Err(ApiError::UserExist);
+        }
+        Ok(false)
+    }
+
+    async fn create_user(&self, user: &User) -> Result<User, ApiError> {
+        //claim the username first so two concurrent registrations can't both succeed
+        self.usernames
+            .compare_and_swap(
+                user.username.as_str(),
+                None as Option<&[u8]>,
+                Some(&user.id.bytes()[..]),
+            )
+            .map_err(sled_store::sled_error)?
+            .map_err(|_| ApiError::UserExist)?;
+        sled_store::insert(&self.users, &sled_store::key(&[user.id]), user).await?;
+        Ok(user.to_owned())
+    }
+
+    async fn update_password(&self, user_id: ObjectId, password: &str) -> Result<(), ApiError> {
+        sled_store::update(
+            &self.users,
+            &sled_store::key(&[user_id]),
+            |user: &mut User| {
+                user.password = password.to_string();
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_email(&self, user_id: ObjectId, email: &str) -> Result<(), ApiError> {
+        let users: Vec<User> = sled_store::scan_prefix(&self.users, &[])?;
+        if users
+            .iter()
+            .any(|user| user.email == email && user.id != user_id)
+        {
+            return Err(ApiError::UserExist);
+        }
+        sled_store::update(
+            &self.users,
+            &sled_store::key(&[user_id]),
+            |user: &mut User| {
+                user.email = email.to_string();
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        let user = sled_store::remove::<User>(&self.users, &sled_store::key(&[user_id]))
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        //frees the username for a new registration
+        self.usernames
+            .remove(user.username.as_str())
+            .map_err(sled_store::sled_error)?;
+        sled_store::flush(&self.usernames).await
+    }
+}
+
+/*
+* Keeps users in process memory, used by the tests and nothing is persisted
+*/
+#[derive(Default)]
+pub struct MemoryUserRepo {
+    users: RwLock<HashMap<String, User>>,
+}
+
+impl MemoryUserRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserRepo for MemoryUserRepo {
+    async fn get_user(&self, username: &str) -> Result<User, ApiError> {
+        self.users
+            .read()
+            .unwrap()
+            .get(username)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<User, ApiError> {
+        self.users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.id == user_id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn user_exist(&self, username: &str, email: &str) -> Result<bool, ApiError> {
+        let users = self.users.read().unwrap();
+        if users.contains_key(username) || users.values().any(|user| user.email == email) {
+            return Err(ApiError::UserExist);
+        }
+        Ok(false)
+    }
+
+    async fn create_user(&self, user: &User) -> Result<User, ApiError> {
+        let mut users = self.users.write().unwrap();
+        if users.contains_key(&user.username) {
+            return Err(ApiError::UserExist);
+        }
+        users.insert(user.username.clone(), user.to_owned());
+        Ok(user.to_owned())
+    }
+
+    async fn update_password(&self, user_id: ObjectId, password: &str) -> Result<(), ApiError> {
+        match self
+            .users
+            .write()
+            .unwrap()
+            .values_mut()
+            .find(|user| user.id == user_id)
+        {
+            Some(user) => {
+                user.password = password.to_string();
+                Ok(())
+            }
+            None => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn update_email(&self, user_id: ObjectId, email: &str) -> Result<(), ApiError> {
+        let mut users = self.users.write().unwrap();
+        if users
+            .values()
+            .any(|user| user.email == email && user.id != user_id)
+        {
+            return Err(ApiError::UserExist);
+        }
+        match users.values_mut().find(|user| user.id == user_id) {
+            Some(user) => {
+                user.email = email.to_string();
+                Ok(())
+            }
+            None => Err(ApiError::NotFound),
+        }
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<(), ApiError> {
+        let mut users = self.users.write().unwrap();
+        let before = users.len();
+        users.retain(|_username, user| user.id != user_id);
+        match users.len() < before {
+            true => Ok(()),
+            false => Err(ApiError::NotFound),
+        }
+    }
 }
@@ -0,0 +1,81 @@
+use axum::{extract::State, Extension, Json};
+use serde::Deserialize;
+
+use crate::{
+    auth::{AuthUser, CurrentSession},
+    error::ApiError,
+    logger::UserDeleted,
+    services, AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordPayload {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeEmailPayload {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountPayload {
+    pub password: String,
+}
+
+pub async fn change_password(
+    State(app_state): State<AppState>,
+    Extension(session): CurrentSession,
+    Json(payload): Json<ChangePasswordPayload>,
+) -> Result<(), ApiError> {
+    if payload.current_password.is_empty() || payload.new_password.is_empty() {
+        return Err(ApiError::MissingCredential);
+    }
+    services::user_service::change_password(
+        &app_state.database,
+        &app_state.events,
+        &session,
+        &payload.current_password,
+        &payload.new_password,
+    )
+    .await
+}
+
+pub async fn change_email(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<ChangeEmailPayload>,
+) -> Result<(), ApiError> {
+    if payload.email.is_empty() || payload.password.is_empty() {
+        return Err(ApiError::MissingCredential);
+    }
+    services::user_service::change_email(
+        &app_state.database,
+        user.id,
+        &payload.password,
+        &payload.email,
+    )
+    .await
+}
+
+pub async fn delete_account(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<DeleteAccountPayload>,
+) -> Result<Extension<UserDeleted>, ApiError> {
+    if payload.password.is_empty() {
+        return Err(ApiError::MissingCredential);
+    }
+    services::user_service::delete_account(
+        &app_state.database,
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        &payload.password,
+    )
+    .await?;
+    //its logs are gone, the request deleting them doesn't leave a new one
+    Ok(Extension(UserDeleted))
+}
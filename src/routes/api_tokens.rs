@@ -0,0 +1,99 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{
+        api_token::{ApiToken, Scope},
+        timestamp,
+    },
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTokenInfo {
+    pub id: ObjectId,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(with = "timestamp::optional")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+//the hash never leaves the backend
+impl From<ApiToken> for ApiTokenInfo {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            id: token.id,
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+    //shown this once, only its hash is stored
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateTokenPayload {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    //null keeps the token valid until it is revoked
+    #[serde(default, with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub async fn get_tokens(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<Vec<ApiTokenInfo>>, ApiError> {
+    let tokens =
+        services::api_token_service::get_tokens(app_state.database.api_token_repo(), user.id)
+            .await?;
+    Ok(Json(tokens.into_iter().map(ApiTokenInfo::from).collect()))
+}
+
+pub async fn create_token(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<CreateTokenPayload>,
+) -> Result<Json<CreatedApiToken>, ApiError> {
+    let (stored, token) = services::api_token_service::create_token(
+        app_state.database.api_token_repo(),
+        user.id,
+        &payload.name,
+        payload.scopes,
+        payload.expires_at,
+    )
+    .await?;
+    Ok(Json(CreatedApiToken {
+        info: stored.into(),
+        token,
+    }))
+}
+
+pub async fn revoke_token(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<(), ApiError> {
+    services::api_token_service::revoke_token(app_state.database.api_token_repo(), user.id, id)
+        .await
+}
@@ -0,0 +1,233 @@
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{attachment::Attachment, timestamp},
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AttachmentInfo {
+    pub id: ObjectId,
+    pub note_id: ObjectId,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    //where to download it, with the Authorization header like every other route
+    pub url: String,
+}
+
+impl From<Attachment> for AttachmentInfo {
+    fn from(attachment: Attachment) -> Self {
+        Self {
+            url: format!(
+                "/notes/id/{}/attachments/{}",
+                attachment.note_id, attachment.id
+            ),
+            id: attachment.id,
+            note_id: attachment.note_id,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StorageUsage {
+    pub used: u64,
+    pub quota: u64,
+}
+
+pub async fn get_usage(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<StorageUsage>, ApiError> {
+    let usage = services::attachment_service::get_usage(
+        app_state.database.attachment_repo(),
+        app_state.attachment_quota,
+        user.id,
+    )
+    .await?;
+    Ok(Json(usage))
+}
+
+pub async fn get_attachments(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<AttachmentInfo>>, ApiError> {
+    let attachments = services::attachment_service::get_attachments(
+        app_state.database.note_repo(),
+        app_state.database.attachment_repo(),
+        user.id,
+        id,
+    )
+    .await?;
+    Ok(Json(
+        attachments.into_iter().map(AttachmentInfo::from).collect(),
+    ))
+}
+
+/// multipart/form-data, every field with a filename becomes an attachment
+pub async fn upload_attachments(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    multipart: Multipart,
+) -> Result<Json<Vec<AttachmentInfo>>, ApiError> {
+    let attachments = services::attachment_service::upload(
+        app_state.database.note_repo(),
+        app_state.database.attachment_repo(),
+        app_state.database.attachment_storage(),
+        app_state.attachment_quota,
+        user.id,
+        id,
+        multipart,
+    )
+    .await?;
+    Ok(Json(
+        attachments.into_iter().map(AttachmentInfo::from).collect(),
+    ))
+}
+
+//types a browser may show in place, everything else is downloaded
+fn is_inline(content_type: &str) -> bool {
+    let content_type = content_type.split(';').next().unwrap_or_default();
+    match content_type.split_once('/') {
+        Some(("image", "svg+xml")) => false,
+        Some(("image" | "audio" | "video", _)) => true,
+        _ => matches!(content_type, "application/pdf" | "text/plain"),
+    }
+}
+
+fn content_disposition(attachment: &Attachment) -> String {
+    let kind = match is_inline(&attachment.content_type) {
+        true => "inline",
+        false => "attachment",
+    };
+    let fallback: String = attachment
+        .filename
+        .chars()
+        .map(|c| match c.is_ascii() && c != '\\' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    let encoded: String = attachment
+        .filename
+        .bytes()
+        .map(
+            |byte| match byte.is_ascii_alphanumeric() || b".-_~".contains(&byte) {
+                true => (byte as char).to_string(),
+                false => format!("%{:02X}", byte),
+            },
+        )
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        kind, fallback, encoded
+    )
+}
+
+/// Streams the file, a single `Range` is answered with 206
+pub async fn download_attachment(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, attachment_id)): Path<(ObjectId, ObjectId)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let attachment = services::attachment_service::get_attachment(
+        app_state.database.note_repo(),
+        app_state.database.attachment_repo(),
+        user.id,
+        id,
+        attachment_id,
+    )
+    .await?;
+    let size = attachment.size;
+    let range = match headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(range) => match services::attachment_service::parse_range(range, size) {
+            Ok(range) => range,
+            Err(err) => {
+                return Ok(
+                    ([(header::CONTENT_RANGE, format!("bytes */{}", size))], err).into_response(),
+                )
+            }
+        },
+        None => None,
+    };
+    let (status, bytes) = match range {
+        Some(range) => (StatusCode::PARTIAL_CONTENT, range),
+        None => (StatusCode::OK, 0..size),
+    };
+    let stream = services::attachment_service::open(
+        app_state.database.attachment_storage(),
+        &attachment,
+        bytes.clone(),
+    )
+    .await?;
+
+    let mut response = (status, Body::from_stream(stream)).into_response();
+    let response_headers = response.headers_mut();
+    let content_type = HeaderValue::from_str(&attachment.content_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    response_headers.insert(header::CONTENT_TYPE, content_type);
+    response_headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from(bytes.end - bytes.start),
+    );
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if status == StatusCode::PARTIAL_CONTENT {
+        let content_range = format!("bytes {}-{}/{}", bytes.start, bytes.end - 1, size);
+        response_headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).unwrap(),
+        );
+    }
+    if let Ok(disposition) = HeaderValue::from_str(&content_disposition(&attachment)) {
+        response_headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    //uploads are untrusted, never let them run as a page of the api
+    response_headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    response_headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("default-src 'none'; sandbox"),
+    );
+    Ok(response)
+}
+
+pub async fn delete_attachment(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, attachment_id)): Path<(ObjectId, ObjectId)>,
+) -> Result<(), ApiError> {
+    services::attachment_service::delete_attachment(
+        app_state.database.note_repo(),
+        app_state.database.attachment_repo(),
+        app_state.database.attachment_storage(),
+        user.id,
+        id,
+        attachment_id,
+    )
+    .await
+}
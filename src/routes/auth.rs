@@ -1,14 +1,12 @@
 use crate::{
-    auth::{generate_acces_token, generate_refresh_token, AuthResponseBody, AuthUser, Claims},
+    auth::{AuthResponseBody, AuthUser},
     error::ApiError,
-    services::user_service::{login_user, register_user},
-    AppState, KEYS,
+    routes::sessions::ClientInfo,
+    services::user_service::{complete_login, login_user, refresh_tokens, register_user},
+    AppState,
 };
 use axum::{extract::State, http::StatusCode, Extension, Json};
-use jsonwebtoken::{decode, Algorithm, Validation};
-use mongodb::bson::{doc, DateTime};
 use serde::{Deserialize, Serialize};
-use tracing::error;
 
 #[derive(Debug, Deserialize)]
 pub struct AuthPayload {
@@ -16,18 +14,71 @@ pub struct AuthPayload {
     pub password: String,
 }
 
+/// Tokens right away, or a challenge when the user has a second factor
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(AuthResponseBody),
+    Challenge(TwoFactorChallenge),
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    //goes to /auth/login/2fa with the code, lives 5 minutes
+    pub challenge_token: String,
+    pub username: String,
+}
+
+impl TwoFactorChallenge {
+    pub fn new(challenge_token: String, username: String) -> Self {
+        Self {
+            two_factor_required: true,
+            challenge_token,
+            username,
+        }
+    }
+}
+
 pub async fn authorize(
     State(app_state): State<AppState>,
+    client: ClientInfo,
     Json(payload): Json<AuthPayload>,
-) -> Result<Json<AuthResponseBody>, ApiError> {
+) -> Result<Json<LoginResponse>, ApiError> {
     //println!("{:?}", payload);
     if payload.username.is_empty() || payload.password.is_empty() {
         return Err(ApiError::MissingCredential);
     }
     let response = login_user(
-        &app_state.database.user_repo(),
+        &app_state.database,
         &payload.username,
         &payload.password,
+        client,
+    )
+    .await?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginPayload {
+    pub challenge_token: String,
+    //from the authenticator app or one of the recovery codes
+    pub code: String,
+}
+
+pub async fn authorize_two_factor(
+    State(app_state): State<AppState>,
+    client: ClientInfo,
+    Json(payload): Json<TwoFactorLoginPayload>,
+) -> Result<Json<AuthResponseBody>, ApiError> {
+    if payload.challenge_token.is_empty() || payload.code.is_empty() {
+        return Err(ApiError::MissingCredential);
+    }
+    let response = complete_login(
+        &app_state.database,
+        &payload.challenge_token,
+        &payload.code,
+        client,
     )
     .await?;
     Ok(Json(response))
@@ -42,16 +93,18 @@ pub struct RegisterPayload {
 
 pub async fn register(
     State(app_state): State<AppState>,
+    client: ClientInfo,
     Json(payload): Json<RegisterPayload>,
 ) -> Result<Json<AuthResponseBody>, ApiError> {
     if payload.username.is_empty() || payload.email.is_empty() || payload.password.is_empty() {
         return Err(ApiError::MissingCredential);
     }
     let reponse = register_user(
-        &app_state.database.user_repo(),
+        &app_state.database,
         &payload.username,
         &payload.email,
         &payload.password,
+        client,
     )
     .await?;
     Ok(Json(reponse))
@@ -69,32 +122,19 @@ pub struct RefreshResponse {
 }
 
 pub async fn refresh_token(
+    State(app_state): State<AppState>,
     Json(payload): Json<RefreshRequest>,
 ) -> Result<Json<RefreshResponse>, ApiError> {
-    let refresh_token = payload.refresh_token;
-    match decode::<Claims>(
-        &refresh_token,
-        &KEYS.decoding,
-        &Validation::new(Algorithm::HS256),
-    ) {
-        Ok(token_data) => {
-            if token_data.claims.exp < DateTime::now().timestamp_millis() as usize {
-                return Err(ApiError::TokenExpired);
-            }
-
-            let new_acces_token = generate_acces_token(&token_data.claims.username)?;
-            let new_refresh_token = generate_refresh_token(&token_data.claims.username)?;
-
-            Ok(Json(RefreshResponse {
-                acces_token: new_acces_token,
-                refresh_token: new_refresh_token,
-            }))
-        }
-        Err(err) => {
-            error!("{}", err.to_string());
-            Err(ApiError::InternalError)
-        }
-    }
+    let (acces_token, refresh_token) = refresh_tokens(
+        &app_state.database,
+        &app_state.events,
+        &payload.refresh_token,
+    )
+    .await?;
+    Ok(Json(RefreshResponse {
+        acces_token,
+        refresh_token,
+    }))
 }
 
 pub async fn check_auth(Extension(_user): AuthUser) -> Result<StatusCode, ApiError> {
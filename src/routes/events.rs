@@ -0,0 +1,52 @@
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures::{stream, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+use crate::{
+    auth::{AuthUser, CurrentSession},
+    services::session_service,
+    AppState,
+};
+
+/// Changes of the notes, todo lists and todos the user can see, as they
+/// happen. `resync` means changes were dropped, fetch the lists again. The
+/// stream ends when its session is revoked.
+pub async fn get_events(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Extension(session): CurrentSession,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let user_id = user.id;
+    let receiver = app_state.events.subscribe();
+    let revoked = session_service::revoked(
+        app_state.database.clone(),
+        app_state.events.subscribe_revoked(),
+        session,
+    );
+    let events = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(change) if change.users.contains(&user_id) => {
+                    match Event::default().event(change.name()).json_data(&change) {
+                        Ok(event) => event,
+                        Err(err) => {
+                            error!("{}", err);
+                            continue;
+                        }
+                    }
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => Event::default().event("resync").data(""),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), receiver));
+        }
+    });
+    Sse::new(events.take_until(revoked)).keep_alive(KeepAlive::default())
+}
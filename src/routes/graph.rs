@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, error::ApiError, services, AppState};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Note,
+    Tag,
+    TodoList,
+}
+
+//ids are prefixed with the kind: "note:<id>", "tag:<name>", "todo_list:<id>"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphNode {
+    pub id: String,
+    pub kind: NodeKind,
+    pub label: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    //[[...]] reference, from the linking note
+    Link,
+    //note -> tag
+    Tag,
+    //note -> pinned todo list
+    Pin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphQuery {
+    //only what is within `depth` edges of this note
+    pub note: Option<ObjectId>,
+    pub depth: Option<usize>,
+}
+
+pub async fn get_graph(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Query(query): Query<GraphQuery>,
+) -> Result<Json<Graph>, ApiError> {
+    let graph = services::graph_service::get_graph(
+        app_state.database.note_repo(),
+        app_state.database.todos_repo(),
+        user.id,
+        query.note,
+        query.depth,
+    )
+    .await?;
+    Ok(Json(graph))
+}
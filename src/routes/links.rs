@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{note::Note, timestamp},
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NoteRef {
+    pub id: ObjectId,
+    pub title: String,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Note> for NoteRef {
+    fn from(note: Note) -> Self {
+        Self {
+            id: note.id,
+            title: note.title,
+            updated_at: note.updated_at,
+        }
+    }
+}
+
+//note = None when nothing (visible to the user) matches the target
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LinkInfo {
+    pub target: String,
+    pub broken: bool,
+    pub note: Option<NoteRef>,
+}
+
+impl LinkInfo {
+    pub fn new(target: String, note: Option<Note>) -> Self {
+        Self {
+            target,
+            broken: note.is_none(),
+            note: note.map(NoteRef::from),
+        }
+    }
+}
+
+pub async fn get_links(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<LinkInfo>>, ApiError> {
+    let links =
+        services::link_service::get_links(app_state.database.note_repo(), user.id, id).await?;
+    Ok(Json(links))
+}
+
+pub async fn get_backlinks(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<NoteRef>>, ApiError> {
+    let backlinks =
+        services::link_service::get_backlinks(app_state.database.note_repo(), user.id, id).await?;
+    Ok(Json(backlinks))
+}
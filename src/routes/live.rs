@@ -0,0 +1,46 @@
+use axum::{
+    extract::{Path, State, WebSocketUpgrade},
+    response::Response,
+    Extension,
+};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    auth::{AuthUser, CurrentSession},
+    error::ApiError,
+    live::{self, Peer, MAX_MESSAGE},
+    services, AppState,
+};
+
+/// Upgrades to a live editing session of the note, viewers follow along.
+/// The socket is closed when its session is revoked.
+pub async fn live_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Extension(session): CurrentSession,
+    Path(id): Path<ObjectId>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let note =
+        services::note_service::get_note_by_id(app_state.database.note_repo(), user.id, id).await?;
+    let role = note.role_of(user.id).ok_or(ApiError::NotFound)?;
+    let peer = Peer {
+        peer: ObjectId::new(),
+        user_id: user.id,
+        username: user.username.clone(),
+        role,
+        cursor: None,
+    };
+    let revoked = services::session_service::revoked(
+        app_state.database.clone(),
+        app_state.events.subscribe_revoked(),
+        session,
+    );
+    let (room, others) = app_state
+        .live_rooms
+        .join(&app_state, &note, peer.clone())
+        .await?;
+    Ok(ws
+        .max_message_size(MAX_MESSAGE)
+        .on_upgrade(move |socket| live::connect(socket, room, user, peer, others, revoked)))
+}
@@ -1,3 +1,57 @@
+pub(crate) mod account;
+pub(crate) mod api_tokens;
+pub(crate) mod attachments;
 pub(crate) mod auth;
+pub(crate) mod events;
+pub(crate) mod graph;
+pub(crate) mod links;
+pub(crate) mod live;
+pub(crate) mod notebooks;
 pub(crate) mod notes;
+pub(crate) mod public;
+pub(crate) mod revisions;
+pub(crate) mod sessions;
+pub(crate) mod share_links;
+pub(crate) mod shares;
+pub(crate) mod sync;
+pub(crate) mod tags;
 pub(crate) mod todos;
+pub(crate) mod trash;
+pub(crate) mod two_factor;
+
+use crate::error::ApiError;
+use axum::http::{header, HeaderMap, HeaderValue};
+
+/// Browsers and integrations asking for rendered output instead of json
+pub fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Strong ETag of a versioned note or todo list
+pub fn etag(version: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", version)).unwrap()
+}
+
+/// The version `If-Match` asks for, `None` without the header or for `*`.
+/// Only a single strong ETag as sent by `etag` is understood.
+pub fn if_match(headers: &HeaderMap) -> Result<Option<u64>, ApiError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_err| ApiError::MissingPayload)?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or(ApiError::MissingPayload)
+}
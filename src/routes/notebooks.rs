@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser, error::ApiError, models::notebook::Notebook, routes::notes::AllNotesResponse,
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NotebookPayload {
+    pub name: String,
+    //None = top level
+    #[serde(default)]
+    pub parent_id: Option<ObjectId>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NotebookContents {
+    pub notebook: Notebook,
+    pub notes: Vec<AllNotesResponse>,
+    pub notebooks: Vec<NotebookContents>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteNotebookQuery {
+    //trash the notes and drop sub notebooks instead of moving them
+    #[serde(default)]
+    pub cascade: bool,
+    //where notes and sub notebooks go, the parent by default
+    pub move_to: Option<ObjectId>,
+}
+
+pub async fn create_notebook(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<NotebookPayload>,
+) -> Result<Json<Notebook>, ApiError> {
+    let notebook = services::notebook_service::create_notebook(
+        app_state.database.notebook_repo(),
+        user.id,
+        &payload.name,
+        payload.parent_id,
+    )
+    .await?;
+    Ok(Json(notebook))
+}
+
+pub async fn get_notebooks(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<Vec<Notebook>>, ApiError> {
+    let notebooks =
+        services::notebook_service::get_notebooks(app_state.database.notebook_repo(), user.id)
+            .await?;
+    Ok(Json(notebooks))
+}
+
+pub async fn get_notebook(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<NotebookContents>, ApiError> {
+    let contents = services::notebook_service::get_contents(
+        app_state.database.notebook_repo(),
+        app_state.database.note_repo(),
+        user.id,
+        id,
+    )
+    .await?;
+    Ok(Json(contents))
+}
+
+pub async fn update_notebook(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Json(payload): Json<NotebookPayload>,
+) -> Result<Json<Notebook>, ApiError> {
+    let notebook = services::notebook_service::update_notebook(
+        app_state.database.notebook_repo(),
+        user.id,
+        id,
+        &payload.name,
+        payload.parent_id,
+    )
+    .await?;
+    Ok(Json(notebook))
+}
+
+pub async fn delete_notebook(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Query(query): Query<DeleteNotebookQuery>,
+) -> Result<(), ApiError> {
+    services::notebook_service::delete_notebook(
+        app_state.database.notebook_repo(),
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        id,
+        query.cascade,
+        query.move_to,
+    )
+    .await
+}
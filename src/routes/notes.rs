@@ -1,12 +1,24 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Response},
     Extension, Json,
 };
-
+use chrono::{DateTime, Utc};
 use mongodb::bson::{doc, oid::ObjectId};
 use serde::{Deserialize, Serialize};
 
-use crate::{auth::AuthUser, error::ApiError, models::note::Note, services, AppState};
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{
+        note::{Note, NoteRole},
+        timestamp,
+    },
+    repository::pagination::{ListQuery, Page},
+    routes::{etag, if_match, wants_html},
+    services, AppState,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateNotePayload {
@@ -21,7 +33,9 @@ pub async fn create_note(
     Json(payload): Json<CreateNotePayload>,
 ) -> Result<Json<Note>, ApiError> {
     let note = services::note_service::create_note(
-        &app_state.database.note_repo(),
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
         user.id,
         payload.title.as_str(),
         payload.content.as_str(),
@@ -35,54 +49,151 @@ pub async fn delete_note(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(id): Path<ObjectId>,
+    headers: HeaderMap,
 ) -> Result<(), ApiError> {
-    services::note_service::delete_note(&app_state.database.note_repo(), id, user.id).await?;
+    services::note_service::delete_note(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        id,
+        user.id,
+        if_match(&headers)?,
+    )
+    .await?;
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AllNotesResponse {
     pub title: String,
     pub id: ObjectId,
     pub tags: Vec<String>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: ObjectId,
+    pub notebook_id: Option<ObjectId>,
+    //shared with others or by someone else, role tells which
+    pub shared: bool,
+    pub role: NoteRole,
+}
+
+impl AllNotesResponse {
+    /// Listing entry as seen by `user_id`
+    pub fn new(note: Note, user_id: ObjectId) -> Self {
+        Self {
+            shared: note.user_id != user_id || !note.shared_with.is_empty(),
+            role: note.role_of(user_id).unwrap_or(NoteRole::Viewer),
+            title: note.title,
+            id: note.id,
+            tags: note.tags,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
+            updated_by: note.updated_by,
+            notebook_id: note.notebook_id,
+        }
+    }
 }
 
 pub async fn get_all_notes_info(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
-) -> Result<Json<Vec<AllNotesResponse>>, ApiError> {
-    let all_notes =
-        services::note_service::get_all_notes_from_user(&app_state.database.note_repo(), user.id)
-            .await?;
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Page<AllNotesResponse>>, ApiError> {
+    let all_notes = services::note_service::get_all_notes_from_user(
+        app_state.database.note_repo(),
+        app_state.database.notebook_repo(),
+        user.id,
+        &query,
+    )
+    .await?;
     Ok(Json(all_notes))
 }
 
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResult {
+    pub id: ObjectId,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub score: f32,
+    //html escaped, matched terms are wrapped in <mark>
+    pub snippet: String,
+}
+
+pub async fn search_notes(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::MissingPayload);
+    }
+    let results = services::note_service::search_notes(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        user.id,
+        &query.q,
+        query.limit.unwrap_or(20),
+    )
+    .await?;
+    Ok(Json(results))
+}
+
+/// `Accept: text/html` returns the rendered content instead of the json note
 pub async fn get_note_by_id(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(id): Path<ObjectId>,
-) -> Result<Json<Note>, ApiError> {
-    let note = services::note_service::get_note_by_id(&app_state.database.note_repo(), user.id, id)
-        .await?;
-    Ok(Json(note))
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let note =
+        services::note_service::get_note_by_id(app_state.database.note_repo(), user.id, id).await?;
+    let etag = [(header::ETAG, etag(note.version))];
+    match wants_html(&headers) {
+        true => Ok((
+            etag,
+            Html(app_state.render_cache.render(&note.content).to_string()),
+        )
+            .into_response()),
+        false => Ok((etag, Json(note)).into_response()),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateNoteQuery {
+    //rename [[Old Title]] references in other notes too
+    #[serde(default)]
+    pub rewrite_links: bool,
 }
 
 pub async fn update_note_by_id(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(id): Path<ObjectId>,
+    Query(query): Query<UpdateNoteQuery>,
+    headers: HeaderMap,
     Json(payload): Json<CreateNotePayload>,
-) -> Result<(), ApiError> {
-    services::note_service::update_note(
-        &app_state.database.note_repo(),
-        user.id,
+) -> Result<impl IntoResponse, ApiError> {
+    let note = services::note_service::update_note(
+        app_state.database.note_repo(),
+        app_state.database.revision_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        &user,
         id,
-        &payload.title,
-        &payload.content,
-        payload.tags.to_owned(),
+        payload,
+        query.rewrite_links,
+        if_match(&headers)?,
     )
     .await?;
-    Ok(())
+    Ok([(header::ETAG, etag(note.version))])
 }
 
 pub async fn pin_todo_list(
@@ -91,8 +202,10 @@ pub async fn pin_todo_list(
     Path((id, todo_list_id)): Path<(ObjectId, ObjectId)>,
 ) -> Result<(), ApiError> {
     services::note_service::pin_todo_list(
-        &app_state.database.note_repo(),
-        &app_state.database.todos_repo(),
+        app_state.database.note_repo(),
+        app_state.database.todos_repo(),
+        &app_state.search_index,
+        &app_state.events,
         user.id,
         id,
         todo_list_id,
@@ -107,7 +220,9 @@ pub async fn unpin_todo_list(
     Path((id, todo_list_id)): Path<(ObjectId, ObjectId)>,
 ) -> Result<(), ApiError> {
     services::note_service::unpin_todo_list(
-        &app_state.database.note_repo(),
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
         user.id,
         id,
         todo_list_id,
@@ -115,3 +230,26 @@ pub async fn unpin_todo_list(
     .await?;
     Ok(())
 }
+
+#[derive(Deserialize, Debug)]
+pub struct MoveNotePayload {
+    pub notebook_id: Option<ObjectId>,
+}
+
+pub async fn move_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Json(payload): Json<MoveNotePayload>,
+) -> Result<Json<Note>, ApiError> {
+    let note = services::note_service::move_note(
+        app_state.database.note_repo(),
+        app_state.database.notebook_repo(),
+        &app_state.events,
+        user.id,
+        id,
+        payload.notebook_id,
+    )
+    .await?;
+    Ok(Json(note))
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Form, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ApiError,
+    markdown::RenderCache,
+    models::{note::Note, timestamp},
+    routes::wants_html,
+    search::escape_html,
+    services, AppState,
+};
+
+/*
+* Routes behind public share links, they sit outside of auth_middleware and
+* only ever expose a single note read only
+*/
+
+pub const PASSWORD_HEADER: &str = "x-share-password";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublicNote {
+    pub title: String,
+    pub content: String,
+    //rendered content
+    pub html: String,
+    pub tags: Vec<String>,
+    #[serde(with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PublicNote {
+    fn new(note: Note, render_cache: &RenderCache) -> Self {
+        Self {
+            html: render_cache.render(&note.content).to_string(),
+            title: note.title,
+            content: note.content,
+            tags: note.tags,
+            updated_at: note.updated_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UnlockForm {
+    pub password: String,
+}
+
+pub async fn get_shared_note(
+    State(app_state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let password = headers
+        .get(PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok());
+    open(&app_state, &token, password, &headers).await
+}
+
+//target of the password form on the html page
+pub async fn unlock_shared_note(
+    State(app_state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<UnlockForm>,
+) -> Result<Response, ApiError> {
+    open(&app_state, &token, Some(&form.password), &headers).await
+}
+
+async fn open(
+    app_state: &AppState,
+    token: &str,
+    password: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let note = services::share_link_service::open_link(
+        app_state.database.note_repo(),
+        app_state.database.share_link_repo(),
+        token,
+        password,
+    )
+    .await;
+    match (note, wants_html(headers)) {
+        (Ok(note), true) => Ok(Html(note_page(&note, &app_state.render_cache)).into_response()),
+        (Ok(note), false) => {
+            Ok(Json(PublicNote::new(note, &app_state.render_cache)).into_response())
+        }
+        (Err(ApiError::Unathorized), true) => Ok((
+            StatusCode::UNAUTHORIZED,
+            Html(password_page(password.is_some())),
+        )
+            .into_response()),
+        (Err(err), _) => Err(err),
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"robots\" content=\"noindex\">\n<title>{}</title>\n</head>\n\
+         <body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+fn note_page(note: &Note, render_cache: &RenderCache) -> String {
+    let tags = note
+        .tags
+        .iter()
+        .map(|tag| format!("<li>{}</li>", escape_html(tag)))
+        .collect::<String>();
+    page(
+        &note.title,
+        &format!(
+            "<h1>{}</h1>\n<ul class=\"tags\">{}</ul>\n<article>\n{}</article>\n<footer>Updated {}</footer>",
+            escape_html(&note.title),
+            tags,
+            render_cache.render(&note.content),
+            timestamp::format(&note.updated_at)
+        ),
+    )
+}
+
+fn password_page(wrong: bool) -> String {
+    let error = if wrong {
+        "<p class=\"error\">Wrong password</p>\n"
+    } else {
+        ""
+    };
+    page(
+        "Password required",
+        &format!(
+            "<h1>Password required</h1>\n{}<form method=\"post\">\n\
+             <input type=\"password\" name=\"password\" autofocus>\n\
+             <button type=\"submit\">Open</button>\n</form>",
+            error
+        ),
+    )
+}
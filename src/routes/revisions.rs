@@ -0,0 +1,113 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{note::Note, revision::NoteRevision},
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevisionInfo {
+    pub rev: u32,
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevisionDiff {
+    pub from: u32,
+    pub to: Option<u32>,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiffQuery {
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+pub async fn get_revisions(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<RevisionInfo>>, ApiError> {
+    let revisions =
+        services::revision_service::get_revisions(app_state.database.revision_repo(), user.id, id)
+            .await?;
+    Ok(Json(revisions))
+}
+
+pub async fn get_revision(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, rev)): Path<(ObjectId, u32)>,
+) -> Result<Json<NoteRevision>, ApiError> {
+    let revision = services::revision_service::get_revision(
+        app_state.database.revision_repo(),
+        user.id,
+        id,
+        rev,
+    )
+    .await?;
+    Ok(Json(revision))
+}
+
+pub async fn restore_revision(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, rev)): Path<(ObjectId, u32)>,
+) -> Result<Json<Note>, ApiError> {
+    let note = services::revision_service::restore_revision(
+        app_state.database.note_repo(),
+        app_state.database.revision_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        &user,
+        id,
+        rev,
+    )
+    .await?;
+    Ok(Json(note))
+}
+
+pub async fn diff_revisions(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<RevisionDiff>, ApiError> {
+    let diff = services::revision_service::diff_revisions(
+        app_state.database.note_repo(),
+        app_state.database.revision_repo(),
+        user.id,
+        id,
+        query.from,
+        query.to,
+    )
+    .await?;
+    Ok(Json(diff))
+}
@@ -0,0 +1,121 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Path, State},
+    http::{header, request::Parts, HeaderName},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use crate::{
+    auth::{AuthUser, CurrentSession},
+    error::ApiError,
+    models::{session::Session, timestamp},
+    services, AppState,
+};
+
+/// What a login request tells about the device, shown in the session list
+#[derive(Debug)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &HeaderName| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        //behind a proxy the first forwarded address is the client
+        let forwarded = header(&HeaderName::from_static("x-forwarded-for"))
+            .and_then(|value| value.split(',').next().map(|ip| ip.trim().to_string()));
+        //the peer is unknown when the router is driven without a server
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        Ok(Self {
+            user_agent: header(&header::USER_AGENT),
+            ip: forwarded.or(peer),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub id: ObjectId,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub last_used_at: DateTime<Utc>,
+    //the session of the token asking
+    pub current: bool,
+}
+
+impl SessionInfo {
+    pub fn new(session: Session, current: ObjectId) -> Self {
+        Self {
+            id: session.id,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            current: session.id == current,
+        }
+    }
+}
+
+pub async fn get_sessions(
+    State(app_state): State<AppState>,
+    Extension(session): CurrentSession,
+) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let sessions =
+        services::session_service::get_sessions(app_state.database.session_repo(), &session)
+            .await?;
+    Ok(Json(sessions))
+}
+
+pub async fn revoke_session(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<(), ApiError> {
+    services::session_service::revoke_session(&app_state.database, &app_state.events, user.id, id)
+        .await
+}
+
+/// Signs out everywhere else, for lost devices
+pub async fn revoke_other_sessions(
+    State(app_state): State<AppState>,
+    Extension(session): CurrentSession,
+) -> Result<(), ApiError> {
+    services::session_service::revoke_other_sessions(
+        &app_state.database,
+        &app_state.events,
+        &session,
+    )
+    .await
+}
+
+pub async fn logout(
+    State(app_state): State<AppState>,
+    Extension(session): CurrentSession,
+) -> Result<(), ApiError> {
+    services::session_service::revoke_session(
+        &app_state.database,
+        &app_state.events,
+        session.user_id,
+        session.id,
+    )
+    .await
+}
@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{share_link::ShareLink, timestamp},
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareLinkInfo {
+    pub id: ObjectId,
+    pub token: String,
+    pub note_id: ObjectId,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub password_protected: bool,
+    pub views: u64,
+    #[serde(with = "timestamp::optional")]
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+//the password hash never leaves the backend
+impl From<ShareLink> for ShareLinkInfo {
+    fn from(link: ShareLink) -> Self {
+        Self {
+            id: link.id,
+            token: link.token,
+            note_id: link.note_id,
+            created_at: link.created_at,
+            expires_at: link.expires_at,
+            password_protected: link.password.is_some(),
+            views: link.views,
+            last_viewed_at: link.last_viewed_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateLinkPayload {
+    #[serde(default, with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExpireLinkPayload {
+    //null keeps the link open until it is revoked
+    #[serde(default, with = "timestamp::optional")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub async fn get_links(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<ShareLinkInfo>>, ApiError> {
+    let links =
+        services::share_link_service::get_links(app_state.database.share_link_repo(), user.id, id)
+            .await?;
+    Ok(Json(links.into_iter().map(ShareLinkInfo::from).collect()))
+}
+
+pub async fn create_link(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Json(payload): Json<CreateLinkPayload>,
+) -> Result<Json<ShareLinkInfo>, ApiError> {
+    let link = services::share_link_service::create_link(
+        app_state.database.note_repo(),
+        app_state.database.share_link_repo(),
+        user.id,
+        id,
+        payload.expires_at,
+        payload.password.as_deref(),
+    )
+    .await?;
+    Ok(Json(link.into()))
+}
+
+pub async fn expire_link(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, link_id)): Path<(ObjectId, ObjectId)>,
+    Json(payload): Json<ExpireLinkPayload>,
+) -> Result<Json<ShareLinkInfo>, ApiError> {
+    let link = services::share_link_service::set_expiry(
+        app_state.database.share_link_repo(),
+        user.id,
+        id,
+        link_id,
+        payload.expires_at,
+    )
+    .await?;
+    Ok(Json(link.into()))
+}
+
+pub async fn revoke_link(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, link_id)): Path<(ObjectId, ObjectId)>,
+) -> Result<(), ApiError> {
+    services::share_link_service::revoke_link(
+        app_state.database.share_link_repo(),
+        user.id,
+        id,
+        link_id,
+    )
+    .await
+}
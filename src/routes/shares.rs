@@ -0,0 +1,75 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, error::ApiError, models::note::NoteRole, services, AppState};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareInfo {
+    pub user_id: ObjectId,
+    pub username: String,
+    pub role: NoteRole,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SharePayload {
+    pub username: String,
+    pub role: NoteRole,
+}
+
+pub async fn get_shares(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Vec<ShareInfo>>, ApiError> {
+    let shares = services::share_service::get_shares(
+        app_state.database.note_repo(),
+        app_state.database.user_repo(),
+        user.id,
+        id,
+    )
+    .await?;
+    Ok(Json(shares))
+}
+
+pub async fn share_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+    Json(payload): Json<SharePayload>,
+) -> Result<Json<ShareInfo>, ApiError> {
+    let share = services::share_service::share_note(
+        app_state.database.note_repo(),
+        app_state.database.user_repo(),
+        user.id,
+        id,
+        &payload.username,
+        payload.role,
+    )
+    .await?;
+    //editors turned viewers stop editing right away
+    app_state
+        .live_rooms
+        .set_role(id, share.user_id, Some(share.role));
+    Ok(Json(share))
+}
+
+pub async fn unshare_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path((id, username)): Path<(ObjectId, String)>,
+) -> Result<(), ApiError> {
+    let user_id = services::share_service::unshare_note(
+        app_state.database.note_repo(),
+        app_state.database.user_repo(),
+        user.id,
+        id,
+        &username,
+    )
+    .await?;
+    app_state.live_rooms.set_role(id, user_id, None);
+    Ok(())
+}
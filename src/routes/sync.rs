@@ -0,0 +1,156 @@
+use axum::{extract::State, Extension, Json};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{
+        note::Note,
+        todo::{TodoList, TodoPriority},
+        tombstone::{SyncEntity, Tombstone},
+    },
+    services, AppState,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncRequest {
+    //token of the last sync, none for the first one
+    pub since: Option<String>,
+    #[serde(default)]
+    pub changes: Vec<LocalChange>,
+}
+
+/// Something the client did while offline, applied in order. `version` is
+/// the one the client last saw, `client_id` names documents it created.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum LocalChange {
+    CreateNote {
+        client_id: String,
+        title: String,
+        content: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    UpdateNote {
+        id: ObjectId,
+        version: u64,
+        title: String,
+        content: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    DeleteNote {
+        id: ObjectId,
+        version: u64,
+    },
+    CreateTodoList {
+        client_id: String,
+        title: String,
+    },
+    RenameTodoList {
+        id: ObjectId,
+        version: u64,
+        title: String,
+    },
+    DeleteTodoList {
+        id: ObjectId,
+        version: u64,
+    },
+    CreateTodo {
+        todo_list_id: SyncId,
+        client_id: String,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+        //of the list, none for lists created in the same sync
+        version: Option<u64>,
+    },
+    UpdateTodo {
+        todo_list_id: ObjectId,
+        id: ObjectId,
+        version: u64,
+        title: String,
+        status: bool,
+        priority: TodoPriority,
+    },
+    DeleteTodo {
+        todo_list_id: ObjectId,
+        id: ObjectId,
+        version: u64,
+    },
+}
+
+/// A server id or the `client_id` of a list created earlier in the same sync
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SyncId {
+    Id(ObjectId),
+    Local(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncResponse {
+    //send it back as `since` next time
+    pub token: String,
+    //the client drops everything it has that is not in this response
+    pub reset: bool,
+    pub notes: Vec<Note>,
+    pub todo_lists: Vec<TodoList>,
+    pub tombstones: Vec<Tombstone>,
+    pub created: Vec<Created>,
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Created {
+    pub client_id: String,
+    pub entity: SyncEntity,
+    pub id: ObjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo_list_id: Option<ObjectId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictReason {
+    //changed on the server since the client saw it
+    Version,
+    //trashed or purged on the server
+    Deleted,
+    //not allowed or not valid
+    Rejected,
+}
+
+/// A local change that was not applied. Carries the server's document when
+/// there still is one so the client can merge and try again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Conflict {
+    //position in `changes`
+    pub index: usize,
+    pub entity: SyncEntity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub reason: ConflictReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<Note>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo_list: Option<TodoList>,
+}
+
+pub async fn sync(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<SyncRequest>,
+) -> Result<Json<SyncResponse>, ApiError> {
+    let res = services::sync_service::sync(
+        &app_state.database,
+        &app_state.search_index,
+        &app_state.events,
+        &user,
+        payload,
+    )
+    .await?;
+    Ok(Json(res))
+}
@@ -0,0 +1,91 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, error::ApiError, services, AppState};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    pub notes: usize,
+}
+
+//tag = None after a delete
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagChange {
+    pub tag: Option<String>,
+    pub notes: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenameTagPayload {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MergeTagsPayload {
+    pub tags: Vec<String>,
+    pub into: String,
+}
+
+pub async fn get_tags(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<Vec<TagCount>>, ApiError> {
+    let tags = services::tag_service::get_tags(app_state.database.note_repo(), user.id).await?;
+    Ok(Json(tags))
+}
+
+pub async fn rename_tag(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(tag): Path<String>,
+    Json(payload): Json<RenameTagPayload>,
+) -> Result<Json<TagChange>, ApiError> {
+    let change = services::tag_service::replace_tags(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        vec![tag],
+        Some(&payload.name),
+    )
+    .await?;
+    Ok(Json(change))
+}
+
+pub async fn merge_tags(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<MergeTagsPayload>,
+) -> Result<Json<TagChange>, ApiError> {
+    let change = services::tag_service::replace_tags(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        payload.tags,
+        Some(&payload.into),
+    )
+    .await?;
+    Ok(Json(change))
+}
+
+pub async fn delete_tag(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(tag): Path<String>,
+) -> Result<Json<TagChange>, ApiError> {
+    let change = services::tag_service::replace_tags(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        vec![tag],
+        None,
+    )
+    .await?;
+    Ok(Json(change))
+}
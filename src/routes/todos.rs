@@ -2,11 +2,15 @@ use crate::{
     auth::AuthUser,
     error::ApiError,
     models::todo::{TodoList, TodoPriority},
+    repository::pagination::{ListQuery, Page},
+    routes::{etag, if_match},
     services::{self},
     AppState,
 };
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::IntoResponse,
     Extension, Json,
 };
 use mongodb::bson::oid::ObjectId;
@@ -35,7 +39,8 @@ pub async fn create_todo_list(
     Json(payload): Json<TodoListPayload>,
 ) -> Result<Json<TodoList>, ApiError> {
     match services::todo_service::create_todo_list(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         user.id,
         payload.title,
     )
@@ -49,8 +54,15 @@ pub async fn create_todo_list(
 pub async fn get_all_todo_lists(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
-) -> Result<Json<Vec<TodoList>>, ApiError> {
-    match services::todo_service::get_all_todo_list(&app_state.database.todos_repo(), user.id).await
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Page<TodoList>>, ApiError> {
+    match services::todo_service::get_all_todo_list(
+        app_state.database.todos_repo(),
+        app_state.database.note_repo(),
+        user.id,
+        &query,
+    )
+    .await
     {
         Ok(res) => Ok(Json(res)),
         Err(err) => Err(err),
@@ -63,7 +75,7 @@ pub async fn get_all_todos_by_id(
     Json(payload): Json<TodoListVecPayload>,
 ) -> Result<Json<Vec<TodoList>>, ApiError> {
     match services::todo_service::get_todo_lists(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
         payload.list,
         user.id,
     )
@@ -74,21 +86,41 @@ pub async fn get_all_todos_by_id(
     }
 }
 
+pub async fn get_todo_list(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(todo_list_id): Path<ObjectId>,
+) -> Result<impl IntoResponse, ApiError> {
+    match services::todo_service::get_todo_list(
+        app_state.database.todos_repo(),
+        todo_list_id,
+        user.id,
+    )
+    .await
+    {
+        Ok(res) => Ok(([(header::ETAG, etag(res.version))], Json(res))),
+        Err(err) => Err(err),
+    }
+}
+
 pub async fn rename_todo_list(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(todo_list_id): Path<ObjectId>,
+    headers: HeaderMap,
     Json(payload): Json<TodoListPayload>,
-) -> Result<(), ApiError> {
+) -> Result<impl IntoResponse, ApiError> {
     match services::todo_service::rename_todo_list(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         todo_list_id,
         user.id,
         payload.title,
+        if_match(&headers)?,
     )
     .await
     {
-        Ok(_res) => Ok(()),
+        Ok(res) => Ok([(header::ETAG, etag(res.version))]),
         Err(err) => Err(err),
     }
 }
@@ -97,11 +129,14 @@ pub async fn delete_todo_list(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(todo_list_id): Path<ObjectId>,
+    headers: HeaderMap,
 ) -> Result<(), ApiError> {
     match services::todo_service::delete_todo_list(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         todo_list_id,
         user.id,
+        if_match(&headers)?,
     )
     .await
     {
@@ -114,19 +149,22 @@ pub async fn create_todo(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path(todo_list_id): Path<ObjectId>,
+    headers: HeaderMap,
     Json(payload): Json<TodoPayload>,
-) -> Result<(), ApiError> {
+) -> Result<impl IntoResponse, ApiError> {
     match services::todo_service::create_todo(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         todo_list_id,
         user.id,
         payload.title,
         payload.status,
         payload.priority,
+        if_match(&headers)?,
     )
     .await
     {
-        Ok(_res) => Ok(()),
+        Ok(res) => Ok([(header::ETAG, etag(res.version))]),
         Err(err) => Err(err),
     }
 }
@@ -135,20 +173,23 @@ pub async fn modify_todo(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path((todo_list_id, todo_id)): Path<(ObjectId, ObjectId)>,
+    headers: HeaderMap,
     Json(payload): Json<TodoPayload>,
-) -> Result<(), ApiError> {
+) -> Result<impl IntoResponse, ApiError> {
     match services::todo_service::modify_todo(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         todo_list_id,
         user.id,
         todo_id,
         payload.title,
         payload.status,
         payload.priority,
+        if_match(&headers)?,
     )
     .await
     {
-        Ok(_res) => Ok(()),
+        Ok(res) => Ok([(header::ETAG, etag(res.version))]),
         Err(err) => Err(err),
     }
 }
@@ -157,16 +198,19 @@ pub async fn delete_todo(
     State(app_state): State<AppState>,
     Extension(user): AuthUser,
     Path((todo_list_id, todo_id)): Path<(ObjectId, ObjectId)>,
-) -> Result<(), ApiError> {
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
     match services::todo_service::delete_todo(
-        &app_state.database.todos_repo(),
+        app_state.database.todos_repo(),
+        &app_state.events,
         todo_list_id,
         user.id,
         todo_id,
+        if_match(&headers)?,
     )
     .await
     {
-        Ok(_res) => Ok(()),
+        Ok(res) => Ok([(header::ETAG, etag(res.version))]),
         Err(err) => Err(err),
     }
 }
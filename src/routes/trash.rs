@@ -0,0 +1,115 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    error::ApiError,
+    models::{note::Note, timestamp, todo::TodoList},
+    services, AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrashedItem {
+    pub id: ObjectId,
+    pub title: String,
+    #[serde(with = "timestamp")]
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl From<Note> for TrashedItem {
+    fn from(note: Note) -> Self {
+        Self {
+            id: note.id,
+            title: note.title,
+            deleted_at: note.deleted_at.unwrap_or(note.updated_at),
+        }
+    }
+}
+
+impl From<TodoList> for TrashedItem {
+    fn from(todo_list: TodoList) -> Self {
+        Self {
+            id: todo_list.id,
+            title: todo_list.title,
+            deleted_at: todo_list.deleted_at.unwrap_or(todo_list.updated_at),
+        }
+    }
+}
+
+//newest first
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrashResponse {
+    pub notes: Vec<TrashedItem>,
+    pub todo_lists: Vec<TrashedItem>,
+}
+
+pub async fn get_trash(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<TrashResponse>, ApiError> {
+    let trash = services::trash_service::get_trash(
+        app_state.database.note_repo(),
+        app_state.database.todos_repo(),
+        user.id,
+    )
+    .await?;
+    Ok(Json(trash))
+}
+
+pub async fn restore_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<Note>, ApiError> {
+    let note = services::trash_service::restore_note(
+        app_state.database.note_repo(),
+        &app_state.search_index,
+        &app_state.events,
+        user.id,
+        id,
+    )
+    .await?;
+    Ok(Json(note))
+}
+
+pub async fn purge_note(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<(), ApiError> {
+    services::trash_service::purge_note(&app_state.database, user.id, id).await
+}
+
+pub async fn restore_todo_list(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<Json<TodoList>, ApiError> {
+    let todo_list = services::trash_service::restore_todo_list(
+        app_state.database.todos_repo(),
+        &app_state.events,
+        user.id,
+        id,
+    )
+    .await?;
+    Ok(Json(todo_list))
+}
+
+pub async fn purge_todo_list(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Path(id): Path<ObjectId>,
+) -> Result<(), ApiError> {
+    services::trash_service::purge_todo_list(
+        app_state.database.todos_repo(),
+        app_state.database.tombstone_repo(),
+        user.id,
+        id,
+    )
+    .await
+}
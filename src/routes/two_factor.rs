@@ -0,0 +1,76 @@
+use axum::{extract::State, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, error::ApiError, services, AppState};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Enrollment {
+    //base32, for typing it into the app by hand
+    pub secret: String,
+    //render it as a QR code
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryCodes {
+    //shown this once, only their hashes are stored
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CodePayload {
+    //from the authenticator app, disabling also takes a recovery code
+    pub code: String,
+}
+
+pub async fn get_status(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<TwoFactorStatus>, ApiError> {
+    let status =
+        services::two_factor_service::get_status(app_state.database.two_factor_repo(), user.id)
+            .await?;
+    Ok(Json(status))
+}
+
+pub async fn enroll(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+) -> Result<Json<Enrollment>, ApiError> {
+    let enrollment =
+        services::two_factor_service::enroll(app_state.database.two_factor_repo(), &user).await?;
+    Ok(Json(enrollment))
+}
+
+pub async fn confirm(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<CodePayload>,
+) -> Result<Json<RecoveryCodes>, ApiError> {
+    let codes = services::two_factor_service::confirm(
+        app_state.database.two_factor_repo(),
+        user.id,
+        &payload.code,
+    )
+    .await?;
+    Ok(Json(codes))
+}
+
+pub async fn disable(
+    State(app_state): State<AppState>,
+    Extension(user): AuthUser,
+    Json(payload): Json<CodePayload>,
+) -> Result<(), ApiError> {
+    services::two_factor_service::disable(
+        app_state.database.two_factor_repo(),
+        user.id,
+        &payload.code,
+    )
+    .await
+}
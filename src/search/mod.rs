@@ -0,0 +1,370 @@
+use crate::{
+    error::ApiError, models::note::Note, repository::note_repo::NoteRepo,
+    routes::notes::SearchResult,
+};
+use chrono::NaiveDate;
+use mongodb::bson::oid::ObjectId;
+use query::Query;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+pub(crate) mod query;
+
+/*
+* In process inverted index over title, tags and content of the notes.
+* It works the same for every storage backend, a user's notes are indexed
+* the first time they search and kept up to date by the note service after that.
+*
+* Only what ranking and the field filters need is kept: term frequencies,
+* title, tags and dates. The content stays in the repository, the results are
+* fetched from there for their snippets and to settle "phrases".
+*
+* The index lives in this process and is rebuilt after every start, so this
+* supports a single instance only. Behind a load balancer every process would
+* miss the changes made through the others.
+*/
+
+const TITLE_WEIGHT: f32 = 3.0;
+const TAGS_WEIGHT: f32 = 2.0;
+const CONTENT_WEIGHT: f32 = 1.0;
+
+//BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+const SNIPPET_BEFORE: usize = 60;
+const SNIPPET_LENGTH: usize = 200;
+
+struct IndexedNote {
+    title: String,
+    tags: Vec<String>,
+    has_todos: bool,
+    created: NaiveDate,
+    updated: NaiveDate,
+    //field weighted term frequencies
+    terms: HashMap<String, f32>,
+    length: f32,
+}
+
+impl IndexedNote {
+    fn new(note: &Note) -> Self {
+        let mut terms: HashMap<String, f32> = HashMap::new();
+        let mut length = 0.0;
+        let tags = note.tags.join(" ");
+        for (text, weight) in [
+            (note.title.as_str(), TITLE_WEIGHT),
+            (tags.as_str(), TAGS_WEIGHT),
+            (note.content.as_str(), CONTENT_WEIGHT),
+        ] {
+            for (term, _start, _end) in tokenize(text) {
+                *terms.entry(term).or_default() += weight;
+                length += weight;
+            }
+        }
+        Self {
+            title: note.title.clone(),
+            tags: note.tags.clone(),
+            has_todos: !note.todo_lists.is_empty(),
+            created: note.created_at.date_naive(),
+            updated: note.updated_at.date_naive(),
+            terms,
+            length,
+        }
+    }
+}
+
+#[derive(Default)]
+struct UserIndex {
+    notes: HashMap<ObjectId, IndexedNote>,
+    postings: HashMap<String, HashSet<ObjectId>>,
+}
+
+//a change to the notes of a user whose index is still being built
+enum Change {
+    Upsert(Box<Note>),
+    Remove(ObjectId),
+}
+
+#[derive(Default)]
+struct Loading {
+    //ensure_loaded calls still reading the repository
+    loaders: usize,
+    changes: Vec<Change>,
+}
+
+#[derive(Default)]
+struct Indexes {
+    users: HashMap<ObjectId, UserIndex>,
+    loading: HashMap<ObjectId, Loading>,
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    indexes: RwLock<Indexes>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the note, ignored until the owner's notes are loaded
+    pub fn upsert(&self, note: &Note) {
+        let mut indexes = self.indexes.write().unwrap();
+        let Indexes { users, loading } = &mut *indexes;
+        if let Some(index) = users.get_mut(&note.user_id) {
+            index.insert(note);
+        } else if let Some(loading) = loading.get_mut(&note.user_id) {
+            loading.changes.push(Change::Upsert(Box::new(note.clone())));
+        }
+    }
+
+    pub fn remove(&self, user_id: ObjectId, note_id: ObjectId) {
+        let mut indexes = self.indexes.write().unwrap();
+        let Indexes { users, loading } = &mut *indexes;
+        if let Some(index) = users.get_mut(&user_id) {
+            index.remove(note_id);
+        } else if let Some(loading) = loading.get_mut(&user_id) {
+            loading.changes.push(Change::Remove(note_id));
+        }
+    }
+
+    /// Drops everything indexed for a user whose account is gone
+    pub fn remove_user(&self, user_id: ObjectId) {
+        let mut indexes = self.indexes.write().unwrap();
+        indexes.users.remove(&user_id);
+        indexes.loading.remove(&user_id);
+    }
+
+    /*
+     * The user is registered as loading before the repository is read, changes
+     * that come in meanwhile are kept and replayed on top of what was read.
+     */
+    pub async fn ensure_loaded<R: NoteRepo + ?Sized>(
+        &self,
+        repo: &R,
+        user_id: ObjectId,
+    ) -> Result<(), ApiError> {
+        {
+            let mut indexes = self.indexes.write().unwrap();
+            if indexes.users.contains_key(&user_id) {
+                return Ok(());
+            }
+            indexes.loading.entry(user_id).or_default().loaders += 1;
+        }
+        let notes = repo.get_notes_from_user(user_id).await;
+
+        let mut indexes = self.indexes.write().unwrap();
+        if indexes.users.contains_key(&user_id) {
+            //another call got there first
+            return Ok(());
+        }
+        let notes = match notes {
+            Ok(notes) => notes,
+            Err(err) => {
+                if let Some(loading) = indexes.loading.get_mut(&user_id) {
+                    loading.loaders -= 1;
+                    if loading.loaders == 0 {
+                        indexes.loading.remove(&user_id);
+                    }
+                }
+                return Err(err);
+            }
+        };
+        //gone with remove_user while loading
+        let Some(loading) = indexes.loading.remove(&user_id) else {
+            return Ok(());
+        };
+        let mut index = UserIndex::default();
+        for note in notes.iter() {
+            index.insert(note);
+        }
+        for change in loading.changes {
+            match change {
+                Change::Upsert(note) => index.insert(&note),
+                Change::Remove(note_id) => index.remove(note_id),
+            }
+        }
+        indexes.users.insert(user_id, index);
+        Ok(())
+    }
+
+    /// Notes matching the query, ranked by its words best first.
+    /// Queries with only field filters return the newest notes first.
+    pub async fn search<R: NoteRepo + ?Sized>(
+        &self,
+        repo: &R,
+        user_id: ObjectId,
+        query: &Query,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, ApiError> {
+        let terms = query.text_terms();
+        //(id, score, needs the content to tell), the lock is gone before any fetch
+        let mut candidates: Vec<(ObjectId, f32, bool)> = {
+            let indexes = self.indexes.read().unwrap();
+            let Some(index) = indexes.users.get(&user_id) else {
+                return Ok(vec![]);
+            };
+            let scores = index.scores(&terms);
+            index
+                .notes
+                .iter()
+                .filter_map(|(id, indexed)| match query.matches(indexed, None) {
+                    Some(false) => None,
+                    matched => Some((
+                        *id,
+                        scores.get(id).copied().unwrap_or(0.0),
+                        matched.is_none(),
+                    )),
+                })
+                .collect()
+        };
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+
+        let mut results = vec![];
+        for (id, score, unsure) in candidates {
+            if results.len() >= limit {
+                break;
+            }
+            let note = match repo.get_note_by_id(id, user_id).await {
+                Ok(note) => note,
+                //deleted since it was indexed
+                Err(ApiError::NotFound) => continue,
+                Err(err) => return Err(err),
+            };
+            if unsure && query.matches(&IndexedNote::new(&note), Some(&note.content)) != Some(true)
+            {
+                continue;
+            }
+            results.push(SearchResult {
+                id,
+                score,
+                snippet: snippet(&note.content, &terms),
+                title: note.title,
+                tags: note.tags,
+            });
+        }
+        Ok(results)
+    }
+}
+
+impl UserIndex {
+    fn insert(&mut self, note: &Note) {
+        self.remove(note.id);
+
+        let indexed = IndexedNote::new(note);
+        for term in indexed.terms.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(note.id);
+        }
+        self.notes.insert(note.id, indexed);
+    }
+
+    fn remove(&mut self, note_id: ObjectId) {
+        if let Some(note) = self.notes.remove(&note_id) {
+            for term in note.terms.keys() {
+                if let Some(ids) = self.postings.get_mut(term) {
+                    ids.remove(&note_id);
+                    if ids.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+    }
+
+    fn scores(&self, terms: &[String]) -> HashMap<ObjectId, f32> {
+        let count = self.notes.len() as f32;
+        let average_length =
+            self.notes.values().map(|note| note.length).sum::<f32>() / count.max(1.0);
+        let mut scores: HashMap<ObjectId, f32> = HashMap::new();
+        let unique: HashSet<&String> = terms.iter().collect();
+        for term in unique {
+            let ids = match self.postings.get(term) {
+                Some(ids) => ids,
+                None => continue,
+            };
+            let idf = (1.0 + (count - ids.len() as f32 + 0.5) / (ids.len() as f32 + 0.5)).ln();
+            for id in ids {
+                let note = &self.notes[id];
+                let tf = note.terms[term];
+                let norm = K1 * (1.0 - B + B * note.length / average_length.max(1.0));
+                *scores.entry(*id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+        scores
+    }
+}
+
+/// Lowercased alphanumeric words with their byte range in the text
+pub fn tokenize(text: &str) -> Vec<(String, usize, usize)> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (index, char) in text.char_indices() {
+        match (char.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                tokens.push((text[begin..index].to_lowercase(), begin, index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((text[begin..].to_lowercase(), begin, text.len()));
+    }
+    tokens
+}
+
+/// Piece of the content around the first match with every matched term
+/// wrapped in <mark>, the rest of the text is html escaped.
+pub fn snippet(content: &str, terms: &[String]) -> String {
+    let tokens = tokenize(content);
+    let matches: Vec<&(String, usize, usize)> = tokens
+        .iter()
+        .filter(|token| terms.contains(&token.0))
+        .collect();
+
+    let first = matches.first().map(|token| token.1).unwrap_or(0);
+    let start = floor_char_boundary(content, first.saturating_sub(SNIPPET_BEFORE));
+    let end = floor_char_boundary(content, (start + SNIPPET_LENGTH).min(content.len()));
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = start;
+    for (_term, match_start, match_end) in matches
+        .into_iter()
+        .filter(|token| token.1 >= start && token.2 <= end)
+    {
+        snippet.push_str(&escape_html(&content[position..*match_start]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&content[*match_start..*match_end]));
+        snippet.push_str("</mark>");
+        position = *match_end;
+    }
+    snippet.push_str(&escape_html(&content[position..end]));
+    if end < content.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,441 @@
+use super::{tokenize, IndexedNote};
+use chrono::NaiveDate;
+use std::fmt;
+
+/*
+* Search query grammar:
+*
+*   query   := and ("OR" and)*
+*   and     := unary ("AND"? unary)*
+*   unary   := ("-" | "NOT") unary | "(" query ")" | term
+*   term    := word | "phrase" | field ":" value
+*
+* Fields: tag:, title:, has:todos, has:tags, created:(>|>=|<|<=)?YYYY-MM-DD,
+* updated:(>|>=|<|<=)?YYYY-MM-DD, before:YYYY-MM-DD, after:YYYY-MM-DD.
+* before/after compare the creation date. Words next to each other are AND-ed.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Word(String),
+    Phrase(String),
+    Tag(String),
+    Title(String),
+    Has(Has),
+    Created(Comparison, NaiveDate),
+    Updated(Comparison, NaiveDate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Has {
+    Todos,
+    Tags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Before,
+    BeforeOrOn,
+    On,
+    AfterOrOn,
+    After,
+}
+
+impl Comparison {
+    fn compare(self, value: NaiveDate, date: NaiveDate) -> bool {
+        match self {
+            Comparison::Before => value < date,
+            Comparison::BeforeOrOn => value <= date,
+            Comparison::On => value == date,
+            Comparison::AfterOrOn => value >= date,
+            Comparison::After => value > date,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    //char offset in the query
+    pub position: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Minus,
+    Or,
+    And,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field(String, String),
+}
+
+//parsing and matching recurse, these keep the stack small whatever comes in
+const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        let length = input.chars().count();
+        if length > MAX_LENGTH {
+            return Err(QueryError {
+                message: format!("Query is longer than {} characters", MAX_LENGTH),
+                position: MAX_LENGTH,
+            });
+        }
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            length,
+            depth: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(QueryError {
+                message: "Query is empty".to_string(),
+                position: 0,
+            });
+        }
+        let query = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(query),
+            Some((Token::RParen, position)) => Err(QueryError {
+                message: "Unmatched `)`".to_string(),
+                position: *position,
+            }),
+            Some((_token, position)) => Err(QueryError {
+                message: "Unexpected input".to_string(),
+                position: *position,
+            }),
+        }
+    }
+
+    /// `None` when it takes the content to tell, the index doesn't keep it.
+    /// Only phrases need it.
+    pub(super) fn matches(&self, note: &IndexedNote, content: Option<&str>) -> Option<bool> {
+        match self {
+            Query::And(left, right) => {
+                match (left.matches(note, content), right.matches(note, content)) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }
+            }
+            Query::Or(left, right) => {
+                match (left.matches(note, content), right.matches(note, content)) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }
+            }
+            Query::Not(query) => query.matches(note, content).map(|matched| !matched),
+            //"well-known" is indexed as two words
+            Query::Word(word) => Some(
+                tokenize(word)
+                    .iter()
+                    .all(|(term, _start, _end)| note.terms.contains_key(term)),
+            ),
+            Query::Phrase(phrase) => {
+                if note.title.to_lowercase().contains(phrase)
+                    || note
+                        .tags
+                        .iter()
+                        .any(|tag| tag.to_lowercase().contains(phrase))
+                {
+                    return Some(true);
+                }
+                match content {
+                    Some(content) => Some(content.to_lowercase().contains(phrase)),
+                    //a phrase in the content has all of its words indexed
+                    None if tokenize(phrase)
+                        .iter()
+                        .all(|(term, _start, _end)| note.terms.contains_key(term)) =>
+                    {
+                        None
+                    }
+                    None => Some(false),
+                }
+            }
+            Query::Tag(tag) => Some(note.tags.iter().any(|t| t.to_lowercase() == *tag)),
+            Query::Title(title) => Some(note.title.to_lowercase().contains(title)),
+            Query::Has(Has::Todos) => Some(note.has_todos),
+            Query::Has(Has::Tags) => Some(!note.tags.is_empty()),
+            Query::Created(comparison, date) => Some(comparison.compare(note.created, *date)),
+            Query::Updated(comparison, date) => Some(comparison.compare(note.updated, *date)),
+        }
+    }
+
+    /// Words that should rank the results, negated parts don't count
+    pub fn text_terms(&self) -> Vec<String> {
+        let mut terms = vec![];
+        self.collect_terms(&mut terms);
+        terms
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.collect_terms(terms);
+                right.collect_terms(terms);
+            }
+            Query::Word(text) | Query::Phrase(text) => {
+                terms.extend(tokenize(text).into_iter().map(|token| token.0))
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        match chars[index] {
+            char if char.is_whitespace() => index += 1,
+            '(' => {
+                tokens.push((Token::LParen, start));
+                index += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, start));
+                index += 1;
+            }
+            '-' if chars
+                .get(index + 1)
+                .is_some_and(|next| !next.is_whitespace()) =>
+            {
+                tokens.push((Token::Minus, start));
+                index += 1;
+            }
+            '"' => {
+                let (phrase, end) = read_phrase(&chars, index)?;
+                tokens.push((Token::Phrase(phrase), start));
+                index = end;
+            }
+            _ => {
+                while index < chars.len()
+                    && !chars[index].is_whitespace()
+                    && !matches!(chars[index], '(' | ')' | '"')
+                {
+                    index += 1;
+                }
+                let word: String = chars[start..index].iter().collect();
+                let token = match word.split_once(':') {
+                    Some((field, value))
+                        if !field.is_empty() && field.chars().all(char::is_alphabetic) =>
+                    {
+                        let value = if value.is_empty() && chars.get(index) == Some(&'"') {
+                            let (phrase, end) = read_phrase(&chars, index)?;
+                            index = end;
+                            phrase
+                        } else {
+                            value.to_string()
+                        };
+                        if value.is_empty() {
+                            return Err(QueryError {
+                                message: format!("Missing value for `{}:`", field),
+                                position: start,
+                            });
+                        }
+                        Token::Field(field.to_lowercase(), value)
+                    }
+                    _ => match word.as_str() {
+                        "OR" => Token::Or,
+                        "AND" => Token::And,
+                        "NOT" => Token::Not,
+                        _ => Token::Word(word),
+                    },
+                };
+                tokens.push((token, start));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+//returns the phrase without quotes and the index right after the closing quote
+fn read_phrase(chars: &[char], open: usize) -> Result<(String, usize), QueryError> {
+    match chars[open + 1..].iter().position(|char| *char == '"') {
+        Some(length) => Ok((
+            chars[open + 1..open + 1 + length].iter().collect(),
+            open + length + 2,
+        )),
+        None => Err(QueryError {
+            message: "Unterminated quote".to_string(),
+            position: open,
+        }),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    length: usize,
+    //negations and parentheses around the current token
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn current_position(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.length)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.position += 1,
+                None | Some(Token::Or) | Some(Token::RParen) => return Ok(query),
+                _ => {}
+            }
+            query = Query::And(Box::new(query), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Query, QueryError> {
+        let position = self.current_position();
+        let token = match self.tokens.get(self.position) {
+            Some((token, _)) => token.clone(),
+            None => {
+                return Err(QueryError {
+                    message: "Expected a search term".to_string(),
+                    position,
+                })
+            }
+        };
+        self.position += 1;
+        let nested = matches!(token, Token::Minus | Token::Not | Token::LParen);
+        if nested {
+            if self.depth == MAX_DEPTH {
+                return Err(QueryError {
+                    message: format!("Query is nested deeper than {} levels", MAX_DEPTH),
+                    position,
+                });
+            }
+            self.depth += 1;
+        }
+        let query = self.term(token, position);
+        if nested {
+            self.depth -= 1;
+        }
+        query
+    }
+
+    fn term(&mut self, token: Token, position: usize) -> Result<Query, QueryError> {
+        match token {
+            Token::Minus | Token::Not => Ok(Query::Not(Box::new(self.unary()?))),
+            Token::LParen => {
+                let query = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(QueryError {
+                        message: "Missing `)`".to_string(),
+                        position: self.current_position(),
+                    });
+                }
+                self.position += 1;
+                Ok(query)
+            }
+            Token::Word(word) => Ok(Query::Word(word.to_lowercase())),
+            Token::Phrase(phrase) => Ok(Query::Phrase(phrase.to_lowercase())),
+            Token::Field(field, value) => field_query(&field, &value, position),
+            Token::RParen | Token::Or | Token::And => Err(QueryError {
+                message: "Expected a search term".to_string(),
+                position,
+            }),
+        }
+    }
+}
+
+fn field_query(field: &str, value: &str, position: usize) -> Result<Query, QueryError> {
+    let value = value.to_lowercase();
+    match field {
+        "tag" => Ok(Query::Tag(value)),
+        "title" => Ok(Query::Title(value)),
+        "has" => match value.as_str() {
+            "todos" => Ok(Query::Has(Has::Todos)),
+            "tags" => Ok(Query::Has(Has::Tags)),
+            _ => Err(QueryError {
+                message: format!(
+                    "Unknown value `{}` for `has:`, expected todos or tags",
+                    value
+                ),
+                position,
+            }),
+        },
+        "created" => {
+            let (comparison, date) = parse_comparison(&value, position)?;
+            Ok(Query::Created(comparison, date))
+        }
+        "updated" => {
+            let (comparison, date) = parse_comparison(&value, position)?;
+            Ok(Query::Updated(comparison, date))
+        }
+        "before" => Ok(Query::Created(
+            Comparison::Before,
+            parse_date(&value, position)?,
+        )),
+        "after" => Ok(Query::Created(
+            Comparison::After,
+            parse_date(&value, position)?,
+        )),
+        _ => Err(QueryError {
+            message: format!(
+                "Unknown field `{}`, expected tag, title, has, created, updated, before or after",
+                field
+            ),
+            position,
+        }),
+    }
+}
+
+fn parse_comparison(value: &str, position: usize) -> Result<(Comparison, NaiveDate), QueryError> {
+    let (comparison, date) = if let Some(date) = value.strip_prefix(">=") {
+        (Comparison::AfterOrOn, date)
+    } else if let Some(date) = value.strip_prefix("<=") {
+        (Comparison::BeforeOrOn, date)
+    } else if let Some(date) = value.strip_prefix('>') {
+        (Comparison::After, date)
+    } else if let Some(date) = value.strip_prefix('<') {
+        (Comparison::Before, date)
+    } else {
+        (Comparison::On, value)
+    };
+    Ok((comparison, parse_date(date, position)?))
+}
+
+fn parse_date(value: &str, position: usize) -> Result<NaiveDate, QueryError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| QueryError {
+        message: format!("Invalid date `{}`, expected YYYY-MM-DD", value),
+        position,
+    })
+}
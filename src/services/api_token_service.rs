@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    auth::{hash_secret, random_token},
+    database::Database,
+    error::ApiError,
+    models::{
+        api_token::{ApiToken, Scope},
+        timestamp,
+        user::User,
+    },
+    repository::api_token_repo::ApiTokenRepo,
+    services::session_service::TOUCH_INTERVAL,
+};
+
+/// Tells personal access tokens apart from JWTs in the Authorization header
+pub const TOKEN_PREFIX: &str = "fnp_";
+
+/// Returns the stored token and the token itself, which is not kept anywhere
+pub async fn create_token(
+    token_repo: &dyn ApiTokenRepo,
+    user_id: ObjectId,
+    name: &str,
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(ApiToken, String), ApiError> {
+    let name = name.trim();
+    let mut unique: Vec<Scope> = vec![];
+    for scope in scopes {
+        if !unique.contains(&scope) {
+            unique.push(scope);
+        }
+    }
+    let now = timestamp::now();
+    if name.is_empty() || unique.is_empty() || expires_at.is_some_and(|at| at <= now) {
+        return Err(ApiError::MissingPayload);
+    }
+    let token = format!("{}{}", TOKEN_PREFIX, random_token());
+    let stored = ApiToken {
+        id: ObjectId::new(),
+        user_id,
+        name: name.to_string(),
+        hash: hash_secret(&token),
+        scopes: unique,
+        created_at: now,
+        expires_at,
+        last_used_at: None,
+    };
+    token_repo.create_token(&stored).await?;
+    Ok((stored, token))
+}
+
+pub async fn get_tokens(
+    token_repo: &dyn ApiTokenRepo,
+    user_id: ObjectId,
+) -> Result<Vec<ApiToken>, ApiError> {
+    token_repo.get_tokens(user_id).await
+}
+
+pub async fn revoke_token(
+    token_repo: &dyn ApiTokenRepo,
+    user_id: ObjectId,
+    token_id: ObjectId,
+) -> Result<(), ApiError> {
+    token_repo.delete_token(token_id, user_id).await
+}
+
+/// The user a personal access token belongs to, for auth_middleware
+pub async fn authenticate(database: &Database, token: &str) -> Result<(User, ApiToken), ApiError> {
+    let token_repo = database.api_token_repo();
+    let mut stored = match token_repo.get_token_by_hash(&hash_secret(token)).await {
+        Ok(stored) => stored,
+        Err(ApiError::NotFound) => return Err(ApiError::Unathorized),
+        Err(err) => return Err(err),
+    };
+    let now = timestamp::now();
+    if stored.is_expired(now) {
+        return Err(ApiError::TokenExpired);
+    }
+    let user = database.user_repo().get_user_by_id(stored.user_id).await?;
+    if stored
+        .last_used_at
+        .is_none_or(|last_used_at| now - last_used_at >= TOUCH_INTERVAL)
+    {
+        token_repo
+            .touch_token(stored.id, stored.user_id, now)
+            .await?;
+        stored.last_used_at = Some(now);
+    }
+    Ok((user, stored))
+}
@@ -0,0 +1,316 @@
+use axum::{
+    extract::multipart::{Field, Multipart, MultipartError},
+    http::StatusCode,
+};
+use futures::{future, StreamExt, TryStreamExt};
+use mongodb::bson::oid::ObjectId;
+use std::ops::Range;
+use tracing::error;
+
+use crate::{
+    error::ApiError,
+    models::{attachment::Attachment, note::Note, timestamp},
+    repository::{
+        attachment_repo::AttachmentRepo,
+        attachment_storage::{AttachmentStorage, ByteStream},
+        note_repo::NoteRepo,
+    },
+    routes::attachments::StorageUsage,
+};
+
+/// ATTACHMENT_QUOTA_BYTES (default 100 MiB) caps what a user can store
+pub fn quota_from_env() -> u64 {
+    std::env::var("ATTACHMENT_QUOTA_BYTES")
+        .ok()
+        .and_then(|quota| quota.parse().ok())
+        .unwrap_or(100 * 1024 * 1024)
+}
+
+//no paths or control characters, those end up in headers and file names
+fn clean_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    match name.trim() {
+        "" => "file".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn guess_content_type(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_name, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+//trusts the field when it names a sensible type, otherwise goes by extension
+fn content_type(declared: Option<&str>, filename: &str) -> String {
+    let declared = declared
+        .map(|content_type| content_type.trim().to_ascii_lowercase())
+        .filter(|content_type| {
+            content_type != "application/octet-stream"
+                && content_type.split_once('/').is_some_and(|(kind, sub)| {
+                    !kind.is_empty()
+                        && !sub.is_empty()
+                        && content_type
+                            .chars()
+                            .all(|c| c.is_ascii_graphic() || c == ' ' || c == ';' || c == '=')
+                })
+        });
+    declared.unwrap_or_else(|| guess_content_type(filename).to_string())
+}
+
+//only running into the body limit is about size, anything else is a broken request
+fn multipart_error(err: MultipartError) -> ApiError {
+    match err.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::QuotaExceeded,
+        _ => ApiError::InvalidUpload(err.body_text()),
+    }
+}
+
+/// Stores every file field of the upload as it streams in, editors can attach
+/// files too but they count against the quota of the note owner. Nothing is
+/// kept when one of the files fails.
+pub async fn upload(
+    note_repo: &dyn NoteRepo,
+    repo: &dyn AttachmentRepo,
+    storage: &dyn AttachmentStorage,
+    quota: u64,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    mut multipart: Multipart,
+) -> Result<Vec<Attachment>, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    if !note.can_edit(user_id) {
+        return Err(ApiError::Forbidden);
+    }
+
+    let mut attachments = Vec::new();
+    let stored = async {
+        while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+            //plain form fields are skipped
+            if field.file_name().is_none() {
+                continue;
+            }
+            let attachment = store_file(repo, storage, quota, user_id, &note, field).await?;
+            attachments.push(attachment);
+        }
+        Ok(())
+    }
+    .await;
+    match stored {
+        Ok(()) if attachments.is_empty() => Err(ApiError::MissingPayload),
+        Ok(()) => Ok(attachments),
+        Err(err) => {
+            discard(repo, storage, &attachments).await;
+            Err(err)
+        }
+    }
+}
+
+async fn store_file(
+    repo: &dyn AttachmentRepo,
+    storage: &dyn AttachmentStorage,
+    quota: u64,
+    user_id: ObjectId,
+    note: &Note,
+    field: Field<'_>,
+) -> Result<Attachment, ApiError> {
+    let filename = clean_filename(field.file_name().unwrap_or_default());
+    let content_type = content_type(field.content_type(), &filename);
+    //stops reading as soon as the file can't fit anymore
+    let available = quota.saturating_sub(repo.storage_used(note.user_id).await?);
+    let mut received = 0;
+    let data = field
+        .map_err(multipart_error)
+        .and_then(move |chunk| {
+            received += chunk.len() as u64;
+            future::ready(match received > available {
+                true => Err(ApiError::QuotaExceeded),
+                false => Ok(chunk),
+            })
+        })
+        .boxed();
+
+    let id = ObjectId::new();
+    let size = storage.put(id, &filename, data).await?;
+    let attachment = Attachment {
+        id,
+        note_id: note.id,
+        user_id: note.user_id,
+        uploaded_by: user_id,
+        content_type,
+        size,
+        filename,
+        created_at: timestamp::now(),
+    };
+    if let Err(err) = repo.create_attachment(&attachment).await {
+        //don't keep a file nothing points to
+        storage.delete(attachment.id).await?;
+        return Err(err);
+    }
+    //a parallel upload may have taken the space since it was checked
+    if repo.storage_used(note.user_id).await? > quota {
+        discard(repo, storage, std::slice::from_ref(&attachment)).await;
+        return Err(ApiError::QuotaExceeded);
+    }
+    Ok(attachment)
+}
+
+//best effort, the error that got us here is the one worth returning
+async fn discard(
+    repo: &dyn AttachmentRepo,
+    storage: &dyn AttachmentStorage,
+    attachments: &[Attachment],
+) {
+    for attachment in attachments {
+        if let Err(err) = repo
+            .delete_attachment(attachment.id, attachment.note_id, attachment.user_id)
+            .await
+        {
+            error!("Could not remove attachment {}: {}", attachment.id, err);
+            continue;
+        }
+        if let Err(err) = storage.delete(attachment.id).await {
+            error!(
+                "Could not remove file of attachment {}: {}",
+                attachment.id, err
+            );
+        }
+    }
+}
+
+pub async fn get_attachments(
+    note_repo: &dyn NoteRepo,
+    repo: &dyn AttachmentRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<Attachment>, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    repo.get_attachments(note_id, note.user_id).await
+}
+
+/// Anyone who can read the note can read its attachments
+pub async fn get_attachment(
+    note_repo: &dyn NoteRepo,
+    repo: &dyn AttachmentRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    attachment_id: ObjectId,
+) -> Result<Attachment, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    repo.get_attachment(attachment_id, note_id, note.user_id)
+        .await
+}
+
+pub async fn open(
+    storage: &dyn AttachmentStorage,
+    attachment: &Attachment,
+    range: Range<u64>,
+) -> Result<ByteStream, ApiError> {
+    match storage.open(attachment.id, range).await {
+        //the metadata outlived its file
+        Err(ApiError::NotFound) => {
+            error!("File of attachment {} is missing", attachment.id);
+            Err(ApiError::InternalError)
+        }
+        res => res,
+    }
+}
+
+pub async fn delete_attachment(
+    note_repo: &dyn NoteRepo,
+    repo: &dyn AttachmentRepo,
+    storage: &dyn AttachmentStorage,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    attachment_id: ObjectId,
+) -> Result<(), ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    if !note.can_edit(user_id) {
+        return Err(ApiError::Forbidden);
+    }
+    let attachment = repo
+        .delete_attachment(attachment_id, note_id, note.user_id)
+        .await?;
+    storage.delete(attachment.id).await
+}
+
+/// Used when the note is gone for good. Files go first, the metadata is what
+/// finds them again if this has to be retried.
+pub async fn delete_attachments_of_note(
+    repo: &dyn AttachmentRepo,
+    storage: &dyn AttachmentStorage,
+    note_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<(), ApiError> {
+    for attachment in repo.get_attachments(note_id, user_id).await? {
+        storage.delete(attachment.id).await?;
+    }
+    repo.delete_attachments_of_note(note_id, user_id).await?;
+    Ok(())
+}
+
+pub async fn get_usage(
+    repo: &dyn AttachmentRepo,
+    quota: u64,
+    user_id: ObjectId,
+) -> Result<StorageUsage, ApiError> {
+    Ok(StorageUsage {
+        used: repo.storage_used(user_id).await?,
+        quota,
+    })
+}
+
+/// Reads a `Range: bytes=...` header. Only single ranges are honoured, `None`
+/// serves the whole file which is also what happens for anything unparsable.
+pub fn parse_range(header: &str, size: u64) -> Result<Option<Range<u64>>, ApiError> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(ApiError::RangeNotSatisfiable),
+            Ok(suffix) => size.saturating_sub(suffix)..size,
+            Err(_) => return Ok(None),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => start..size,
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => start..size.min(end + 1),
+            _ => return Ok(None),
+        },
+    };
+    match range.start < range.end {
+        true => Ok(Some(range)),
+        false => Err(ApiError::RangeNotSatisfiable),
+    }
+}
@@ -0,0 +1,168 @@
+use mongodb::bson::oid::ObjectId;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::{
+    error::ApiError,
+    models::note::Note,
+    repository::{note_repo::NoteRepo, todo_repo::TodoRepo},
+    routes::graph::{EdgeKind, Graph, GraphEdge, GraphNode, NodeKind},
+    services::link_service,
+};
+
+const DEFAULT_DEPTH: usize = 1;
+const MAX_DEPTH: usize = 5;
+
+/*
+* Graph of the notes the user owns: their tags, the todo lists pinned to them
+* and the [[...]] references between them. Broken references and links to
+* notes of other users are left out.
+*/
+pub async fn get_graph(
+    note_repo: &dyn NoteRepo,
+    todo_repo: &dyn TodoRepo,
+    user_id: ObjectId,
+    around: Option<ObjectId>,
+    depth: Option<usize>,
+) -> Result<Graph, ApiError> {
+    let mut notes = note_repo.get_notes_from_user(user_id).await?;
+    notes.sort_by_key(|note| (note.created_at, note.id));
+    if let Some(note_id) = around {
+        if !notes.iter().any(|note| note.id == note_id) {
+            return Err(ApiError::NotFound);
+        }
+    }
+    let pinned: Vec<ObjectId> = notes
+        .iter()
+        .flat_map(|note| note.todo_lists.iter().copied())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let todo_lists = todo_repo.get_todo_lists(pinned, user_id).await?;
+
+    let mut graph = Graph::default();
+    for note in &notes {
+        graph.nodes.push(GraphNode {
+            id: note_node(note.id),
+            kind: NodeKind::Note,
+            label: note.title.clone(),
+        });
+    }
+    let tags: BTreeSet<&String> = notes.iter().flat_map(|note| &note.tags).collect();
+    for tag in tags {
+        graph.nodes.push(GraphNode {
+            id: tag_node(tag),
+            kind: NodeKind::Tag,
+            label: tag.clone(),
+        });
+    }
+    for todo_list in &todo_lists {
+        graph.nodes.push(GraphNode {
+            id: todo_list_node(todo_list.id),
+            kind: NodeKind::TodoList,
+            label: todo_list.title.clone(),
+        });
+    }
+
+    let live_lists: HashSet<ObjectId> = todo_lists.iter().map(|list| list.id).collect();
+    let ids: HashSet<ObjectId> = notes.iter().map(|note| note.id).collect();
+    let by_title = link_service::index_titles(notes.clone());
+    for note in &notes {
+        let mut linked = BTreeSet::new();
+        for link in &note.links {
+            let target = match (link.id, &link.key) {
+                (Some(id), _) => ids.contains(&id).then_some(id),
+                (None, Some(key)) => by_title.get(key).map(|target| target.id),
+                (None, None) => None,
+            };
+            if let Some(target) = target.filter(|target| *target != note.id) {
+                linked.insert(target);
+            }
+        }
+        graph
+            .edges
+            .extend(linked.into_iter().map(|target| GraphEdge {
+                source: note_node(note.id),
+                target: note_node(target),
+                kind: EdgeKind::Link,
+            }));
+        graph.edges.extend(note_edges(note, &live_lists));
+    }
+
+    match around {
+        Some(note_id) => Ok(neighbourhood(
+            graph,
+            &note_node(note_id),
+            depth.unwrap_or(DEFAULT_DEPTH).min(MAX_DEPTH),
+        )),
+        None => Ok(graph),
+    }
+}
+
+fn note_edges<'a>(
+    note: &'a Note,
+    live_lists: &'a HashSet<ObjectId>,
+) -> impl Iterator<Item = GraphEdge> + 'a {
+    let tags = note.tags.iter().map(|tag| GraphEdge {
+        source: note_node(note.id),
+        target: tag_node(tag),
+        kind: EdgeKind::Tag,
+    });
+    let pins = note
+        .todo_lists
+        .iter()
+        .filter(|id| live_lists.contains(id))
+        .map(|id| GraphEdge {
+            source: note_node(note.id),
+            target: todo_list_node(*id),
+            kind: EdgeKind::Pin,
+        });
+    tags.chain(pins)
+}
+
+//nodes at most `depth` edges away from `root` in either direction
+fn neighbourhood(graph: Graph, root: &str, depth: usize) -> Graph {
+    let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &graph.edges {
+        adjacent.entry(&edge.source).or_default().push(&edge.target);
+        adjacent.entry(&edge.target).or_default().push(&edge.source);
+    }
+    let mut distance: HashMap<&str, usize> = HashMap::from([(root, 0)]);
+    let mut queue = VecDeque::from([root]);
+    while let Some(node) = queue.pop_front() {
+        let next = distance[node] + 1;
+        if next > depth {
+            continue;
+        }
+        for neighbour in adjacent.get(node).into_iter().flatten() {
+            if !distance.contains_key(neighbour) {
+                distance.insert(neighbour, next);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    let kept: HashSet<String> = distance.keys().map(|node| node.to_string()).collect();
+    Graph {
+        nodes: graph
+            .nodes
+            .into_iter()
+            .filter(|node| kept.contains(&node.id))
+            .collect(),
+        edges: graph
+            .edges
+            .into_iter()
+            .filter(|edge| kept.contains(&edge.source) && kept.contains(&edge.target))
+            .collect(),
+    }
+}
+
+fn note_node(id: ObjectId) -> String {
+    format!("note:{}", id)
+}
+
+fn tag_node(tag: &str) -> String {
+    format!("tag:{}", tag)
+}
+
+fn todo_list_node(id: ObjectId) -> String {
+    format!("todo_list:{}", id)
+}
@@ -0,0 +1,235 @@
+use mongodb::bson::oid::ObjectId;
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    error::ApiError,
+    models::note::{title_key, Note, WikiLink},
+    repository::note_repo::NoteRepo,
+    routes::links::{LinkInfo, NoteRef},
+};
+
+/*
+* [[Note Title]], [[Note Title|shown text]] and [[id:<ObjectId>]] references.
+* Code spans and fenced code blocks are skipped so examples of the syntax
+* do not become links. Titles resolve within the notes of the note owner,
+* the oldest note wins when several share a title.
+*/
+
+/// References in the order they appear, a target is only listed once
+pub fn parse_links(content: &str) -> Vec<WikiLink> {
+    let mut links: Vec<WikiLink> = vec![];
+    for (_range, inner) in references(content) {
+        let target = target_of(inner);
+        let link = match target.strip_prefix("id:") {
+            Some(id) => WikiLink {
+                target: target.to_string(),
+                //an invalid id stays as a broken link
+                id: ObjectId::parse_str(id.trim()).ok(),
+                key: None,
+            },
+            None => WikiLink {
+                target: target.to_string(),
+                id: None,
+                key: Some(title_key(target)),
+            },
+        };
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// Points `[[old_title]]` references at `new_title`, aliases are kept
+pub fn rewrite_links(content: &str, old_title: &str, new_title: &str) -> String {
+    //the new title could not be referenced with the bracket syntax
+    if new_title.contains(['[', ']', '|', '\n']) || new_title.trim().is_empty() {
+        return content.to_string();
+    }
+    let old_key = title_key(old_title);
+    let mut rewritten = content.to_string();
+    for (range, inner) in references(content).into_iter().rev() {
+        let target = target_of(inner);
+        if target.starts_with("id:") || title_key(target) != old_key {
+            continue;
+        }
+        let alias = inner.find('|').map(|bar| &inner[bar..]).unwrap_or_default();
+        rewritten.replace_range(range, &format!("{}{}", new_title.trim(), alias));
+    }
+    rewritten
+}
+
+fn target_of(inner: &str) -> &str {
+    inner.split('|').next().unwrap_or_default().trim()
+}
+
+//byte range and text between the brackets of every reference outside of code
+fn references(content: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = vec![];
+    let mut fence: Option<(u8, usize)> = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let marker = fence_marker(line);
+        match (fence, marker) {
+            (Some((open, open_len)), Some((close, close_len)))
+                if open == close && close_len >= open_len =>
+            {
+                fence = None
+            }
+            (Some(_), _) => {}
+            (None, Some(marker)) => fence = Some(marker),
+            (None, None) => scan_line(line, offset, &mut found),
+        }
+        offset += line.len();
+    }
+    found
+}
+
+fn fence_marker(line: &str) -> Option<(u8, usize)> {
+    let trimmed = line.trim_start().as_bytes();
+    let first = *trimmed.first()?;
+    if first != b'`' && first != b'~' {
+        return None;
+    }
+    let len = trimmed.iter().take_while(|byte| **byte == first).count();
+    (len >= 3).then_some((first, len))
+}
+
+fn scan_line<'a>(line: &'a str, offset: usize, found: &mut Vec<(Range<usize>, &'a str)>) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'`' => {
+                let run = bytes[i..].iter().take_while(|byte| **byte == b'`').count();
+                i += run;
+                //an unclosed run is plain text
+                if let Some(end) = closing_run(bytes, i, run) {
+                    i = end;
+                }
+            }
+            b'[' if bytes.get(i + 1) == Some(&b'[') => {
+                let start = i + 2;
+                match line[start..].find("]]") {
+                    Some(len) if is_reference(&line[start..start + len]) => {
+                        found.push((
+                            offset + start..offset + start + len,
+                            &line[start..start + len],
+                        ));
+                        i = start + len + 2;
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+//end of the next backtick run of exactly `run` characters
+fn closing_run(bytes: &[u8], from: usize, run: usize) -> Option<usize> {
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        let len = bytes[i..].iter().take_while(|byte| **byte == b'`').count();
+        if len == run {
+            return Some(i + len);
+        }
+        i += len;
+    }
+    None
+}
+
+fn is_reference(inner: &str) -> bool {
+    !inner.contains(['[', '\n']) && !target_of(inner).is_empty()
+}
+
+/// The note each title key resolves to, the oldest one when titles repeat
+pub fn index_titles(mut notes: Vec<Note>) -> HashMap<String, Note> {
+    notes.sort_by_key(|note| (note.created_at, note.id));
+    let mut by_title = HashMap::new();
+    for note in notes {
+        by_title.entry(title_key(&note.title)).or_insert(note);
+    }
+    by_title
+}
+
+async fn notes_by_title<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    owner_id: ObjectId,
+    keys: &[String],
+) -> Result<HashMap<String, Note>, ApiError> {
+    Ok(index_titles(
+        note_repo.get_notes_by_titles(owner_id, keys).await?,
+    ))
+}
+
+/// Notes of the owner whose references resolve to `note`
+pub async fn linking_notes<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    note: &Note,
+) -> Result<Vec<Note>, ApiError> {
+    let key = title_key(&note.title);
+    //a [[Title]] shared with an older note points there instead
+    let resolves_here = notes_by_title(note_repo, note.user_id, std::slice::from_ref(&key))
+        .await?
+        .get(&key)
+        .is_some_and(|found| found.id == note.id);
+    let mut notes = note_repo
+        .get_linking_notes(note.user_id, note.id, &key)
+        .await?;
+    notes.retain(|other| {
+        other.id != note.id
+            && other.links.iter().any(|link| {
+                link.id == Some(note.id) || (resolves_here && link.key.as_deref() == Some(&key))
+            })
+    });
+    Ok(notes)
+}
+
+pub async fn get_links<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<LinkInfo>, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    let keys: Vec<String> = note
+        .links
+        .iter()
+        .filter_map(|link| link.key.clone())
+        .collect();
+    let by_title = notes_by_title(note_repo, note.user_id, &keys).await?;
+    let mut links = vec![];
+    for link in note.links {
+        let resolved = match (link.id, &link.key) {
+            (Some(id), _) => match note_repo.get_note_by_id(id, user_id).await {
+                Ok(target) => Some(target),
+                Err(ApiError::NotFound) => None,
+                Err(err) => return Err(err),
+            },
+            //titles of notes the user can not see count as broken
+            (None, Some(key)) => by_title
+                .get(key)
+                .filter(|target| target.role_of(user_id).is_some())
+                .cloned(),
+            (None, None) => None,
+        };
+        links.push(LinkInfo::new(link.target, resolved));
+    }
+    Ok(links)
+}
+
+pub async fn get_backlinks<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<NoteRef>, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    let mut notes = linking_notes(note_repo, &note).await?;
+    notes.retain(|other| other.role_of(user_id).is_some());
+    notes.sort_by_key(|other| std::cmp::Reverse(other.updated_at));
+    Ok(notes.into_iter().map(NoteRef::from).collect())
+}
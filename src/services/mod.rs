@@ -1,3 +1,16 @@
+pub(crate) mod api_token_service;
+pub(crate) mod attachment_service;
+pub(crate) mod graph_service;
+pub(crate) mod link_service;
 pub(crate) mod note_service;
+pub(crate) mod notebook_service;
+pub(crate) mod revision_service;
+pub(crate) mod session_service;
+pub(crate) mod share_link_service;
+pub(crate) mod share_service;
+pub(crate) mod sync_service;
+pub(crate) mod tag_service;
 pub(crate) mod todo_service;
+pub(crate) mod trash_service;
+pub(crate) mod two_factor_service;
 pub(crate) mod user_service;
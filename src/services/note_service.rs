@@ -1,45 +1,218 @@
 use crate::{
     error::ApiError,
-    models::note::Note,
-    repository::{note_repo::NoteRepo, todo_repo::TodoRepo},
-    routes::notes::AllNotesResponse,
+    events::{ChangeKind, EventBus},
+    models::{
+        note::{normalize_tags, title_key, Note},
+        user::User,
+    },
+    repository::{
+        note_repo::NoteRepo,
+        notebook_repo::NotebookRepo,
+        pagination::{ListQuery, Page},
+        revision_repo::RevisionRepo,
+        todo_repo::TodoRepo,
+    },
+    routes::notes::{AllNotesResponse, CreateNotePayload, SearchResult},
+    search::{query::Query, SearchIndex},
+    services::{link_service, notebook_service, revision_service},
 };
 use mongodb::bson::oid::ObjectId;
 
-pub async fn create_note<R: NoteRepo>(
+pub async fn create_note<R: NoteRepo + ?Sized>(
     repo: &R,
+    search_index: &SearchIndex,
+    events: &EventBus,
     user_id: ObjectId,
     title: &str,
     content: &str,
     tags: Vec<String>,
 ) -> Result<Note, ApiError> {
-    let create_res = repo.create_note(user_id, title, content, tags).await?;
+    let mut create_res = repo
+        .create_note(user_id, title, content, normalize_tags(tags))
+        .await?;
+    let links = link_service::parse_links(content);
+    if !links.is_empty() {
+        repo.set_links(create_res.id, user_id, links.clone())
+            .await?;
+        create_res.links = links;
+    }
+    search_index.upsert(&create_res);
+    events.note(ChangeKind::Created, &create_res);
     Ok(create_res)
 }
 
-pub async fn delete_note<R: NoteRepo>(
+//moves the note to the trash, revisions stay until it is purged
+pub async fn delete_note<R: NoteRepo + ?Sized>(
     repo: &R,
+    search_index: &SearchIndex,
+    events: &EventBus,
     note_id: ObjectId,
     user_id: ObjectId,
+    expected: Option<u64>,
 ) -> Result<(), ApiError> {
-    repo.delete_note(note_id, user_id).await?;
+    let note = repo.delete_note(note_id, user_id, expected).await?;
+    search_index.remove(user_id, note_id);
+    events.note(ChangeKind::Deleted, &note);
     Ok(())
 }
 
-pub async fn update_note<R: NoteRepo>(
+//fails when the note moved on from the version the client last saw
+fn check_version(note: &Note, expected: u64) -> Result<(), ApiError> {
+    match note.version == expected {
+        true => Ok(()),
+        false => Err(ApiError::PreconditionFailed(note.version)),
+    }
+}
+
+/// `rewrite_links` points `[[Old Title]]` references of other notes the user
+/// can edit at the new title when the note is renamed. With `expected` set
+/// the change is only applied to that version of the note.
+#[allow(clippy::too_many_arguments)]
+pub async fn update_note<R: NoteRepo + ?Sized>(
     repo: &R,
-    user_id: ObjectId,
+    revision_repo: &dyn RevisionRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user: &User,
     note_id: ObjectId,
-    title: &str,
-    content: &str,
+    payload: CreateNotePayload,
+    rewrite_links: bool,
+    expected: Option<u64>,
+) -> Result<Note, ApiError> {
+    let CreateNotePayload {
+        title,
+        content,
+        tags,
+    } = payload;
+    let tags = normalize_tags(tags);
+    let current = repo.get_note_by_id(note_id, user.id).await?;
+    if !current.can_edit(user.id) {
+        return Err(ApiError::Forbidden);
+    }
+    if let Some(expected) = expected {
+        check_version(&current, expected)?;
+    }
+    if current.title == title && current.content == content && current.tags == tags {
+        return Ok(current);
+    }
+    //looked up before the title changes
+    let referring = match rewrite_links && title_key(&current.title) != title_key(&title) {
+        true => link_service::linking_notes(repo, &current).await?,
+        false => vec![],
+    };
+    let old_title = current.title.clone();
+    let updated = save_note(
+        repo,
+        revision_repo,
+        search_index,
+        events,
+        user,
+        current,
+        title.clone(),
+        content,
+        tags,
+        true,
+    )
+    .await?;
+
+    for note in referring {
+        if !note.can_edit(user.id) {
+            continue;
+        }
+        let content = link_service::rewrite_links(&note.content, &old_title, &title);
+        if content != note.content {
+            let (title, tags) = (note.title.clone(), note.tags.clone());
+            match save_note(
+                repo,
+                revision_repo,
+                search_index,
+                events,
+                user,
+                note,
+                title,
+                content,
+                tags,
+                true,
+            )
+            .await
+            {
+                //edited by someone else meanwhile, their version wins
+                Ok(_) | Err(ApiError::PreconditionFailed(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    Ok(updated)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn save_note<R: NoteRepo + ?Sized>(
+    repo: &R,
+    revision_repo: &dyn RevisionRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user: &User,
+    current: Note,
+    title: String,
+    content: String,
     tags: Vec<String>,
-) -> Result<(), ApiError> {
-    repo.update_note(user_id, note_id, title, content, tags)
+    keep_revision: bool,
+) -> Result<Note, ApiError> {
+    let mut updated = repo
+        .update_note(user.id, current.id, &title, &content, tags, current.version)
         .await?;
-    Ok(())
+
+    //keep what was overwritten, only once the update got through so a save
+    //losing against another one leaves nothing behind
+    if keep_revision {
+        revision_service::record_revision(revision_repo, &current, user).await?;
+    }
+
+    let links = link_service::parse_links(&content);
+    if links != current.links {
+        repo.set_links(current.id, current.user_id, links.clone())
+            .await?;
+    }
+
+    updated.links = links;
+    search_index.upsert(&updated);
+    events.note(ChangeKind::Updated, &updated);
+    Ok(updated)
+}
+
+/// Content merged by a live editing session. Only the first save of a session
+/// keeps a revision, the state from before everyone started typing.
+#[allow(clippy::too_many_arguments)]
+pub async fn save_live_content<R: NoteRepo + ?Sized>(
+    repo: &R,
+    revision_repo: &dyn RevisionRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    editor: &User,
+    current: Note,
+    content: String,
+    keep_revision: bool,
+) -> Result<Note, ApiError> {
+    if !current.can_edit(editor.id) {
+        return Err(ApiError::Forbidden);
+    }
+    let (title, tags) = (current.title.clone(), current.tags.clone());
+    save_note(
+        repo,
+        revision_repo,
+        search_index,
+        events,
+        editor,
+        current,
+        title,
+        content,
+        tags,
+        keep_revision,
+    )
+    .await
 }
 
-pub async fn get_note_by_id<R: NoteRepo>(
+pub async fn get_note_by_id<R: NoteRepo + ?Sized>(
     repo: &R,
     user_id: ObjectId,
     note_id: ObjectId,
@@ -48,17 +221,70 @@ pub async fn get_note_by_id<R: NoteRepo>(
     Ok(res)
 }
 
-pub async fn get_all_notes_from_user<R: NoteRepo>(
+pub async fn get_all_notes_from_user<R: NoteRepo + ?Sized>(
     repo: &R,
+    notebook_repo: &dyn NotebookRepo,
     user_id: ObjectId,
-) -> Result<Vec<AllNotesResponse>, ApiError> {
-    let res = repo.get_all_notes_from_user(user_id).await?;
+    query: &ListQuery,
+) -> Result<Page<AllNotesResponse>, ApiError> {
+    let options = query.options()?;
+    let notebook_ids = match query.notebook {
+        Some(notebook_id) => Some(
+            notebook_service::notebook_ids(
+                notebook_repo,
+                user_id,
+                notebook_id,
+                query.recursive.unwrap_or(false),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let res = repo
+        .get_all_notes_from_user(user_id, &query.tags(), notebook_ids.as_deref(), &options)
+        .await?;
     Ok(res)
 }
 
-pub async fn pin_todo_list<R: NoteRepo, T: TodoRepo>(
+/// Owner only, `None` takes the note out of its notebook
+pub async fn move_note<R: NoteRepo + ?Sized>(
+    repo: &R,
+    notebook_repo: &dyn NotebookRepo,
+    events: &EventBus,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    notebook_id: Option<ObjectId>,
+) -> Result<Note, ApiError> {
+    if let Some(notebook_id) = notebook_id {
+        notebook_repo.get_notebook(notebook_id, user_id).await?;
+    }
+    let note = repo.get_note_by_id(note_id, user_id).await?;
+    if note.user_id != user_id {
+        return Err(ApiError::Forbidden);
+    }
+    repo.move_note(note_id, user_id, notebook_id).await?;
+    let note = repo.get_note_by_id(note_id, user_id).await?;
+    events.note(ChangeKind::Updated, &note);
+    Ok(note)
+}
+
+pub async fn search_notes<R: NoteRepo + ?Sized>(
+    repo: &R,
+    search_index: &SearchIndex,
+    user_id: ObjectId,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SearchResult>, ApiError> {
+    let query = Query::parse(query).map_err(|err| ApiError::InvalidQuery(err.to_string()))?;
+    search_index.ensure_loaded(repo, user_id).await?;
+    search_index.search(repo, user_id, &query, limit).await
+}
+
+pub async fn pin_todo_list<R: NoteRepo + ?Sized>(
     note_repo: &R,
-    todo_repo: &T,
+    todo_repo: &dyn TodoRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
     user_id: ObjectId,
     note_id: ObjectId,
     todo_list_id: ObjectId,
@@ -66,11 +292,17 @@ pub async fn pin_todo_list<R: NoteRepo, T: TodoRepo>(
     note_repo
         .pin_todo_list(todo_repo, todo_list_id, note_id, user_id)
         .await?;
+    //has:todos filters on the pinned lists
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    search_index.upsert(&note);
+    events.note(ChangeKind::Updated, &note);
     Ok(())
 }
 
-pub async fn unpin_todo_list<R: NoteRepo>(
+pub async fn unpin_todo_list<R: NoteRepo + ?Sized>(
     note_repo: &R,
+    search_index: &SearchIndex,
+    events: &EventBus,
     user_id: ObjectId,
     note_id: ObjectId,
     todo_list_id: ObjectId,
@@ -78,5 +310,8 @@ pub async fn unpin_todo_list<R: NoteRepo>(
     note_repo
         .unpin_todo_list(todo_list_id, note_id, user_id)
         .await?;
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    search_index.upsert(&note);
+    events.note(ChangeKind::Updated, &note);
     Ok(())
 }
@@ -0,0 +1,228 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    error::ApiError,
+    events::{ChangeKind, EventBus},
+    models::{note::Note, notebook::Notebook},
+    repository::{note_repo::NoteRepo, notebook_repo::NotebookRepo},
+    routes::{notebooks::NotebookContents, notes::AllNotesResponse},
+    search::SearchIndex,
+};
+
+fn valid_name(name: &str) -> Result<&str, ApiError> {
+    match name.trim() {
+        "" => Err(ApiError::MissingPayload),
+        name => Ok(name),
+    }
+}
+
+//the notebook and everything nested below it, parents before children
+fn subtree(notebooks: &[Notebook], root: ObjectId) -> Vec<ObjectId> {
+    let mut ids = vec![root];
+    let mut i = 0;
+    while i < ids.len() {
+        let parent = ids[i];
+        ids.extend(
+            notebooks
+                .iter()
+                .filter(|notebook| notebook.parent_id == Some(parent))
+                .map(|notebook| notebook.id),
+        );
+        i += 1;
+    }
+    ids
+}
+
+/// Ids a notebook filter matches, checks the notebook belongs to the user
+pub async fn notebook_ids(
+    repo: &dyn NotebookRepo,
+    user_id: ObjectId,
+    notebook_id: ObjectId,
+    recursive: bool,
+) -> Result<Vec<ObjectId>, ApiError> {
+    if !recursive {
+        repo.get_notebook(notebook_id, user_id).await?;
+        return Ok(vec![notebook_id]);
+    }
+    let notebooks = repo.get_notebooks(user_id).await?;
+    if !notebooks.iter().any(|notebook| notebook.id == notebook_id) {
+        return Err(ApiError::NotFound);
+    }
+    Ok(subtree(&notebooks, notebook_id))
+}
+
+pub async fn create_notebook(
+    repo: &dyn NotebookRepo,
+    user_id: ObjectId,
+    name: &str,
+    parent_id: Option<ObjectId>,
+) -> Result<Notebook, ApiError> {
+    let name = valid_name(name)?;
+    if let Some(parent_id) = parent_id {
+        repo.get_notebook(parent_id, user_id).await?;
+    }
+    repo.create_notebook(user_id, name, parent_id).await
+}
+
+pub async fn get_notebooks(
+    repo: &dyn NotebookRepo,
+    user_id: ObjectId,
+) -> Result<Vec<Notebook>, ApiError> {
+    repo.get_notebooks(user_id).await
+}
+
+/// The notebook with its notes and sub notebooks, all the way down
+pub async fn get_contents(
+    repo: &dyn NotebookRepo,
+    note_repo: &dyn NoteRepo,
+    user_id: ObjectId,
+    notebook_id: ObjectId,
+) -> Result<NotebookContents, ApiError> {
+    let notebooks = repo.get_notebooks(user_id).await?;
+    let root = notebooks
+        .iter()
+        .find(|notebook| notebook.id == notebook_id)
+        .cloned()
+        .ok_or(ApiError::NotFound)?;
+    let mut notes = note_repo.get_notes_from_user(user_id).await?;
+    notes.sort_by(|a, b| a.title.cmp(&b.title).then(a.id.cmp(&b.id)));
+    let notes: Vec<AllNotesResponse> = notes
+        .into_iter()
+        .filter(|note| note.notebook_id.is_some())
+        .map(|note| AllNotesResponse::new(note, user_id))
+        .collect();
+    Ok(contents(root, &notebooks, &notes))
+}
+
+fn contents(
+    notebook: Notebook,
+    notebooks: &[Notebook],
+    notes: &[AllNotesResponse],
+) -> NotebookContents {
+    let children = notebooks
+        .iter()
+        .filter(|child| child.parent_id == Some(notebook.id))
+        .map(|child| contents(child.clone(), notebooks, notes))
+        .collect();
+    let notes = notes
+        .iter()
+        .filter(|note| note.notebook_id == Some(notebook.id))
+        .cloned()
+        .collect();
+    NotebookContents {
+        notebook,
+        notes,
+        notebooks: children,
+    }
+}
+
+/// Renames and moves, a notebook can not end up inside itself
+pub async fn update_notebook(
+    repo: &dyn NotebookRepo,
+    user_id: ObjectId,
+    notebook_id: ObjectId,
+    name: &str,
+    parent_id: Option<ObjectId>,
+) -> Result<Notebook, ApiError> {
+    let name = valid_name(name)?;
+    let notebooks = repo.get_notebooks(user_id).await?;
+    if !notebooks.iter().any(|notebook| notebook.id == notebook_id) {
+        return Err(ApiError::NotFound);
+    }
+    if let Some(parent_id) = parent_id {
+        if !notebooks.iter().any(|notebook| notebook.id == parent_id) {
+            return Err(ApiError::NotFound);
+        }
+        if subtree(&notebooks, notebook_id).contains(&parent_id) {
+            return Err(ApiError::InvalidParent);
+        }
+    }
+    repo.update_notebook(notebook_id, user_id, name, parent_id)
+        .await
+}
+
+//live notes of the user inside one of the notebooks
+async fn notes_in(
+    note_repo: &dyn NoteRepo,
+    user_id: ObjectId,
+    notebook_ids: &[ObjectId],
+) -> Result<Vec<Note>, ApiError> {
+    Ok(note_repo
+        .get_notes_from_user(user_id)
+        .await?
+        .into_iter()
+        .filter(|note| {
+            note.notebook_id
+                .is_some_and(|notebook_id| notebook_ids.contains(&notebook_id))
+        })
+        .collect())
+}
+
+/// `cascade` deletes the sub notebooks and moves every note inside to the
+/// trash (out of any notebook so a restore lands at the top level). Otherwise
+/// only the notebook goes, its notes and sub notebooks move to `move_to` or
+/// the parent of the deleted notebook.
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_notebook(
+    repo: &dyn NotebookRepo,
+    note_repo: &dyn NoteRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user_id: ObjectId,
+    notebook_id: ObjectId,
+    cascade: bool,
+    move_to: Option<ObjectId>,
+) -> Result<(), ApiError> {
+    let notebooks = repo.get_notebooks(user_id).await?;
+    let notebook = notebooks
+        .iter()
+        .find(|notebook| notebook.id == notebook_id)
+        .ok_or(ApiError::NotFound)?;
+    let nested = subtree(&notebooks, notebook_id);
+
+    if cascade {
+        let notes = notes_in(note_repo, user_id, &nested).await?;
+        for note_id in note_repo.trash_notes_in(user_id, &nested).await? {
+            search_index.remove(user_id, note_id);
+            match notes.iter().find(|note| note.id == note_id) {
+                Some(note) => events.note(ChangeKind::Deleted, note),
+                None => events.note_deleted(user_id, note_id),
+            }
+        }
+        note_repo.move_notes(user_id, &nested, None).await?;
+        return repo.delete_notebooks(user_id, &nested).await;
+    }
+
+    let target = move_to.or(notebook.parent_id);
+    if let Some(target) = target {
+        if !notebooks.iter().any(|notebook| notebook.id == target) {
+            return Err(ApiError::NotFound);
+        }
+        if nested.contains(&target) {
+            return Err(ApiError::InvalidParent);
+        }
+    }
+    let moved: Vec<ObjectId> = notes_in(note_repo, user_id, &[notebook_id])
+        .await?
+        .iter()
+        .map(|note| note.id)
+        .collect();
+    note_repo
+        .move_notes(user_id, &[notebook_id], target)
+        .await?;
+    if !moved.is_empty() {
+        for note in note_repo.get_notes_from_user(user_id).await? {
+            if moved.contains(&note.id) {
+                events.note(ChangeKind::Updated, &note);
+            }
+        }
+    }
+    for child in notebooks
+        .iter()
+        .filter(|child| child.parent_id == Some(notebook_id))
+    {
+        repo.update_notebook(child.id, user_id, &child.name, target)
+            .await?;
+    }
+    repo.delete_notebooks(user_id, &[notebook_id]).await
+}
@@ -0,0 +1,157 @@
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{
+    error::ApiError,
+    events::EventBus,
+    models::{note::Note, revision::NoteRevision, user::User},
+    repository::{note_repo::NoteRepo, revision_repo::RevisionRepo},
+    routes::{
+        notes::CreateNotePayload,
+        revisions::{DiffLine, DiffOp, RevisionDiff, RevisionInfo},
+    },
+    search::SearchIndex,
+    services::note_service,
+};
+
+/// Keeps `note` as the next revision. A number taken by a concurrent save in
+/// between is skipped, every miss means someone else got theirs.
+pub async fn record_revision(
+    revision_repo: &dyn RevisionRepo,
+    note: &Note,
+    author: &User,
+) -> Result<NoteRevision, ApiError> {
+    loop {
+        let rev = revision_repo
+            .last_revision_number(note.id, note.user_id)
+            .await?
+            + 1;
+        let revision = NoteRevision {
+            id: ObjectId::new(),
+            note_id: note.id,
+            user_id: note.user_id,
+            rev,
+            author_id: author.id,
+            author: author.username.clone(),
+            created_at: Utc::now(),
+            title: note.title.clone(),
+            content: note.content.clone(),
+            tags: note.tags.clone(),
+        };
+        if revision_repo.create_revision(&revision).await? {
+            return Ok(revision);
+        }
+    }
+}
+
+pub async fn get_revisions(
+    revision_repo: &dyn RevisionRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<RevisionInfo>, ApiError> {
+    let revisions = revision_repo.get_revisions(note_id, user_id).await?;
+    Ok(revisions
+        .into_iter()
+        .map(|revision| RevisionInfo {
+            rev: revision.rev,
+            author: revision.author,
+            created_at: revision.created_at,
+            title: revision.title,
+        })
+        .collect())
+}
+
+pub async fn get_revision(
+    revision_repo: &dyn RevisionRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    rev: u32,
+) -> Result<NoteRevision, ApiError> {
+    revision_repo.get_revision(note_id, user_id, rev).await
+}
+
+/// Puts the revision back as the current note, the overwritten state is
+/// recorded as a new revision so a restore can be undone too.
+pub async fn restore_revision<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    revision_repo: &dyn RevisionRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user: &User,
+    note_id: ObjectId,
+    rev: u32,
+) -> Result<Note, ApiError> {
+    let revision = revision_repo.get_revision(note_id, user.id, rev).await?;
+    note_service::update_note(
+        note_repo,
+        revision_repo,
+        search_index,
+        events,
+        user,
+        note_id,
+        CreateNotePayload {
+            title: revision.title,
+            content: revision.content,
+            tags: revision.tags,
+        },
+        false,
+        None,
+    )
+    .await?;
+    note_repo.get_note_by_id(note_id, user.id).await
+}
+
+/// Line based diff of the content, `to` = None compares against the current note
+pub async fn diff_revisions<R: NoteRepo + ?Sized>(
+    note_repo: &R,
+    revision_repo: &dyn RevisionRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    from: u32,
+    to: Option<u32>,
+) -> Result<RevisionDiff, ApiError> {
+    let old = revision_repo.get_revision(note_id, user_id, from).await?;
+    let new = match to {
+        Some(rev) => {
+            revision_repo
+                .get_revision(note_id, user_id, rev)
+                .await?
+                .content
+        }
+        None => note_repo.get_note_by_id(note_id, user_id).await?.content,
+    };
+
+    Ok(RevisionDiff {
+        from,
+        to,
+        lines: diff_lines(&old.content, &new),
+    })
+}
+
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    //"b" and "b\n" as the last line should not show up as a change
+    let old = with_trailing_newline(old);
+    let new = with_trailing_newline(new);
+    TextDiff::from_lines(&old, &new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Delete => DiffOp::Delete,
+                ChangeTag::Insert => DiffOp::Insert,
+            },
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect()
+}
+
+fn with_trailing_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
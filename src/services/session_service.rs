@@ -0,0 +1,136 @@
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    auth::REFRESH_TOKEN_SECS,
+    database::Database,
+    error::ApiError,
+    events::EventBus,
+    models::{session::Session, timestamp},
+    repository::session_repo::SessionRepo,
+    routes::sessions::{ClientInfo, SessionInfo},
+};
+
+//last_used_at is only written when it is older than this, not on every request
+pub const TOUCH_INTERVAL: Duration = Duration::minutes(1);
+
+pub async fn start_session(
+    session_repo: &dyn SessionRepo,
+    user_id: ObjectId,
+    client: ClientInfo,
+) -> Result<Session, ApiError> {
+    let now = timestamp::now();
+    let session = Session {
+        id: ObjectId::new(),
+        user_id,
+        user_agent: client.user_agent,
+        ip: client.ip,
+        created_at: now,
+        last_used_at: now,
+    };
+    session_repo.create_session(&session).await?;
+    Ok(session)
+}
+
+/// The session a token names, as long as it wasn't revoked
+pub async fn current_session(
+    session_repo: &dyn SessionRepo,
+    user_id: ObjectId,
+    session_id: ObjectId,
+) -> Result<Session, ApiError> {
+    let mut session = match session_repo.get_session(session_id, user_id).await {
+        Ok(session) => session,
+        Err(ApiError::NotFound) => return Err(ApiError::Unathorized),
+        Err(err) => return Err(err),
+    };
+    let now = timestamp::now();
+    if now - session.last_used_at >= TOUCH_INTERVAL {
+        session_repo.touch_session(session_id, user_id, now).await?;
+        session.last_used_at = now;
+    }
+    Ok(session)
+}
+
+pub async fn get_sessions(
+    session_repo: &dyn SessionRepo,
+    current: &Session,
+) -> Result<Vec<SessionInfo>, ApiError> {
+    //idle for longer than a refresh token lives, nothing can use them anymore
+    let alive_since = timestamp::now() - Duration::seconds(REFRESH_TOKEN_SECS as i64);
+    let sessions = session_repo.get_sessions(current.user_id).await?;
+    Ok(sessions
+        .into_iter()
+        .filter(|session| session.last_used_at >= alive_since)
+        .map(|session| SessionInfo::new(session, current.id))
+        .collect())
+}
+
+/// Ends the session and every token issued for it
+pub async fn revoke_session(
+    database: &Database,
+    events: &EventBus,
+    user_id: ObjectId,
+    session_id: ObjectId,
+) -> Result<(), ApiError> {
+    database
+        .session_repo()
+        .delete_session(session_id, user_id)
+        .await?;
+    events.session_revoked(session_id);
+    database
+        .refresh_token_repo()
+        .revoke_family(session_id)
+        .await
+}
+
+/// Ends every session of the user but `current`
+pub async fn revoke_other_sessions(
+    database: &Database,
+    events: &EventBus,
+    current: &Session,
+) -> Result<(), ApiError> {
+    let revoked = database
+        .session_repo()
+        .delete_other_sessions(current.user_id, current.id)
+        .await?;
+    for session_id in revoked {
+        events.session_revoked(session_id);
+        database
+            .refresh_token_repo()
+            .revoke_family(session_id)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Resolves once the session is revoked, ends streams and sockets that outlive
+/// the request that opened them. `revocations` has to be subscribed before
+/// this is first polled, the session is looked up then and whenever
+/// revocations were missed.
+pub async fn revoked(
+    database: Arc<Database>,
+    mut revocations: broadcast::Receiver<ObjectId>,
+    session: Arc<Session>,
+) {
+    let session_repo = database.session_repo();
+    let is_gone = || async {
+        matches!(
+            session_repo.get_session(session.id, session.user_id).await,
+            Err(ApiError::NotFound)
+        )
+    };
+    if is_gone().await {
+        return;
+    }
+    loop {
+        match revocations.recv().await {
+            Ok(session_id) if session_id == session.id => return,
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) if is_gone().await => return,
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use bcrypt::{hash, verify};
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use tracing::{error, warn};
+
+use crate::{
+    auth::{random_token, PASSWORD_COST},
+    error::ApiError,
+    models::{note::Note, share_link::ShareLink, timestamp},
+    repository::{note_repo::NoteRepo, share_link_repo::ShareLinkRepo},
+};
+
+//after that many wrong passwords in a row only one try per LOCKOUT is left
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+const LOCKOUT: Duration = Duration::minutes(15);
+
+/// Only the owner of a note can publish it
+pub async fn create_link(
+    note_repo: &dyn NoteRepo,
+    link_repo: &dyn ShareLinkRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    expires_at: Option<DateTime<Utc>>,
+    password: Option<&str>,
+) -> Result<ShareLink, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    if note.user_id != user_id {
+        return Err(ApiError::Forbidden);
+    }
+    let password = match password.filter(|password| !password.is_empty()) {
+        Some(password) => Some(hash(password, PASSWORD_COST).map_err(|err| {
+            error!("{}", err);
+            ApiError::InternalError
+        })?),
+        None => None,
+    };
+    let link = ShareLink {
+        id: ObjectId::new(),
+        token: random_token(),
+        note_id,
+        user_id,
+        created_at: timestamp::now(),
+        expires_at,
+        password,
+        views: 0,
+        last_viewed_at: None,
+        failed_attempts: 0,
+        last_failed_at: None,
+    };
+    link_repo.create_link(&link).await?;
+    Ok(link)
+}
+
+pub async fn get_links(
+    link_repo: &dyn ShareLinkRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<ShareLink>, ApiError> {
+    link_repo.get_links(note_id, user_id).await
+}
+
+pub async fn set_expiry(
+    link_repo: &dyn ShareLinkRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    link_id: ObjectId,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<ShareLink, ApiError> {
+    link_repo
+        .set_expiry(link_id, note_id, user_id, expires_at)
+        .await
+}
+
+pub async fn revoke_link(
+    link_repo: &dyn ShareLinkRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    link_id: ObjectId,
+) -> Result<(), ApiError> {
+    link_repo.delete_link(link_id, note_id, user_id).await
+}
+
+/// Note behind a public token, expired links and trashed notes are `NotFound`,
+/// a missing or wrong password is `Unathorized`, too many wrong ones in a row
+/// `TooManyAttempts`. Every successful open counts as a view.
+pub async fn open_link(
+    note_repo: &dyn NoteRepo,
+    link_repo: &dyn ShareLinkRepo,
+    token: &str,
+    password: Option<&str>,
+) -> Result<Note, ApiError> {
+    let link = link_repo.get_link_by_token(token).await?;
+    let now = timestamp::now();
+    if link.is_expired(now) {
+        return Err(ApiError::NotFound);
+    }
+    let note = note_repo.get_note_by_id(link.note_id, link.user_id).await?;
+    if let Some(hashed) = &link.password {
+        let password = password.ok_or(ApiError::Unathorized)?;
+        if link.failed_attempts >= MAX_FAILED_ATTEMPTS
+            && link
+                .last_failed_at
+                .is_some_and(|last_failed_at| now - last_failed_at < LOCKOUT)
+        {
+            return Err(ApiError::TooManyAttempts);
+        }
+        match verify(password, hashed) {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Wrong password for share link {}", link.id);
+                link_repo
+                    .record_failed_attempt(link.id, link.user_id, now)
+                    .await?;
+                return Err(ApiError::Unathorized);
+            }
+            Err(err) => {
+                error!("{}", err);
+                return Err(ApiError::InternalError);
+            }
+        }
+    }
+    link_repo.record_view(link.id, link.user_id, now).await?;
+    Ok(note)
+}
@@ -0,0 +1,80 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    error::ApiError,
+    models::note::NoteRole,
+    repository::{note_repo::NoteRepo, user_repo::UserRepo},
+    routes::shares::ShareInfo,
+};
+
+pub async fn get_shares(
+    note_repo: &dyn NoteRepo,
+    user_repo: &dyn UserRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Vec<ShareInfo>, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    let mut shares = vec![];
+    for share in note.shared_with {
+        //a deleted account just drops out of the list
+        let Ok(user) = user_repo.get_user_by_id(share.user_id).await else {
+            continue;
+        };
+        shares.push(ShareInfo {
+            user_id: user.id,
+            username: user.username,
+            role: share.role,
+        });
+    }
+    Ok(shares)
+}
+
+/// Only the owner can share, sharing again with someone changes their role
+pub async fn share_note(
+    note_repo: &dyn NoteRepo,
+    user_repo: &dyn UserRepo,
+    owner_id: ObjectId,
+    note_id: ObjectId,
+    username: &str,
+    role: NoteRole,
+) -> Result<ShareInfo, ApiError> {
+    if role == NoteRole::Owner {
+        return Err(ApiError::MissingPayload);
+    }
+    let note = note_repo.get_note_by_id(note_id, owner_id).await?;
+    if note.user_id != owner_id {
+        return Err(ApiError::Forbidden);
+    }
+    let user = user_repo.get_user(username).await?;
+    if user.id == owner_id {
+        return Err(ApiError::MissingPayload);
+    }
+    note_repo
+        .share_note(note_id, owner_id, user.id, role)
+        .await?;
+    Ok(ShareInfo {
+        user_id: user.id,
+        username: user.username,
+        role,
+    })
+}
+
+/// The owner revokes anyone, everyone else can only leave the note.
+/// Returns the id of the user who lost access.
+pub async fn unshare_note(
+    note_repo: &dyn NoteRepo,
+    user_repo: &dyn UserRepo,
+    user_id: ObjectId,
+    note_id: ObjectId,
+    username: &str,
+) -> Result<ObjectId, ApiError> {
+    let note = note_repo.get_note_by_id(note_id, user_id).await?;
+    let user = user_repo.get_user(username).await?;
+    if note.user_id != user_id && user.id != user_id {
+        return Err(ApiError::Forbidden);
+    }
+    note_repo
+        .unshare_note(note_id, note.user_id, user.id)
+        .await?;
+    Ok(user.id)
+}
@@ -0,0 +1,439 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use std::collections::HashMap;
+
+use crate::{
+    database::Database,
+    error::ApiError,
+    events::EventBus,
+    models::{
+        timestamp,
+        tombstone::{SyncEntity, Tombstone},
+        user::User,
+    },
+    routes::{
+        notes::CreateNotePayload,
+        sync::{Conflict, ConflictReason, Created, LocalChange, SyncId, SyncRequest, SyncResponse},
+    },
+    search::SearchIndex,
+    services::{note_service, todo_service, trash_service},
+};
+
+/*
+* Offline sync. A client pushes what it did while offline and pulls everything
+* of its own that changed since its last sync token. Local changes go through
+* the same services as the routes, a stale version turns into a conflict that
+* carries the server's document instead of failing the whole sync.
+*/
+
+//a write that started before the token was taken can land a little after it,
+//those are sent again next time, versions make that harmless
+const TOKEN_OVERLAP: Duration = Duration::seconds(5);
+
+fn encode_token(time: &DateTime<Utc>) -> String {
+    URL_SAFE_NO_PAD.encode(timestamp::format(time))
+}
+
+fn decode_token(token: &str) -> Result<DateTime<Utc>, ApiError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| ApiError::InvalidSyncToken)?;
+    let text = String::from_utf8(bytes).map_err(|_| ApiError::InvalidSyncToken)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| ApiError::InvalidSyncToken)
+}
+
+/*
+* State of the local changes applied so far in one sync
+*/
+#[derive(Default)]
+struct Batch {
+    //server version of documents changed earlier in the sync, keyed by the
+    //version the client based its changes on, so later changes of the same
+    //document don't conflict with the earlier ones
+    versions: HashMap<ObjectId, (u64, u64)>,
+    //client_id -> id of the todo lists created in the sync
+    todo_lists: HashMap<String, ObjectId>,
+    created: Vec<Created>,
+}
+
+impl Batch {
+    fn expected(&self, id: ObjectId, version: u64) -> u64 {
+        match self.versions.get(&id) {
+            Some(&(base, current)) if base == version => current,
+            _ => version,
+        }
+    }
+
+    fn applied(&mut self, id: ObjectId, version: u64, current: u64) {
+        self.versions.insert(id, (version, current));
+    }
+
+    fn todo_list_id(&self, id: &SyncId) -> Result<ObjectId, ApiError> {
+        match id {
+            SyncId::Id(id) => Ok(*id),
+            SyncId::Local(client_id) => self
+                .todo_lists
+                .get(client_id)
+                .copied()
+                .ok_or(ApiError::NotFound),
+        }
+    }
+}
+
+pub async fn sync(
+    database: &Database,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user: &User,
+    request: SyncRequest,
+) -> Result<SyncResponse, ApiError> {
+    let since = match request.since {
+        Some(token) => Some(decode_token(&token)?),
+        None => None,
+    };
+    //purges older than the retention left no tombstones behind
+    let since =
+        since.filter(|since| *since >= timestamp::now() - trash_service::retention_from_env());
+
+    let mut batch = Batch::default();
+    let mut conflicts = vec![];
+    for (index, change) in request.changes.into_iter().enumerate() {
+        let (entity, id, todo_list_id) = target(&change, &batch);
+        if let Err(err) = apply(database, search_index, events, user, &mut batch, change).await {
+            let reason = match err {
+                ApiError::InternalError => return Err(err),
+                ApiError::PreconditionFailed(_) => ConflictReason::Version,
+                ApiError::NotFound => ConflictReason::Deleted,
+                _ => ConflictReason::Rejected,
+            };
+            let note = match (entity, id) {
+                (SyncEntity::Note, Some(id)) => {
+                    found(database.note_repo().get_note_by_id(id, user.id).await)?
+                }
+                _ => None,
+            };
+            let todo_list = match todo_list_id {
+                Some(id) => found(database.todos_repo().get_todo_list(id, user.id).await)?,
+                None => None,
+            };
+            conflicts.push(Conflict {
+                index,
+                entity,
+                id,
+                reason,
+                note,
+                todo_list,
+            });
+        }
+    }
+
+    let read_at = timestamp::now();
+    let mut notes = database
+        .note_repo()
+        .get_notes_changed_since(user.id, since)
+        .await?;
+    let mut todo_lists = database
+        .todos_repo()
+        .get_todo_lists_changed_since(user.id, since)
+        .await?;
+    let mut tombstones = match since {
+        Some(since) => {
+            database
+                .tombstone_repo()
+                .get_tombstones_since(user.id, since)
+                .await?
+        }
+        None => vec![],
+    };
+    //trashed documents are gone as far as clients are concerned
+    tombstones.extend(notes.iter().filter_map(|note| {
+        note.deleted_at.map(|deleted_at| Tombstone {
+            id: note.id,
+            user_id: note.user_id,
+            entity: SyncEntity::Note,
+            deleted_at,
+        })
+    }));
+    tombstones.extend(todo_lists.iter().filter_map(|todo_list| {
+        todo_list.deleted_at.map(|deleted_at| Tombstone {
+            id: todo_list.id,
+            user_id: todo_list.user_id,
+            entity: SyncEntity::TodoList,
+            deleted_at,
+        })
+    }));
+    notes.retain(|note| note.deleted_at.is_none());
+    todo_lists.retain(|todo_list| todo_list.deleted_at.is_none());
+
+    Ok(SyncResponse {
+        token: encode_token(&(read_at - TOKEN_OVERLAP)),
+        reset: since.is_none(),
+        notes,
+        todo_lists,
+        tombstones,
+        created: batch.created,
+        conflicts,
+    })
+}
+
+//document a change is about: entity, its id and the todo list involved
+fn target(change: &LocalChange, batch: &Batch) -> (SyncEntity, Option<ObjectId>, Option<ObjectId>) {
+    match change {
+        LocalChange::CreateNote { .. } => (SyncEntity::Note, None, None),
+        LocalChange::UpdateNote { id, .. } | LocalChange::DeleteNote { id, .. } => {
+            (SyncEntity::Note, Some(*id), None)
+        }
+        LocalChange::CreateTodoList { .. } => (SyncEntity::TodoList, None, None),
+        LocalChange::RenameTodoList { id, .. } | LocalChange::DeleteTodoList { id, .. } => {
+            (SyncEntity::TodoList, Some(*id), Some(*id))
+        }
+        LocalChange::CreateTodo { todo_list_id, .. } => (
+            SyncEntity::Todo,
+            None,
+            batch.todo_list_id(todo_list_id).ok(),
+        ),
+        LocalChange::UpdateTodo {
+            todo_list_id, id, ..
+        }
+        | LocalChange::DeleteTodo {
+            todo_list_id, id, ..
+        } => (SyncEntity::Todo, Some(*id), Some(*todo_list_id)),
+    }
+}
+
+//a document that is not there is no error for a conflict
+fn found<T>(res: Result<T, ApiError>) -> Result<Option<T>, ApiError> {
+    match res {
+        Ok(doc) => Ok(Some(doc)),
+        Err(ApiError::NotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+//deleting what is already gone did what the client wanted. Not finding the
+//document is no proof of that: collaborators can't delete the notes of others,
+//those stay around and are rejected. Todos go with their list, `id` is the list.
+async fn already_gone(
+    database: &Database,
+    user_id: ObjectId,
+    entity: SyncEntity,
+    id: ObjectId,
+    res: Result<(), ApiError>,
+) -> Result<(), ApiError> {
+    if !matches!(res, Err(ApiError::NotFound)) {
+        return res;
+    }
+    if found(database.tombstone_repo().get_tombstone(user_id, id).await)?.is_some() {
+        return Ok(());
+    }
+    let (trashed, visible) = match entity {
+        SyncEntity::Note => {
+            let note_repo = database.note_repo();
+            let trashed = note_repo.get_trashed_notes(user_id).await?;
+            let visible = found(note_repo.get_note_by_id(id, user_id).await)?;
+            (trashed.iter().any(|note| note.id == id), visible.is_some())
+        }
+        SyncEntity::TodoList | SyncEntity::Todo => {
+            let todo_repo = database.todos_repo();
+            let trashed = todo_repo.get_trashed_todo_lists(user_id).await?;
+            let visible = found(todo_repo.get_todo_list(id, user_id).await)?;
+            (trashed.iter().any(|list| list.id == id), visible.is_some())
+        }
+    };
+    match (trashed, visible) {
+        (true, _) => Ok(()),
+        //the client gets the list back to see what became of the todo
+        (false, true) if entity == SyncEntity::Todo => Err(ApiError::NotFound),
+        (false, true) => Err(ApiError::Forbidden),
+        (false, false) => Err(ApiError::NotFound),
+    }
+}
+
+async fn apply(
+    database: &Database,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user: &User,
+    batch: &mut Batch,
+    change: LocalChange,
+) -> Result<(), ApiError> {
+    let note_repo = database.note_repo();
+    let todo_repo = database.todos_repo();
+    match change {
+        LocalChange::CreateNote {
+            client_id,
+            title,
+            content,
+            tags,
+        } => {
+            let note = note_service::create_note(
+                note_repo,
+                search_index,
+                events,
+                user.id,
+                &title,
+                &content,
+                tags,
+            )
+            .await?;
+            batch.created.push(Created {
+                client_id,
+                entity: SyncEntity::Note,
+                id: note.id,
+                todo_list_id: None,
+            });
+        }
+        LocalChange::UpdateNote {
+            id,
+            version,
+            title,
+            content,
+            tags,
+        } => {
+            let note = note_service::update_note(
+                note_repo,
+                database.revision_repo(),
+                search_index,
+                events,
+                user,
+                id,
+                CreateNotePayload {
+                    title,
+                    content,
+                    tags,
+                },
+                false,
+                Some(batch.expected(id, version)),
+            )
+            .await?;
+            batch.applied(id, version, note.version);
+        }
+        LocalChange::DeleteNote { id, version } => {
+            let expected = batch.expected(id, version);
+            let res = note_service::delete_note(
+                note_repo,
+                search_index,
+                events,
+                id,
+                user.id,
+                Some(expected),
+            )
+            .await;
+            already_gone(database, user.id, SyncEntity::Note, id, res).await?;
+        }
+        LocalChange::CreateTodoList { client_id, title } => {
+            let todo_list =
+                todo_service::create_todo_list(todo_repo, events, user.id, title).await?;
+            batch.todo_lists.insert(client_id.clone(), todo_list.id);
+            batch.created.push(Created {
+                client_id,
+                entity: SyncEntity::TodoList,
+                id: todo_list.id,
+                todo_list_id: None,
+            });
+        }
+        LocalChange::RenameTodoList { id, version, title } => {
+            let todo_list = todo_service::rename_todo_list(
+                todo_repo,
+                events,
+                id,
+                user.id,
+                title,
+                Some(batch.expected(id, version)),
+            )
+            .await?;
+            batch.applied(id, version, todo_list.version);
+        }
+        LocalChange::DeleteTodoList { id, version } => {
+            let expected = batch.expected(id, version);
+            let res =
+                todo_service::delete_todo_list(todo_repo, events, id, user.id, Some(expected))
+                    .await;
+            already_gone(database, user.id, SyncEntity::TodoList, id, res).await?;
+        }
+        LocalChange::CreateTodo {
+            todo_list_id,
+            client_id,
+            title,
+            status,
+            priority,
+            version,
+        } => {
+            let todo_list_id = batch.todo_list_id(&todo_list_id)?;
+            let todo_list = todo_service::create_todo(
+                todo_repo,
+                events,
+                todo_list_id,
+                user.id,
+                title,
+                status,
+                priority,
+                version.map(|version| batch.expected(todo_list_id, version)),
+            )
+            .await?;
+            if let Some(version) = version {
+                batch.applied(todo_list_id, version, todo_list.version);
+            }
+            //new todos go to the end of the list
+            let todo = todo_list.todos.last().ok_or(ApiError::InternalError)?;
+            batch.created.push(Created {
+                client_id,
+                entity: SyncEntity::Todo,
+                id: todo.id,
+                todo_list_id: Some(todo_list_id),
+            });
+        }
+        LocalChange::UpdateTodo {
+            todo_list_id,
+            id,
+            version,
+            title,
+            status,
+            priority,
+        } => {
+            let todo_list = todo_service::modify_todo(
+                todo_repo,
+                events,
+                todo_list_id,
+                user.id,
+                id,
+                title,
+                status,
+                priority,
+                Some(batch.expected(todo_list_id, version)),
+            )
+            .await?;
+            batch.applied(todo_list_id, version, todo_list.version);
+        }
+        LocalChange::DeleteTodo {
+            todo_list_id,
+            id,
+            version,
+        } => {
+            let res = todo_service::delete_todo(
+                todo_repo,
+                events,
+                todo_list_id,
+                user.id,
+                id,
+                Some(batch.expected(todo_list_id, version)),
+            )
+            .await;
+            if let Ok(todo_list) = &res {
+                batch.applied(todo_list_id, version, todo_list.version);
+            }
+            already_gone(
+                database,
+                user.id,
+                SyncEntity::Todo,
+                todo_list_id,
+                res.map(|_| ()),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
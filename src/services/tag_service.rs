@@ -0,0 +1,60 @@
+use mongodb::bson::oid::ObjectId;
+use std::collections::BTreeMap;
+
+use crate::{
+    error::ApiError,
+    events::{ChangeKind, EventBus},
+    models::note::normalize_tag,
+    repository::note_repo::NoteRepo,
+    routes::tags::{TagChange, TagCount},
+    search::SearchIndex,
+};
+
+/// Tags of the notes the user owns, most used first
+pub async fn get_tags(
+    note_repo: &dyn NoteRepo,
+    user_id: ObjectId,
+) -> Result<Vec<TagCount>, ApiError> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for note in note_repo.get_notes_from_user(user_id).await? {
+        for tag in note.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut tags: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(tag, notes)| TagCount { tag, notes })
+        .collect();
+    tags.sort_by_key(|tag| std::cmp::Reverse(tag.notes));
+    Ok(tags)
+}
+
+/// Merging into one of the merged tags or renaming to the same name is fine,
+/// `from` is matched as stored so tags from before normalization can be fixed
+pub async fn replace_tags(
+    note_repo: &dyn NoteRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user_id: ObjectId,
+    from: Vec<String>,
+    to: Option<&str>,
+) -> Result<TagChange, ApiError> {
+    let to = to.map(normalize_tag);
+    if from.is_empty() || to.as_deref() == Some("") {
+        return Err(ApiError::MissingPayload);
+    }
+    let changed = note_repo
+        .rewrite_tags(user_id, &from, to.as_deref())
+        .await?;
+    if changed.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+    for note in changed.iter().filter(|note| note.deleted_at.is_none()) {
+        search_index.upsert(note);
+        events.note(ChangeKind::Updated, note);
+    }
+    Ok(TagChange {
+        tag: to,
+        notes: changed.len(),
+    })
+}
@@ -2,29 +2,50 @@ use mongodb::bson::oid::ObjectId;
 
 use crate::{
     error::ApiError,
+    events::{ChangeKind, EventBus},
     models::todo::{TodoList, TodoPriority},
-    repository::todo_repo::TodoRepo,
+    repository::{
+        note_repo::NoteRepo,
+        pagination::{ListQuery, Page},
+        todo_repo::TodoRepo,
+    },
 };
 
-pub async fn create_todo_list<R: TodoRepo>(
+pub async fn create_todo_list<R: TodoRepo + ?Sized>(
     repo: &R,
+    events: &EventBus,
     user_id: ObjectId,
     title: String,
 ) -> Result<TodoList, ApiError> {
-    match repo.create_todo_list(title, user_id).await {
-        Ok(res) => Ok(res),
-        Err(err) => Err(err),
-    }
+    let todo_list = repo.create_todo_list(title, user_id).await?;
+    events.todo_list(ChangeKind::Created, &todo_list);
+    Ok(todo_list)
 }
 
-pub async fn get_all_todo_list<R: TodoRepo>(
+/// A tag filter keeps the todo lists pinned to notes with all of the tags
+pub async fn get_all_todo_list<R: TodoRepo + ?Sized>(
     repo: &R,
+    note_repo: &dyn NoteRepo,
     user_id: ObjectId,
-) -> Result<Vec<TodoList>, ApiError> {
-    repo.get_all_todo_lists(user_id).await
+    query: &ListQuery,
+) -> Result<Page<TodoList>, ApiError> {
+    let options = query.options()?;
+    let tags = query.tags();
+    if tags.is_empty() {
+        return repo.get_all_todo_lists(user_id, None, &options).await;
+    }
+    let pinned: Vec<ObjectId> = note_repo
+        .get_notes_from_user(user_id)
+        .await?
+        .into_iter()
+        .filter(|note| tags.iter().all(|tag| note.tags.contains(tag)))
+        .flat_map(|note| note.todo_lists)
+        .collect();
+    repo.get_all_todo_lists(user_id, Some(&pinned), &options)
+        .await
 }
 
-pub async fn get_todo_lists<R: TodoRepo>(
+pub async fn get_todo_lists<R: TodoRepo + ?Sized>(
     repo: &R,
     list: Vec<ObjectId>,
     user_id: ObjectId,
@@ -32,53 +53,102 @@ pub async fn get_todo_lists<R: TodoRepo>(
     repo.get_todo_lists(list, user_id).await
 }
 
-pub async fn delete_todo_list<R: TodoRepo>(
+pub async fn get_todo_list<R: TodoRepo + ?Sized>(
     repo: &R,
     todo_list_id: ObjectId,
     user_id: ObjectId,
+) -> Result<TodoList, ApiError> {
+    repo.get_todo_list(todo_list_id, user_id).await
+}
+
+pub async fn delete_todo_list<R: TodoRepo + ?Sized>(
+    repo: &R,
+    events: &EventBus,
+    todo_list_id: ObjectId,
+    user_id: ObjectId,
+    expected: Option<u64>,
 ) -> Result<(), ApiError> {
-    repo.delete_todo_list(todo_list_id, user_id).await
+    let todo_list = repo
+        .delete_todo_list(todo_list_id, user_id, expected)
+        .await?;
+    events.todo_list(ChangeKind::Deleted, &todo_list);
+    Ok(())
 }
 
-pub async fn rename_todo_list<R: TodoRepo>(
+pub async fn rename_todo_list<R: TodoRepo + ?Sized>(
     repo: &R,
+    events: &EventBus,
     todo_list_id: ObjectId,
     user_id: ObjectId,
     title: String,
-) -> Result<(), ApiError> {
-    repo.rename_todo_list(todo_list_id, user_id, title).await
+    expected: Option<u64>,
+) -> Result<TodoList, ApiError> {
+    let todo_list = repo
+        .rename_todo_list(todo_list_id, user_id, title, expected)
+        .await?;
+    events.todo_list(ChangeKind::Updated, &todo_list);
+    Ok(todo_list)
 }
 
-pub async fn create_todo<R: TodoRepo>(
+#[allow(clippy::too_many_arguments)]
+pub async fn create_todo<R: TodoRepo + ?Sized>(
     repo: &R,
+    events: &EventBus,
     todo_list_id: ObjectId,
     user_id: ObjectId,
     title: String,
     status: bool,
     priority: TodoPriority,
-) -> Result<(), ApiError> {
-    repo.create_todo(todo_list_id, user_id, title, status, priority)
-        .await
+    expected: Option<u64>,
+) -> Result<TodoList, ApiError> {
+    let todo_list = repo
+        .create_todo(todo_list_id, user_id, title, status, priority, expected)
+        .await?;
+    //new todos go to the end of the list
+    if let Some(todo) = todo_list.todos.last() {
+        events.todo(ChangeKind::Created, &todo_list, todo.id);
+    }
+    Ok(todo_list)
 }
 
-pub async fn modify_todo<R: TodoRepo>(
+#[allow(clippy::too_many_arguments)]
+pub async fn modify_todo<R: TodoRepo + ?Sized>(
     repo: &R,
+    events: &EventBus,
     todo_list_id: ObjectId,
     user_id: ObjectId,
     todo_id: ObjectId,
     title: String,
     status: bool,
     priority: TodoPriority,
-) -> Result<(), ApiError> {
-    repo.modify_todo(todo_list_id, user_id, todo_id, title, status, priority)
-        .await
+    expected: Option<u64>,
+) -> Result<TodoList, ApiError> {
+    let todo_list = repo
+        .modify_todo(
+            todo_list_id,
+            user_id,
+            todo_id,
+            title,
+            status,
+            priority,
+            expected,
+        )
+        .await?;
+    events.todo(ChangeKind::Updated, &todo_list, todo_id);
+    Ok(todo_list)
 }
 
-pub async fn delete_todo<R: TodoRepo>(
+pub async fn delete_todo<R: TodoRepo + ?Sized>(
     repo: &R,
+    events: &EventBus,
     todo_list_id: ObjectId,
     user_id: ObjectId,
     todo_id: ObjectId,
-) -> Result<(), ApiError> {
-    repo.delete_todo(todo_list_id, user_id, todo_id).await
+    expected: Option<u64>,
+) -> Result<TodoList, ApiError> {
+    let todo_list = repo
+        .delete_todo(todo_list_id, user_id, todo_id, expected)
+        .await?;
+    events.todo(ChangeKind::Deleted, &todo_list, todo_id);
+    Ok(todo_list)
 }
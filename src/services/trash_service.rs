@@ -0,0 +1,211 @@
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::{
+    database::Database,
+    error::ApiError,
+    events::{ChangeKind, EventBus},
+    models::{
+        note::Note,
+        timestamp,
+        todo::TodoList,
+        tombstone::{SyncEntity, Tombstone},
+    },
+    repository::{note_repo::NoteRepo, todo_repo::TodoRepo, tombstone_repo::TombstoneRepo},
+    routes::trash::{TrashResponse, TrashedItem},
+    search::SearchIndex,
+    services::attachment_service,
+};
+
+pub async fn get_trash(
+    note_repo: &dyn NoteRepo,
+    todo_repo: &dyn TodoRepo,
+    user_id: ObjectId,
+) -> Result<TrashResponse, ApiError> {
+    let notes = note_repo.get_trashed_notes(user_id).await?;
+    let todo_lists = todo_repo.get_trashed_todo_lists(user_id).await?;
+    Ok(TrashResponse {
+        notes: notes.into_iter().map(TrashedItem::from).collect(),
+        todo_lists: todo_lists.into_iter().map(TrashedItem::from).collect(),
+    })
+}
+
+pub async fn restore_note(
+    note_repo: &dyn NoteRepo,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<Note, ApiError> {
+    let note = note_repo.restore_note(note_id, user_id).await?;
+    search_index.upsert(&note);
+    //back from the clients' point of view
+    events.note(ChangeKind::Created, &note);
+    Ok(note)
+}
+
+pub async fn purge_note(
+    database: &Database,
+    user_id: ObjectId,
+    note_id: ObjectId,
+) -> Result<(), ApiError> {
+    let note_repo = database.note_repo();
+    let trashed = note_repo.get_trashed_notes(user_id).await?;
+    if !trashed.iter().any(|note| note.id == note_id) {
+        return Err(ApiError::NotFound);
+    }
+    //the note goes last, it is what leads a retry to the rest
+    remove_note_data(database, note_id, user_id).await?;
+    note_repo.purge_note(note_id, user_id).await?;
+    database
+        .tombstone_repo()
+        .add_tombstones(&[tombstone(SyncEntity::Note, note_id, user_id)])
+        .await
+}
+
+//for clients that sync after the document is gone
+fn tombstone(entity: SyncEntity, id: ObjectId, user_id: ObjectId) -> Tombstone {
+    Tombstone {
+        id,
+        user_id,
+        entity,
+        deleted_at: timestamp::now(),
+    }
+}
+
+/// Everything else stored for a note that is gone for good
+pub async fn remove_note_data(
+    database: &Database,
+    note_id: ObjectId,
+    user_id: ObjectId,
+) -> Result<(), ApiError> {
+    database
+        .revision_repo()
+        .delete_revisions(note_id, user_id)
+        .await?;
+    database
+        .share_link_repo()
+        .delete_links_of_note(note_id)
+        .await?;
+    database
+        .live_doc_repo()
+        .delete_live_doc(note_id, user_id)
+        .await?;
+    attachment_service::delete_attachments_of_note(
+        database.attachment_repo(),
+        database.attachment_storage(),
+        note_id,
+        user_id,
+    )
+    .await
+}
+
+pub async fn restore_todo_list(
+    todo_repo: &dyn TodoRepo,
+    events: &EventBus,
+    user_id: ObjectId,
+    todo_list_id: ObjectId,
+) -> Result<TodoList, ApiError> {
+    let todo_list = todo_repo.restore_todo_list(todo_list_id, user_id).await?;
+    events.todo_list(ChangeKind::Created, &todo_list);
+    Ok(todo_list)
+}
+
+pub async fn purge_todo_list(
+    todo_repo: &dyn TodoRepo,
+    tombstone_repo: &dyn TombstoneRepo,
+    user_id: ObjectId,
+    todo_list_id: ObjectId,
+) -> Result<(), ApiError> {
+    todo_repo.purge_todo_list(todo_list_id, user_id).await?;
+    tombstone_repo
+        .add_tombstones(&[tombstone(SyncEntity::TodoList, todo_list_id, user_id)])
+        .await
+}
+
+/// Permanently removes everything that sat in the trash for longer than `retention`
+pub async fn purge_expired(database: &Database, retention: Duration) -> Result<(), ApiError> {
+    let before = timestamp::now() - retention;
+    let note_repo = database.note_repo();
+    let mut notes = vec![];
+    //a note whose data can't be removed stays in the trash for the next round
+    for note in note_repo.get_notes_deleted_before(before).await? {
+        let purged = match remove_note_data(database, note.id, note.user_id).await {
+            Ok(()) => note_repo.purge_note(note.id, note.user_id).await,
+            Err(err) => Err(err),
+        };
+        match purged {
+            Ok(()) => notes.push(note),
+            Err(err) => error!("Purging note {} from the trash failed: {}", note.id, err),
+        }
+    }
+    let todo_lists = match database
+        .todos_repo()
+        .purge_todo_lists_deleted_before(before)
+        .await
+    {
+        Ok(todo_lists) => todo_lists,
+        Err(err) => {
+            error!("Purging todo lists from the trash failed: {}", err);
+            vec![]
+        }
+    };
+    let tombstones: Vec<Tombstone> = notes
+        .iter()
+        .map(|note| tombstone(SyncEntity::Note, note.id, note.user_id))
+        .chain(
+            todo_lists
+                .iter()
+                .map(|todo_list| tombstone(SyncEntity::TodoList, todo_list.id, todo_list.user_id)),
+        )
+        .collect();
+    let tombstone_repo = database.tombstone_repo();
+    tombstone_repo.add_tombstones(&tombstones).await?;
+    //syncs that old start over anyway
+    tombstone_repo.delete_tombstones_before(before).await?;
+    if !notes.is_empty() || !todo_lists.is_empty() {
+        info!(
+            "Purged {} notes and {} todo lists from the trash",
+            notes.len(),
+            todo_lists.len()
+        );
+    }
+    Ok(())
+}
+
+/// TRASH_RETENTION_DAYS (default 30) is how long deleted items can be restored
+pub fn retention_from_env() -> Duration {
+    let retention_days: i64 = std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30);
+    Duration::days(retention_days)
+}
+
+/*
+* The trash is checked once per TRASH_PURGE_INTERVAL_SECS (default an hour)
+*/
+pub fn spawn_purge_task(database: Arc<Database>) {
+    let retention = retention_from_env();
+    let interval_secs: u64 = std::env::var("TRASH_PURGE_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(3600);
+    info!(
+        "Trash keeps items for {} days, checked every {}s",
+        retention.num_days(),
+        interval_secs
+    );
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(err) = purge_expired(&database, retention).await {
+                error!("Purging the trash failed: {}", err);
+            }
+        }
+    });
+}
@@ -0,0 +1,252 @@
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use mongodb::bson::oid::ObjectId;
+use sha1::Sha1;
+use tracing::{error, warn};
+
+use crate::{
+    auth::hash_secret,
+    error::ApiError,
+    models::{timestamp, two_factor::TwoFactor, user::User},
+    repository::two_factor_repo::TwoFactorRepo,
+    routes::two_factor::{Enrollment, RecoveryCodes, TwoFactorStatus},
+};
+
+/*
+* RFC 6238 with the parameters every authenticator app defaults to:
+* HMAC-SHA1, 30 second steps, 6 digits
+*/
+const ISSUER: &str = "Flexnotes";
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+//codes of the neighbouring steps pass too, phone clocks drift
+const DRIFT_STEPS: i64 = 1;
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODES: usize = 10;
+//after that many wrong codes in a row only one try per LOCKOUT is left
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+const LOCKOUT: Duration = Duration::minutes(15);
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, ApiError> {
+    BASE32_NOPAD.decode(secret.as_bytes()).map_err(|err| {
+        error!("{}", err);
+        ApiError::InternalError
+    })
+}
+
+fn totp(key: &[u8], step: i64) -> Result<String, ApiError> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).map_err(|err| {
+        error!("{}", err);
+        ApiError::InternalError
+    })?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    //dynamic truncation, the last nibble picks 4 bytes of the hash
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    Ok(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+fn step_at(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(STEP_SECS)
+}
+
+/// The code an authenticator app shows at `at`, the tests play the app
+#[cfg(test)]
+pub fn code_at(secret: &str, at: DateTime<Utc>) -> Result<String, ApiError> {
+    totp(&decode_secret(secret)?, step_at(at))
+}
+
+/// Time step within the drift window the code belongs to
+fn matching_step(secret: &str, code: &str, now: DateTime<Utc>) -> Result<Option<i64>, ApiError> {
+    let key = decode_secret(secret)?;
+    let current = step_at(now);
+    for step in current - DRIFT_STEPS..=current + DRIFT_STEPS {
+        if totp(&key, step)? == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+//users type codes with spaces and dashes, recovery codes in any case
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|char| !char.is_whitespace() && *char != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_totp(code: &str) -> bool {
+    code.len() == DIGITS as usize && code.chars().all(|char| char.is_ascii_digit())
+}
+
+fn recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::fill(&mut bytes);
+    let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn otpauth_uri(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}",
+        issuer = ISSUER,
+        user = encode_uri_component(username),
+    )
+}
+
+pub async fn get_status(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user_id: ObjectId,
+) -> Result<TwoFactorStatus, ApiError> {
+    match two_factor_repo.get_two_factor(user_id).await {
+        Ok(two_factor) if two_factor.enabled => Ok(TwoFactorStatus {
+            enabled: true,
+            recovery_codes_left: two_factor.recovery_codes.len(),
+        }),
+        Ok(_) | Err(ApiError::NotFound) => Ok(TwoFactorStatus {
+            enabled: false,
+            recovery_codes_left: 0,
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn is_enabled(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user_id: ObjectId,
+) -> Result<bool, ApiError> {
+    Ok(get_status(two_factor_repo, user_id).await?.enabled)
+}
+
+/// Starts over with a new secret, nothing changes for login until it is confirmed
+pub async fn enroll(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user: &User,
+) -> Result<Enrollment, ApiError> {
+    //replacing an enabled secret would skip the code disabling asks for
+    if is_enabled(two_factor_repo, user.id).await? {
+        return Err(ApiError::Forbidden);
+    }
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::fill(&mut bytes);
+    let secret = BASE32_NOPAD.encode(&bytes);
+    two_factor_repo
+        .save_two_factor(&TwoFactor {
+            user_id: user.id,
+            secret: secret.clone(),
+            enabled: false,
+            recovery_codes: vec![],
+            created_at: timestamp::now(),
+            last_step: 0,
+            failed_attempts: 0,
+            last_failed_at: None,
+        })
+        .await?;
+    Ok(Enrollment {
+        otpauth_uri: otpauth_uri(&user.username, &secret),
+        secret,
+    })
+}
+
+/// Turns a pending enrollment on, the recovery codes are only shown here
+pub async fn confirm(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user_id: ObjectId,
+    code: &str,
+) -> Result<RecoveryCodes, ApiError> {
+    let pending = two_factor_repo.get_two_factor(user_id).await?;
+    if pending.enabled {
+        return Err(ApiError::Forbidden);
+    }
+    let code = normalize(code);
+    let step = match is_totp(&code) {
+        true => matching_step(&pending.secret, &code, timestamp::now())?,
+        false => None,
+    }
+    .ok_or(ApiError::InvalidCode)?;
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES).map(|_| recovery_code()).collect();
+    two_factor_repo
+        .save_two_factor(&TwoFactor {
+            enabled: true,
+            recovery_codes: recovery_codes
+                .iter()
+                .map(|code| hash_secret(&normalize(code)))
+                .collect(),
+            last_step: step,
+            ..pending
+        })
+        .await?;
+    Ok(RecoveryCodes { recovery_codes })
+}
+
+/// Accepts a code from the authenticator app or an unused recovery code,
+/// each of them works once
+pub async fn verify(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user_id: ObjectId,
+    code: &str,
+) -> Result<(), ApiError> {
+    let two_factor = two_factor_repo.get_two_factor(user_id).await?;
+    if !two_factor.enabled {
+        return Err(ApiError::NotFound);
+    }
+    let now = timestamp::now();
+    if two_factor.failed_attempts >= MAX_FAILED_ATTEMPTS
+        && two_factor
+            .last_failed_at
+            .is_some_and(|last_failed_at| now - last_failed_at < LOCKOUT)
+    {
+        return Err(ApiError::TooManyAttempts);
+    }
+    let code = normalize(code);
+    let result = match is_totp(&code) {
+        true => match matching_step(&two_factor.secret, &code, now)? {
+            Some(step) => two_factor_repo.use_step(user_id, step).await,
+            None => Err(ApiError::InvalidCode),
+        },
+        false => {
+            two_factor_repo
+                .use_recovery_code(user_id, &hash_secret(&code))
+                .await
+        }
+    };
+    if let Err(ApiError::InvalidCode) = result {
+        warn!("Wrong two-factor code for user {}", user_id);
+        two_factor_repo.record_failed_attempt(user_id, now).await?;
+    }
+    result
+}
+
+/// Takes a valid code, a stolen session alone can't turn the second factor off
+pub async fn disable(
+    two_factor_repo: &dyn TwoFactorRepo,
+    user_id: ObjectId,
+    code: &str,
+) -> Result<(), ApiError> {
+    verify(two_factor_repo, user_id, code).await?;
+    two_factor_repo.delete_two_factor(user_id).await
+}
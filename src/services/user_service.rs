@@ -1,23 +1,69 @@
 use crate::{
-    auth::{generate_acces_token, generate_refresh_token, AuthResponseBody},
+    auth::{
+        decode_token, generate_acces_token, generate_challenge_token, generate_refresh_token,
+        random_token, AuthResponseBody, TokenType, PASSWORD_COST, REFRESH_TOKEN_SECS,
+    },
+    database::Database,
     error::ApiError,
-    models::user::User,
-    repository::user_repo::UserRepo,
+    events::EventBus,
+    models::{refresh_token::RefreshToken, session::Session, timestamp, user::User},
+    repository::refresh_token_repo::RefreshTokenRepo,
+    routes::{
+        auth::{LoginResponse, TwoFactorChallenge},
+        sessions::ClientInfo,
+    },
+    search::SearchIndex,
+    services::{session_service, trash_service, two_factor_service},
 };
 use bcrypt::*;
+use chrono::Duration;
 use mongodb::bson::oid::ObjectId;
-use tracing::error;
-pub async fn register_user<R: UserRepo>(
-    repo: &R,
+use tracing::{error, info, warn};
+
+/// Access token and a refresh token of the session, which is stored for /auth/refresh
+async fn issue_tokens(
+    token_repo: &dyn RefreshTokenRepo,
+    user: &User,
+    session: &Session,
+) -> Result<(String, String), ApiError> {
+    let stored = RefreshToken {
+        id: random_token(),
+        user_id: user.id,
+        family: session.id,
+        expires_at: timestamp::now() + Duration::seconds(REFRESH_TOKEN_SECS as i64),
+        used_at: None,
+    };
+    let token = generate_acces_token(&user.username, session.id)?;
+    let refresh_token = generate_refresh_token(&user.username, session.id, &stored.id)?;
+    token_repo.create_refresh_token(&stored).await?;
+    Ok((token, refresh_token))
+}
+
+//every login is a session of its own
+async fn sign_in(
+    database: &Database,
+    user: User,
+    client: ClientInfo,
+) -> Result<AuthResponseBody, ApiError> {
+    let session = session_service::start_session(database.session_repo(), user.id, client).await?;
+    let (token, refresh_token) =
+        issue_tokens(database.refresh_token_repo(), &user, &session).await?;
+    Ok(AuthResponseBody::new(token, refresh_token, user.username))
+}
+
+pub async fn register_user(
+    database: &Database,
     username: &str,
     email: &str,
     password: &str,
+    client: ClientInfo,
 ) -> Result<AuthResponseBody, ApiError> {
-    if (repo.user_exist(username, password)).await? {
+    let repo = database.user_repo();
+    if (repo.user_exist(username, email)).await? {
         return Err(ApiError::UserExist);
     }
 
-    let hashed_password = hash(password, DEFAULT_COST).map_err(|err| {
+    let hashed_password = hash(password, PASSWORD_COST).map_err(|err| {
         error!("{}", err);
         ApiError::InternalError
     })?;
@@ -31,32 +77,199 @@ pub async fn register_user<R: UserRepo>(
 
     repo.create_user(&user).await?;
 
-    let token = generate_acces_token(username)?;
-    let refresh_token = generate_refresh_token(username)?;
-    Ok(AuthResponseBody::new(
-        token,
-        refresh_token,
-        username.to_string(),
-    ))
+    sign_in(database, user, client).await
 }
 
-pub async fn login_user<R: UserRepo>(
-    repo: &R,
+pub async fn login_user(
+    database: &Database,
     username: &str,
     password: &str,
-) -> Result<AuthResponseBody, ApiError> {
-    let user = repo.get_user(username).await?;
+    client: ClientInfo,
+) -> Result<LoginResponse, ApiError> {
+    let user = database.user_repo().get_user(username).await?;
 
     match verify(password, &user.password) {
-        Ok(true) => {
-            let token = generate_acces_token(&user.username)?;
-            let refresh_token = generate_refresh_token(&user.username)?;
-            Ok(AuthResponseBody::new(token, refresh_token, user.username))
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::Unathorized),
+        Err(err) => {
+            error!("{}", err);
+            return Err(ApiError::InternalError);
         }
-        Ok(false) => Err(ApiError::Unathorized),
+    }
+    //no session until the second factor is in
+    if two_factor_service::is_enabled(database.two_factor_repo(), user.id).await? {
+        let challenge_token = generate_challenge_token(&user.username)?;
+        return Ok(LoginResponse::Challenge(TwoFactorChallenge::new(
+            challenge_token,
+            user.username,
+        )));
+    }
+    Ok(LoginResponse::Tokens(
+        sign_in(database, user, client).await?,
+    ))
+}
+
+/// Second step of a login with two-factor authentication
+pub async fn complete_login(
+    database: &Database,
+    challenge_token: &str,
+    code: &str,
+    client: ClientInfo,
+) -> Result<AuthResponseBody, ApiError> {
+    let claims = decode_token(challenge_token, TokenType::Challenge)?;
+    let user = database.user_repo().get_user(&claims.username).await?;
+    match two_factor_service::verify(database.two_factor_repo(), user.id, code).await {
+        Ok(()) => sign_in(database, user, client).await,
+        //turned off since the challenge was issued
+        Err(ApiError::NotFound) => Err(ApiError::Unathorized),
+        Err(err) => Err(err),
+    }
+}
+
+/// Swaps a refresh token for a new pair. Every refresh token works once, one
+/// that shows up again was stolen or replayed and ends its whole family.
+pub async fn refresh_tokens(
+    database: &Database,
+    events: &EventBus,
+    refresh_token: &str,
+) -> Result<(String, String), ApiError> {
+    let token_repo = database.refresh_token_repo();
+    let claims = decode_token(refresh_token, TokenType::Refresh)?;
+    let stored = match token_repo.use_refresh_token(&claims.jti).await {
+        Ok(stored) => stored,
+        //revoked
+        Err(ApiError::NotFound) => return Err(ApiError::Unathorized),
+        Err(err) => return Err(err),
+    };
+    if stored.used_at.is_some() {
+        warn!(
+            "Refresh token of {} was used twice, revoking its family",
+            claims.username
+        );
+        return match session_service::revoke_session(
+            database,
+            events,
+            stored.user_id,
+            stored.family,
+        )
+        .await
+        {
+            Ok(()) | Err(ApiError::NotFound) => Err(ApiError::Unathorized),
+            Err(err) => Err(err),
+        };
+    }
+    let session =
+        session_service::current_session(database.session_repo(), stored.user_id, stored.family)
+            .await?;
+    let user = database.user_repo().get_user_by_id(stored.user_id).await?;
+    issue_tokens(token_repo, &user, &session).await
+}
+
+//wrong passwords of a signed in user are forbidden, not a reason to log out
+fn check_password(user: &User, password: &str) -> Result<(), ApiError> {
+    match verify(password, &user.password) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ApiError::Forbidden),
         Err(err) => {
             error!("{}", err);
             Err(ApiError::InternalError)
         }
     }
 }
+
+/// Needs the current password, every session but `current` ends
+pub async fn change_password(
+    database: &Database,
+    events: &EventBus,
+    current: &Session,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), ApiError> {
+    let repo = database.user_repo();
+    let user = repo.get_user_by_id(current.user_id).await?;
+    check_password(&user, current_password)?;
+    let hashed_password = hash(new_password, PASSWORD_COST).map_err(|err| {
+        error!("{}", err);
+        ApiError::InternalError
+    })?;
+    repo.update_password(user.id, &hashed_password).await?;
+    session_service::revoke_other_sessions(database, events, current).await
+}
+
+pub async fn change_email(
+    database: &Database,
+    user_id: ObjectId,
+    password: &str,
+    email: &str,
+) -> Result<(), ApiError> {
+    let repo = database.user_repo();
+    let user = repo.get_user_by_id(user_id).await?;
+    check_password(&user, password)?;
+    repo.update_email(user.id, email).await
+}
+
+/// Removes the user and everything stored for them. Whatever hangs off a
+/// record is removed before the record itself and the user goes last, so a
+/// deletion that failed halfway can be started again.
+pub async fn delete_account(
+    database: &Database,
+    search_index: &SearchIndex,
+    events: &EventBus,
+    user_id: ObjectId,
+    password: &str,
+) -> Result<(), ApiError> {
+    let user = database.user_repo().get_user_by_id(user_id).await?;
+    check_password(&user, password)?;
+
+    //the notes are what finds their revisions, links and files
+    let note_repo = database.note_repo();
+    let mut notes = note_repo.get_notes_from_user(user.id).await?;
+    notes.extend(note_repo.get_trashed_notes(user.id).await?);
+    for note in notes.iter() {
+        trash_service::remove_note_data(database, note.id, user.id).await?;
+    }
+    note_repo.purge_notes_of_user(user.id).await?;
+    search_index.remove_user(user.id);
+    database
+        .todos_repo()
+        .purge_todo_lists_of_user(user.id)
+        .await?;
+    let notebook_repo = database.notebook_repo();
+    let notebook_ids: Vec<ObjectId> = notebook_repo
+        .get_notebooks(user.id)
+        .await?
+        .iter()
+        .map(|notebook| notebook.id)
+        .collect();
+    notebook_repo
+        .delete_notebooks(user.id, &notebook_ids)
+        .await?;
+    database
+        .tombstone_repo()
+        .delete_tombstones_of_user(user.id)
+        .await?;
+
+    let token_repo = database.api_token_repo();
+    for token in token_repo.get_tokens(user.id).await? {
+        token_repo.delete_token(token.id, user.id).await?;
+    }
+    match database.two_factor_repo().delete_two_factor(user.id).await {
+        Ok(()) | Err(ApiError::NotFound) => {}
+        Err(err) => return Err(err),
+    }
+    database
+        .logs_repo()
+        .delete_logs_of_user(user.id)
+        .await
+        .map_err(|err| {
+            error!("{}", err);
+            ApiError::InternalError
+        })?;
+    for session in database.session_repo().get_sessions(user.id).await? {
+        session_service::revoke_session(database, events, user.id, session.id).await?;
+    }
+
+    database.user_repo().delete_user(user.id).await?;
+    info!("Deleted the account of {}", user.username);
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use super::{oid, TestApp};
+use crate::{
+    database::Database,
+    error::ApiError,
+    logger::MemoryLogger,
+    models::note::NoteRole,
+    repository::{
+        attachment_storage::{AttachmentStorage, ByteStream, MemoryStorage, UploadStream},
+        note_repo::{NoteRepo, SledNoteRepo},
+    },
+};
+use async_trait::async_trait;
+use axum::http::{Method, StatusCode};
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+impl TestApp {
+    async fn login_with(&self, username: &str, password: &str) -> StatusCode {
+        self.request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": username, "password": password })),
+        )
+        .await
+        .status
+    }
+
+    async fn delete_account(&self, token: &str, password: &str) -> StatusCode {
+        self.request(
+            Method::DELETE,
+            "/auth/account",
+            Some(token),
+            Some(json!({ "password": password })),
+        )
+        .await
+        .status
+    }
+}
+
+#[tokio::test]
+async fn changing_the_password_ends_other_sessions() {
+    let app = TestApp::new();
+    let laptop = app.register("alice").await;
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "password" })),
+        )
+        .await;
+    let phone = res.body["access_token"].as_str().unwrap().to_string();
+
+    let res = app
+        .patch(
+            "/auth/account/password",
+            &laptop,
+            Some(json!({ "current_password": "guess", "new_password": "s3cret" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app
+        .patch(
+            "/auth/account/password",
+            &laptop,
+            Some(json!({ "current_password": "password", "new_password": "" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .patch(
+            "/auth/account/password",
+            &laptop,
+            Some(json!({ "current_password": "password", "new_password": "s3cret" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/auth/check", &laptop).await.status,
+        StatusCode::ACCEPTED
+    );
+    assert_eq!(
+        app.get("/auth/check", &phone).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.login_with("alice", "password").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(app.login_with("alice", "s3cret").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn changing_the_email_frees_the_old_one() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    app.register("bob").await;
+
+    let res = app
+        .patch(
+            "/auth/account/email",
+            &alice,
+            Some(json!({ "email": "bob@flexnotes.test", "password": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FOUND);
+    let res = app
+        .patch(
+            "/auth/account/email",
+            &alice,
+            Some(json!({ "email": "alice@example.test", "password": "guess" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .patch(
+            "/auth/account/email",
+            &alice,
+            Some(json!({ "email": "alice@example.test", "password": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let user = app
+        .state
+        .database
+        .user_repo()
+        .get_user("alice")
+        .await
+        .unwrap();
+    assert_eq!(user.email, "alice@example.test");
+    //someone else can sign up with the old address now
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(json!({
+                "username": "carol",
+                "email": "alice@flexnotes.test",
+                "password": "password",
+            })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleting_the_account_removes_everything_of_the_user() {
+    let logs = Arc::new(MemoryLogger::new());
+    let app = TestApp::with_database(Database::memory().with_logs(logs.clone()));
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let alice_id = app
+        .state
+        .database
+        .user_repo()
+        .get_user("alice")
+        .await
+        .unwrap()
+        .id;
+
+    let note = app.create_note(&alice, "Diary", "private").await;
+    let res = app
+        .upload(&alice, &note, &[("photo.txt", "text/plain", b"photo")])
+        .await;
+    let attachment = oid(&res.body[0]["id"]).parse::<ObjectId>().unwrap();
+    let trashed = app.create_note(&alice, "Old", "").await;
+    app.delete(&format!("/notes/id/{}", trashed), &alice).await;
+    app.create_todo_list(&alice, "Chores").await;
+    app.post("/notebooks", &alice, json!({ "name": "Journal" }))
+        .await;
+    app.post(
+        "/auth/tokens",
+        &alice,
+        json!({ "name": "backup", "scopes": ["notes:read"] }),
+    )
+    .await;
+    let shared = app.create_note(&bob, "Plan", "").await;
+    app.post(
+        &format!("/notes/id/{}/shares", shared),
+        &bob,
+        json!({ "username": "alice", "role": "editor" }),
+    )
+    .await;
+
+    assert!(logs.logs_of_user(alice_id) > 0);
+
+    assert_eq!(
+        app.delete_account(&alice, "guess").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(app.delete_account(&alice, "password").await, StatusCode::OK);
+
+    assert_eq!(
+        app.get("/auth/check", &alice).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.login_with("alice", "password").await,
+        StatusCode::NOT_FOUND
+    );
+    let database = &app.state.database;
+    assert!(database
+        .note_repo()
+        .get_notes_changed_since(alice_id, None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(database
+        .note_repo()
+        .get_trashed_notes(alice_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(database
+        .todos_repo()
+        .get_todo_lists_changed_since(alice_id, None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(database
+        .notebook_repo()
+        .get_notebooks(alice_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(database
+        .api_token_repo()
+        .get_tokens(alice_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(database
+        .session_repo()
+        .get_sessions(alice_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        database.attachment_storage().open(attachment, 0..5).await,
+        Err(ApiError::NotFound)
+    ));
+    assert_eq!(logs.logs_of_user(alice_id), 0);
+    let res = app.get(&format!("/notes/id/{}/shares", shared), &bob).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(res.body.as_array().unwrap().is_empty());
+
+    //the name is free again and comes without the old data
+    let alice = app.register("alice").await;
+    let res = app.get("/notes", &alice).await;
+    assert!(res.body["items"].as_array().unwrap().is_empty());
+}
+
+/*
+* Memory storage that can't delete files while `broken` is set
+*/
+#[derive(Default)]
+pub(super) struct FlakyStorage {
+    files: MemoryStorage,
+    pub(super) broken: AtomicBool,
+}
+
+#[async_trait]
+impl AttachmentStorage for FlakyStorage {
+    async fn put(
+        &self,
+        id: ObjectId,
+        filename: &str,
+        data: UploadStream<'_>,
+    ) -> Result<u64, ApiError> {
+        self.files.put(id, filename, data).await
+    }
+
+    async fn open(&self, id: ObjectId, range: Range<u64>) -> Result<ByteStream, ApiError> {
+        self.files.open(id, range).await
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), ApiError> {
+        match self.broken.load(Ordering::SeqCst) {
+            true => Err(ApiError::InternalError),
+            false => self.files.delete(id).await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_deletion_that_failed_halfway_can_be_retried() {
+    let storage = Arc::new(FlakyStorage::default());
+    let app = TestApp::with_database(Database::memory().with_attachment_storage(storage.clone()));
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Photos", "").await;
+    let res = app
+        .upload(&token, &note, &[("photo.txt", "text/plain", b"photo")])
+        .await;
+    let attachment = oid(&res.body[0]["id"]).parse::<ObjectId>().unwrap();
+
+    storage.broken.store(true, Ordering::SeqCst);
+    assert_eq!(
+        app.delete_account(&token, "password").await,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    //the note is kept, it is what leads the retry to the file
+    let res = app.get(&format!("/notes/id/{}", note), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(storage.open(attachment, 0..5).await.is_ok());
+
+    storage.broken.store(false, Ordering::SeqCst);
+    assert_eq!(app.delete_account(&token, "password").await, StatusCode::OK);
+    assert!(matches!(
+        storage.open(attachment, 0..5).await,
+        Err(ApiError::NotFound)
+    ));
+    assert_eq!(
+        app.login_with("alice", "password").await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn sled_drops_the_shares_of_a_deleted_user() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let repo = SledNoteRepo::new(
+        db.open_tree("notes").unwrap(),
+        db.open_tree("note_shares").unwrap(),
+    );
+    let owner = ObjectId::new();
+    let user = ObjectId::new();
+    let own = repo.create_note(user, "Mine", "", vec![]).await.unwrap();
+    let note = repo.create_note(owner, "Plan", "", vec![]).await.unwrap();
+    repo.share_note(note.id, owner, user, NoteRole::Viewer)
+        .await
+        .unwrap();
+    repo.share_note(own.id, user, owner, NoteRole::Viewer)
+        .await
+        .unwrap();
+
+    let purged = repo.purge_notes_of_user(user).await.unwrap();
+    assert_eq!(purged.len(), 1);
+    assert_eq!(purged[0].id, own.id);
+    assert!(repo.get_note_by_id(note.id, user).await.is_err());
+    assert!(repo.get_note_by_id(own.id, owner).await.is_err());
+    let note = repo.get_note_by_id(note.id, owner).await.unwrap();
+    assert!(note.shared_with.is_empty());
+}
@@ -0,0 +1,178 @@
+use super::{oid, TestApp};
+use crate::{
+    auth::hash_secret,
+    models::{
+        api_token::{ApiToken, Scope},
+        timestamp,
+    },
+};
+use axum::http::StatusCode;
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+#[tokio::test]
+async fn tokens_are_limited_to_their_scopes() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Groceries", "- milk").await;
+
+    let res = app
+        .post(
+            "/auth/tokens",
+            &token,
+            json!({ "name": "backup script", "scopes": ["notes:read"] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["name"], "backup script");
+    let api_token = res.body["token"].as_str().unwrap().to_string();
+    assert!(api_token.starts_with("fnp_"));
+
+    //reading notes is what the token is for
+    let res = app.get(&format!("/notes/id/{}", note), &api_token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["title"], "Groceries");
+    assert_eq!(
+        app.get("/auth/check", &api_token).await.status,
+        StatusCode::ACCEPTED
+    );
+
+    //but nothing else
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", note),
+            &api_token,
+            Some(json!({ "title": "Shopping" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        app.get("/todos", &api_token).await.status,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        app.get("/trash", &api_token).await.status,
+        StatusCode::FORBIDDEN
+    );
+    //a token can't mint more tokens
+    let res = app
+        .post(
+            "/auth/tokens",
+            &api_token,
+            json!({ "name": "escalated", "scopes": ["todos:write"] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    //the secret is shown once, the list has no trace of it
+    let res = app.get("/auth/tokens", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let tokens = res.body.as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["scopes"], json!(["notes:read"]));
+    assert!(tokens[0].get("token").is_none());
+    assert!(tokens[0].get("hash").is_none());
+    assert!(tokens[0]["last_used_at"].is_string());
+    let id = oid(&tokens[0]["id"]);
+
+    let res = app.delete(&format!("/auth/tokens/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get(&format!("/notes/id/{}", note), &api_token)
+            .await
+            .status,
+        StatusCode::UNAUTHORIZED
+    );
+    let res = app.delete(&format!("/auth/tokens/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn write_scopes_reach_their_own_group_only() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let res = app
+        .post(
+            "/auth/tokens",
+            &token,
+            json!({ "name": "todo sync", "scopes": ["todos:read", "todos:write"] }),
+        )
+        .await;
+    let api_token = res.body["token"].as_str().unwrap().to_string();
+
+    let list = app.create_todo_list(&api_token, "Chores").await;
+    let res = app.get(&format!("/todos/id/{}", list), &api_token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app
+        .post("/notes/create", &api_token, json!({ "title": "Nope" }))
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    //someone else's token is none of bob's business
+    let id = oid(&app.get("/auth/tokens", &token).await.body[0]["id"]);
+    let bob = app.register("bob").await;
+    let res = app.delete(&format!("/auth/tokens/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn expired_and_invalid_tokens_are_rejected() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+
+    for payload in [
+        json!({ "name": "", "scopes": ["notes:read"] }),
+        json!({ "name": "no scopes", "scopes": [] }),
+        json!({
+            "name": "already over",
+            "scopes": ["notes:read"],
+            "expires_at": timestamp::format(&(timestamp::now() - Duration::hours(1))),
+        }),
+    ] {
+        let res = app.post("/auth/tokens", &token, payload).await;
+        assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    }
+    let res = app
+        .post(
+            "/auth/tokens",
+            &token,
+            json!({ "name": "everything", "scopes": ["notes:admin"] }),
+        )
+        .await;
+    assert!(res.status.is_client_error());
+
+    //a token that ran out since it was created
+    let user = app
+        .state
+        .database
+        .user_repo()
+        .get_user("alice")
+        .await
+        .unwrap();
+    let expired = "fnp_expired";
+    let now = timestamp::now();
+    app.state
+        .database
+        .api_token_repo()
+        .create_token(&ApiToken {
+            id: ObjectId::new(),
+            user_id: user.id,
+            name: "old".to_string(),
+            hash: hash_secret(expired),
+            scopes: vec![Scope::NotesRead],
+            created_at: now - Duration::days(30),
+            expires_at: Some(now - Duration::days(1)),
+            last_used_at: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        app.get("/notes", expired).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.get("/notes", "fnp_made-up").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+}
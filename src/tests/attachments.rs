@@ -0,0 +1,316 @@
+use super::{oid, TestApp, TestResponse};
+use crate::{
+    error::ApiError,
+    repository::attachment_storage::{AttachmentStorage, LocalFsStorage},
+    services::attachment_service::parse_range,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Method, Request, StatusCode},
+};
+use futures::{StreamExt, TryStreamExt};
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+const BOUNDARY: &str = "flexnotes-boundary";
+
+impl TestApp {
+    /// Uploads files as (filename, content type, bytes) in one multipart body
+    pub(super) async fn upload(
+        &self,
+        token: &str,
+        note: &str,
+        files: &[(&str, &str, &[u8])],
+    ) -> TestResponse {
+        let mut body = Vec::new();
+        for (filename, content_type, data) in files {
+            body.extend(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                    BOUNDARY, filename, content_type
+                )
+                .bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{}--\r\n", BOUNDARY).bytes());
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/notes/id/{}/attachments", note))
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        self.send(request).await
+    }
+
+    async fn download(&self, token: &str, url: &str, range: Option<&str>) -> TestResponse {
+        let mut builder = Request::builder()
+            .uri(url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        if let Some(range) = range {
+            builder = builder.header(header::RANGE, range);
+        }
+        self.send(builder.body(Body::empty()).unwrap()).await
+    }
+}
+
+fn header(res: &TestResponse, name: header::HeaderName) -> &str {
+    res.headers[name].to_str().unwrap()
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(parse_range("bytes=0-4", 10).unwrap(), Some(0..5));
+    assert_eq!(parse_range("bytes=5-", 10).unwrap(), Some(5..10));
+    assert_eq!(parse_range("bytes=-3", 10).unwrap(), Some(7..10));
+    assert_eq!(parse_range("bytes=8-100", 10).unwrap(), Some(8..10));
+    //multiple or broken ranges are served whole
+    assert_eq!(parse_range("bytes=0-1,4-5", 10).unwrap(), None);
+    assert_eq!(parse_range("bytes=5-2", 10).unwrap(), None);
+    assert_eq!(parse_range("items=0-1", 10).unwrap(), None);
+    assert!(matches!(
+        parse_range("bytes=10-", 10),
+        Err(ApiError::RangeNotSatisfiable)
+    ));
+    assert!(matches!(
+        parse_range("bytes=-0", 10),
+        Err(ApiError::RangeNotSatisfiable)
+    ));
+}
+
+#[tokio::test]
+async fn upload_download_and_ranges() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Trip", "").await;
+
+    let res = app
+        .upload(
+            &token,
+            &note,
+            &[
+                ("../notes.txt", "text/plain", b"hello attachments"),
+                ("map.png", "application/octet-stream", b"png"),
+            ],
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let uploaded = res.body.as_array().unwrap();
+    assert_eq!(uploaded[0]["filename"], "notes.txt");
+    assert_eq!(uploaded[0]["size"], 17);
+    //guessed from the extension when the client doesn't know
+    assert_eq!(uploaded[1]["content_type"], "image/png");
+    let url = uploaded[0]["url"].as_str().unwrap().to_string();
+    assert_eq!(
+        url,
+        format!("/notes/id/{}/attachments/{}", note, oid(&uploaded[0]["id"]))
+    );
+
+    let res = app
+        .get(&format!("/notes/id/{}/attachments", note), &token)
+        .await;
+    assert_eq!(res.body.as_array().unwrap().len(), 2);
+
+    let res = app.download(&token, &url, None).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body, "hello attachments");
+    assert_eq!(header(&res, header::CONTENT_TYPE), "text/plain");
+    assert_eq!(header(&res, header::ACCEPT_RANGES), "bytes");
+    assert_eq!(
+        header(&res, header::CONTENT_DISPOSITION),
+        "inline; filename=\"notes.txt\"; filename*=UTF-8''notes.txt"
+    );
+
+    let res = app.download(&token, &url, Some("bytes=0-4")).await;
+    assert_eq!(res.status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body, "hello");
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes 0-4/17");
+    assert_eq!(header(&res, header::CONTENT_LENGTH), "5");
+
+    let res = app.download(&token, &url, Some("bytes=-11")).await;
+    assert_eq!(res.body, "attachments");
+
+    let res = app.download(&token, &url, Some("bytes=17-")).await;
+    assert_eq!(res.status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes */17");
+
+    let res = app.delete(&url, &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.download(&token, &url, None).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn viewers_download_and_strangers_see_nothing() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    let note = app.create_note(&alice, "Plan", "").await;
+    app.post(
+        &format!("/notes/id/{}/shares", note),
+        &alice,
+        json!({ "username": "bob", "role": "viewer" }),
+    )
+    .await;
+    let res = app
+        .upload(&alice, &note, &[("plan.txt", "text/plain", b"plan")])
+        .await;
+    let url = res.body[0]["url"].as_str().unwrap().to_string();
+
+    let res = app.download(&bob, &url, None).await;
+    assert_eq!(res.body, "plan");
+    let res = app
+        .upload(&bob, &note, &[("mine.txt", "text/plain", b"mine")])
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app.delete(&url, &bob).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app.download(&carol, &url, None).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn uploads_count_against_the_quota() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Photos", "").await;
+    let photo = vec![7u8; 600 * 1024];
+
+    let res = app
+        .upload(&token, &note, &[("a.jpg", "image/jpeg", &photo)])
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let url = res.body[0]["url"].as_str().unwrap().to_string();
+    let res = app
+        .upload(&token, &note, &[("b.jpg", "image/jpeg", &photo)])
+        .await;
+    assert_eq!(res.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let res = app.get("/notes/attachments", &token).await;
+    assert_eq!(
+        res.body,
+        json!({ "used": 600 * 1024, "quota": 1024 * 1024 })
+    );
+
+    app.delete(&url, &token).await;
+    let res = app
+        .upload(&token, &note, &[("b.jpg", "image/jpeg", &photo)])
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn parallel_uploads_cannot_both_use_the_last_space() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Photos", "").await;
+    let photo = vec![7u8; 600 * 1024];
+
+    let (a, b): (&[_], &[_]) = (
+        &[("a.jpg", "image/jpeg", &photo[..])],
+        &[("b.jpg", "image/jpeg", &photo[..])],
+    );
+    let (first, second) = tokio::join!(app.upload(&token, &note, a), app.upload(&token, &note, b));
+    //at most one of them fits, both backing off is fine too
+    let stored = [first.status, second.status]
+        .iter()
+        .filter(|status| **status == StatusCode::OK)
+        .count();
+    assert!(stored <= 1);
+    let res = app.get("/notes/attachments", &token).await;
+    assert_eq!(res.body["used"], stored * 600 * 1024);
+}
+
+#[tokio::test]
+async fn broken_uploads_are_bad_requests() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Trip", "").await;
+
+    //cut off before the closing boundary
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/notes/id/{}/attachments", note))
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nhalf a fi",
+            BOUNDARY
+        )))
+        .unwrap();
+    let res = app.send(request).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    //nothing of the broken request is kept
+    let res = app.get("/notes/attachments", &token).await;
+    assert_eq!(res.body["used"], 0);
+    let res = app
+        .get(&format!("/notes/id/{}/attachments", note), &token)
+        .await;
+    assert_eq!(res.body, json!([]));
+
+    let res = app.upload(&token, &note, &[]).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn purging_a_note_removes_its_files() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Old", "").await;
+    let res = app
+        .upload(&token, &note, &[("old.txt", "text/plain", b"old")])
+        .await;
+    let id = oid(&res.body[0]["id"]).parse::<ObjectId>().unwrap();
+
+    //trashed notes keep their attachments until they are purged
+    app.delete(&format!("/notes/id/{}", note), &token).await;
+    let storage = app.state.database.attachment_storage();
+    assert!(storage.open(id, 0..3).await.is_ok());
+
+    let res = app.delete(&format!("/trash/notes/{}", note), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(matches!(
+        storage.open(id, 0..3).await,
+        Err(ApiError::NotFound)
+    ));
+    let res = app.get("/notes/attachments", &token).await;
+    assert_eq!(res.body["used"], 0);
+}
+
+#[tokio::test]
+async fn local_fs_storage_streams_ranges() {
+    let root = std::env::temp_dir().join(format!("flexnotes-{}", ObjectId::new()));
+    let storage = LocalFsStorage::new(&root);
+    let id = ObjectId::new();
+    let data = futures::stream::iter(["01234", "56789"].map(|chunk| Ok(Bytes::from(chunk))));
+    let size = storage.put(id, "file.txt", data.boxed()).await.unwrap();
+    assert_eq!(size, 10);
+
+    let chunks: Vec<Bytes> = storage
+        .open(id, 2..6)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(chunks.concat(), b"2345");
+
+    storage.delete(id).await.unwrap();
+    storage.delete(id).await.unwrap();
+    assert!(matches!(
+        storage.open(id, 0..1).await,
+        Err(ApiError::NotFound)
+    ));
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,153 @@
+use super::TestApp;
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+#[tokio::test]
+async fn register_and_login() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["username"], "alice");
+    assert_eq!(res.body["token_type"], "Bearer");
+
+    let token = res.body["access_token"].as_str().unwrap();
+    let res = app.get("/auth/check", token).await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn login_with_wrong_password() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "wrong" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "bob", "password": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn register_duplicate_user() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(json!({ "username": "alice2", "email": "alice@flexnotes.test", "password": "x" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FOUND);
+
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(json!({ "username": "alice", "email": "other@flexnotes.test", "password": "x" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FOUND);
+}
+
+#[tokio::test]
+async fn protected_routes_require_token() {
+    let app = TestApp::new();
+
+    let res = app.request(Method::GET, "/notes", None, None).await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app.request(Method::GET, "/auth/check", None, None).await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn refresh_tokens_rotate_and_reuse_revokes_the_family() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let login = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "password" })),
+        )
+        .await;
+    let access = login.body["access_token"].as_str().unwrap();
+    let first = login.body["refresh_token"].as_str().unwrap();
+    let refresh = |token: &str| {
+        app.request(
+            Method::POST,
+            "/auth/refresh",
+            None,
+            Some(json!({ "refresh_token": token })),
+        )
+    };
+
+    //neither token stands in for the other
+    assert_eq!(
+        app.get("/auth/check", first).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(refresh(access).await.status, StatusCode::UNAUTHORIZED);
+
+    let res = refresh(first).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let second = res.body["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(second, first);
+    let res = app
+        .get("/auth/check", res.body["acces_token"].as_str().unwrap())
+        .await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+
+    let res = refresh(&second).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let third = res.body["refresh_token"].as_str().unwrap().to_string();
+
+    //the first one again, everything of that login is revoked
+    assert_eq!(refresh(first).await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&third).await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        app.get("/auth/check", access).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(refresh("garbage").await.status, StatusCode::UNAUTHORIZED);
+
+    //other logins keep working
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "password" })),
+        )
+        .await;
+    let res = refresh(res.body["refresh_token"].as_str().unwrap()).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
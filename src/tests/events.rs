@@ -0,0 +1,211 @@
+use super::{oid, TestApp};
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::timeout;
+use tower::ServiceExt;
+
+/*
+* Reads the server-sent events of one `/events` stream
+*/
+struct EventStream {
+    body: Body,
+    buffer: String,
+}
+
+impl TestApp {
+    async fn events(&self, uri: &str, token: Option<&str>) -> Result<EventStream, StatusCode> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = builder.body(Body::empty()).unwrap();
+        let res = self.router.clone().oneshot(request).await.unwrap();
+        if res.status() != StatusCode::OK {
+            return Err(res.status());
+        }
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+        Ok(EventStream {
+            body: res.into_body(),
+            buffer: String::new(),
+        })
+    }
+}
+
+impl EventStream {
+    /// Name and data of the next event, keep-alive comments are skipped
+    async fn next(&mut self) -> (String, Value) {
+        timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(end) = self.buffer.find("\n\n") {
+                    let block: String = self.buffer.drain(..end + 2).collect();
+                    let field = |name: &str| {
+                        block
+                            .lines()
+                            .find_map(|line| line.strip_prefix(name))
+                            .map(|value| value.trim().to_string())
+                    };
+                    if let Some(event) = field("event:") {
+                        let data = field("data:").unwrap_or_default();
+                        return (event, serde_json::from_str(&data).unwrap_or(Value::Null));
+                    }
+                    continue;
+                }
+                let frame = self.body.frame().await.unwrap().unwrap();
+                if let Ok(data) = frame.into_data() {
+                    self.buffer.push_str(std::str::from_utf8(&data).unwrap());
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Whether the server ended the stream, only keep-alives may come before
+    async fn ended(&mut self) -> bool {
+        timeout(Duration::from_secs(5), async {
+            while let Some(frame) = self.body.frame().await {
+                if frame.is_err() {
+                    return;
+                }
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+#[tokio::test]
+async fn changes_reach_the_streams_of_their_users() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    assert_eq!(
+        app.events("/events", None).await.err(),
+        Some(StatusCode::UNAUTHORIZED)
+    );
+    let mut alice_events = app.events("/events", Some(&alice)).await.unwrap();
+    //how a browser's EventSource connects
+    let mut bob_events = app
+        .events(&format!("/events?access_token={}", bob), None)
+        .await
+        .unwrap();
+    let mut carol_events = app.events("/events", Some(&carol)).await.unwrap();
+
+    let note = app.create_note(&alice, "Plan", "v1").await;
+    let (event, data) = alice_events.next().await;
+    assert_eq!(event, "note.created");
+    assert_eq!(data, json!({ "id": { "$oid": note }, "version": 1 }));
+
+    app.post(
+        &format!("/notes/id/{}/shares", note),
+        &alice,
+        json!({ "username": "bob", "role": "viewer" }),
+    )
+    .await;
+    app.patch(
+        &format!("/notes/id/{}", note),
+        &alice,
+        Some(json!({ "title": "Plan", "content": "v2", "tags": [] })),
+    )
+    .await;
+    for events in [&mut alice_events, &mut bob_events] {
+        let (event, data) = events.next().await;
+        assert_eq!(event, "note.updated");
+        assert_eq!(data["version"], 2);
+    }
+
+    let list = app.create_todo_list(&alice, "Groceries").await;
+    assert_eq!(alice_events.next().await.0, "todo_list.created");
+    let list_uri = format!("/todos/id/{}", list);
+    app.post(
+        &list_uri,
+        &alice,
+        json!({ "title": "Milk", "status": false, "priority": "Normal" }),
+    )
+    .await;
+    let (event, data) = alice_events.next().await;
+    assert_eq!(event, "todo.created");
+    assert_eq!(oid(&data["todo_list_id"]), list);
+    assert_eq!(data["version"], 2);
+    let todo = oid(&data["id"]);
+
+    let pin_uri = format!("/notes/id/{}/pin/{}", note, list);
+    assert_eq!(
+        app.patch(&pin_uri, &alice, None).await.status,
+        StatusCode::OK
+    );
+    assert_eq!(alice_events.next().await.0, "note.updated");
+    assert_eq!(bob_events.next().await.0, "note.updated");
+    app.delete(&pin_uri, &alice).await;
+    assert_eq!(alice_events.next().await.0, "note.updated");
+    assert_eq!(bob_events.next().await.0, "note.updated");
+
+    let todo_uri = format!("{}/todo/id/{}", list_uri, todo);
+    app.patch(
+        &todo_uri,
+        &alice,
+        Some(json!({ "title": "Milk", "status": true, "priority": "Normal" })),
+    )
+    .await;
+    assert_eq!(alice_events.next().await.0, "todo.updated");
+    app.delete(&todo_uri, &alice).await;
+    assert_eq!(alice_events.next().await.0, "todo.deleted");
+    app.delete(&list_uri, &alice).await;
+    assert_eq!(alice_events.next().await.0, "todo_list.deleted");
+
+    app.delete(&format!("/notes/id/{}", note), &alice).await;
+    for events in [&mut alice_events, &mut bob_events] {
+        let (event, data) = events.next().await;
+        assert_eq!(event, "note.deleted");
+        assert_eq!(data, json!({ "id": { "$oid": note } }));
+    }
+    app.post(&format!("/trash/notes/{}/restore", note), &alice, json!({}))
+        .await;
+    assert_eq!(alice_events.next().await.0, "note.created");
+
+    //nothing of the above was carol's business
+    let own = app.create_note(&carol, "Mine", "").await;
+    let (event, data) = carol_events.next().await;
+    assert_eq!(event, "note.created");
+    assert_eq!(oid(&data["id"]), own);
+}
+
+#[tokio::test]
+async fn streams_end_with_their_session() {
+    let app = TestApp::new();
+    let laptop = app.register("alice").await;
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(json!({ "username": "alice", "password": "password" })),
+        )
+        .await;
+    let phone = res.body["access_token"].as_str().unwrap().to_string();
+    let mut laptop_events = app.events("/events", Some(&laptop)).await.unwrap();
+    let mut phone_events = app.events("/events", Some(&phone)).await.unwrap();
+
+    //signs out every other device
+    let res = app
+        .patch(
+            "/auth/account/password",
+            &laptop,
+            Some(json!({ "current_password": "password", "new_password": "s3cret" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(phone_events.ended().await);
+
+    app.create_note(&laptop, "Plan", "").await;
+    assert_eq!(laptop_events.next().await.0, "note.created");
+    app.request(Method::POST, "/auth/logout", Some(&laptop), None)
+        .await;
+    assert!(laptop_events.ended().await);
+}
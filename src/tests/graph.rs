@@ -0,0 +1,97 @@
+use super::TestApp;
+use axum::http::StatusCode;
+use serde_json::Value;
+
+fn ids(graph: &Value, field: &str) -> Vec<String> {
+    let mut ids: Vec<String> = graph[field]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| match field {
+            "nodes" => item["id"].as_str().unwrap().to_string(),
+            _ => format!(
+                "{} {} {}",
+                item["source"].as_str().unwrap(),
+                item["kind"].as_str().unwrap(),
+                item["target"].as_str().unwrap()
+            ),
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn graph_has_notes_tags_lists_and_links() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let a = app
+        .create_note(&token, "A", "[[B]] [[B]] [[Nowhere]]")
+        .await;
+    let b = app.create_note(&token, "B", "").await;
+    let list = app.create_todo_list(&token, "Chores").await;
+    let res = app
+        .patch(&format!("/notes/id/{}/pin/{}", b, list), &token, None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/notes/graph", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        ids(&res.body, "nodes"),
+        vec![
+            format!("note:{}", a),
+            format!("note:{}", b),
+            "tag:test".to_string(),
+            format!("todo_list:{}", list),
+        ]
+    );
+    assert_eq!(
+        ids(&res.body, "edges"),
+        vec![
+            format!("note:{} link note:{}", a, b),
+            format!("note:{} tag tag:test", a),
+            format!("note:{} pin todo_list:{}", b, list),
+            format!("note:{} tag tag:test", b),
+        ]
+    );
+    let kinds: Vec<&str> = res.body["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|node| node["kind"].as_str().unwrap())
+        .collect();
+    assert!(kinds.contains(&"todo_list"));
+}
+
+#[tokio::test]
+async fn neighbourhood_is_depth_limited() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let a = app.create_note(&token, "A", "[[B]]").await;
+    let b = app.create_note(&token, "B", "[[C]]").await;
+    let c = app.create_note(&token, "C", "").await;
+
+    //A - B and A - tag:test are one edge away, C only through B or the tag
+    let res = app
+        .get(&format!("/notes/graph?note={}&depth=1", a), &token)
+        .await;
+    assert_eq!(
+        ids(&res.body, "nodes"),
+        vec![
+            format!("note:{}", a),
+            format!("note:{}", b),
+            "tag:test".to_string()
+        ]
+    );
+    assert_eq!(res.body["edges"].as_array().unwrap().len(), 3);
+
+    let res = app
+        .get(&format!("/notes/graph?note={}&depth=2", a), &token)
+        .await;
+    assert!(ids(&res.body, "nodes").contains(&format!("note:{}", c)));
+
+    let other = app.register("bob").await;
+    let res = app.get(&format!("/notes/graph?note={}", a), &other).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
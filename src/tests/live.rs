@@ -0,0 +1,308 @@
+use super::TestApp;
+use automerge::{
+    sync::{self, SyncDoc},
+    transaction::Transactable,
+    AutoCommit, ObjId, ReadDoc, ROOT,
+};
+use axum::http::{Method, StatusCode};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, time::timeout};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+impl TestApp {
+    /// Serves the router on a local port, websockets need a real connection
+    async fn serve(&self) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = self.router.clone();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        addr
+    }
+}
+
+/*
+* A peer with its own copy of the document, like an editor in the browser
+*/
+struct LivePeer {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    doc: AutoCommit,
+    state: sync::State,
+    presence: Vec<Value>,
+    closed: bool,
+}
+
+impl LivePeer {
+    async fn connect(addr: SocketAddr, note: &str, token: &str) -> Self {
+        Self::connect_with(addr, note, token, AutoCommit::new()).await
+    }
+
+    /// Connects again with the copy of the document it had before
+    async fn reconnect(self, addr: SocketAddr, note: &str, token: &str) -> Self {
+        Self::connect_with(addr, note, token, self.doc).await
+    }
+
+    async fn connect_with(addr: SocketAddr, note: &str, token: &str, doc: AutoCommit) -> Self {
+        let url = format!("ws://{}/live/notes/{}?access_token={}", addr, note, token);
+        let (socket, _res) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let mut peer = Self {
+            socket,
+            doc,
+            state: sync::State::new(),
+            presence: vec![],
+            closed: false,
+        };
+        peer.send_sync().await;
+        peer.until(|peer| peer.content().is_some()).await;
+        peer
+    }
+
+    fn content(&self) -> Option<ObjId> {
+        self.doc
+            .get(ROOT, "content")
+            .unwrap()
+            .map(|(_value, id)| id)
+    }
+
+    fn text(&self) -> String {
+        self.doc.text(self.content().unwrap()).unwrap()
+    }
+
+    async fn send_sync(&mut self) {
+        if let Some(message) = self.doc.sync().generate_sync_message(&mut self.state) {
+            let _ = self.socket.send(Message::binary(message.encode())).await;
+        }
+    }
+
+    /// Handles messages of the room until `done` holds
+    async fn until(&mut self, done: impl Fn(&Self) -> bool) {
+        timeout(Duration::from_secs(5), async {
+            while !done(self) {
+                match self.socket.next().await {
+                    Some(Ok(Message::Binary(bytes))) => {
+                        let message = sync::Message::decode(&bytes).unwrap();
+                        self.doc
+                            .sync()
+                            .receive_sync_message(&mut self.state, message)
+                            .unwrap();
+                        self.send_sync().await;
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        self.presence.push(serde_json::from_str(&text).unwrap())
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => self.closed = true,
+                    Some(Ok(_)) => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn insert(&mut self, pos: usize, text: &str) {
+        let content = self.content().unwrap();
+        self.doc.splice_text(&content, pos, 0, text).unwrap();
+        self.send_sync().await;
+    }
+}
+
+async fn wait_until_closed(app: &TestApp, note: &str) {
+    let note = note.parse().unwrap();
+    timeout(Duration::from_secs(5), async {
+        while app.state.live_rooms.is_open(note) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+/// Checks the saved note again on every change event until it has `content`
+async fn wait_for_content(app: &TestApp, token: &str, note: &str, content: &str) {
+    let mut changes = app.state.events.subscribe();
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let res = app.get(&format!("/notes/id/{}", note), token).await;
+            if res.body["content"] == content {
+                return;
+            }
+            changes.recv().await.unwrap();
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn peers_merge_edits_and_the_note_is_saved() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let note = app.create_note(&alice, "Meeting", "agenda").await;
+    app.post(
+        &format!("/notes/id/{}/shares", note),
+        &alice,
+        json!({ "username": "bob", "role": "editor" }),
+    )
+    .await;
+    let addr = app.serve().await;
+
+    let mut first = LivePeer::connect(addr, &note, &alice).await;
+    assert_eq!(first.text(), "agenda");
+    let mut second = LivePeer::connect(addr, &note, &bob).await;
+    first
+        .until(|peer| peer.presence.iter().any(|m| m["type"] == "join"))
+        .await;
+    assert_eq!(second.presence[0]["type"], "welcome");
+    assert_eq!(second.presence[0]["peers"][0]["username"], "alice");
+
+    //typed at the same time on both sides, nothing gets lost
+    first.insert(0, "# ").await;
+    second.insert(6, ":\n- budget").await;
+    first
+        .until(|peer| peer.text() == "# agenda:\n- budget")
+        .await;
+    second
+        .until(|peer| peer.text() == "# agenda:\n- budget")
+        .await;
+    wait_for_content(&app, &alice, &note, "# agenda:\n- budget").await;
+
+    second
+        .socket
+        .send(Message::text(
+            json!({ "type": "cursor", "cursor": {"anchor": 3, "head": 5} }).to_string(),
+        ))
+        .await
+        .unwrap();
+    first
+        .until(|peer| peer.presence.iter().any(|m| m["type"] == "cursor"))
+        .await;
+    let cursor = first
+        .presence
+        .iter()
+        .find(|m| m["type"] == "cursor")
+        .unwrap();
+    assert_eq!(cursor["cursor"]["head"], 5);
+
+    //a save over the rest api is merged into the session
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", note),
+            &alice,
+            Some(json!({ "title": "Meeting", "content": "# agenda:\n- budget\n- hiring", "tags": [] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    second.insert(0, "!").await;
+    first
+        .until(|peer| peer.text() == "!# agenda:\n- budget\n- hiring")
+        .await;
+    wait_for_content(&app, &alice, &note, "!# agenda:\n- budget\n- hiring").await;
+
+    //one revision for the state before the session, not one per save
+    let res = app
+        .get(&format!("/notes/id/{}/revisions", note), &alice)
+        .await;
+    assert_eq!(res.body.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn viewers_follow_but_cannot_edit() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let carol = app.register("carol").await;
+    let dave = app.register("dave").await;
+    let note = app.create_note(&alice, "Plan", "draft").await;
+    app.post(
+        &format!("/notes/id/{}/shares", note),
+        &alice,
+        json!({ "username": "carol", "role": "viewer" }),
+    )
+    .await;
+    let addr = app.serve().await;
+
+    let url = format!("ws://{}/live/notes/{}", addr, note);
+    assert!(tokio_tungstenite::connect_async(url).await.is_err());
+    let url = format!("ws://{}/live/notes/{}?access_token={}", addr, note, dave);
+    assert!(tokio_tungstenite::connect_async(url).await.is_err());
+
+    let mut owner = LivePeer::connect(addr, &note, &alice).await;
+    let mut viewer = LivePeer::connect(addr, &note, &carol).await;
+    owner.insert(5, " v2").await;
+    viewer.until(|peer| peer.text() == "draft v2").await;
+
+    viewer.insert(0, "x").await;
+    viewer.until(|peer| peer.closed).await;
+    wait_for_content(&app, &alice, &note, "draft v2").await;
+}
+
+#[tokio::test]
+async fn sockets_close_when_their_session_is_revoked() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let note = app.create_note(&alice, "Plan", "draft").await;
+    let addr = app.serve().await;
+    let mut peer = LivePeer::connect(addr, &note, &alice).await;
+
+    let res = app
+        .request(Method::POST, "/auth/logout", Some(&alice), None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    peer.until(|peer| peer.closed).await;
+}
+
+#[tokio::test]
+async fn reconnecting_after_the_room_closed_keeps_the_history() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let note = app.create_note(&alice, "Plan", "draft").await;
+    let addr = app.serve().await;
+
+    let mut peer = LivePeer::connect(addr, &note, &alice).await;
+    peer.insert(5, " v2").await;
+    wait_for_content(&app, &alice, &note, "draft v2").await;
+    peer.socket.close(None).await.unwrap();
+    wait_until_closed(&app, &note).await;
+
+    //the new room continues the saved document instead of starting over
+    let mut peer = peer.reconnect(addr, &note, &alice).await;
+    peer.insert(0, "# ").await;
+    let heads = peer.doc.get_heads();
+    peer.until(|peer| peer.state.shared_heads == heads).await;
+    wait_for_content(&app, &alice, &note, "# draft v2").await;
+    let other = LivePeer::connect(addr, &note, &alice).await;
+    assert_eq!(other.text(), "# draft v2");
+}
+
+#[tokio::test]
+async fn losing_the_share_ends_editing() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let note = app.create_note(&alice, "Plan", "draft").await;
+    let share = json!({ "username": "bob", "role": "editor" });
+    app.post(&format!("/notes/id/{}/shares", note), &alice, share)
+        .await;
+    let addr = app.serve().await;
+    let mut owner = LivePeer::connect(addr, &note, &alice).await;
+
+    //turned into a viewer while connected, edits are refused from then on
+    let mut editor = LivePeer::connect(addr, &note, &bob).await;
+    let share = json!({ "username": "bob", "role": "viewer" });
+    app.post(&format!("/notes/id/{}/shares", note), &alice, share)
+        .await;
+    editor.insert(0, "x").await;
+    editor.until(|peer| peer.closed).await;
+
+    //unshared, the socket is closed
+    let mut viewer = LivePeer::connect(addr, &note, &bob).await;
+    let res = app
+        .delete(&format!("/notes/id/{}/shares/bob", note), &alice)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    viewer.until(|peer| peer.closed).await;
+
+    owner.insert(5, " v2").await;
+    wait_for_content(&app, &alice, &note, "draft v2").await;
+}
@@ -0,0 +1,82 @@
+use super::TestApp;
+use crate::markdown::{to_html, RenderCache};
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use std::sync::Arc;
+
+#[test]
+fn renders_commonmark_and_gfm() {
+    assert_eq!(
+        to_html("# Title\n\nSome *text* ~~gone~~\n\n---"),
+        "<h1>Title</h1>\n<p>Some <em>text</em> <del>gone</del></p>\n<hr>\n"
+    );
+    assert_eq!(
+        to_html("```rust\nlet x = \"<hi>\";\n```"),
+        "<pre><code class=\"language-rust\">let x = \"&lt;hi&gt;\";\n</code></pre>\n"
+    );
+    assert_eq!(
+        to_html("- [ ] todo\n- [x] done"),
+        "<ul>\n<li><input disabled=\"\" type=\"checkbox\">\ntodo</li>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\">\ndone</li>\n</ul>\n"
+    );
+    assert_eq!(
+        to_html("| Name | Qty |\n| :--- | ---: |\n| a | 2 |"),
+        "<table><thead><tr><th style=\"text-align: left\">Name</th><th style=\"text-align: right\">Qty</th></tr></thead><tbody>\n<tr><td style=\"text-align: left\">a</td><td style=\"text-align: right\">2</td></tr>\n</tbody></table>\n"
+    );
+    assert_eq!(
+        to_html("[site](https://example.com \"Title\") ![alt](/img.png) <me@example.com>"),
+        "<p><a href=\"https://example.com\" title=\"Title\" rel=\"nofollow noopener\">site</a> <img src=\"/img.png\" alt=\"alt\"> <a href=\"mailto:me@example.com\" rel=\"nofollow noopener\">me@example.com</a></p>\n"
+    );
+}
+
+#[test]
+fn output_is_sanitized() {
+    assert_eq!(
+        to_html("<script>alert(1)</script>\n\ntext <img src=x onerror=alert(1)>"),
+        "&lt;script&gt;alert(1)&lt;/script&gt;\n<p>text &lt;img src=x onerror=alert(1)&gt;</p>\n"
+    );
+    assert_eq!(
+        to_html("[click](javascript:alert(1)) ![x](JaVaScRiPt:alert(1)) <javascript:alert(1)> [d](data:text/html,x)"),
+        "<p><a rel=\"nofollow noopener\">click</a> <img alt=\"x\"> <a rel=\"nofollow noopener\">javascript:alert(1)</a> <a rel=\"nofollow noopener\">d</a></p>\n"
+    );
+    assert_eq!(
+        to_html("[q](/a\"onmouseover=\"x) [rel](notes/1#top)"),
+        "<p><a href=\"/a%22onmouseover=%22x\" rel=\"nofollow noopener\">q</a> <a href=\"notes/1#top\" rel=\"nofollow noopener\">rel</a></p>\n"
+    );
+}
+
+#[test]
+fn cache_is_keyed_on_content() {
+    let cache = RenderCache::new();
+    let first = cache.render("# same");
+    assert!(Arc::ptr_eq(&first, &cache.render("# same")));
+    assert_eq!(&*cache.render("# other"), "<h1>other</h1>\n");
+}
+
+#[tokio::test]
+async fn notes_render_as_html_on_request() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app
+        .create_note(&token, "Plan", "# Plan\n\n- [x] *done*")
+        .await;
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("/notes/id/{}", id))
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.send(request).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body,
+        "<h1>Plan</h1>\n<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\">\n<em>done</em></li>\n</ul>\n"
+    );
+
+    //json stays the default
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.body["content"], "# Plan\n\n- [x] *done*");
+}
@@ -0,0 +1,103 @@
+use crate::{
+    migrations,
+    models::{note::Note, timestamp, todo::TodoList},
+    repository::sled_store,
+};
+use mongodb::bson::{self, doc, oid::ObjectId};
+
+#[test]
+fn sled_backfill_uses_the_object_id() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let notes = db.open_tree("notes").unwrap();
+    let todos = db.open_tree("todos").unwrap();
+    let user_id = ObjectId::new();
+    let note_id = ObjectId::new();
+    let list_id = ObjectId::new();
+    let todo_id = ObjectId::new();
+
+    //documents written before timestamps existed
+    let note = doc! {
+        "_id": note_id, "user_id": user_id, "title": "Old", "content": "",
+        "tags": [], "todo_lists": []
+    };
+    let list = doc! {
+        "_id": list_id, "user_id": user_id, "title": "Old",
+        "todos": [{"_id": todo_id, "title": "Todo", "status": false, "priority": "Low"}]
+    };
+    let key = sled_store::key(&[user_id, note_id]);
+    notes.insert(&key, bson::to_vec(&note).unwrap()).unwrap();
+    let list_key = sled_store::key(&[user_id, list_id]);
+    todos
+        .insert(&list_key, bson::to_vec(&list).unwrap())
+        .unwrap();
+
+    migrations::backfill_timestamps_sled(&notes, &todos);
+
+    let note: Note = sled_store::get(&notes, &key).unwrap().unwrap();
+    assert_eq!(note.created_at, timestamp::from_object_id(&note_id));
+    assert_eq!(note.updated_at, note.created_at);
+    assert_eq!(note.updated_by, user_id);
+
+    let list: TodoList = sled_store::get(&todos, &list_key).unwrap().unwrap();
+    assert_eq!(list.created_at, timestamp::from_object_id(&list_id));
+    assert_eq!(
+        list.todos[0].created_at,
+        timestamp::from_object_id(&todo_id)
+    );
+    assert_eq!(list.todos[0].updated_by, user_id);
+}
+
+#[test]
+fn sled_backfill_parses_links() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let notes = db.open_tree("notes").unwrap();
+    let user_id = ObjectId::new();
+    let note_id = ObjectId::new();
+    let note = doc! {
+        "_id": note_id, "user_id": user_id, "title": "Old", "content": "See [[Other]]",
+        "tags": [], "todo_lists": [], "created_at": "2024-01-01T00:00:00.000Z",
+        "updated_at": "2024-01-01T00:00:00.000Z", "updated_by": user_id
+    };
+    let key = sled_store::key(&[user_id, note_id]);
+    notes.insert(&key, bson::to_vec(&note).unwrap()).unwrap();
+
+    migrations::backfill_links_sled(&notes);
+
+    let note: Note = sled_store::get(&notes, &key).unwrap().unwrap();
+    assert_eq!(note.links.len(), 1);
+    assert_eq!(note.links[0].key.as_deref(), Some("other"));
+}
+
+#[test]
+fn timestamps_are_dates_in_bson_and_strings_in_json() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let tree = db.open_tree("notes").unwrap();
+    let user_id = ObjectId::new();
+    let note_id = ObjectId::new();
+    //written while timestamps were strings
+    let old = doc! {
+        "_id": note_id, "user_id": user_id, "title": "Old", "content": "",
+        "tags": [], "todo_lists": [], "created_at": "2024-01-01T00:00:00.000Z",
+        "updated_at": "2024-01-02T00:00:00.000Z", "updated_by": user_id,
+        "deleted_at": null
+    };
+    let key = sled_store::key(&[user_id, note_id]);
+    tree.insert(&key, bson::to_vec(&old).unwrap()).unwrap();
+    let note: Note = sled_store::get(&tree, &key).unwrap().unwrap();
+    assert_eq!(
+        timestamp::format(&note.updated_at),
+        "2024-01-02T00:00:00.000Z"
+    );
+
+    let stored: bson::Document = bson::from_slice(&sled_store::encode(&note).unwrap()).unwrap();
+    assert_eq!(
+        stored.get_datetime("created_at").unwrap().to_chrono(),
+        note.created_at
+    );
+    assert_eq!(stored.get("deleted_at"), Some(&bson::Bson::Null));
+    let again: Note = sled_store::decode(&sled_store::encode(&note).unwrap()).unwrap();
+    assert_eq!(again.updated_at, note.updated_at);
+
+    let json = serde_json::to_value(&note).unwrap();
+    assert_eq!(json["created_at"], "2024-01-01T00:00:00.000Z");
+}
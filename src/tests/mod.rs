@@ -0,0 +1,166 @@
+use crate::{app, database::Database, AppState};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::sync::Once;
+use tower::ServiceExt;
+
+mod account;
+mod api_tokens;
+mod attachments;
+mod auth;
+mod events;
+mod graph;
+mod live;
+mod markdown;
+mod migrations;
+mod notebooks;
+mod notes;
+mod pagination;
+mod revisions;
+mod search;
+mod search_query;
+mod sessions;
+mod share_links;
+mod shares;
+mod sync;
+mod tags;
+mod todos;
+mod trash;
+mod two_factor;
+mod versions;
+mod wiki_links;
+
+static INIT: Once = Once::new();
+
+/*
+* Drives the whole router from main.rs against the in-memory repositories
+*/
+pub struct TestApp {
+    router: Router,
+    //for reaching the repositories directly
+    pub state: AppState,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_database(Database::memory())
+    }
+
+    /// For tests that swap out part of the in-memory database
+    pub fn with_database(database: Database) -> Self {
+        INIT.call_once(|| std::env::set_var("JWT_SECRET", "flexnotes-test-secret"));
+        let state = AppState::with_database(database);
+        Self {
+            router: app(state.clone()),
+            state,
+        }
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> TestResponse {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        };
+        self.send(request).await
+    }
+
+    /// Sends a hand built request, for headers the helpers above don't set
+    pub async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        //errors and empty responses aren't json, keep them as plain strings
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).to_string()));
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn get(&self, uri: &str, token: &str) -> TestResponse {
+        self.request(Method::GET, uri, Some(token), None).await
+    }
+
+    pub async fn post(&self, uri: &str, token: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, Some(token), Some(body))
+            .await
+    }
+
+    pub async fn patch(&self, uri: &str, token: &str, body: Option<Value>) -> TestResponse {
+        self.request(Method::PATCH, uri, Some(token), body).await
+    }
+
+    pub async fn delete(&self, uri: &str, token: &str) -> TestResponse {
+        self.request(Method::DELETE, uri, Some(token), None).await
+    }
+
+    /// Registers a user and returns its access token
+    pub async fn register(&self, username: &str) -> String {
+        let res = self
+            .request(
+                Method::POST,
+                "/auth/register",
+                None,
+                Some(json!({
+                    "username": username,
+                    "email": format!("{}@flexnotes.test", username),
+                    "password": "password",
+                })),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        res.body["access_token"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a note and returns its id
+    pub async fn create_note(&self, token: &str, title: &str, content: &str) -> String {
+        let res = self
+            .post(
+                "/notes/create",
+                token,
+                json!({ "title": title, "content": content, "tags": ["test"] }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        oid(&res.body["_id"])
+    }
+
+    /// Creates a todo list and returns its id
+    pub async fn create_todo_list(&self, token: &str, title: &str) -> String {
+        let res = self.post("/todos", token, json!({ "title": title })).await;
+        assert_eq!(res.status, StatusCode::OK);
+        oid(&res.body["_id"])
+    }
+}
+
+/// ObjectIds are serialized as { "$oid": "..." }
+pub fn oid(value: &Value) -> String {
+    value["$oid"].as_str().unwrap().to_string()
+}
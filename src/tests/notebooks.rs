@@ -0,0 +1,152 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+impl TestApp {
+    async fn create_notebook(&self, token: &str, name: &str, parent: Option<&str>) -> String {
+        let res = self
+            .post(
+                "/notebooks",
+                token,
+                json!({ "name": name, "parent_id": parent }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        oid(&res.body["_id"])
+    }
+
+    async fn move_note(&self, token: &str, note: &str, notebook: Option<&str>) -> StatusCode {
+        self.patch(
+            &format!("/notes/id/{}/notebook", note),
+            token,
+            Some(json!({ "notebook_id": notebook })),
+        )
+        .await
+        .status
+    }
+}
+
+#[tokio::test]
+async fn nested_contents_and_listing_filter() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let work = app.create_notebook(&token, "Work", None).await;
+    let meetings = app.create_notebook(&token, "Meetings", Some(&work)).await;
+    let top = app.create_note(&token, "Roadmap", "").await;
+    let nested = app.create_note(&token, "Standup", "").await;
+    app.create_note(&token, "Loose", "").await;
+    assert_eq!(
+        app.move_note(&token, &top, Some(&work)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        app.move_note(&token, &nested, Some(&meetings)).await,
+        StatusCode::OK
+    );
+
+    let res = app.get(&format!("/notebooks/{}", work), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["notebook"]["name"], "Work");
+    assert_eq!(res.body["notes"][0]["title"], "Roadmap");
+    assert_eq!(res.body["notebooks"][0]["notebook"]["name"], "Meetings");
+    assert_eq!(res.body["notebooks"][0]["notes"][0]["title"], "Standup");
+
+    let res = app.get(&format!("/notes?notebook={}", work), &token).await;
+    let items = res.body["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(oid(&items[0]["id"]), top);
+    assert_eq!(oid(&items[0]["notebook_id"]), work);
+
+    let res = app
+        .get(&format!("/notes?notebook={}&recursive=true", work), &token)
+        .await;
+    assert_eq!(res.body["items"].as_array().unwrap().len(), 2);
+
+    //moving a notebook into its own child would make a cycle
+    let res = app
+        .patch(
+            &format!("/notebooks/{}", work),
+            &token,
+            Some(json!({ "name": "Work", "parent_id": meetings })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    let res = app
+        .patch(
+            &format!("/notebooks/{}", work),
+            &token,
+            Some(json!({ "name": "Work", "parent_id": ObjectId::new() })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let other = app.register("bob").await;
+    assert_eq!(
+        app.move_note(&other, &top, Some(&work)).await,
+        StatusCode::NOT_FOUND
+    );
+    let res = app.get(&format!("/notes?notebook={}", work), &other).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn delete_rehomes_or_cascades() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let work = app.create_notebook(&token, "Work", None).await;
+    let meetings = app.create_notebook(&token, "Meetings", Some(&work)).await;
+    let archive = app
+        .create_notebook(&token, "Archive", Some(&meetings))
+        .await;
+    let note = app.create_note(&token, "Standup", "").await;
+    let archived = app.create_note(&token, "Old standup", "").await;
+    app.move_note(&token, &note, Some(&meetings)).await;
+    app.move_note(&token, &archived, Some(&archive)).await;
+
+    //the notes can't go into the notebook being deleted or one that isn't there
+    let res = app
+        .delete(
+            &format!("/notebooks/{}?move_to={}", meetings, archive),
+            &token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    let res = app
+        .delete(
+            &format!("/notebooks/{}?move_to={}", meetings, ObjectId::new()),
+            &token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    //the note and the sub notebook move up to Work
+    let res = app
+        .delete(&format!("/notebooks/{}", meetings), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get(&format!("/notes/id/{}", note), &token).await;
+    assert_eq!(oid(&res.body["notebook_id"]), work);
+    let res = app.get(&format!("/notebooks/{}", work), &token).await;
+    assert_eq!(res.body["notebooks"][0]["notebook"]["name"], "Archive");
+
+    let res = app
+        .delete(&format!("/notebooks/{}?cascade=true", work), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get("/notebooks", &token).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 0);
+    let res = app.get(&format!("/notes/id/{}", archived), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    //restored notes come back outside of any notebook
+    let res = app
+        .post(
+            &format!("/trash/notes/{}/restore", archived),
+            &token,
+            json!({}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(res.body["notebook_id"].is_null());
+}
@@ -0,0 +1,157 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn create_and_get_note() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Groceries", "- milk").await;
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["title"], "Groceries");
+    assert_eq!(res.body["content"], "- milk");
+    assert_eq!(res.body["tags"], json!(["test"]));
+}
+
+#[tokio::test]
+async fn list_notes() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let first = app.create_note(&token, "First", "").await;
+    let second = app.create_note(&token, "Second", "").await;
+
+    let res = app.get("/notes", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let notes = res.body["items"].as_array().unwrap();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(oid(&notes[0]["id"]), first);
+    assert_eq!(oid(&notes[1]["id"]), second);
+    assert_eq!(notes[1]["title"], "Second");
+}
+
+#[tokio::test]
+async fn update_note() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Draft", "old").await;
+
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", id),
+            &token,
+            Some(json!({ "title": "Final", "content": "new", "tags": ["done"] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.body["title"], "Final");
+    assert_eq!(res.body["content"], "new");
+    assert_eq!(res.body["tags"], json!(["done"]));
+}
+
+#[tokio::test]
+async fn delete_note() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Trash", "").await;
+
+    let res = app.delete(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.delete(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn notes_are_scoped_to_their_owner() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_note(&alice, "Secret", "").await;
+
+    let res = app.get(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.delete(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.get("/notes", &bob).await;
+    assert_eq!(res.body["items"], json!([]));
+
+    let res = app.get(&format!("/notes/id/{}", id), &alice).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn pin_and_unpin_todo_list() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let note = app.create_note(&token, "Sprint", "").await;
+    let list = app.create_todo_list(&token, "Tasks").await;
+
+    let res = app
+        .patch(&format!("/notes/id/{}/pin/{}", note, list), &token, None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", note), &token).await;
+    assert_eq!(res.body["todo_lists"], json!([{ "$oid": list }]));
+
+    let res = app
+        .delete(&format!("/notes/id/{}/pin/{}", note, list), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", note), &token).await;
+    assert_eq!(res.body["todo_lists"], json!([]));
+
+    let res = app
+        .delete(&format!("/notes/id/{}/pin/{}", note, list), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn cannot_pin_foreign_todo_list() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let note = app.create_note(&alice, "Mine", "").await;
+    let list = app.create_todo_list(&bob, "Bobs").await;
+
+    let res = app
+        .patch(&format!("/notes/id/{}/pin/{}", note, list), &alice, None)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn notes_track_timestamps() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Draft", "old").await;
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    let created_at = res.body["created_at"].as_str().unwrap().to_string();
+    assert_eq!(res.body["updated_at"], created_at);
+    assert_eq!(res.body["updated_by"], res.body["user_id"]);
+
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    app.patch(
+        &format!("/notes/id/{}", id),
+        &token,
+        Some(json!({ "title": "Draft", "content": "new", "tags": [] })),
+    )
+    .await;
+
+    let res = app.get("/notes", &token).await;
+    let note = &res.body["items"][0];
+    assert_eq!(note["created_at"], created_at);
+    assert!(note["updated_at"].as_str().unwrap() > created_at.as_str());
+}
@@ -0,0 +1,124 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+fn titles(body: &Value) -> Vec<String> {
+    body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn create(app: &TestApp, token: &str, title: &str, tags: &[&str]) -> String {
+    let res = app
+        .post(
+            "/notes/create",
+            token,
+            json!({ "title": title, "content": "", "tags": tags }),
+        )
+        .await;
+    oid(&res.body["_id"])
+}
+
+#[tokio::test]
+async fn notes_are_paged_with_a_cursor() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    for title in ["delta", "alpha", "echo", "charlie", "bravo"] {
+        create(&app, &token, title, &[]).await;
+    }
+
+    let mut seen = vec![];
+    let mut uri = "/notes?sort=title&limit=2".to_string();
+    loop {
+        let res = app.get(&uri, &token).await;
+        assert_eq!(res.status, StatusCode::OK);
+        seen.extend(titles(&res.body));
+        match res.body["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/notes?sort=title&limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["alpha", "bravo", "charlie", "delta", "echo"]);
+
+    let res = app
+        .get("/notes?sort=title&order=desc&limit=3", &token)
+        .await;
+    assert_eq!(titles(&res.body), ["echo", "delta", "charlie"]);
+}
+
+#[tokio::test]
+async fn notes_default_to_creation_order() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    create(&app, &token, "second", &[]).await;
+    create(&app, &token, "first", &[]).await;
+
+    let res = app.get("/notes", &token).await;
+    assert_eq!(titles(&res.body), ["second", "first"]);
+    assert_eq!(res.body["next_cursor"], Value::Null);
+
+    let res = app.get("/notes?sort=updated&order=desc", &token).await;
+    assert_eq!(titles(&res.body), ["first", "second"]);
+}
+
+#[tokio::test]
+async fn notes_filtered_by_tags() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    create(&app, &token, "both", &["rust", "work"]).await;
+    create(&app, &token, "rust", &["rust"]).await;
+    create(&app, &token, "none", &[]).await;
+
+    let res = app.get("/notes?tag=rust", &token).await;
+    assert_eq!(titles(&res.body), ["both", "rust"]);
+    let res = app.get("/notes?tag=rust,work", &token).await;
+    assert_eq!(titles(&res.body), ["both"]);
+    //matched like stored tags, empty entries are ignored
+    let res = app.get("/notes?tag=Rust,,%20WORK%20", &token).await;
+    assert_eq!(titles(&res.body), ["both"]);
+}
+
+#[tokio::test]
+async fn todo_lists_are_paged_and_filtered() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+
+    let res = app.get("/todos", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body, json!({ "items": [], "next_cursor": null }));
+
+    let chores = app.create_todo_list(&token, "Chores").await;
+    app.create_todo_list(&token, "Shopping").await;
+    app.create_todo_list(&token, "Backlog").await;
+    let note = create(&app, &token, "Home", &["home"]).await;
+    app.patch(&format!("/notes/id/{}/pin/{}", note, chores), &token, None)
+        .await;
+
+    let res = app.get("/todos?sort=title&limit=2", &token).await;
+    assert_eq!(titles(&res.body), ["Backlog", "Chores"]);
+    let cursor = res.body["next_cursor"].as_str().unwrap();
+    let res = app
+        .get(
+            &format!("/todos?sort=title&limit=2&cursor={}", cursor),
+            &token,
+        )
+        .await;
+    assert_eq!(titles(&res.body), ["Shopping"]);
+
+    let res = app.get("/todos?tag=home", &token).await;
+    assert_eq!(titles(&res.body), ["Chores"]);
+}
+
+#[tokio::test]
+async fn invalid_cursor_is_bad_request() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+
+    let res = app.get("/notes?cursor=nope", &token).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let res = app.get("/notes?sort=colour", &token).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
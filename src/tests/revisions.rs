@@ -0,0 +1,199 @@
+use super::TestApp;
+use crate::{
+    models::{revision::NoteRevision, user::User},
+    repository::revision_repo::{MemoryRevisionRepo, RevisionRepo, SledRevisionRepo},
+    services::revision_service,
+};
+use axum::http::StatusCode;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+async fn edit(app: &TestApp, token: &str, id: &str, content: &str) {
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", id),
+            token,
+            Some(json!({ "title": "Plan", "content": content, "tags": ["test"] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn updates_record_revisions() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "v1").await;
+    edit(&app, &token, &id, "v2").await;
+    edit(&app, &token, &id, "v3").await;
+    //nothing changed, no revision
+    edit(&app, &token, &id, "v3").await;
+
+    let res = app
+        .get(&format!("/notes/id/{}/revisions", id), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let revisions = res.body.as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0]["rev"], 1);
+    assert_eq!(revisions[0]["author"], "alice");
+    assert_eq!(revisions[1]["rev"], 2);
+
+    let res = app
+        .get(&format!("/notes/id/{}/revisions/1", id), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["content"], "v1");
+
+    let res = app
+        .get(&format!("/notes/id/{}/revisions/2", id), &token)
+        .await;
+    assert_eq!(res.body["content"], "v2");
+
+    let res = app
+        .get(&format!("/notes/id/{}/revisions/3", id), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn restore_revision() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "original").await;
+    edit(&app, &token, &id, "oops").await;
+
+    let res = app
+        .post(
+            &format!("/notes/id/{}/revisions/1/restore", id),
+            &token,
+            json!({}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["content"], "original");
+
+    //the overwritten content is kept as well
+    let res = app
+        .get(&format!("/notes/id/{}/revisions/2", id), &token)
+        .await;
+    assert_eq!(res.body["content"], "oops");
+}
+
+#[tokio::test]
+async fn diff_revisions() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "a\nb\nc").await;
+    edit(&app, &token, &id, "a\nB\nc\nd").await;
+    edit(&app, &token, &id, "a\nB").await;
+
+    let res = app
+        .get(
+            &format!("/notes/id/{}/revisions/diff?from=1&to=2", id),
+            &token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body["lines"],
+        json!([
+            { "op": "equal", "old_line": 1, "new_line": 1, "text": "a" },
+            { "op": "delete", "old_line": 2, "new_line": null, "text": "b" },
+            { "op": "insert", "old_line": null, "new_line": 2, "text": "B" },
+            { "op": "equal", "old_line": 3, "new_line": 3, "text": "c" },
+            { "op": "insert", "old_line": null, "new_line": 4, "text": "d" },
+        ])
+    );
+
+    //without `to` the current note is used
+    let res = app
+        .get(&format!("/notes/id/{}/revisions/diff?from=2", id), &token)
+        .await;
+    assert_eq!(res.body["to"], json!(null));
+    let ops: Vec<&str> = res.body["lines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|line| line["op"].as_str().unwrap())
+        .collect();
+    assert_eq!(ops, ["equal", "equal", "delete", "delete"]);
+}
+
+#[tokio::test]
+async fn revisions_are_scoped_to_their_owner() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_note(&alice, "Plan", "v1").await;
+    edit(&app, &alice, &id, "v2").await;
+
+    let res = app.get(&format!("/notes/id/{}/revisions", id), &bob).await;
+    assert_eq!(res.body, json!([]));
+
+    let res = app
+        .post(
+            &format!("/notes/id/{}/revisions/1/restore", id),
+            &bob,
+            json!({}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+//a save that read the same last number as another one
+async fn numbers_are_taken_once(repo: &dyn RevisionRepo) {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "v1").await;
+    let user: User = app
+        .state
+        .database
+        .user_repo()
+        .get_user("alice")
+        .await
+        .unwrap();
+    let note = app
+        .state
+        .database
+        .note_repo()
+        .get_note_by_id(id.parse().unwrap(), user.id)
+        .await
+        .unwrap();
+
+    let taken = NoteRevision {
+        id: ObjectId::new(),
+        note_id: note.id,
+        user_id: note.user_id,
+        rev: 1,
+        author_id: user.id,
+        author: user.username.clone(),
+        created_at: Utc::now(),
+        title: note.title.clone(),
+        content: "other save".to_string(),
+        tags: vec![],
+    };
+    assert!(repo.create_revision(&taken).await.unwrap());
+    let again = NoteRevision {
+        id: ObjectId::new(),
+        ..taken.clone()
+    };
+    assert!(!repo.create_revision(&again).await.unwrap());
+
+    let revision = revision_service::record_revision(repo, &note, &user)
+        .await
+        .unwrap();
+    assert_eq!(revision.rev, 2);
+    let revisions = repo.get_revisions(note.id, note.user_id).await.unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].content, "other save");
+    assert_eq!(revisions[1].content, "v1");
+}
+
+#[tokio::test]
+async fn revision_numbers_are_unique() {
+    numbers_are_taken_once(&MemoryRevisionRepo::new()).await;
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    numbers_are_taken_once(&SledRevisionRepo::new(db.open_tree("revisions").unwrap())).await;
+}
@@ -0,0 +1,112 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use serde_json::json;
+
+async fn create(app: &TestApp, token: &str, title: &str, content: &str, tags: &[&str]) -> String {
+    let res = app
+        .post(
+            "/notes/create",
+            token,
+            json!({ "title": title, "content": content, "tags": tags }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    oid(&res.body["_id"])
+}
+
+fn ids(body: &serde_json::Value) -> Vec<String> {
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|result| oid(&result["id"]))
+        .collect()
+}
+
+#[tokio::test]
+async fn search_ranks_title_matches_first() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let body = create(&app, &token, "Groceries", "buy rust remover", &[]).await;
+    let title = create(&app, &token, "Rust ownership", "borrowing rules", &[]).await;
+    create(&app, &token, "Holidays", "beach", &[]).await;
+
+    let res = app.get("/notes/search?q=rust", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(ids(&res.body), [title, body]);
+}
+
+#[tokio::test]
+async fn search_matches_tags() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let tagged = create(&app, &token, "Standup", "nothing", &["Meeting"]).await;
+    create(&app, &token, "Other", "nothing", &["misc"]).await;
+
+    let res = app.get("/notes/search?q=meeting", &token).await;
+    assert_eq!(ids(&res.body), [tagged]);
+    assert_eq!(res.body[0]["tags"], json!(["meeting"]));
+}
+
+#[tokio::test]
+async fn search_highlights_snippets() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    create(
+        &app,
+        &token,
+        "Notes",
+        "Use <b>cargo</b> build, then Cargo test",
+        &[],
+    )
+    .await;
+
+    let res = app.get("/notes/search?q=cargo", &token).await;
+    assert_eq!(
+        res.body[0]["snippet"],
+        "Use &lt;b&gt;<mark>cargo</mark>&lt;/b&gt; build, then <mark>Cargo</mark> test"
+    );
+}
+
+#[tokio::test]
+async fn index_follows_updates_and_deletes() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = create(&app, &token, "Draft", "apples", &[]).await;
+
+    //load the index before changing anything
+    let res = app.get("/notes/search?q=apples", &token).await;
+    assert_eq!(ids(&res.body), vec![id.clone()]);
+
+    app.patch(
+        &format!("/notes/id/{}", id),
+        &token,
+        Some(json!({ "title": "Draft", "content": "oranges", "tags": [] })),
+    )
+    .await;
+    let res = app.get("/notes/search?q=apples", &token).await;
+    assert_eq!(res.body, json!([]));
+    let res = app.get("/notes/search?q=oranges", &token).await;
+    assert_eq!(ids(&res.body), vec![id.clone()]);
+
+    let added = create(&app, &token, "Fresh", "oranges again", &[]).await;
+    let res = app.get("/notes/search?q=oranges", &token).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 2);
+
+    app.delete(&format!("/notes/id/{}", id), &token).await;
+    let res = app.get("/notes/search?q=oranges", &token).await;
+    assert_eq!(ids(&res.body), [added]);
+}
+
+#[tokio::test]
+async fn search_is_scoped_to_the_user() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    create(&app, &alice, "Secret", "password", &[]).await;
+
+    let res = app.get("/notes/search?q=password", &bob).await;
+    assert_eq!(res.body, json!([]));
+
+    let res = app.get("/notes/search?q=", &bob).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
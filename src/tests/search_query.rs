@@ -0,0 +1,249 @@
+use super::{oid, TestApp};
+use crate::search::query::{Comparison, Has, Query};
+use axum::http::StatusCode;
+use chrono::NaiveDate;
+use serde_json::json;
+
+fn and(left: Query, right: Query) -> Query {
+    Query::And(Box::new(left), Box::new(right))
+}
+
+fn not(query: Query) -> Query {
+    Query::Not(Box::new(query))
+}
+
+#[test]
+fn parse_field_filters() {
+    let query =
+        Query::parse(r#"tag:rust -tag:draft title:"team meeting" has:todos created:>2026-01-01"#)
+            .unwrap();
+    assert_eq!(
+        query,
+        and(
+            and(
+                and(
+                    and(
+                        Query::Tag("rust".to_string()),
+                        not(Query::Tag("draft".to_string()))
+                    ),
+                    Query::Title("team meeting".to_string())
+                ),
+                Query::Has(Has::Todos)
+            ),
+            Query::Created(
+                Comparison::After,
+                NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()
+            )
+        )
+    );
+}
+
+#[test]
+fn parse_boolean_operators() {
+    let query = Query::parse("a OR b c").unwrap();
+    assert_eq!(
+        query,
+        Query::Or(
+            Box::new(Query::Word("a".to_string())),
+            Box::new(and(
+                Query::Word("b".to_string()),
+                Query::Word("c".to_string())
+            ))
+        )
+    );
+
+    let query = Query::parse(r#"NOT (tag:a OR "Big Plan") AND before:2025-12-31"#).unwrap();
+    assert_eq!(
+        query,
+        and(
+            not(Query::Or(
+                Box::new(Query::Tag("a".to_string())),
+                Box::new(Query::Phrase("big plan".to_string()))
+            )),
+            Query::Created(
+                Comparison::Before,
+                NaiveDate::from_ymd_opt(2025, 12, 31).unwrap()
+            )
+        )
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let error = |input: &str| Query::parse(input).unwrap_err().to_string();
+
+    assert_eq!(error(""), "Query is empty at position 0");
+    assert_eq!(
+        error(r#"title:"meeting"#),
+        "Unterminated quote at position 6"
+    );
+    assert_eq!(
+        error("colour:red"),
+        "Unknown field `colour`, expected tag, title, has, created, updated, before or after at position 0"
+    );
+    assert_eq!(
+        error("rust created:>2026-13-01"),
+        "Invalid date `2026-13-01`, expected YYYY-MM-DD at position 5"
+    );
+    assert_eq!(error("(rust"), "Missing `)` at position 5");
+    assert_eq!(error("rust)"), "Unmatched `)` at position 4");
+    assert_eq!(error("rust OR"), "Expected a search term at position 7");
+    assert_eq!(error("tag:"), "Missing value for `tag:` at position 0");
+    assert_eq!(
+        error("has:cats"),
+        "Unknown value `cats` for `has:`, expected todos or tags at position 0"
+    );
+}
+
+#[test]
+fn deep_or_long_queries_are_rejected() {
+    let error = |input: &str| Query::parse(input).unwrap_err().to_string();
+
+    let nested = format!("{}rust{}", "(".repeat(32), ")".repeat(32));
+    assert!(Query::parse(&nested).is_ok());
+    assert!(Query::parse(&format!("{}rust", "-".repeat(32))).is_ok());
+    assert_eq!(
+        error(&format!("{}rust", "-".repeat(10_000))),
+        "Query is longer than 1000 characters at position 1000"
+    );
+    assert_eq!(
+        error(&format!("{}rust", "-".repeat(40))),
+        "Query is nested deeper than 32 levels at position 32"
+    );
+    assert_eq!(
+        error(&format!("{}rust", "(".repeat(500))),
+        "Query is nested deeper than 32 levels at position 32"
+    );
+}
+
+async fn create(app: &TestApp, token: &str, title: &str, content: &str, tags: &[&str]) -> String {
+    let res = app
+        .post(
+            "/notes/create",
+            token,
+            json!({ "title": title, "content": content, "tags": tags }),
+        )
+        .await;
+    oid(&res.body["_id"])
+}
+
+async fn search(app: &TestApp, token: &str, query: &str) -> Vec<String> {
+    let res = app
+        .get(
+            &format!(
+                "/notes/search?q={}",
+                query.replace(' ', "%20").replace('"', "%22")
+            ),
+            token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let mut titles: Vec<String> = res
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["title"].as_str().unwrap().to_string())
+        .collect();
+    titles.sort();
+    titles
+}
+
+#[tokio::test]
+async fn search_with_filters() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    create(&app, &token, "Rust notes", "ownership", &["rust"]).await;
+    create(&app, &token, "Rust draft", "lifetimes", &["rust", "Draft"]).await;
+    let meeting = create(&app, &token, "Weekly meeting", "agenda", &["work"]).await;
+    create(&app, &token, "Untagged", "ownership of the house", &[]).await;
+    let list = app.create_todo_list(&token, "Actions").await;
+    app.patch(&format!("/notes/id/{}/pin/{}", meeting, list), &token, None)
+        .await;
+
+    assert_eq!(
+        search(&app, &token, "tag:rust").await,
+        ["Rust draft", "Rust notes"]
+    );
+    assert_eq!(
+        search(&app, &token, "tag:rust -tag:draft").await,
+        ["Rust notes"]
+    );
+    assert_eq!(
+        search(&app, &token, r#"title:"weekly meeting""#).await,
+        ["Weekly meeting"]
+    );
+    assert_eq!(search(&app, &token, "has:todos").await, ["Weekly meeting"]);
+    assert_eq!(
+        search(&app, &token, "ownership -has:tags").await,
+        ["Untagged"]
+    );
+    assert_eq!(
+        search(&app, &token, "tag:work OR lifetimes").await,
+        ["Rust draft", "Weekly meeting"]
+    );
+    assert_eq!(
+        search(&app, &token, "after:2000-01-01 -tag:rust")
+            .await
+            .len(),
+        2
+    );
+    assert!(search(&app, &token, "before:2000-01-01").await.is_empty());
+}
+
+#[tokio::test]
+async fn phrases_are_checked_against_the_content() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    create(&app, &token, "Market", "fresh apples and pears", &[]).await;
+    create(&app, &token, "Pantry", "apples, not that fresh", &[]).await;
+    create(&app, &token, "Fresh apples", "recipe", &[]).await;
+
+    assert_eq!(
+        search(&app, &token, r#""fresh apples""#).await,
+        ["Fresh apples", "Market"]
+    );
+    assert_eq!(
+        search(&app, &token, r#"apples -"fresh apples""#).await,
+        ["Pantry"]
+    );
+    assert_eq!(
+        search(&app, &token, r#""apples and" OR recipe"#).await,
+        ["Fresh apples", "Market"]
+    );
+}
+
+#[tokio::test]
+async fn deeply_nested_search_is_bad_request() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let res = app
+        .get(
+            &format!("/notes/search?q={}rust", "-".repeat(10_000)),
+            &token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let res = app
+        .get(
+            &format!("/notes/search?q={}rust", "%28".repeat(900)),
+            &token,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn search_parse_error_is_bad_request() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+
+    let res = app
+        .get("/notes/search?q=created:%3Eyesterday", &token)
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        res.body,
+        "Invalid search query: Invalid date `yesterday`, expected YYYY-MM-DD at position 0"
+    );
+}
@@ -0,0 +1,125 @@
+use super::{oid, TestApp};
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use serde_json::{json, Value};
+
+impl TestApp {
+    /// Logs in from a device with the given user agent, returns the tokens
+    async fn login_from(&self, username: &str, user_agent: &str) -> Value {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::USER_AGENT, user_agent)
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+            .body(Body::from(
+                json!({ "username": username, "password": "password" }).to_string(),
+            ))
+            .unwrap();
+        let res = self.send(request).await;
+        assert_eq!(res.status, StatusCode::OK);
+        res.body
+    }
+}
+
+fn access(login: &Value) -> &str {
+    login["access_token"].as_str().unwrap()
+}
+
+#[tokio::test]
+async fn sessions_are_listed_and_revoked() {
+    let app = TestApp::new();
+    let registered = app.register("alice").await;
+    let laptop = app.login_from("alice", "Laptop").await;
+    let phone = app.login_from("alice", "Phone").await;
+
+    let res = app.get("/auth/sessions", access(&laptop)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let sessions = res.body.as_array().unwrap();
+    assert_eq!(sessions.len(), 3);
+    let current: Vec<&Value> = sessions
+        .iter()
+        .filter(|session| session["current"] == true)
+        .collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["user_agent"], "Laptop");
+    assert_eq!(current[0]["ip"], "203.0.113.7");
+    let phone_id = sessions
+        .iter()
+        .find(|session| session["user_agent"] == "Phone")
+        .map(|session| oid(&session["id"]))
+        .unwrap();
+
+    //the phone got lost
+    let res = app
+        .delete(&format!("/auth/sessions/{}", phone_id), access(&laptop))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/auth/check", access(&phone)).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/refresh",
+            None,
+            Some(json!({ "refresh_token": phone["refresh_token"] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        app.get("/auth/check", access(&laptop)).await.status,
+        StatusCode::ACCEPTED
+    );
+
+    //someone else's session is none of alice's business
+    let bob = app.register("bob").await;
+    let res = app.get("/auth/sessions", &bob).await;
+    let bob_session = oid(&res.body[0]["id"]);
+    let res = app
+        .delete(&format!("/auth/sessions/{}", bob_session), access(&laptop))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    //signing out everywhere else keeps the laptop
+    let res = app.delete("/auth/sessions", access(&laptop)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/auth/check", &registered).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    let res = app.get("/auth/sessions", access(&laptop)).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 1);
+    assert_eq!(
+        app.get("/auth/check", &bob).await.status,
+        StatusCode::ACCEPTED
+    );
+}
+
+#[tokio::test]
+async fn logout_ends_the_session() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let login = app.login_from("alice", "Laptop").await;
+
+    let res = app
+        .request(Method::POST, "/auth/logout", Some(access(&login)), None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/notes", access(&login)).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/refresh",
+            None,
+            Some(json!({ "refresh_token": login["refresh_token"] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,225 @@
+use super::{oid, TestApp};
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use serde_json::{json, Value};
+
+fn public_request(token: &str, accept: &str, password: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(Method::GET)
+        .uri(format!("/public/{}", token))
+        .header(header::ACCEPT, accept);
+    if let Some(password) = password {
+        builder = builder.header("x-share-password", password);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+async fn create_link(app: &TestApp, token: &str, note_id: &str, body: Value) -> Value {
+    let res = app
+        .post(&format!("/notes/id/{}/share-links", note_id), token, body)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    res.body
+}
+
+#[tokio::test]
+async fn public_links_serve_json_and_html() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let id = app
+        .create_note(&alice, "Meeting <1>", "agenda & notes")
+        .await;
+    let link = create_link(&app, &alice, &id, json!({})).await;
+    let token = link["token"].as_str().unwrap();
+    assert!(token.len() >= 40);
+    assert_eq!(link["password_protected"], false);
+    assert_eq!(link["views"], 0);
+
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["title"], "Meeting <1>");
+    assert_eq!(res.body["content"], "agenda & notes");
+    assert_eq!(res.body["html"], "<p>agenda &amp; notes</p>\n");
+    assert!(res.body.get("user_id").is_none());
+
+    let res = app.send(public_request(token, "text/html", None)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let html = res.body.as_str().unwrap();
+    assert!(html.contains("<h1>Meeting &lt;1&gt;</h1>"));
+    assert!(html.contains("agenda &amp; notes"));
+
+    let res = app
+        .get(&format!("/notes/id/{}/share-links", id), &alice)
+        .await;
+    let links = res.body.as_array().unwrap();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0]["views"], 2);
+    assert!(links[0]["last_viewed_at"].is_string());
+
+    let res = app
+        .send(public_request("not-a-token", "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn revoked_and_expired_links_stop_working() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_note(&alice, "Plan", "v1").await;
+    let link = create_link(&app, &alice, &id, json!({})).await;
+    let token = link["token"].as_str().unwrap();
+    let link_id = oid(&link["id"]);
+
+    //only the owner manages links
+    let res = app
+        .delete(&format!("/notes/id/{}/share-links/{}", id, link_id), &bob)
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app
+        .post(&format!("/notes/id/{}/share-links", id), &bob, json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    //and only through the note they belong to
+    let other = app.create_note(&alice, "Other", "").await;
+    let res = app
+        .patch(
+            &format!("/notes/id/{}/share-links/{}", other, link_id),
+            &alice,
+            Some(json!({ "expires_at": "2000-01-01T00:00:00Z" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app
+        .delete(
+            &format!("/notes/id/{}/share-links/{}", other, link_id),
+            &alice,
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .patch(
+            &format!("/notes/id/{}/share-links/{}", id, link_id),
+            &alice,
+            Some(json!({ "expires_at": "2000-01-01T00:00:00Z" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["expires_at"], "2000-01-01T00:00:00.000Z");
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    app.patch(
+        &format!("/notes/id/{}/share-links/{}", id, link_id),
+        &alice,
+        Some(json!({ "expires_at": null })),
+    )
+    .await;
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    //trashed notes are not served
+    app.delete(&format!("/notes/id/{}", id), &alice).await;
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    app.post(&format!("/trash/notes/{}/restore", id), &alice, json!({}))
+        .await;
+
+    let res = app
+        .delete(&format!("/notes/id/{}/share-links/{}", id, link_id), &alice)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn password_protected_links() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let id = app.create_note(&alice, "Secret", "v1").await;
+    let link = create_link(&app, &alice, &id, json!({ "password": "hunter2" })).await;
+    let token = link["token"].as_str().unwrap();
+    assert_eq!(link["password_protected"], true);
+    assert!(link.get("password").is_none());
+
+    let res = app
+        .send(public_request(token, "application/json", None))
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let res = app
+        .send(public_request(token, "application/json", Some("wrong")))
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let res = app.send(public_request(token, "text/html", None)).await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert!(res
+        .body
+        .as_str()
+        .unwrap()
+        .contains("<form method=\"post\">"));
+
+    let res = app
+        .send(public_request(token, "application/json", Some("hunter2")))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["content"], "v1");
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("/public/{}", token))
+        .header(header::ACCEPT, "text/html")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("password=hunter2"))
+        .unwrap();
+    let res = app.send(request).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(res.body.as_str().unwrap().contains("<h1>Secret</h1>"));
+
+    //only the successful opens count
+    let res = app
+        .get(&format!("/notes/id/{}/share-links", id), &alice)
+        .await;
+    assert_eq!(res.body[0]["views"], 2);
+}
+
+#[tokio::test]
+async fn guessing_link_passwords_is_limited() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let id = app.create_note(&alice, "Secret", "v1").await;
+    let link = create_link(&app, &alice, &id, json!({ "password": "hunter2" })).await;
+    let token = link["token"].as_str().unwrap();
+    let open = |password: &'static str| {
+        app.send(public_request(token, "application/json", Some(password)))
+    };
+
+    //a right password starts the count over
+    for _ in 0..4 {
+        assert_eq!(open("wrong").await.status, StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(open("hunter2").await.status, StatusCode::OK);
+    for _ in 0..5 {
+        assert_eq!(open("wrong").await.status, StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(open("wrong").await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(open("hunter2").await.status, StatusCode::TOO_MANY_REQUESTS);
+}
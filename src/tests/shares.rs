@@ -0,0 +1,189 @@
+use super::{oid, TestApp};
+use crate::{
+    models::note::NoteRole,
+    repository::{note_repo::NoteRepo, note_repo::SledNoteRepo, pagination::ListQuery},
+};
+use axum::http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+#[tokio::test]
+async fn viewers_can_read_but_not_edit() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_note(&alice, "Plan", "v1").await;
+
+    let res = app.get(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .post(
+            &format!("/notes/id/{}/shares", id),
+            &alice,
+            json!({ "username": "bob", "role": "viewer" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["username"], "bob");
+    assert_eq!(res.body["role"], "viewer");
+
+    let res = app.get(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["content"], "v1");
+
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", id),
+            &bob,
+            Some(json!({ "title": "Plan", "content": "v2", "tags": [] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app.get("/notes", &bob).await;
+    let items = res.body["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(oid(&items[0]["id"]), id);
+    assert_eq!(items[0]["shared"], true);
+    assert_eq!(items[0]["role"], "viewer");
+
+    let res = app.get("/notes", &alice).await;
+    assert_eq!(res.body["items"][0]["shared"], true);
+    assert_eq!(res.body["items"][0]["role"], "owner");
+}
+
+#[tokio::test]
+async fn editors_can_update() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let id = app.create_note(&alice, "Plan", "v1").await;
+    app.post(
+        &format!("/notes/id/{}/shares", id),
+        &alice,
+        json!({ "username": "bob", "role": "editor" }),
+    )
+    .await;
+
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", id),
+            &bob,
+            Some(json!({ "title": "Plan", "content": "v2", "tags": [] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", id), &alice).await;
+    assert_eq!(res.body["content"], "v2");
+    assert_ne!(res.body["updated_by"], res.body["user_id"]);
+
+    //only the owner manages access
+    let res = app
+        .post(
+            &format!("/notes/id/{}/shares", id),
+            &bob,
+            json!({ "username": "alice", "role": "viewer" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app.delete(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn revoking_access() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    let id = app.create_note(&alice, "Plan", "v1").await;
+    for username in ["bob", "carol"] {
+        app.post(
+            &format!("/notes/id/{}/shares", id),
+            &alice,
+            json!({ "username": username, "role": "viewer" }),
+        )
+        .await;
+    }
+
+    let res = app.get(&format!("/notes/id/{}/shares", id), &alice).await;
+    let shares = res.body.as_array().unwrap();
+    assert_eq!(shares.len(), 2);
+    assert_eq!(shares[0]["username"], "bob");
+
+    //bob can't remove carol but can leave
+    let res = app
+        .delete(&format!("/notes/id/{}/shares/carol", id), &bob)
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = app
+        .delete(&format!("/notes/id/{}/shares/bob", id), &bob)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get(&format!("/notes/id/{}", id), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .delete(&format!("/notes/id/{}/shares/carol", id), &alice)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get("/notes", &carol).await;
+    assert_eq!(res.body["items"], json!([]));
+
+    let res = app
+        .post(
+            &format!("/notes/id/{}/shares", id),
+            &alice,
+            json!({ "username": "alice", "role": "editor" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let res = app
+        .post(
+            &format!("/notes/id/{}/shares", id),
+            &alice,
+            json!({ "username": "nobody", "role": "editor" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn sled_keeps_a_share_index() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let repo = SledNoteRepo::new(
+        db.open_tree("notes").unwrap(),
+        db.open_tree("note_shares").unwrap(),
+    );
+    let owner = ObjectId::new();
+    let user = ObjectId::new();
+    let note = repo.create_note(owner, "Plan", "v1", vec![]).await.unwrap();
+    let options = ListQuery::default().options().unwrap();
+
+    assert!(repo.get_note_by_id(note.id, user).await.is_err());
+    repo.share_note(note.id, owner, user, NoteRole::Editor)
+        .await
+        .unwrap();
+    repo.update_note(user, note.id, "Plan", "v2", vec![], note.version)
+        .await
+        .unwrap();
+    let shared = repo.get_note_by_id(note.id, user).await.unwrap();
+    assert_eq!(shared.content, "v2");
+    assert_eq!(shared.updated_by, user);
+    let page = repo
+        .get_all_notes_from_user(user, &[], None, &options)
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].role, NoteRole::Editor);
+
+    repo.unshare_note(note.id, owner, user).await.unwrap();
+    assert!(repo.get_note_by_id(note.id, user).await.is_err());
+    let page = repo
+        .get_all_notes_from_user(user, &[], None, &options)
+        .await
+        .unwrap();
+    assert!(page.items.is_empty());
+}
@@ -0,0 +1,249 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+
+impl TestApp {
+    async fn sync(&self, token: &str, since: &Value, changes: Value) -> Value {
+        let res = self
+            .post(
+                "/sync",
+                token,
+                json!({ "since": since, "changes": changes }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        res.body
+    }
+}
+
+//the document with `id` out of a list of the sync response
+fn find<'a>(docs: &'a Value, id: &str) -> Option<&'a Value> {
+    docs.as_array()
+        .unwrap()
+        .iter()
+        .find(|doc| oid(&doc["_id"]) == id)
+}
+
+fn tombstoned(res: &Value, id: &str) -> bool {
+    res["tombstones"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|tombstone| oid(&tombstone["_id"]) == id)
+}
+
+#[tokio::test]
+async fn offline_changes_are_pushed_and_pulled() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let plan = app.create_note(&alice, "Plan", "v1").await;
+    app.create_note(&bob, "Not alice's", "").await;
+
+    let first = app.sync(&alice, &Value::Null, json!([])).await;
+    assert_eq!(first["reset"], true);
+    assert_eq!(first["notes"].as_array().unwrap().len(), 1);
+    assert!(find(&first["notes"], &plan).is_some());
+
+    let res = app
+        .sync(
+            &alice,
+            &first["token"],
+            json!([
+                { "op": "create_note", "client_id": "n1", "title": "Offline", "content": "" },
+                { "op": "create_todo_list", "client_id": "l1", "title": "Groceries" },
+                { "op": "create_todo", "todo_list_id": "l1", "client_id": "t1",
+                  "title": "Milk", "status": false, "priority": "Normal" },
+                { "op": "update_note", "id": { "$oid": plan }, "version": 1,
+                  "title": "Plan", "content": "v2" },
+                //a second edit based on the same version as the first one
+                { "op": "update_note", "id": { "$oid": plan }, "version": 1,
+                  "title": "Plan", "content": "v3" },
+            ]),
+        )
+        .await;
+    assert_eq!(res["reset"], false);
+    assert_eq!(res["conflicts"], json!([]));
+    let created = res["created"].as_array().unwrap();
+    assert_eq!(
+        created
+            .iter()
+            .map(|created| (
+                created["client_id"].as_str().unwrap(),
+                created["entity"].as_str().unwrap()
+            ))
+            .collect::<Vec<_>>(),
+        vec![("n1", "note"), ("l1", "todo_list"), ("t1", "todo")]
+    );
+    let list = oid(&created[1]["id"]);
+    assert_eq!(oid(&created[2]["todo_list_id"]), list);
+    assert!(find(&res["notes"], &oid(&created[0]["id"])).is_some());
+    let todo_list = find(&res["todo_lists"], &list).unwrap();
+    assert_eq!(todo_list["todos"][0]["title"], "Milk");
+    let note = find(&res["notes"], &plan).unwrap();
+    assert_eq!(
+        (note["content"].as_str(), note["version"].as_u64()),
+        (Some("v3"), Some(3))
+    );
+
+    //edited online in the meantime
+    app.patch(
+        &format!("/notes/id/{}", plan),
+        &alice,
+        Some(json!({ "title": "Plan", "content": "online", "tags": [] })),
+    )
+    .await;
+    let res = app
+        .sync(
+            &alice,
+            &res["token"],
+            json!([
+                { "op": "update_note", "id": { "$oid": plan }, "version": 3,
+                  "title": "Plan", "content": "offline" },
+                { "op": "delete_note", "id": { "$oid": oid(&created[0]["id"]) }, "version": 1 },
+            ]),
+        )
+        .await;
+    assert_eq!(res["conflicts"].as_array().unwrap().len(), 1);
+    let conflict = &res["conflicts"][0];
+    assert_eq!(
+        (&conflict["index"], &conflict["entity"], &conflict["reason"]),
+        (&json!(0), &json!("note"), &json!("version"))
+    );
+    assert_eq!(conflict["note"]["content"], "online");
+    assert_eq!(conflict["note"]["version"], 4);
+    assert!(tombstoned(&res, &oid(&created[0]["id"])));
+    assert!(find(&res["notes"], &oid(&created[0]["id"])).is_none());
+
+    //deleting it again is no conflict, purging leaves a tombstone behind
+    app.delete(&format!("/todos/id/{}", list), &alice).await;
+    assert_eq!(
+        app.delete(&format!("/trash/todos/{}", list), &alice)
+            .await
+            .status,
+        StatusCode::OK
+    );
+    let res = app
+        .sync(
+            &alice,
+            &res["token"],
+            json!([
+                { "op": "delete_note", "id": { "$oid": oid(&created[0]["id"]) }, "version": 1 },
+                { "op": "rename_todo_list", "id": { "$oid": list }, "version": 2, "title": "Food" },
+            ]),
+        )
+        .await;
+    assert_eq!(res["conflicts"].as_array().unwrap().len(), 1);
+    assert_eq!(res["conflicts"][0]["index"], 1);
+    assert_eq!(res["conflicts"][0]["reason"], "deleted");
+    assert!(tombstoned(&res, &list));
+    assert!(find(&res["todo_lists"], &list).is_none());
+}
+
+#[tokio::test]
+async fn invalid_sync_tokens_are_rejected() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let res = app
+        .post(
+            "/sync",
+            &alice,
+            json!({ "since": "not a token", "changes": [] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        app.request(axum::http::Method::POST, "/sync", None, Some(json!({})))
+            .await
+            .status,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn collaborators_cannot_delete_notes_by_syncing() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let plan = app.create_note(&alice, "Plan", "").await;
+    app.post(
+        &format!("/notes/id/{}/shares", plan),
+        &alice,
+        json!({ "username": "bob", "role": "editor" }),
+    )
+    .await;
+
+    let unknown = ObjectId::new().to_hex();
+    let res = app
+        .sync(
+            &bob,
+            &Value::Null,
+            json!([
+                { "op": "delete_note", "id": { "$oid": plan }, "version": 1 },
+                { "op": "delete_note", "id": { "$oid": unknown }, "version": 1 },
+            ]),
+        )
+        .await;
+    let conflicts = res["conflicts"].as_array().unwrap();
+    assert_eq!(conflicts.len(), 2);
+    assert_eq!(
+        (&conflicts[0]["index"], &conflicts[0]["reason"]),
+        (&json!(0), &json!("rejected"))
+    );
+    assert_eq!(oid(&conflicts[0]["note"]["_id"]), plan);
+    //without a tombstone not finding it is no success either
+    assert_eq!(conflicts[1]["reason"], "deleted");
+    assert!(!tombstoned(&res, &plan));
+
+    let res = app.get(&format!("/notes/id/{}", plan), &alice).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleting_unknown_todos_is_a_conflict() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let list = app.create_todo_list(&alice, "Chores").await;
+    let trashed = app.create_todo_list(&alice, "Old").await;
+    app.post(
+        &format!("/todos/id/{}", trashed),
+        &alice,
+        json!({ "title": "Dishes", "status": false, "priority": "Low" }),
+    )
+    .await;
+    let res = app.get(&format!("/todos/id/{}", trashed), &alice).await;
+    let todo = oid(&res.body["todos"][0]["_id"]);
+    app.delete(&format!("/todos/id/{}", trashed), &alice).await;
+    let foreign = app.create_todo_list(&bob, "Bob's").await;
+
+    let unknown = ObjectId::new().to_hex();
+    let res = app
+        .sync(
+            &alice,
+            &Value::Null,
+            json!([
+                //gone with its list
+                { "op": "delete_todo", "todo_list_id": { "$oid": trashed },
+                  "id": { "$oid": todo }, "version": 2 },
+                { "op": "delete_todo", "todo_list_id": { "$oid": list },
+                  "id": { "$oid": unknown }, "version": 1 },
+                { "op": "delete_todo", "todo_list_id": { "$oid": foreign },
+                  "id": { "$oid": unknown }, "version": 1 },
+            ]),
+        )
+        .await;
+    let conflicts = res["conflicts"].as_array().unwrap();
+    assert_eq!(conflicts.len(), 2);
+    assert_eq!(
+        (&conflicts[0]["index"], &conflicts[0]["reason"]),
+        (&json!(1), &json!("deleted"))
+    );
+    assert_eq!(oid(&conflicts[0]["todo_list"]["_id"]), list);
+    assert_eq!(
+        (&conflicts[1]["index"], &conflicts[1]["reason"]),
+        (&json!(2), &json!("deleted"))
+    );
+    assert_eq!(conflicts[1]["todo_list"], Value::Null);
+}
@@ -0,0 +1,114 @@
+use super::{oid, TestApp};
+use crate::{
+    models::note::normalize_tags,
+    repository::note_repo::{NoteRepo, SledNoteRepo},
+};
+use axum::http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+
+async fn create(app: &TestApp, token: &str, title: &str, tags: &[&str]) -> String {
+    let res = app
+        .post(
+            "/notes/create",
+            token,
+            json!({ "title": title, "content": "", "tags": tags }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    oid(&res.body["_id"])
+}
+
+#[test]
+fn tags_are_trimmed_lowercase_and_unique() {
+    let tags = vec![" Rust".to_string(), "rust ".into(), "".into(), "Web".into()];
+    assert_eq!(normalize_tags(tags), ["rust", "web"]);
+}
+
+#[tokio::test]
+async fn listing_counts_normalized_tags() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = create(&app, &token, "A", &["Rust", " rust ", "web"]).await;
+    create(&app, &token, "B", &["rust"]).await;
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.body["tags"], json!(["rust", "web"]));
+
+    let res = app.get("/tags", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.body,
+        json!([{ "tag": "rust", "notes": 2 }, { "tag": "web", "notes": 1 }])
+    );
+}
+
+#[tokio::test]
+async fn rename_merge_and_delete() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let a = create(&app, &token, "A", &["draft", "todo"]).await;
+    let b = create(&app, &token, "B", &["wip"]).await;
+    let other = app.register("bob").await;
+    create(&app, &other, "Theirs", &["draft"]).await;
+
+    let res = app
+        .patch("/tags/draft", &token, Some(json!({ "name": " Pending " })))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body, json!({ "tag": "pending", "notes": 1 }));
+
+    //the order is kept and a tag shows up once
+    let res = app
+        .post(
+            "/tags/merge",
+            &token,
+            json!({ "tags": ["pending", "wip", "todo"], "into": "todo" }),
+        )
+        .await;
+    assert_eq!(res.body["notes"], 2);
+    let res = app.get(&format!("/notes/id/{}", a), &token).await;
+    assert_eq!(res.body["tags"], json!(["todo"]));
+    let res = app.get("/notes/search?q=tag:todo", &token).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 2);
+
+    let res = app.delete("/tags/todo", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get(&format!("/notes/id/{}", b), &token).await;
+    assert_eq!(res.body["tags"], json!([]));
+    let res = app.delete("/tags/todo", &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    //other users keep their tags
+    let res = app.get("/tags", &other).await;
+    assert_eq!(res.body[0]["tag"], "draft");
+}
+
+#[tokio::test]
+async fn sled_rewrites_tags_in_one_transaction() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let repo = SledNoteRepo::new(
+        db.open_tree("notes").unwrap(),
+        db.open_tree("note_shares").unwrap(),
+    );
+    let user = ObjectId::new();
+    let first = repo
+        .create_note(user, "A", "", vec!["Old".into(), "keep".into()])
+        .await
+        .unwrap();
+    let trashed = repo
+        .create_note(user, "B", "", vec!["old".into()])
+        .await
+        .unwrap();
+    repo.delete_note(trashed.id, user, None).await.unwrap();
+
+    let changed = repo
+        .rewrite_tags(user, &["Old".into(), "old".into()], Some("new"))
+        .await
+        .unwrap();
+    assert_eq!(changed.len(), 2);
+    let note = repo.get_note_by_id(first.id, user).await.unwrap();
+    assert_eq!(note.tags, ["new", "keep"]);
+    let restored = repo.restore_note(trashed.id, user).await.unwrap();
+    assert_eq!(restored.tags, ["new"]);
+}
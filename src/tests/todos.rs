@@ -0,0 +1,138 @@
+use super::{oid, TestApp};
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn create_and_list_todo_lists() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let list = app.create_todo_list(&token, "Chores").await;
+
+    let res = app.get("/todos", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let lists = res.body["items"].as_array().unwrap();
+    assert_eq!(lists.len(), 1);
+    assert_eq!(oid(&lists[0]["_id"]), list);
+    assert_eq!(lists[0]["title"], "Chores");
+}
+
+#[tokio::test]
+async fn rename_and_delete_todo_list() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let list = app.create_todo_list(&token, "Chores").await;
+
+    let res = app
+        .patch(
+            &format!("/todos/id/{}", list),
+            &token,
+            Some(json!({ "title": "Weekend" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/todos", &token).await;
+    assert_eq!(res.body["items"][0]["title"], "Weekend");
+
+    let res = app.delete(&format!("/todos/id/{}", list), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.delete(&format!("/todos/id/{}", list), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn create_modify_and_delete_todo() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let list = app.create_todo_list(&token, "Chores").await;
+
+    let res = app
+        .post(
+            &format!("/todos/id/{}", list),
+            &token,
+            json!({ "title": "Dishes", "status": false, "priority": "High" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/todos", &token).await;
+    let todo = &res.body["items"][0]["todos"][0];
+    assert_eq!(todo["title"], "Dishes");
+    assert_eq!(todo["priority"], "High");
+    let todo_id = oid(&todo["_id"]);
+
+    let res = app
+        .patch(
+            &format!("/todos/id/{}/todo/id/{}", list, todo_id),
+            &token,
+            Some(json!({ "title": "Dishes", "status": true, "priority": "Low" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/todos", &token).await;
+    let todo = &res.body["items"][0]["todos"][0];
+    assert_eq!(todo["status"], true);
+    assert_eq!(todo["priority"], "Low");
+
+    let res = app
+        .delete(&format!("/todos/id/{}/todo/id/{}", list, todo_id), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/todos", &token).await;
+    assert_eq!(res.body["items"][0]["todos"], json!([]));
+}
+
+#[tokio::test]
+async fn todo_lists_are_scoped_to_their_owner() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let list = app.create_todo_list(&alice, "Chores").await;
+
+    let res = app
+        .post(
+            &format!("/todos/id/{}", list),
+            &bob,
+            json!({ "title": "Sneaky", "status": false, "priority": "Normal" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.delete(&format!("/todos/id/{}", list), &bob).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn todos_track_timestamps() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let list = app.create_todo_list(&token, "Chores").await;
+    let res = app.get("/todos", &token).await;
+    let created_at = res.body["items"][0]["created_at"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        res.body["items"][0]["updated_by"],
+        res.body["items"][0]["user_id"]
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    app.post(
+        &format!("/todos/id/{}", list),
+        &token,
+        json!({ "title": "Dishes", "status": false, "priority": "High" }),
+    )
+    .await;
+
+    let res = app.get("/todos", &token).await;
+    let todo_list = &res.body["items"][0];
+    let todo = &todo_list["todos"][0];
+    assert_eq!(todo_list["created_at"], created_at);
+    assert!(todo_list["updated_at"].as_str().unwrap() > created_at.as_str());
+    assert_eq!(todo["created_at"], todo_list["updated_at"]);
+    assert_eq!(todo["updated_by"], todo_list["user_id"]);
+}
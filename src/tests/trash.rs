@@ -0,0 +1,217 @@
+use super::{account::FlakyStorage, oid, TestApp};
+use crate::{
+    database::Database, error::ApiError, repository::attachment_storage::AttachmentStorage,
+    services::trash_service,
+};
+use axum::http::StatusCode;
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+use std::sync::{atomic::Ordering, Arc};
+
+impl TestApp {
+    //ids of what sync clients are told is gone
+    async fn tombstones(&self, token: &str, since: &Value) -> Vec<String> {
+        let res = self
+            .post("/sync", token, json!({ "since": since, "changes": [] }))
+            .await;
+        res.body["tombstones"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tombstone| oid(&tombstone["_id"]))
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn deleted_notes_go_to_the_trash() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Groceries", "milk").await;
+
+    let res = app.delete(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app.get("/notes", &token).await;
+    assert_eq!(res.body["items"], json!([]));
+    let res = app.get("/notes/search?q=milk", &token).await;
+    assert_eq!(res.body, json!([]));
+
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let notes = res.body["notes"].as_array().unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(oid(&notes[0]["id"]), id);
+    assert_eq!(notes[0]["title"], "Groceries");
+    assert!(notes[0]["deleted_at"].is_string());
+
+    let res = app
+        .post(&format!("/trash/notes/{}/restore", id), &token, json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["deleted_at"], json!(null));
+
+    let res = app.get("/notes/search?q=milk", &token).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 1);
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body["notes"], json!([]));
+
+    //only trashed notes can be restored or purged
+    let res = app
+        .post(&format!("/trash/notes/{}/restore", id), &token, json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app.delete(&format!("/trash/notes/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn purging_a_note_removes_its_revisions() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "v1").await;
+    app.patch(
+        &format!("/notes/id/{}", id),
+        &token,
+        Some(json!({ "title": "Plan", "content": "v2", "tags": [] })),
+    )
+    .await;
+    app.delete(&format!("/notes/id/{}", id), &token).await;
+
+    //still there while the note can be restored
+    let res = app
+        .get(&format!("/notes/id/{}/revisions", id), &token)
+        .await;
+    assert_eq!(res.body.as_array().unwrap().len(), 1);
+
+    let res = app.delete(&format!("/trash/notes/{}", id), &token).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app
+        .get(&format!("/notes/id/{}/revisions", id), &token)
+        .await;
+    assert_eq!(res.body, json!([]));
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body["notes"], json!([]));
+}
+
+#[tokio::test]
+async fn todo_lists_go_to_the_trash() {
+    let app = TestApp::new();
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let list = app.create_todo_list(&alice, "Chores").await;
+
+    app.delete(&format!("/todos/id/{}", list), &alice).await;
+    let res = app.get("/todos", &alice).await;
+    assert_eq!(res.body["items"], json!([]));
+    let res = app
+        .post(
+            &format!("/todos/id/{}", list),
+            &alice,
+            json!({ "title": "Dishes", "status": false, "priority": "Low" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.get("/trash", &bob).await;
+    assert_eq!(res.body["todo_lists"], json!([]));
+    let res = app
+        .post(&format!("/trash/todos/{}/restore", list), &bob, json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .post(&format!("/trash/todos/{}/restore", list), &alice, json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["title"], "Chores");
+
+    app.delete(&format!("/todos/id/{}", list), &alice).await;
+    let res = app.delete(&format!("/trash/todos/{}", list), &alice).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app.get("/trash", &alice).await;
+    assert_eq!(res.body["todo_lists"], json!([]));
+}
+
+#[tokio::test]
+async fn expired_items_are_purged() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let old = app.create_note(&token, "Old", "").await;
+    let list = app.create_todo_list(&token, "Old list").await;
+    app.delete(&format!("/notes/id/{}", old), &token).await;
+    app.delete(&format!("/todos/id/{}", list), &token).await;
+
+    trash_service::purge_expired(&app.state.database, Duration::days(1))
+        .await
+        .unwrap();
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body["notes"].as_array().unwrap().len(), 1);
+
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    trash_service::purge_expired(&app.state.database, Duration::zero())
+        .await
+        .unwrap();
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body, json!({ "notes": [], "todo_lists": [] }));
+}
+
+#[tokio::test]
+async fn a_purge_that_failed_halfway_can_be_retried() {
+    let storage = Arc::new(FlakyStorage::default());
+    let app = TestApp::with_database(Database::memory().with_attachment_storage(storage.clone()));
+    let token = app.register("alice").await;
+    let mut attachments = vec![];
+    let mut notes = vec![];
+    for title in ["Photos", "Scans"] {
+        let note = app.create_note(&token, title, "").await;
+        let res = app
+            .upload(&token, &note, &[("photo.txt", "text/plain", b"photo")])
+            .await;
+        attachments.push(oid(&res.body[0]["id"]).parse::<ObjectId>().unwrap());
+        app.delete(&format!("/notes/id/{}", note), &token).await;
+        notes.push(note);
+    }
+    let res = app
+        .post("/sync", &token, json!({ "since": null, "changes": [] }))
+        .await;
+    let since = res.body["token"].clone();
+
+    storage.broken.store(true, Ordering::SeqCst);
+    let res = app
+        .delete(&format!("/trash/notes/{}", notes[0]), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::INTERNAL_SERVER_ERROR);
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    trash_service::purge_expired(&app.state.database, Duration::zero())
+        .await
+        .unwrap();
+    //the notes are kept, they are what leads the retry to the files
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body["notes"].as_array().unwrap().len(), 2);
+    for attachment in attachments.iter() {
+        assert!(storage.open(*attachment, 0..5).await.is_ok());
+    }
+
+    storage.broken.store(false, Ordering::SeqCst);
+    let res = app
+        .delete(&format!("/trash/notes/{}", notes[0]), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(app.tombstones(&token, &since).await.contains(&notes[0]));
+    trash_service::purge_expired(&app.state.database, Duration::zero())
+        .await
+        .unwrap();
+    assert!(app.tombstones(&token, &since).await.contains(&notes[1]));
+    let res = app.get("/trash", &token).await;
+    assert_eq!(res.body["notes"], json!([]));
+    for attachment in attachments.iter() {
+        assert!(matches!(
+            storage.open(*attachment, 0..5).await,
+            Err(ApiError::NotFound)
+        ));
+    }
+}
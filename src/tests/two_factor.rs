@@ -0,0 +1,204 @@
+use super::TestApp;
+use crate::{models::timestamp, services::two_factor_service::code_at};
+use axum::http::{Method, StatusCode};
+use chrono::{DateTime, Duration};
+use serde_json::{json, Value};
+
+impl TestApp {
+    /// Password step of a login, returns the body
+    async fn login(&self, username: &str) -> Value {
+        let res = self
+            .request(
+                Method::POST,
+                "/auth/login",
+                None,
+                Some(json!({ "username": username, "password": "password" })),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        res.body
+    }
+
+    async fn complete_login(&self, challenge: &Value, code: &str) -> StatusCode {
+        self.request(
+            Method::POST,
+            "/auth/login/2fa",
+            None,
+            Some(json!({ "challenge_token": challenge["challenge_token"], "code": code })),
+        )
+        .await
+        .status
+    }
+
+    /// Enrolls and confirms a second factor, returns the secret and recovery codes
+    async fn enable_two_factor(&self, token: &str) -> (String, Vec<String>) {
+        let res = self.post("/auth/2fa/enroll", token, json!({})).await;
+        assert_eq!(res.status, StatusCode::OK);
+        let secret = res.body["secret"].as_str().unwrap().to_string();
+        let code = code_at(&secret, timestamp::now()).unwrap();
+        let res = self
+            .post("/auth/2fa/confirm", token, json!({ "code": code }))
+            .await;
+        assert_eq!(res.status, StatusCode::OK);
+        let codes = res.body["recovery_codes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|code| code.as_str().unwrap().to_string())
+            .collect();
+        (secret, codes)
+    }
+}
+
+#[test]
+fn codes_match_the_rfc_6238_test_vectors() {
+    //"12345678901234567890" in base32
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    for (seconds, code) in [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+    ] {
+        let at = DateTime::from_timestamp(seconds, 0).unwrap();
+        assert_eq!(code_at(secret, at).unwrap(), code);
+    }
+}
+
+#[tokio::test]
+async fn login_asks_for_the_second_factor_once_it_is_confirmed() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+
+    let res = app.post("/auth/2fa/enroll", &token, json!({})).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let secret = res.body["secret"].as_str().unwrap().to_string();
+    let uri = res.body["otpauth_uri"].as_str().unwrap();
+    assert!(uri.starts_with("otpauth://totp/Flexnotes:alice?"));
+    assert!(uri.contains(&format!("secret={}", secret)));
+
+    //pending until a code proves the app has the secret
+    assert!(app.login("alice").await["access_token"].is_string());
+    let res = app
+        .post("/auth/2fa/confirm", &token, json!({ "code": "12345" }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let code = code_at(&secret, timestamp::now()).unwrap();
+    let res = app
+        .post("/auth/2fa/confirm", &token, json!({ "code": code }))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.body["recovery_codes"].as_array().unwrap().len(), 10);
+    let res = app.get("/auth/2fa", &token).await;
+    assert_eq!(
+        res.body,
+        json!({ "enabled": true, "recovery_codes_left": 10 })
+    );
+
+    let challenge = app.login("alice").await;
+    assert_eq!(challenge["two_factor_required"], true);
+    assert!(challenge.get("access_token").is_none());
+    //the challenge is no access token
+    let challenge_token = challenge["challenge_token"].as_str().unwrap();
+    assert_eq!(
+        app.get("/auth/check", challenge_token).await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        app.complete_login(&challenge, "000 000 0").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    //the phone clock is a step ahead
+    let code = code_at(&secret, timestamp::now() + Duration::seconds(30)).unwrap();
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login/2fa",
+            None,
+            Some(json!({ "challenge_token": challenge_token, "code": code })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let access_token = res.body["access_token"].as_str().unwrap();
+    assert_eq!(
+        app.get("/auth/check", access_token).await.status,
+        StatusCode::ACCEPTED
+    );
+
+    //a code works once, even with a fresh challenge
+    let challenge = app.login("alice").await;
+    assert_eq!(
+        app.complete_login(&challenge, &code).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    //an access token is no challenge
+    let res = app
+        .request(
+            Method::POST,
+            "/auth/login/2fa",
+            None,
+            Some(json!({ "challenge_token": token, "code": code })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    //enrolling again would replace the secret without a code
+    let res = app.post("/auth/2fa/enroll", &token, json!({})).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once_and_disable_the_second_factor() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let (_secret, codes) = app.enable_two_factor(&token).await;
+
+    //typed from a printout
+    let challenge = app.login("alice").await;
+    let typed = format!(" {} ", codes[0].to_uppercase());
+    assert_eq!(app.complete_login(&challenge, &typed).await, StatusCode::OK);
+    let challenge = app.login("alice").await;
+    assert_eq!(
+        app.complete_login(&challenge, &codes[0]).await,
+        StatusCode::UNAUTHORIZED
+    );
+    let res = app.get("/auth/2fa", &token).await;
+    assert_eq!(res.body["recovery_codes_left"], 9);
+
+    let res = app
+        .post("/auth/2fa/disable", &token, json!({ "code": "zzzz-zzzz" }))
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let res = app
+        .post("/auth/2fa/disable", &token, json!({ "code": codes[1] }))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        app.get("/auth/2fa", &token).await.body,
+        json!({ "enabled": false, "recovery_codes_left": 0 })
+    );
+    assert!(app.login("alice").await["access_token"].is_string());
+}
+
+#[tokio::test]
+async fn guessing_codes_locks_the_second_factor() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let (secret, _codes) = app.enable_two_factor(&token).await;
+
+    let challenge = app.login("alice").await;
+    for _ in 0..5 {
+        assert_eq!(
+            app.complete_login(&challenge, "aaaa-aaaa").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+    //even the right code waits out the lockout
+    let code = code_at(&secret, timestamp::now() + Duration::seconds(30)).unwrap();
+    assert_eq!(
+        app.complete_login(&challenge, &code).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
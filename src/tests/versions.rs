@@ -0,0 +1,261 @@
+use super::{oid, TestApp, TestResponse};
+use crate::{
+    error::ApiError,
+    models::todo::TodoPriority,
+    repository::{
+        note_repo::{NoteRepo, SledNoteRepo},
+        todo_repo::{SledTodoRepo, TodoRepo},
+    },
+};
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+
+impl TestApp {
+    async fn if_match(
+        &self,
+        method: Method,
+        uri: &str,
+        token: &str,
+        version: &str,
+        body: Option<Value>,
+    ) -> TestResponse {
+        let builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::IF_MATCH, version);
+        let request = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => builder.body(Body::empty()).unwrap(),
+        };
+        self.send(request).await
+    }
+}
+
+fn etag(res: &TestResponse) -> &str {
+    res.headers[header::ETAG].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn stale_note_edits_are_rejected() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "v1").await;
+    let uri = format!("/notes/id/{}", id);
+
+    let res = app.get(&uri, &token).await;
+    assert_eq!(etag(&res), "\"1\"");
+    assert_eq!(res.body["version"], 1);
+
+    //two devices start from version 1, the second one loses
+    let edit = |content: &str| Some(json!({ "title": "Plan", "content": content, "tags": [] }));
+    let res = app
+        .if_match(Method::PATCH, &uri, &token, "\"1\"", edit("phone"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(etag(&res), "\"2\"");
+    let res = app
+        .if_match(Method::PATCH, &uri, &token, "\"1\"", edit("laptop"))
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(res.body["version"], 2);
+    assert_eq!(etag(&res), "\"2\"");
+    let res = app.get(&uri, &token).await;
+    assert_eq!(res.body["content"], "phone");
+    //the rejected edit left no revision behind
+    let res = app.get(&format!("{}/revisions", uri), &token).await;
+    assert_eq!(res.body.as_array().unwrap().len(), 1);
+
+    //without If-Match the last write still wins
+    let res = app.patch(&uri, &token, edit("laptop")).await;
+    assert_eq!(etag(&res), "\"3\"");
+    let res = app
+        .if_match(Method::PATCH, &uri, &token, "W/\"3\"", edit("tablet"))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app
+        .if_match(Method::DELETE, &uri, &token, "\"2\"", None)
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    let res = app
+        .if_match(Method::DELETE, &uri, &token, "\"3\"", None)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn todo_changes_bump_the_list_version() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let list = app.create_todo_list(&token, "Groceries").await;
+    let uri = format!("/todos/id/{}", list);
+    let todo = json!({ "title": "Milk", "status": false, "priority": "Normal" });
+
+    let res = app.get(&uri, &token).await;
+    assert_eq!(etag(&res), "\"1\"");
+    let res = app
+        .if_match(Method::POST, &uri, &token, "\"1\"", Some(todo.clone()))
+        .await;
+    assert_eq!(etag(&res), "\"2\"");
+    let res = app.get(&uri, &token).await;
+    let todo_id = oid(&res.body["todos"][0]["_id"]);
+    let todo_uri = format!("{}/todo/id/{}", uri, todo_id);
+
+    let done = json!({ "title": "Milk", "status": true, "priority": "Normal" });
+    let res = app
+        .if_match(
+            Method::PATCH,
+            &todo_uri,
+            &token,
+            "\"1\"",
+            Some(done.clone()),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(res.body["version"], 2);
+    let res = app
+        .if_match(Method::PATCH, &todo_uri, &token, "\"2\"", Some(done))
+        .await;
+    assert_eq!(etag(&res), "\"3\"");
+
+    let res = app
+        .if_match(
+            Method::PATCH,
+            &uri,
+            &token,
+            "\"2\"",
+            Some(json!({ "title": "Shopping" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    let res = app
+        .if_match(Method::DELETE, &todo_uri, &token, "\"3\"", None)
+        .await;
+    assert_eq!(etag(&res), "\"4\"");
+    let res = app
+        .if_match(Method::DELETE, &uri, &token, "\"3\"", None)
+        .await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    let res = app.if_match(Method::DELETE, &uri, &token, "*", None).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sled_updates_compare_versions() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let notes = SledNoteRepo::new(
+        db.open_tree("notes").unwrap(),
+        db.open_tree("note_shares").unwrap(),
+    );
+    let todos = SledTodoRepo::new(db.open_tree("todos").unwrap());
+    let user = ObjectId::new();
+
+    let note = notes.create_note(user, "Plan", "v1", vec![]).await.unwrap();
+    let updated = notes
+        .update_note(user, note.id, "Plan", "v2", vec![], 1)
+        .await
+        .unwrap();
+    assert_eq!(updated.version, 2);
+    assert!(matches!(
+        notes
+            .update_note(user, note.id, "Plan", "v3", vec![], 1)
+            .await,
+        Err(ApiError::PreconditionFailed(2))
+    ));
+
+    let list = todos.create_todo_list("Chores".into(), user).await.unwrap();
+    let list = todos
+        .create_todo(
+            list.id,
+            user,
+            "Dishes".into(),
+            false,
+            TodoPriority::Low,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(list.version, 2);
+    assert!(matches!(
+        todos
+            .rename_todo_list(list.id, user, "Home".into(), Some(1))
+            .await,
+        Err(ApiError::PreconditionFailed(2))
+    ));
+    assert_eq!(
+        todos.get_todo_list(list.id, user).await.unwrap().title,
+        "Chores"
+    );
+
+    //deletes check the version in the same write
+    assert!(matches!(
+        notes.delete_note(note.id, user, Some(1)).await,
+        Err(ApiError::PreconditionFailed(2))
+    ));
+    assert!(notes.get_note_by_id(note.id, user).await.is_ok());
+    let trashed = notes.delete_note(note.id, user, Some(2)).await.unwrap();
+    assert!(trashed.deleted_at.is_some());
+    assert!(matches!(
+        todos.delete_todo_list(list.id, user, Some(1)).await,
+        Err(ApiError::PreconditionFailed(2))
+    ));
+    assert!(todos.get_todo_list(list.id, user).await.is_ok());
+    assert!(todos.delete_todo_list(list.id, user, Some(2)).await.is_ok());
+    assert!(matches!(
+        todos.get_todo_list(list.id, user).await,
+        Err(ApiError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn the_browser_client_can_use_versions() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let id = app.create_note(&token, "Plan", "v1").await;
+    let uri = format!("/notes/id/{}", id);
+    let origin = "http://localhost:5173";
+
+    let res = app
+        .send(
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri(&uri)
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PATCH")
+                .header(
+                    header::ACCESS_CONTROL_REQUEST_HEADERS,
+                    "authorization,content-type,if-match",
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+    let allowed = res.headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+        .to_str()
+        .unwrap();
+    assert!(allowed.split(',').any(|name| name.trim() == "if-match"));
+
+    let res = app
+        .send(
+            Request::builder()
+                .uri(&uri)
+                .header(header::ORIGIN, origin)
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::ACCESS_CONTROL_EXPOSE_HEADERS], "etag");
+    assert!(res.headers.contains_key(header::ETAG));
+}
@@ -0,0 +1,134 @@
+use super::{oid, TestApp};
+use crate::services::link_service::{parse_links, rewrite_links};
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[test]
+fn parses_titles_ids_and_skips_code() {
+    let id = mongodb::bson::oid::ObjectId::new();
+    let content = format!(
+        "See [[ Daily Log |today]] and [[id:{}]], [[daily log]] again.\n\
+         `[[Not A Link]]` and\n```\n[[Also Not]]\n```\n[[id:nope]] [[]]",
+        id
+    );
+    let links = parse_links(&content);
+    assert_eq!(links.len(), 4);
+    assert_eq!(links[0].target, "Daily Log");
+    assert_eq!(links[0].key.as_deref(), Some("daily log"));
+    assert_eq!(links[1].id, Some(id));
+    assert_eq!(links[2].target, "daily log");
+    assert_eq!(links[3].target, "id:nope");
+    assert!(links[3].id.is_none() && links[3].key.is_none());
+}
+
+#[test]
+fn rewrite_keeps_aliases() {
+    let content = "[[Old]] [[old|alias]] [[Older]] `[[Old]]`";
+    assert_eq!(
+        rewrite_links(content, "Old", "New"),
+        "[[New]] [[New|alias]] [[Older]] `[[Old]]`"
+    );
+    //titles the syntax can not express are left alone
+    assert_eq!(rewrite_links(content, "Old", "a|b"), content);
+}
+
+#[tokio::test]
+async fn links_and_backlinks() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let target = app.create_note(&token, "Target", "").await;
+    let source = app
+        .create_note(
+            &token,
+            "Source",
+            &format!("[[target]] [[Missing]] [[id:{}|by id]]", target),
+        )
+        .await;
+
+    let res = app
+        .get(&format!("/notes/id/{}/links", source), &token)
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let links = res.body.as_array().unwrap();
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[0]["broken"], false);
+    assert_eq!(oid(&links[0]["note"]["id"]), target);
+    assert_eq!(links[1]["target"], "Missing");
+    assert_eq!(links[1]["broken"], true);
+    assert!(links[1]["note"].is_null());
+    assert_eq!(links[2]["note"]["title"], "Target");
+
+    let res = app
+        .get(&format!("/notes/id/{}/backlinks", target), &token)
+        .await;
+    let backlinks = res.body.as_array().unwrap();
+    assert_eq!(backlinks.len(), 1);
+    assert_eq!(oid(&backlinks[0]["id"]), source);
+
+    //a note created later fixes the broken link
+    app.create_note(&token, "missing", "").await;
+    let res = app
+        .get(&format!("/notes/id/{}/links", source), &token)
+        .await;
+    assert_eq!(res.body[1]["broken"], false);
+
+    //trashed notes neither link nor get linked
+    app.delete(&format!("/notes/id/{}", source), &token).await;
+    let res = app
+        .get(&format!("/notes/id/{}/backlinks", target), &token)
+        .await;
+    assert_eq!(res.body.as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn rename_rewrites_referring_notes() {
+    let app = TestApp::new();
+    let token = app.register("alice").await;
+    let target = app.create_note(&token, "Draft", "").await;
+    let source = app
+        .create_note(&token, "Index", "Read [[Draft|the draft]] first")
+        .await;
+
+    //without the flag the link breaks
+    let res = app
+        .patch(
+            &format!("/notes/id/{}", target),
+            &token,
+            Some(json!({ "title": "Essay", "content": "", "tags": [] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let res = app
+        .get(&format!("/notes/id/{}/links", source), &token)
+        .await;
+    assert_eq!(res.body[0]["broken"], true);
+
+    //renaming back restores it, the flag carries it over to the new title
+    app.patch(
+        &format!("/notes/id/{}", target),
+        &token,
+        Some(json!({ "title": "Draft", "content": "", "tags": [] })),
+    )
+    .await;
+    let res = app
+        .patch(
+            &format!("/notes/id/{}?rewrite_links=true", target),
+            &token,
+            Some(json!({ "title": "Final", "content": "", "tags": [] })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get(&format!("/notes/id/{}", source), &token).await;
+    assert_eq!(res.body["content"], "Read [[Final|the draft]] first");
+    let res = app
+        .get(&format!("/notes/id/{}/backlinks", target), &token)
+        .await;
+    assert_eq!(oid(&res.body[0]["id"]), source);
+
+    //the rewrite is recorded like any other edit
+    let res = app
+        .get(&format!("/notes/id/{}/revisions", source), &token)
+        .await;
+    assert_eq!(res.body.as_array().unwrap().len(), 1);
+}